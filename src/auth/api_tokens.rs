@@ -0,0 +1,70 @@
+use bson::Uuid;
+use rand::{distributions::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
+
+use crate::models::user::{
+    api_token::{ApiToken, Scope},
+    User,
+};
+use crate::repository::database::DatabaseRepository;
+
+/// Prefix of every personal access token. Lets `AuthorizationService` tell them apart from JWTs.
+pub const API_TOKEN_PREFIX: &str = "scr_";
+
+/// Length of the random part of a personal access token
+const API_TOKEN_LENGTH: usize = 40;
+
+/// Number of characters of the token kept in clear text to help users tell their tokens apart
+const DISPLAY_PREFIX_LENGTH: usize = 8;
+
+/// Create a new personal access token.
+/// Returns the token, which is only shown once, and the record to store with only its hash.
+pub fn new_api_token(name: &str, scopes: Vec<Scope>, date_expires: Option<i64>) -> (String, ApiToken) {
+    let secret: String = rand::thread_rng().sample_iter(&Alphanumeric).take(API_TOKEN_LENGTH).map(char::from).collect();
+    let token = format!("{}{}", API_TOKEN_PREFIX, secret);
+
+    let record = ApiToken {
+        id: Uuid::new().to_string(),
+        name: name.to_owned(),
+        token_hash: hash_api_token(&token),
+        prefix: token[..API_TOKEN_PREFIX.len() + DISPLAY_PREFIX_LENGTH].to_owned(),
+        scopes,
+        date_created: chrono::Utc::now().timestamp(),
+        date_expires,
+        date_last_used: None,
+        date_revoked: None,
+    };
+    (token, record)
+}
+
+/// Check if a bearer token looks like a personal access token rather than a JWT
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(API_TOKEN_PREFIX)
+}
+
+/// Hash a personal access token for storage and lookup.
+/// Tokens are long and random, so a fast hash is enough.
+pub fn hash_api_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Find the user and the active token matching a personal access token.
+/// Returns `None` if the token is unknown, revoked or expired, or if the account is disabled or pending deletion.
+pub async fn authenticate_api_token(db: &DatabaseRepository, token: &str) -> Result<Option<(User, ApiToken)>, String> {
+    let token_hash = hash_api_token(token);
+    let user = match db.get_user_by_api_token(&token_hash).await.map_err(|e| e.to_string())? {
+        Some(user) if user.date_disabled.is_none() && user.date_deleted.is_none() => user,
+        _ => return Ok(None),
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    let api_token = match user.api_tokens.iter().find(|t| t.token_hash == token_hash) {
+        Some(api_token) if api_token.is_active(now) => api_token.clone(),
+        _ => return Ok(None),
+    };
+
+    if let Err(e) = db.touch_api_token(&token_hash).await {
+        log::error!("Error recording use of API token {}: {}", api_token.id, e);
+    }
+    Ok(Some((user, api_token)))
+}
@@ -0,0 +1,74 @@
+use actix_web::{http::header, HttpRequest};
+use lazy_static::lazy_static;
+use std::{env, net::IpAddr};
+
+lazy_static! {
+    /// The reverse proxies whose `X-Forwarded-For` header is trusted, loaded from the
+    /// comma-separated IPs in `TRUSTED_PROXIES`. Empty when it is not set.
+    pub static ref TRUSTED_PROXIES: Vec<IpAddr> = parse_proxies(&env::var("TRUSTED_PROXIES").unwrap_or_default())
+        .unwrap_or_else(|e| panic!("Failed to load trusted proxies: {}", e));
+}
+
+/// Get the IP of the client of a request, for rate limiting and sessions.
+///
+/// This is the address of the connection, unless it comes from a trusted proxy. Then it is the
+/// last address in `X-Forwarded-For` that is not a trusted proxy, since clients can write any
+/// addresses before the ones appended by the proxies. Returns `None` if the connection has no
+/// address, which only happens in tests.
+pub fn client_ip(req: &HttpRequest) -> Option<String> {
+    let peer = req.peer_addr()?.ip();
+    let forwarded_for = req.headers().get(header::X_FORWARDED_FOR).and_then(|value| value.to_str().ok());
+    Some(resolve(peer, forwarded_for, &TRUSTED_PROXIES).to_string())
+}
+
+fn resolve(peer: IpAddr, forwarded_for: Option<&str>, trusted: &[IpAddr]) -> IpAddr {
+    if !trusted.contains(&peer) {
+        return peer;
+    }
+    let mut client = peer;
+    for hop in forwarded_for.unwrap_or_default().rsplit(',') {
+        match hop.trim().parse::<IpAddr>() {
+            Ok(ip) => {
+                client = ip;
+                if !trusted.contains(&ip) {
+                    break;
+                }
+            }
+            // A malformed hop was written by the client, so the last proxy's view is all that can be trusted
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+fn parse_proxies(value: &str) -> Result<Vec<IpAddr>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|proxy| !proxy.is_empty())
+        .map(|proxy| proxy.parse::<IpAddr>().map_err(|_| format!("Invalid proxy IP {}", proxy)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+        let trusted = parse_proxies("10.0.0.1, 10.0.0.2").unwrap();
+
+        // forwarded headers from untrusted connections are ignored
+        assert_eq!(resolve(client, Some("198.51.100.1"), &trusted), client);
+        assert_eq!(resolve(proxy, Some("203.0.113.7"), &trusted), client);
+        // addresses written by the client before the proxy's are ignored
+        assert_eq!(resolve(proxy, Some("198.51.100.1, 203.0.113.7"), &trusted), client);
+        assert_eq!(resolve(proxy, Some("203.0.113.7, 10.0.0.2"), &trusted), client);
+        assert_eq!(resolve(proxy, Some("garbage, 203.0.113.7"), &trusted), client);
+        assert_eq!(resolve(proxy, None, &trusted), proxy);
+        assert!(parse_proxies("10.0.0.1, nope").is_err());
+        assert!(parse_proxies("").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,166 @@
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+/// How long a key set is cached when the response has no `max-age`
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(5 * 60);
+
+/// How long to wait before retrying a failed fetch, while the last good key set is served
+const RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Minimum time between two fetches triggered by an unknown `kid`,
+/// so tokens with made-up key ids cannot make us hammer the provider
+const DEFAULT_REFETCH_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// A shared in-memory cache of JWK sets, by URL.
+///
+/// Key sets are cached for the `max-age` of their response. An expired key set is still
+/// served while it is refreshed in the background, and if the refresh fails the last
+/// good key set keeps being served. Only the first request for a URL waits for the fetch.
+#[derive(Clone)]
+pub struct JwksCache {
+    client: reqwest::Client,
+    entries: Arc<RwLock<HashMap<String, CachedJwks>>>,
+    refetch_cooldown: Duration,
+}
+
+#[derive(Clone)]
+struct CachedJwks {
+    keys: JwkSet,
+    expires_at: Instant,
+    fetched_at: Instant,
+    refreshing: bool,
+}
+
+impl Default for JwksCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JwksCache {
+    pub fn new() -> Self {
+        Self::with_refetch_cooldown(DEFAULT_REFETCH_COOLDOWN)
+    }
+
+    /// A cache that refetches a key set on an unknown `kid` at most once per `refetch_cooldown`
+    pub fn with_refetch_cooldown(refetch_cooldown: Duration) -> Self {
+        JwksCache {
+            client: reqwest::Client::new(),
+            entries: Arc::new(RwLock::new(HashMap::new())),
+            refetch_cooldown,
+        }
+    }
+
+    /// Get the key set at `url`, fetching it only if it was never fetched before
+    pub async fn get(&self, url: &str) -> Result<JwkSet, String> {
+        let cached = self.entries.read().unwrap().get(url).cloned();
+        match cached {
+            Some(cached) => {
+                if cached.expires_at <= Instant::now() && !cached.refreshing {
+                    self.refresh_in_background(url);
+                }
+                Ok(cached.keys)
+            }
+            None => self.refresh(url).await,
+        }
+    }
+
+    /// Find the key with the given `kid` in the key set at `url`.
+    /// Refetches the key set if the key is unknown, since the provider may have rotated its keys.
+    pub async fn find(&self, url: &str, kid: &str) -> Result<Option<Jwk>, String> {
+        let keys = self.get(url).await?;
+        if let Some(jwk) = keys.find(kid) {
+            return Ok(Some(jwk.to_owned()));
+        }
+
+        let fetched_at = self.entries.read().unwrap().get(url).map(|cached| cached.fetched_at);
+        if fetched_at.map(|t| t.elapsed() < self.refetch_cooldown).unwrap_or(false) {
+            return Ok(None);
+        }
+
+        log::debug!("Unknown kid {}; refetching JWKs from {}", kid, url);
+        let keys = self.refresh(url).await?;
+        Ok(keys.find(kid).cloned())
+    }
+
+    /// Fetch the key set at `url` and cache it.
+    /// If the fetch fails, the last good key set is returned and the error is logged.
+    pub async fn refresh(&self, url: &str) -> Result<JwkSet, String> {
+        match self.fetch(url).await {
+            Ok((keys, max_age)) => {
+                let now = Instant::now();
+                let cached = CachedJwks {
+                    keys: keys.clone(),
+                    expires_at: now + max_age.unwrap_or(DEFAULT_MAX_AGE),
+                    fetched_at: now,
+                    refreshing: false,
+                };
+                self.entries.write().unwrap().insert(url.to_string(), cached);
+                Ok(keys)
+            }
+            Err(e) => {
+                log::error!("Error fetching JWKs from {}: {}", url, e);
+                let mut entries = self.entries.write().unwrap();
+                match entries.get_mut(url) {
+                    Some(cached) => {
+                        cached.expires_at = Instant::now() + RETRY_AFTER;
+                        cached.fetched_at = Instant::now();
+                        cached.refreshing = false;
+                        Ok(cached.keys.clone())
+                    }
+                    None => Err(e),
+                }
+            }
+        }
+    }
+
+    fn refresh_in_background(&self, url: &str) {
+        if let Some(cached) = self.entries.write().unwrap().get_mut(url) {
+            cached.refreshing = true;
+        }
+        let cache = self.clone();
+        let url = url.to_string();
+        actix_rt::spawn(async move {
+            let _ = cache.refresh(&url).await;
+        });
+    }
+
+    /// Retrieve a JWK set and the `max-age` of its `Cache-Control` header
+    async fn fetch(&self, url: &str) -> Result<(JwkSet, Option<Duration>), String> {
+        log::debug!("Retrieving latest JWKs from {}", url);
+        let res = self.client.get(url).send().await.map_err(|e| e.to_string())?;
+        let res = res.error_for_status().map_err(|e| e.to_string())?;
+
+        let max_age = res.headers().get(reqwest::header::CACHE_CONTROL).and_then(|value| value.to_str().ok()).and_then(parse_max_age);
+
+        let keys = res.json::<JwkSet>().await.map_err(|e| e.to_string())?;
+        Ok((keys, max_age))
+    }
+}
+
+/// Read the `max-age` directive of a `Cache-Control` header
+fn parse_max_age(cache_control: &str) -> Option<Duration> {
+    cache_control
+        .split(',')
+        .map(str::trim)
+        .find_map(|directive| directive.strip_prefix("max-age="))
+        .and_then(|max_age| max_age.parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_max_age() {
+        assert_eq!(parse_max_age("public, max-age=19845, must-revalidate"), Some(Duration::from_secs(19845)));
+        assert_eq!(parse_max_age("max-age=0"), Some(Duration::from_secs(0)));
+        assert_eq!(parse_max_age("no-cache"), None);
+        assert_eq!(parse_max_age("max-age=soon"), None);
+    }
+}
@@ -0,0 +1,345 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    decode, decode_header, encode,
+    errors::{Error, ErrorKind},
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters,
+        RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use lazy_static::lazy_static;
+use ring::signature::{Ed25519KeyPair, KeyPair, RsaKeyPair};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{env, fs, path::Path};
+
+/// Default number of hours a retired key can still verify tokens
+const DEFAULT_GRACE_HOURS: i64 = 24;
+
+lazy_static! {
+    /// The keys used to sign and verify the JWTs issued by this server, loaded from the environment.
+    ///
+    /// If `JWT_KEYS_PATH` is set, keys are loaded from that file (see [`KeyStore::from_config_file`]).
+    /// Otherwise tokens are signed with HS256 using `JWT_SECRET`.
+    pub static ref KEYS: KeyStore = KeyStore::from_env().unwrap_or_else(|e| panic!("Failed to load JWT signing keys: {}", e));
+}
+
+/// A key used to sign and verify JWTs
+pub struct SigningKey {
+    /// The key id set in the `kid` header of the tokens signed with this key
+    pub kid: Option<String>,
+
+    /// The signing algorithm of the key
+    pub algorithm: Algorithm,
+
+    /// The timestamp after which the key is retired. Retired keys only verify tokens during the grace window.
+    pub retired_at: Option<i64>,
+
+    /// The public key in JWK format. Symmetric keys are never published.
+    pub jwk: Option<Jwk>,
+
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+}
+
+/// The set of keys used to sign and verify JWTs
+pub struct KeyStore {
+    keys: Vec<SigningKey>,
+    active: usize,
+    grace_seconds: i64,
+}
+
+/// The JSON file listing the signing keys.
+///
+/// ```json
+/// {
+///     "active_kid": "2024-01",
+///     "keys": [
+///         { "kid": "2024-01", "algorithm": "RS256", "private_key_path": "2024-01.pem" },
+///         { "kid": "2023-06", "algorithm": "EdDSA", "private_key_path": "2023-06.pem", "retired_at": 1700000000 }
+///     ]
+/// }
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeysConfig {
+    /// The key id of the key used to sign new tokens. Can be overridden with `JWT_ACTIVE_KID`.
+    pub active_kid: String,
+
+    /// Every key that can sign or verify tokens
+    pub keys: Vec<KeyConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeyConfig {
+    /// The key id, published as `kid` in the JWKS
+    pub kid: String,
+
+    /// `RS256` or `EdDSA`
+    pub algorithm: Algorithm,
+
+    /// Path to the PKCS#8 (or PKCS#1 for RSA) PEM private key, relative to the config file
+    pub private_key_path: String,
+
+    /// The timestamp after which the key is retired. This field is optional.
+    pub retired_at: Option<i64>,
+}
+
+impl KeyStore {
+    /// Load the keys from the environment.
+    ///
+    /// Reads `JWT_KEYS_PATH`, `JWT_ACTIVE_KID` and `JWT_KEY_GRACE_HOURS`,
+    /// falling back to HS256 with `JWT_SECRET`.
+    pub fn from_env() -> Result<Self, String> {
+        let grace_hours = match env::var("JWT_KEY_GRACE_HOURS") {
+            Ok(hours) => hours.parse::<i64>().map_err(|_| "JWT_KEY_GRACE_HOURS must be a number".to_string())?,
+            Err(_) => DEFAULT_GRACE_HOURS,
+        };
+
+        match env::var("JWT_KEYS_PATH") {
+            Ok(path) => Self::from_config_file(Path::new(&path), env::var("JWT_ACTIVE_KID").ok(), grace_hours),
+            Err(_) => {
+                let secret = env::var("JWT_SECRET").map_err(|_| "JWT_SECRET or JWT_KEYS_PATH must be set".to_string())?;
+                Ok(Self::from_secret(&secret))
+            }
+        }
+    }
+
+    /// A key store with a single HS256 key and no published keys
+    pub fn from_secret(secret: &str) -> Self {
+        let key = SigningKey {
+            kid: None,
+            algorithm: Algorithm::HS256,
+            retired_at: None,
+            jwk: None,
+            encoding: EncodingKey::from_secret(secret.as_ref()),
+            decoding: DecodingKey::from_secret(secret.as_ref()),
+        };
+        KeyStore {
+            keys: vec![key],
+            active: 0,
+            grace_seconds: 0,
+        }
+    }
+
+    /// Load the keys listed in a JSON config file
+    pub fn from_config_file(path: &Path, active_kid: Option<String>, grace_hours: i64) -> Result<Self, String> {
+        let config = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let config: KeysConfig = serde_json::from_str(&config).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        Self::from_config(config, base_dir, active_kid, grace_hours)
+    }
+
+    /// Load the keys of a config, resolving private key paths relative to `base_dir`
+    pub fn from_config(config: KeysConfig, base_dir: &Path, active_kid: Option<String>, grace_hours: i64) -> Result<Self, String> {
+        let active_kid = active_kid.unwrap_or(config.active_kid);
+
+        let mut keys = vec![];
+        for key in config.keys {
+            let pem = fs::read(base_dir.join(&key.private_key_path)).map_err(|e| format!("Failed to read key {}: {}", key.kid, e))?;
+            keys.push(SigningKey::from_pem(key.kid, key.algorithm, &pem, key.retired_at)?);
+        }
+
+        let active = keys
+            .iter()
+            .position(|k| k.kid.as_deref() == Some(active_kid.as_str()))
+            .ok_or(format!("Active key {} is not configured", active_kid))?;
+        if keys[active].retired_at.map(|t| t <= chrono::Utc::now().timestamp()).unwrap_or(false) {
+            return Err(format!("Active key {} is retired", active_kid));
+        }
+
+        Ok(KeyStore {
+            keys,
+            active,
+            grace_seconds: chrono::Duration::hours(grace_hours).num_seconds(),
+        })
+    }
+
+    /// Sign claims with the active key
+    pub fn encode<T: Serialize>(&self, claims: &T) -> Result<String, Error> {
+        let key = &self.keys[self.active];
+        let mut header = Header::new(key.algorithm);
+        header.kid = key.kid.to_owned();
+        encode(&header, claims, &key.encoding)
+    }
+
+    /// Verify a token with the key matching its `kid` header and decode its claims
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<T, Error> {
+        let header = decode_header(token)?;
+        let key = self.keys.iter().find(|k| k.kid == header.kid && self.can_verify(k)).ok_or(ErrorKind::InvalidSignature)?;
+        let decoded = decode::<T>(token, &key.decoding, &Validation::new(key.algorithm))?;
+        Ok(decoded.claims)
+    }
+
+    /// The public keys that can currently verify tokens, including retired keys in their grace window
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self.keys.iter().filter(|k| self.can_verify(k)).filter_map(|k| k.jwk.to_owned()).collect(),
+        }
+    }
+
+    fn can_verify(&self, key: &SigningKey) -> bool {
+        match key.retired_at {
+            Some(retired_at) => chrono::Utc::now().timestamp() < retired_at + self.grace_seconds,
+            None => true,
+        }
+    }
+}
+
+impl SigningKey {
+    /// Load an RS256 or EdDSA key from a PEM private key and derive its public JWK
+    pub fn from_pem(kid: String, algorithm: Algorithm, pem: &[u8], retired_at: Option<i64>) -> Result<Self, String> {
+        let parsed = pem::parse(pem).map_err(|e| format!("Invalid PEM for key {}: {}", kid, e))?;
+        let invalid = |e: String| format!("Invalid {:?} key {}: {}", algorithm, kid, e);
+
+        let (encoding, params) = match algorithm {
+            Algorithm::RS256 => {
+                let key_pair = match parsed.tag.as_str() {
+                    "RSA PRIVATE KEY" => RsaKeyPair::from_der(&parsed.contents),
+                    _ => RsaKeyPair::from_pkcs8(&parsed.contents),
+                }
+                .map_err(|e| invalid(e.to_string()))?;
+                let public_key = key_pair.public_key();
+                let params = AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n: URL_SAFE_NO_PAD.encode(public_key.modulus().big_endian_without_leading_zero()),
+                    e: URL_SAFE_NO_PAD.encode(public_key.exponent().big_endian_without_leading_zero()),
+                });
+                (EncodingKey::from_rsa_pem(pem).map_err(|e| invalid(e.to_string()))?, params)
+            }
+            Algorithm::EdDSA => {
+                let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(&parsed.contents).map_err(|e| invalid(e.to_string()))?;
+                let params = AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x: URL_SAFE_NO_PAD.encode(key_pair.public_key().as_ref()),
+                });
+                (EncodingKey::from_ed_pem(pem).map_err(|e| invalid(e.to_string()))?, params)
+            }
+            _ => return Err(format!("Unsupported algorithm {:?} for key {}", algorithm, kid)),
+        };
+
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                algorithm: Some(algorithm),
+                key_id: Some(kid.to_owned()),
+                ..Default::default()
+            },
+            algorithm: params,
+        };
+        let decoding = DecodingKey::from_jwk(&jwk).map_err(|e| invalid(e.to_string()))?;
+
+        Ok(SigningKey {
+            kid: Some(kid),
+            algorithm,
+            retired_at,
+            jwk: Some(jwk),
+            encoding,
+            decoding,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::jwt::Claims;
+
+    fn claims() -> Claims {
+        let now = chrono::Utc::now().timestamp() as usize;
+        Claims {
+            iss: "scrippt".to_string(),
+            sub: "user".to_string(),
+            aud: "localhost".to_string(),
+            exp: now + 60,
+            nbf: now,
+            iat: now,
+            jti: "jti".to_string(),
+            roles: vec![],
+            sid: "sid".to_string(),
+        }
+    }
+
+    fn key_config(kid: &str, algorithm: Algorithm, file: &str, retired_at: Option<i64>) -> KeyConfig {
+        KeyConfig {
+            kid: kid.to_string(),
+            algorithm,
+            private_key_path: file.to_string(),
+            retired_at,
+        }
+    }
+
+    fn store(active_kid: &str, keys: Vec<KeyConfig>) -> KeyStore {
+        let config = KeysConfig {
+            active_kid: active_kid.to_string(),
+            keys,
+        };
+        KeyStore::from_config(config, Path::new("tests/fixtures/keys"), None, 1).unwrap()
+    }
+
+    #[test]
+    fn test_sign_and_verify_asymmetric() {
+        for (algorithm, file) in [(Algorithm::RS256, "rsa-test.pem"), (Algorithm::EdDSA, "ed25519-test.pem")] {
+            let keys = store("current", vec![key_config("current", algorithm, file, None)]);
+            let token = keys.encode(&claims()).unwrap();
+            assert_eq!(decode_header(&token).unwrap().kid.as_deref(), Some("current"));
+            let decoded: Claims = keys.decode(&token).unwrap();
+            assert_eq!(decoded.sub, "user");
+
+            // The published key verifies the token on its own
+            let jwks = keys.jwks();
+            assert_eq!(jwks.keys.len(), 1);
+            let decoding = DecodingKey::from_jwk(jwks.find("current").unwrap()).unwrap();
+            assert!(decode::<Claims>(&token, &decoding, &Validation::new(algorithm)).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_key_rotation_grace_window() {
+        let now = chrono::Utc::now().timestamp();
+        let old = store("old", vec![key_config("old", Algorithm::EdDSA, "ed25519-test.pem", None)]);
+        let token = old.encode(&claims()).unwrap();
+
+        // Retired within the grace window: still verifies and is still published
+        let rotated = store(
+            "new",
+            vec![
+                key_config("new", Algorithm::RS256, "rsa-test.pem", None),
+                key_config("old", Algorithm::EdDSA, "ed25519-test.pem", Some(now - 60)),
+            ],
+        );
+        assert!(rotated.decode::<Claims>(&token).is_ok());
+        assert_eq!(rotated.jwks().keys.len(), 2);
+        let new_token = rotated.encode(&claims()).unwrap();
+        assert_eq!(decode_header(&new_token).unwrap().kid.as_deref(), Some("new"));
+
+        // Past the grace window: rejected and no longer published
+        let expired = store(
+            "new",
+            vec![
+                key_config("new", Algorithm::RS256, "rsa-test.pem", None),
+                key_config("old", Algorithm::EdDSA, "ed25519-test.pem", Some(now - 2 * 3600)),
+            ],
+        );
+        assert!(expired.decode::<Claims>(&token).is_err());
+        assert_eq!(expired.jwks().keys.len(), 1);
+    }
+
+    #[test]
+    fn test_secret_keys_are_not_published() {
+        let keys = KeyStore::from_secret("secret");
+        let token = keys.encode(&claims()).unwrap();
+        assert!(keys.decode::<Claims>(&token).is_ok());
+        assert!(keys.jwks().keys.is_empty());
+        assert!(KeyStore::from_secret("other").decode::<Claims>(&token).is_err());
+    }
+
+    #[test]
+    fn test_active_key_must_exist() {
+        let config = KeysConfig {
+            active_kid: "missing".to_string(),
+            keys: vec![key_config("current", Algorithm::RS256, "rsa-test.pem", None)],
+        };
+        assert!(KeyStore::from_config(config, Path::new("tests/fixtures/keys"), None, 1).is_err());
+    }
+}
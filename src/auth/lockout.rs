@@ -0,0 +1,213 @@
+use redis::RedisError;
+use serde::{Deserialize, Serialize};
+
+use crate::repository::redis::RedisRepository;
+
+/// Number of seconds failed attempts are remembered after the last one
+const ATTEMPT_WINDOW_SECONDS: usize = 24 * 60 * 60;
+
+/// Number of seconds of the first lockout. Every further failure doubles it.
+const BASE_LOCKOUT_SECONDS: usize = 60;
+
+/// Longest lockout, however many attempts failed
+const MAX_LOCKOUT_SECONDS: usize = 60 * 60;
+
+/// A route protected against brute-force guessing
+#[derive(Clone, Copy, Debug)]
+pub struct Policy {
+    /// The name of the route, used in Redis keys
+    pub name: &'static str,
+
+    /// What failed attempts are counted against, besides the client IP
+    pub subject: Subject,
+
+    /// Number of failed attempts allowed per email or user before it is locked out
+    pub email_attempts: i64,
+
+    /// Number of failed attempts allowed per client IP before it is locked out.
+    /// Higher than per email, since many users can share an IP.
+    pub ip_attempts: i64,
+}
+
+/// What the failed attempts of a policy are counted against
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Subject {
+    /// The email the attempt is for, for routes used before the user is known
+    Email,
+
+    /// The id of the user the attempt is for
+    User,
+}
+
+impl Subject {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Subject::Email => "email",
+            Subject::User => "user",
+        }
+    }
+}
+
+impl Policy {
+    /// Pick the value the failed attempts of an account are counted against
+    pub fn subject_of<'a>(&self, email: &'a str, id: &'a str) -> &'a str {
+        match self.subject {
+            Subject::Email => email,
+            Subject::User => id,
+        }
+    }
+}
+
+/// Logging in with a password
+pub const LOGIN: Policy = Policy {
+    name: "login",
+    subject: Subject::Email,
+    email_attempts: 5,
+    ip_attempts: 20,
+};
+
+/// Guessing the 6 digit email verification code
+pub const VERIFY_EMAIL: Policy = Policy {
+    name: "verify_email",
+    subject: Subject::Email,
+    email_attempts: 5,
+    ip_attempts: 20,
+};
+
+/// Guessing the 6 digit password reset code
+pub const RESET_PASSWORD: Policy = Policy {
+    name: "reset_password",
+    subject: Subject::Email,
+    email_attempts: 5,
+    ip_attempts: 20,
+};
+
+/// Guessing a TOTP or recovery code, when logging in or disabling two-factor authentication.
+/// Counted per user, since every password login starts a new challenge.
+pub const TWO_FACTOR: Policy = Policy {
+    name: "two_factor",
+    subject: Subject::User,
+    email_attempts: 5,
+    ip_attempts: 20,
+};
+
+/// Every policy, so the lockout state of an account can be looked up at once
+pub const POLICIES: [Policy; 4] = [LOGIN, VERIFY_EMAIL, RESET_PASSWORD, TWO_FACTOR];
+
+/// A struct representing the brute-force protection state of an account for one policy.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LockoutState {
+    /// The name of the protected route.
+    pub policy: String,
+
+    /// The number of failed attempts in the current window.
+    pub failed_attempts: i64,
+
+    /// The number of seconds until the lockout ends. This field is optional.
+    pub locked_for: Option<usize>,
+}
+
+/// Check if an email (or user, see [`Policy::subject`]) or a client IP is locked out.
+/// Returns the number of seconds until the longest of the two lockouts ends.
+pub async fn check_lockout(redis: &RedisRepository, policy: &Policy, email: &str, ip: Option<&str>) -> Result<Option<usize>, RedisError> {
+    let mut locked_for = remaining(redis, &lockout_key(policy, policy.subject.as_str(), &email.to_lowercase())).await?;
+    if let Some(ip) = ip {
+        locked_for = locked_for.max(remaining(redis, &lockout_key(policy, "ip", ip)).await?);
+    }
+    Ok(locked_for)
+}
+
+/// Record a failed attempt for an email (or user) and a client IP, and lock them out
+/// once they are over the allowed number of attempts.
+///
+/// Attempts and lockouts are stored in the following key-value format:
+/// ```
+/// attempts:<policy>:<email|user|ip>:<value> -> count
+/// lockout:<policy>:<email|user|ip>:<value> -> locked
+/// ```
+///
+/// Returns the number of seconds the email (or user) is locked out for, if this attempt locked it out.
+pub async fn record_failure(redis: &RedisRepository, policy: &Policy, email: &str, ip: Option<&str>) -> Result<Option<usize>, RedisError> {
+    let locked_for = record(redis, policy, policy.subject.as_str(), &email.to_lowercase(), policy.email_attempts).await?;
+    if let Some(ip) = ip {
+        if let Some(seconds) = record(redis, policy, "ip", ip, policy.ip_attempts).await? {
+            log::warn!("Client {} locked out of {} for {} seconds", ip, policy.name, seconds);
+        }
+    }
+    Ok(locked_for)
+}
+
+/// Forget the failed attempts of an email (or user) after a successful attempt
+pub async fn clear_failures(redis: &RedisRepository, policy: &Policy, email: &str) -> Result<(), RedisError> {
+    let email = email.to_lowercase();
+    redis.del(&attempts_key(policy, policy.subject.as_str(), &email)).await?;
+    redis.del(&lockout_key(policy, policy.subject.as_str(), &email)).await
+}
+
+/// Get the lockout state of the account with the given email and user id for every policy
+pub async fn lockout_state(redis: &RedisRepository, email: &str, id: &str) -> Result<Vec<LockoutState>, RedisError> {
+    let mut states = vec![];
+    for policy in POLICIES.iter() {
+        let kind = policy.subject.as_str();
+        let value = policy.subject_of(email, id).to_lowercase();
+        let failed_attempts = redis.get(&attempts_key(policy, kind, &value)).await?.parse::<i64>().unwrap_or(0);
+        let locked_for = remaining(redis, &lockout_key(policy, kind, &value)).await?;
+        states.push(LockoutState {
+            policy: policy.name.to_string(),
+            failed_attempts,
+            locked_for,
+        });
+    }
+    Ok(states)
+}
+
+/// Number of seconds an email or IP is locked out for after `failures` failed attempts.
+/// The lockout doubles with every failure over the allowed number of attempts.
+pub fn lockout_seconds(failures: i64, allowed: i64) -> Option<usize> {
+    if failures <= allowed {
+        return None;
+    }
+    let doublings = (failures - allowed - 1).min(16) as u32;
+    Some(BASE_LOCKOUT_SECONDS.saturating_mul(2usize.pow(doublings)).min(MAX_LOCKOUT_SECONDS))
+}
+
+async fn record(redis: &RedisRepository, policy: &Policy, kind: &str, value: &str, allowed: i64) -> Result<Option<usize>, RedisError> {
+    let key = attempts_key(policy, kind, value);
+    let failures = redis.incr(&key).await?;
+    redis.expire(&key, ATTEMPT_WINDOW_SECONDS).await?;
+
+    match lockout_seconds(failures, allowed) {
+        Some(seconds) => {
+            redis.set_ex(&lockout_key(policy, kind, value), "locked", seconds).await?;
+            Ok(Some(seconds))
+        }
+        None => Ok(None),
+    }
+}
+
+async fn remaining(redis: &RedisRepository, key: &str) -> Result<Option<usize>, RedisError> {
+    let ttl = redis.ttl(key).await?;
+    Ok(if ttl > 0 { Some(ttl as usize) } else { None })
+}
+
+fn attempts_key(policy: &Policy, kind: &str, value: &str) -> String {
+    format!("attempts:{}:{}:{}", policy.name, kind, value)
+}
+
+fn lockout_key(policy: &Policy, kind: &str, value: &str) -> String {
+    format!("lockout:{}:{}:{}", policy.name, kind, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lockout_seconds() {
+        assert_eq!(lockout_seconds(5, 5), None);
+        assert_eq!(lockout_seconds(6, 5), Some(60));
+        assert_eq!(lockout_seconds(7, 5), Some(120));
+        assert_eq!(lockout_seconds(8, 5), Some(240));
+        assert_eq!(lockout_seconds(100, 5), Some(MAX_LOCKOUT_SECONDS));
+    }
+}
@@ -1,2 +1,12 @@
+pub mod api_tokens;
+pub mod client_ip;
+pub mod jwks;
 pub mod jwt;
+pub mod keys;
+pub mod lockout;
+pub mod oidc;
+pub mod sessions;
+pub mod tokens;
+pub mod totp;
 pub mod user_auth;
+pub mod webauthn;
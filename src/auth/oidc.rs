@@ -0,0 +1,237 @@
+use jsonwebtoken::{decode, decode_header, errors::ErrorKind, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, env, fmt, fs, path::Path};
+
+use crate::auth::jwks::JwksCache;
+
+/// Google's OpenID Connect key set. Can be overridden with `GOOGLE_JWKS_URL`.
+const DEFAULT_GOOGLE_JWKS_URL: &str = "https://www.googleapis.com/oauth2/v3/certs";
+
+/// The identity of a user as reported by an OpenID Connect provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternalIdentity {
+    /// The name of the provider the identity comes from (e.g. `google`)
+    pub provider: String,
+
+    /// The provider's unique identifier for the user
+    pub subject: String,
+
+    pub email: String,
+    pub email_verified: bool,
+    pub name: Option<String>,
+}
+
+/// The names of the ID token claims holding each identity field.
+/// Every field defaults to the standard OpenID Connect claim.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClaimMapping {
+    pub subject: String,
+    pub email: String,
+    pub email_verified: String,
+    pub name: String,
+}
+
+impl Default for ClaimMapping {
+    fn default() -> Self {
+        ClaimMapping {
+            subject: "sub".to_string(),
+            email: "email".to_string(),
+            email_verified: "email_verified".to_string(),
+            name: "name".to_string(),
+        }
+    }
+}
+
+/// An OpenID Connect identity provider users can sign in with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcProvider {
+    /// The name of the provider, used in the `/auth/{provider}` route
+    pub name: String,
+
+    /// The accepted `iss` claims
+    pub issuers: Vec<String>,
+
+    /// The URL of the provider's JWK set
+    pub jwks_url: String,
+
+    /// The accepted `aud` claims, usually our client id with the provider
+    pub audiences: Vec<String>,
+
+    /// The accepted signing algorithms. Defaults to RS256.
+    #[serde(default = "default_algorithms")]
+    pub algorithms: Vec<Algorithm>,
+
+    /// How identity fields are read from the ID token claims
+    #[serde(default)]
+    pub claims: ClaimMapping,
+}
+
+/// Errors that can happen while verifying an ID token
+#[derive(Debug)]
+pub enum OidcError {
+    /// The provider's key set could not be retrieved
+    Jwks(String),
+
+    /// The ID token is malformed, expired, or not signed by the provider
+    Token(jsonwebtoken::errors::Error),
+
+    /// The ID token does not have a claim the provider is configured to read
+    MissingClaim(String),
+}
+
+impl fmt::Display for OidcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OidcError::Jwks(e) => write!(f, "Failed to retrieve the provider keys: {}", e),
+            OidcError::Token(e) => write!(f, "{}", e),
+            OidcError::MissingClaim(claim) => write!(f, "ID token is missing the {} claim", claim),
+        }
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for OidcError {
+    fn from(e: jsonwebtoken::errors::Error) -> Self {
+        OidcError::Token(e)
+    }
+}
+
+impl From<ErrorKind> for OidcError {
+    fn from(kind: ErrorKind) -> Self {
+        OidcError::Token(kind.into())
+    }
+}
+
+impl OidcProvider {
+    /// The built-in Google provider
+    pub fn google(client_id: &str, jwks_url: &str) -> Self {
+        OidcProvider {
+            name: "google".to_string(),
+            issuers: vec!["accounts.google.com".to_string(), "https://accounts.google.com".to_string()],
+            jwks_url: jwks_url.to_string(),
+            audiences: vec![client_id.to_string()],
+            algorithms: default_algorithms(),
+            claims: ClaimMapping::default(),
+        }
+    }
+
+    /// Verify an ID token issued by the provider and read the identity it carries.
+    ///
+    /// # Usage
+    /// ```rust
+    /// let identity = provider.verify("token", &jwks).await?;
+    /// ```
+    pub async fn verify(&self, token: &str, jwks: &JwksCache) -> Result<ExternalIdentity, OidcError> {
+        let header = decode_header(token)?;
+        if !self.algorithms.contains(&header.alg) {
+            return Err(ErrorKind::InvalidAlgorithm.into());
+        }
+
+        // Find the JWK that corresponds to the `kid` in the token's header
+        let kid = header.kid.ok_or(ErrorKind::InvalidKeyFormat)?;
+        let jwk = jwks.find(&self.jwks_url, &kid).await.map_err(OidcError::Jwks)?;
+        let jwk = jwk.ok_or(ErrorKind::InvalidKeyFormat)?;
+        let decoding_key = DecodingKey::from_jwk(&jwk)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&self.audiences);
+        validation.set_issuer(&self.issuers);
+
+        let claims = decode::<Value>(token, &decoding_key, &validation)?.claims;
+        self.map_claims(&claims)
+    }
+
+    /// Read the identity fields from the ID token claims
+    fn map_claims(&self, claims: &Value) -> Result<ExternalIdentity, OidcError> {
+        let string_claim = |claim: &str| claims.get(claim).and_then(Value::as_str).map(str::to_string);
+
+        let subject = string_claim(&self.claims.subject).ok_or(OidcError::MissingClaim(self.claims.subject.to_owned()))?;
+        let email = string_claim(&self.claims.email).ok_or(OidcError::MissingClaim(self.claims.email.to_owned()))?;
+
+        // Some providers send `email_verified` as a string
+        let email_verified = match claims.get(&self.claims.email_verified) {
+            Some(Value::Bool(verified)) => *verified,
+            Some(Value::String(verified)) => verified == "true",
+            _ => false,
+        };
+
+        Ok(ExternalIdentity {
+            provider: self.name.to_owned(),
+            subject,
+            email,
+            email_verified,
+            name: string_claim(&self.claims.name),
+        })
+    }
+}
+
+/// The OpenID Connect providers users can sign in with, by name
+#[derive(Debug, Default)]
+pub struct OidcProviders {
+    providers: HashMap<String, OidcProvider>,
+}
+
+impl OidcProviders {
+    /// Load the providers from the environment.
+    ///
+    /// Google is configured with `GOOGLE_CLIENT_ID` (and optionally `GOOGLE_JWKS_URL`).
+    /// Other providers are read from the JSON list of providers at `OIDC_PROVIDERS_PATH`.
+    pub fn from_env() -> Result<Self, String> {
+        let mut providers = OidcProviders::default();
+
+        if let Ok(client_id) = env::var("GOOGLE_CLIENT_ID") {
+            let jwks_url = env::var("GOOGLE_JWKS_URL").unwrap_or_else(|_| DEFAULT_GOOGLE_JWKS_URL.to_string());
+            providers.add(OidcProvider::google(&client_id, &jwks_url));
+        }
+
+        if let Ok(path) = env::var("OIDC_PROVIDERS_PATH") {
+            let config = fs::read_to_string(Path::new(&path)).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            let configured: Vec<OidcProvider> = serde_json::from_str(&config).map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+            for provider in configured {
+                providers.add(provider);
+            }
+        }
+
+        Ok(providers)
+    }
+
+    /// Add a provider, replacing any provider with the same name
+    pub fn add(&mut self, provider: OidcProvider) {
+        self.providers.insert(provider.name.to_owned(), provider);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&OidcProvider> {
+        self.providers.get(name)
+    }
+}
+
+fn default_algorithms() -> Vec<Algorithm> {
+    vec![Algorithm::RS256]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claim_mapping() {
+        let mut provider = OidcProvider::google("client", DEFAULT_GOOGLE_JWKS_URL);
+        let claims = serde_json::json!({"sub": "123", "email": "john@email.com", "email_verified": true, "name": "John"});
+        let identity = provider.map_claims(&claims).unwrap();
+        assert_eq!(identity.provider, "google");
+        assert_eq!(identity.subject, "123");
+        assert!(identity.email_verified);
+        assert_eq!(identity.name.as_deref(), Some("John"));
+
+        // Custom claim names, and `email_verified` sent as a string
+        provider.claims.email = "preferred_username".to_string();
+        let claims = serde_json::json!({"sub": "456", "preferred_username": "jane@corp.com", "email_verified": "false"});
+        let identity = provider.map_claims(&claims).unwrap();
+        assert_eq!(identity.email, "jane@corp.com");
+        assert!(!identity.email_verified);
+        assert!(identity.name.is_none());
+
+        assert!(provider.map_claims(&serde_json::json!({"sub": "789"})).is_err());
+    }
+}
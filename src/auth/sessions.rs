@@ -0,0 +1,115 @@
+use actix_web::{http::header, HttpRequest};
+use redis::RedisError;
+
+use crate::auth::client_ip::client_ip;
+use crate::models::user::session::Session;
+use crate::repository::redis::RedisRepository;
+
+/// The client a session was created or refreshed from
+#[derive(Clone, Debug, Default)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl ClientInfo {
+    /// Read the user agent and client IP of a request
+    pub fn from_request(req: &HttpRequest) -> Self {
+        ClientInfo {
+            user_agent: req.headers().get(header::USER_AGENT).and_then(|ua| ua.to_str().ok()).map(str::to_owned),
+            ip: client_ip(req),
+        }
+    }
+}
+
+/// Record a new session for a user.
+///
+/// Sessions are stored in the following key-value format:
+/// ```
+/// session:<session_id> -> Session as JSON
+/// sessions:<user_id> -> set of session_id
+/// ```
+///
+/// The session expires with the refresh token family of the same id.
+pub async fn create_session(redis: &RedisRepository, user_id: &str, session_id: &str, client: &ClientInfo, seconds: usize) -> Result<(), RedisError> {
+    let now = chrono::Utc::now().timestamp();
+    let session = Session {
+        id: session_id.to_owned(),
+        user_agent: client.user_agent.to_owned(),
+        ip: client.ip.to_owned(),
+        date_created: now,
+        date_last_seen: now,
+    };
+    save_session(redis, &session, seconds).await?;
+    redis.sadd(&user_sessions_key(user_id), session_id).await
+}
+
+/// Update the last-seen time and client of a session, and extend it by `seconds`.
+/// Unknown sessions are ignored.
+pub async fn touch_session(redis: &RedisRepository, session_id: &str, client: &ClientInfo, seconds: usize) -> Result<(), RedisError> {
+    let mut session = match get_session(redis, session_id).await? {
+        Some(session) => session,
+        None => return Ok(()),
+    };
+    session.date_last_seen = chrono::Utc::now().timestamp();
+    if client.user_agent.is_some() {
+        session.user_agent = client.user_agent.to_owned();
+    }
+    if client.ip.is_some() {
+        session.ip = client.ip.to_owned();
+    }
+    save_session(redis, &session, seconds).await
+}
+
+/// Get a session by id
+pub async fn get_session(redis: &RedisRepository, session_id: &str) -> Result<Option<Session>, RedisError> {
+    let value = redis.get(&session_key(session_id)).await?;
+    if value.is_empty() {
+        return Ok(None);
+    }
+    match serde_json::from_str(&value) {
+        Ok(session) => Ok(Some(session)),
+        Err(e) => {
+            log::error!("Failed to parse session {}: {}", session_id, e);
+            Ok(None)
+        }
+    }
+}
+
+/// Check if a session still exists, i.e. it was neither revoked nor expired
+pub async fn session_exists(redis: &RedisRepository, session_id: &str) -> Result<bool, RedisError> {
+    redis.exists(&session_key(session_id)).await
+}
+
+/// Get every active session of a user, most recently seen first.
+/// Expired sessions are removed from the user's set along the way.
+pub async fn list_sessions(redis: &RedisRepository, user_id: &str) -> Result<Vec<Session>, RedisError> {
+    let mut sessions = vec![];
+    for session_id in redis.smembers(&user_sessions_key(user_id)).await? {
+        match get_session(redis, &session_id).await? {
+            Some(session) => sessions.push(session),
+            None => redis.srem(&user_sessions_key(user_id), &session_id).await?,
+        }
+    }
+    sessions.sort_by(|a, b| b.date_last_seen.cmp(&a.date_last_seen));
+    Ok(sessions)
+}
+
+/// Delete a session. Its access tokens are rejected from then on.
+pub async fn delete_session(redis: &RedisRepository, user_id: &str, session_id: &str) -> Result<(), RedisError> {
+    redis.del(&session_key(session_id)).await?;
+    redis.srem(&user_sessions_key(user_id), session_id).await
+}
+
+async fn save_session(redis: &RedisRepository, session: &Session, seconds: usize) -> Result<(), RedisError> {
+    let value = serde_json::to_string(session).expect("Failed to serialize session");
+    redis.set_ex(&session_key(&session.id), &value, seconds).await
+}
+
+fn session_key(session_id: &str) -> String {
+    format!("session:{}", session_id)
+}
+
+fn user_sessions_key(user_id: &str) -> String {
+    format!("sessions:{}", user_id)
+}
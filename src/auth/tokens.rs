@@ -0,0 +1,268 @@
+use bson::Uuid;
+use rand::{distributions::Alphanumeric, Rng};
+use redis::RedisError;
+use sha2::{Digest, Sha256};
+use std::{env, fmt};
+
+use crate::auth::jwt::{encode_jwt, Claims};
+use crate::auth::sessions::{self, ClientInfo};
+use crate::handlers::types::AuthResponse;
+use crate::models::user::role::Role;
+use crate::repository::{database::DatabaseRepository, redis::RedisRepository};
+
+/// Number of days a refresh token (and its family) stays valid without being used
+pub const REFRESH_TOKEN_TTL_DAYS: i64 = 30;
+
+/// Length of the opaque refresh token handed to the client
+const REFRESH_TOKEN_LENGTH: usize = 64;
+
+/// Errors that can happen while issuing or rotating tokens
+#[derive(Debug)]
+pub enum TokenError {
+    /// The refresh token is unknown, expired or belongs to a revoked family
+    Invalid,
+
+    /// A refresh token that was already rotated was presented again.
+    /// The whole token family has been revoked.
+    Reused,
+
+    /// Failed to sign the access token
+    Jwt(jsonwebtoken::errors::Error),
+
+    /// Failed to read or write the token store
+    Redis(RedisError),
+
+    /// Failed to read the user the token belongs to
+    Database(String),
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Invalid => write!(f, "Invalid refresh token"),
+            TokenError::Reused => write!(f, "Refresh token has already been used"),
+            TokenError::Jwt(e) => write!(f, "{}", e),
+            TokenError::Redis(e) => write!(f, "{}", e),
+            TokenError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for TokenError {
+    fn from(e: jsonwebtoken::errors::Error) -> Self {
+        TokenError::Jwt(e)
+    }
+}
+
+impl From<RedisError> for TokenError {
+    fn from(e: RedisError) -> Self {
+        TokenError::Redis(e)
+    }
+}
+
+/// Issue a new access token and a refresh token that starts a new token family.
+/// Every family is a session of the user, recorded with the client it was issued to.
+///
+/// Refresh tokens are stored hashed in Redis in the following key-value format:
+/// ```
+/// refresh_token:<sha256(token)> -> user_id:family_id:status
+/// refresh_family:<family_id> -> user_id:issued_at
+/// ```
+///
+/// The status is either `active` or `used`. A family is revoked by deleting its key.
+pub async fn issue_tokens(redis: &RedisRepository, id: &str, roles: &[Role], client: &ClientInfo) -> Result<AuthResponse, TokenError> {
+    let family = Uuid::new().to_string();
+    let family_value = format!("{}:{}", id, chrono::Utc::now().timestamp());
+    redis.set_ex(&family_key(&family), &family_value, refresh_ttl_seconds()).await?;
+    sessions::create_session(redis, id, &family, client, refresh_ttl_seconds()).await?;
+
+    new_token_pair(redis, id, &family, roles).await
+}
+
+/// Exchange a refresh token for a new access token and refresh token.
+///
+/// The presented token is marked as `used`. Presenting a `used` token again
+/// is treated as token theft and revokes every token in its family.
+///
+/// The new access token carries the user's current roles. Tokens of deleted
+/// or disabled accounts are rejected. The session of the family is marked as seen.
+pub async fn rotate_refresh_token(
+    redis: &RedisRepository,
+    db: &DatabaseRepository,
+    refresh_token: &str,
+    client: &ClientInfo,
+) -> Result<AuthResponse, TokenError> {
+    let key = token_key(refresh_token);
+    if !redis.exists(&key).await? {
+        return Err(TokenError::Invalid);
+    }
+
+    let value = redis.get(&key).await?;
+    let parts: Vec<&str> = value.split(':').collect();
+    if parts.len() != 3 {
+        return Err(TokenError::Invalid);
+    }
+    let (id, family) = (parts[0], parts[1]);
+
+    // Mark the token as used atomically so two concurrent refreshes cannot both succeed.
+    // GETSET discards the TTL, so it is set again right after.
+    let previous = redis.getset(&key, &format!("{}:{}:used", id, family)).await?;
+    redis.expire(&key, refresh_ttl_seconds()).await?;
+
+    if previous.as_deref() != Some(format!("{}:{}:active", id, family).as_str()) {
+        log::warn!("Refresh token reuse detected for user {}; revoking token family {}", id, family);
+        revoke_family(redis, family).await?;
+        return Err(TokenError::Reused);
+    }
+
+    // The family must still exist and must not predate a "log out everywhere"
+    let family_value = redis.get(&family_key(family)).await?;
+    let issued_at = match family_value.split(':').nth(1).map(|t| t.parse::<i64>()) {
+        Some(Ok(issued_at)) => issued_at,
+        _ => return Err(TokenError::Invalid),
+    };
+    if issued_before_epoch(redis, id, issued_at).await? {
+        revoke_family(redis, family).await?;
+        return Err(TokenError::Invalid);
+    }
+    redis.expire(&family_key(family), refresh_ttl_seconds()).await?;
+
+    let user = match db.get_user(id).await.map_err(|e| TokenError::Database(e.to_string()))? {
+        Some(user) if user.date_disabled.is_none() && user.date_deleted.is_none() => user,
+        _ => {
+            revoke_family(redis, family).await?;
+            return Err(TokenError::Invalid);
+        }
+    };
+
+    sessions::touch_session(redis, family, client, refresh_ttl_seconds()).await?;
+    new_token_pair(redis, id, family, &user.roles).await
+}
+
+/// Revoke every refresh token of a token family, and with it the session of the family
+pub async fn revoke_family(redis: &RedisRepository, family: &str) -> Result<(), TokenError> {
+    let family_value = redis.get(&family_key(family)).await?;
+    redis.del(&family_key(family)).await?;
+    if let Some(id) = family_value.split(':').next().filter(|id| !id.is_empty()) {
+        sessions::delete_session(redis, id, family).await?;
+    }
+    Ok(())
+}
+
+/// Revoke the token family a refresh token belongs to.
+/// Unknown refresh tokens are ignored.
+pub async fn revoke_refresh_token(redis: &RedisRepository, refresh_token: &str) -> Result<(), TokenError> {
+    let value = redis.get(&token_key(refresh_token)).await?;
+    match value.split(':').nth(1) {
+        Some(family) => revoke_family(redis, family).await,
+        None => Ok(()),
+    }
+}
+
+/// Add an access token to the denylist until it expires.
+///
+/// Denylisted tokens are stored in the following key-value format:
+/// ```
+/// denylist:<jti> -> revoked
+/// ```
+pub async fn revoke_access_token(redis: &RedisRepository, jti: &str, exp: usize) -> Result<(), TokenError> {
+    let now = chrono::Utc::now().timestamp() as usize;
+    if exp <= now {
+        return Ok(());
+    }
+    redis.set_ex(&denylist_key(jti), "revoked", exp - now).await?;
+    Ok(())
+}
+
+/// Revoke every access and refresh token issued to a user up to now.
+///
+/// The user's token epoch is stored in the following key-value format:
+/// ```
+/// token_epoch:<user_id> -> timestamp
+/// ```
+///
+/// Any token issued before the epoch is rejected. This is used to
+/// log out everywhere, and after a password change or account deletion.
+/// Every session of the user is revoked as well, which also rejects the
+/// tokens issued earlier in the same second as the epoch, so a login right
+/// after the revocation is not rejected.
+pub async fn revoke_all_tokens(redis: &RedisRepository, id: &str) -> Result<(), TokenError> {
+    let now = chrono::Utc::now().timestamp();
+    redis.set(&epoch_key(id), &now.to_string()).await?;
+    for session in sessions::list_sessions(redis, id).await? {
+        revoke_family(redis, &session.id).await?;
+    }
+    Ok(())
+}
+
+/// Delete every token record of a user whose account is deleted for good
+pub async fn purge_tokens(redis: &RedisRepository, id: &str) -> Result<(), TokenError> {
+    for session in sessions::list_sessions(redis, id).await? {
+        revoke_family(redis, &session.id).await?;
+    }
+    redis.del(&epoch_key(id)).await?;
+    Ok(())
+}
+
+/// Check if an access token was revoked, either by itself, through its session
+/// or through the user's token epoch
+pub async fn is_access_token_revoked(redis: &RedisRepository, claims: &Claims) -> Result<bool, TokenError> {
+    if redis.exists(&denylist_key(&claims.jti)).await? {
+        return Ok(true);
+    }
+    if !claims.sid.is_empty() && !sessions::session_exists(redis, &claims.sid).await? {
+        return Ok(true);
+    }
+    issued_before_epoch(redis, &claims.sub, claims.iat as i64).await
+}
+
+/// Check if something issued to a user at `issued_at` predates the user's token epoch
+async fn issued_before_epoch(redis: &RedisRepository, id: &str, issued_at: i64) -> Result<bool, TokenError> {
+    let epoch = redis.get(&epoch_key(id)).await?;
+    match epoch.parse::<i64>() {
+        Ok(epoch) => Ok(issued_at < epoch),
+        Err(_) => Ok(false),
+    }
+}
+
+/// Sign an access token and store a new active refresh token in the given family
+async fn new_token_pair(redis: &RedisRepository, id: &str, family: &str, roles: &[Role]) -> Result<AuthResponse, TokenError> {
+    let domain = env::var("DOMAIN").unwrap();
+    let app_name = env::var("APP_NAME").unwrap();
+    let token = encode_jwt(app_name, id.to_owned(), domain, roles.to_vec(), family.to_owned())?;
+
+    let refresh_token = generate_refresh_token();
+    let value = format!("{}:{}:active", id, family);
+    redis.set_ex(&token_key(&refresh_token), &value, refresh_ttl_seconds()).await?;
+
+    Ok(AuthResponse {
+        id: id.to_owned(),
+        token,
+        refresh_token,
+    })
+}
+
+/// Generate a random opaque refresh token
+fn generate_refresh_token() -> String {
+    rand::thread_rng().sample_iter(&Alphanumeric).take(REFRESH_TOKEN_LENGTH).map(char::from).collect()
+}
+
+fn token_key(refresh_token: &str) -> String {
+    format!("refresh_token:{}", hex::encode(Sha256::digest(refresh_token.as_bytes())))
+}
+
+fn family_key(family: &str) -> String {
+    format!("refresh_family:{}", family)
+}
+
+fn denylist_key(jti: &str) -> String {
+    format!("denylist:{}", jti)
+}
+
+fn epoch_key(id: &str) -> String {
+    format!("token_epoch:{}", id)
+}
+
+fn refresh_ttl_seconds() -> usize {
+    chrono::Duration::days(REFRESH_TOKEN_TTL_DAYS).num_seconds() as usize
+}
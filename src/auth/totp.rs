@@ -0,0 +1,167 @@
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng};
+use reqwest::Url;
+use sha1::Sha1;
+
+/// Number of digits of a TOTP code
+const DIGITS: u32 = 6;
+
+/// Number of seconds a TOTP code is valid for (RFC 6238 time step)
+pub const STEP_SECONDS: u64 = 30;
+
+/// Number of steps before and after the current one that are still accepted,
+/// to allow for clock drift between the server and the authenticator app
+const ALLOWED_SKEW: u64 = 1;
+
+/// Number of recovery codes generated when 2FA is enabled
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Generate a random 160 bit TOTP secret encoded in base32
+pub fn generate_secret() -> String {
+    let secret = rand::thread_rng().gen::<[u8; 20]>();
+    base32_encode(&secret)
+}
+
+/// Build the `otpauth://` URI that authenticator apps use to enroll a secret
+///
+/// # Usage
+/// ```rust
+/// let uri = otpauth_uri("JBSWY3DPEHPK3PXP", "john@email.com", "scrippt");
+/// // otpauth://totp/scrippt:john%40email.com?secret=JBSWY3DPEHPK3PXP&issuer=scrippt&...
+/// ```
+pub fn otpauth_uri(secret: &str, account: &str, issuer: &str) -> String {
+    let mut uri = Url::parse("otpauth://totp/").unwrap();
+    uri.set_path(&format!("{}:{}", issuer, account));
+    uri.query_pairs_mut()
+        .append_pair("secret", secret)
+        .append_pair("issuer", issuer)
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", &DIGITS.to_string())
+        .append_pair("period", &STEP_SECONDS.to_string());
+    uri.to_string()
+}
+
+/// Verify a TOTP code against a base32 secret at the given unix time.
+/// Returns the time step the code matched, so callers can reject replays of the same step.
+pub fn verify_code(secret: &str, code: &str, now: u64) -> Option<u64> {
+    let key = base32_decode(secret)?;
+    let code = code.trim();
+    let current = now / STEP_SECONDS;
+    (current.saturating_sub(ALLOWED_SKEW)..=current + ALLOWED_SKEW).find(|step| hotp(&key, *step) == code)
+}
+
+/// Generate the TOTP code for a base32 secret at the given unix time
+pub fn generate_code(secret: &str, now: u64) -> Option<String> {
+    let key = base32_decode(secret)?;
+    Some(hotp(&key, now / STEP_SECONDS))
+}
+
+/// Generate one-time recovery codes in the `xxxxx-xxxxx` format
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = rand::thread_rng().sample_iter(&Alphanumeric).take(10).map(char::from).collect();
+            let code = code.to_lowercase();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// HMAC-based one-time password (RFC 4226)
+fn hotp(key: &[u8], counter: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // Dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+    format!("{:0width$}", binary % 10u32.pow(DIGITS), width = DIGITS as usize)
+}
+
+/// Encode bytes in base32 (RFC 4648) without padding
+fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            out.push(BASE32_ALPHABET[((buffer >> (bits - 5)) & 0x1f) as usize] as char);
+            bits -= 5;
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+/// Decode base32 (RFC 4648), ignoring padding, whitespace and case
+fn base32_decode(data: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in data.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET.iter().position(|a| *a as char == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            out.push(((buffer >> (bits - 8)) & 0xff) as u8);
+            bits -= 8;
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238 appendix B test secret ("12345678901234567890") in base32
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn test_base32_roundtrip() {
+        assert_eq!(base32_encode(b"12345678901234567890"), RFC_SECRET);
+        assert_eq!(base32_decode(RFC_SECRET).unwrap(), b"12345678901234567890");
+        assert!(base32_decode("not base32!").is_none());
+    }
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        // The RFC lists 8 digit codes; the last 6 digits are the 6 digit codes
+        assert_eq!(generate_code(RFC_SECRET, 59).unwrap(), "287082");
+        assert_eq!(generate_code(RFC_SECRET, 1111111109).unwrap(), "081804");
+        assert_eq!(generate_code(RFC_SECRET, 1234567890).unwrap(), "005924");
+        assert_eq!(generate_code(RFC_SECRET, 2000000000).unwrap(), "279037");
+    }
+
+    #[test]
+    fn test_verify_code_with_skew() {
+        let secret = generate_secret();
+        let now = 1_700_000_000;
+        let code = generate_code(&secret, now).unwrap();
+        assert_eq!(verify_code(&secret, &code, now), Some(now / STEP_SECONDS));
+        assert!(verify_code(&secret, &code, now + STEP_SECONDS).is_some());
+        assert!(verify_code(&secret, &code, now + 3 * STEP_SECONDS).is_none());
+    }
+
+    #[test]
+    fn test_otpauth_uri() {
+        let uri = otpauth_uri("JBSWY3DPEHPK3PXP", "john@email.com", "scrippt");
+        assert!(uri.starts_with("otpauth://totp/scrippt:john"));
+        assert!(uri.contains("secret=JBSWY3DPEHPK3PXP"));
+        assert!(uri.contains("issuer=scrippt"));
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert!(codes.iter().all(|c| c.len() == 11 && c.chars().nth(5) == Some('-')));
+    }
+}
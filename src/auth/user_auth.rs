@@ -1,38 +1,198 @@
+use crate::auth::api_tokens::{authenticate_api_token, is_api_token};
 use crate::auth::jwt::decode_jwt;
-use actix_web::{dev, error::ErrorUnauthorized, Error, FromRequest, HttpRequest};
-use futures::future::{err, ok, Ready};
-use std::env;
+use crate::auth::tokens::is_access_token_revoked;
+use crate::models::user::{api_token::Scope, role::Role};
+use crate::repository::{database::DatabaseRepository, redis::RedisRepository};
+use actix_web::{
+    dev,
+    error::{ErrorForbidden, ErrorInternalServerError, ErrorUnauthorized},
+    web::Data,
+    Error, FromRequest, HttpRequest,
+};
+use futures::future::LocalBoxFuture;
 
 /// Authorization service extractor
 ///
 /// Requires:
-///     Authorization header with Bearer token
+///     Authorization header with Bearer token, either a JWT or a personal access token
+///     Token must not have been revoked (logout, log out everywhere, session or token revocation)
+///
+/// Personal access tokens never grant roles, and only allow the routes covered by their scopes.
 pub struct AuthorizationService {
+    /// The id of the authenticated user
     pub id: String,
+
+    /// The unique identifier of the token used to authenticate
+    pub jti: String,
+
+    /// The session of the token used to authenticate, empty for a personal access token
+    pub sid: String,
+
+    /// The expiration time of the token used to authenticate, 0 if it never expires
+    pub exp: usize,
+
+    /// The roles of the authenticated user when the token was issued
+    pub roles: Vec<Role>,
+
+    /// The scopes of the personal access token used to authenticate, `None` for a JWT
+    pub scopes: Option<Vec<Scope>>,
+}
+
+impl AuthorizationService {
+    /// Check if the token used to authenticate allows `scope`. JWTs allow everything.
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        match &self.scopes {
+            Some(scopes) => scopes.contains(&scope),
+            None => true,
+        }
+    }
 }
 
 impl FromRequest for AuthorizationService {
     type Error = Error;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
 
     fn from_request(req: &HttpRequest, _payload: &mut dev::Payload) -> Self::Future {
-        let auth = req.headers().get("Authorization");
-        if auth.is_none() {
-            return err(ErrorUnauthorized("No Authorization header"));
-        }
+        let req = req.clone();
+        Box::pin(async move {
+            let auth = req.headers().get("Authorization");
+            if auth.is_none() {
+                return Err(ErrorUnauthorized("No Authorization header"));
+            }
 
-        // Extract JWT from header
-        let split: Vec<&str> = auth.unwrap().to_str().unwrap().split_whitespace().collect();
-        if split[0] != "Bearer" {
-            return err(ErrorUnauthorized("Token is not a Bearer token"));
-        }
-        let token = split[1].trim();
-        let secret = env::var("JWT_SECRET").expect("JWT_SECRET not set");
+            // Extract JWT from header
+            let split: Vec<&str> = auth.unwrap().to_str().unwrap_or_default().split_whitespace().collect();
+            if split.len() != 2 || split[0] != "Bearer" {
+                return Err(ErrorUnauthorized("Token is not a Bearer token"));
+            }
+            let token = split[1].trim();
+
+            if is_api_token(token) {
+                return authorize_api_token(&req, token).await;
+            }
+
+            // Decode JWT
+            let claims = match decode_jwt(token.to_string()) {
+                Ok(claims) => claims,
+                Err(_) => return Err(ErrorUnauthorized("Invalid token")),
+            };
+
+            // Reject tokens that were revoked
+            let redis = match req.app_data::<Data<RedisRepository>>() {
+                Some(redis) => redis,
+                None => {
+                    log::error!("RedisRepository is not registered as app data");
+                    return Err(ErrorInternalServerError("Error checking token"));
+                }
+            };
+            match is_access_token_revoked(redis, &claims).await {
+                Ok(false) => Ok(AuthorizationService {
+                    id: claims.sub,
+                    jti: claims.jti,
+                    sid: claims.sid,
+                    exp: claims.exp,
+                    roles: claims.roles,
+                    scopes: None,
+                }),
+                Ok(true) => Err(ErrorUnauthorized("Token has been revoked")),
+                Err(e) => {
+                    log::error!("Error checking token revocation: {}", e);
+                    Err(ErrorInternalServerError("Error checking token"))
+                }
+            }
+        })
+    }
+}
+
+/// Session authorization service extractor
+///
+/// Requires:
+///     Everything `AuthorizationService` requires
+///     Token must be a JWT, not a personal access token
+///
+/// Used by routes that manage the account itself, so a leaked personal access token
+/// cannot be used to take over the account.
+pub struct SessionAuthorizationService(pub AuthorizationService);
+
+impl FromRequest for SessionAuthorizationService {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
 
-        // Decode JWT
-        match decode_jwt(token.to_string(), &secret) {
-            Ok(claims) => ok(AuthorizationService { id: claims.sub }),
-            Err(_) => err(ErrorUnauthorized("Invalid token")),
+    fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        let auth = AuthorizationService::from_request(req, payload);
+        Box::pin(async move {
+            let auth = auth.await?;
+            match auth.scopes {
+                None => Ok(SessionAuthorizationService(auth)),
+                Some(_) => Err(ErrorForbidden("Personal access tokens cannot be used for this route")),
+            }
+        })
+    }
+}
+
+/// Staff authorization service extractor
+///
+/// Requires:
+///     Everything `AuthorizationService` requires
+///     Token must grant the `staff` or `admin` role
+pub struct StaffAuthorizationService(pub AuthorizationService);
+
+impl FromRequest for StaffAuthorizationService {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        let auth = AuthorizationService::from_request(req, payload);
+        Box::pin(async move { require_role(auth.await?, Role::Staff).map(StaffAuthorizationService) })
+    }
+}
+
+/// Admin authorization service extractor
+///
+/// Requires:
+///     Everything `AuthorizationService` requires
+///     Token must grant the `admin` role
+pub struct AdminAuthorizationService(pub AuthorizationService);
+
+impl FromRequest for AdminAuthorizationService {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        let auth = AuthorizationService::from_request(req, payload);
+        Box::pin(async move { require_role(auth.await?, Role::Admin).map(AdminAuthorizationService) })
+    }
+}
+
+fn require_role(auth: AuthorizationService, role: Role) -> Result<AuthorizationService, Error> {
+    if role.granted_by(&auth.roles) {
+        Ok(auth)
+    } else {
+        Err(ErrorForbidden("Insufficient role"))
+    }
+}
+
+async fn authorize_api_token(req: &HttpRequest, token: &str) -> Result<AuthorizationService, Error> {
+    let db = match req.app_data::<Data<DatabaseRepository>>() {
+        Some(db) => db,
+        None => {
+            log::error!("DatabaseRepository is not registered as app data");
+            return Err(ErrorInternalServerError("Error checking token"));
+        }
+    };
+    match authenticate_api_token(db, token).await {
+        Ok(Some((user, api_token))) => Ok(AuthorizationService {
+            id: user.id.map(|id| id.to_hex()).unwrap_or_default(),
+            jti: api_token.id,
+            sid: String::new(),
+            exp: api_token.date_expires.unwrap_or(0) as usize,
+            roles: vec![],
+            scopes: Some(api_token.scopes),
+        }),
+        Ok(None) => Err(ErrorUnauthorized("Invalid token")),
+        Err(e) => {
+            log::error!("Error checking API token: {}", e);
+            Err(ErrorInternalServerError("Error checking token"))
         }
     }
 }
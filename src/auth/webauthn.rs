@@ -0,0 +1,549 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::Rng;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{env, fmt};
+
+use crate::models::user::{passkey::Passkey, User};
+use crate::utils::cbor::{self, Value};
+
+/// Number of seconds a registration or login challenge is valid for
+pub const CHALLENGE_TTL_SECONDS: usize = 5 * 60;
+
+/// COSE algorithm identifier of ECDSA with P-256 and SHA-256
+pub const ES256: i64 = -7;
+
+/// COSE algorithm identifier of RSASSA-PKCS1-v1_5 with SHA-256
+pub const RS256: i64 = -257;
+
+/// Authenticator data flag set when the user touched the authenticator
+const FLAG_USER_PRESENT: u8 = 0x01;
+
+/// Authenticator data flag set when the authenticator verified the user (PIN, biometrics)
+const FLAG_USER_VERIFIED: u8 = 0x04;
+
+/// Authenticator data flag set when a new credential is included
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+/// Errors that can happen while verifying a WebAuthn ceremony
+#[derive(Debug)]
+pub enum WebauthnError {
+    /// The response could not be decoded
+    Malformed(String),
+
+    /// The response was made for another ceremony or challenge
+    Challenge,
+
+    /// The response was made for another website
+    Origin,
+
+    /// The response was made for another relying party
+    RelyingParty,
+
+    /// The user did not touch the authenticator
+    UserPresence,
+
+    /// The public key uses an algorithm other than ES256 and RS256
+    UnsupportedAlgorithm(i64),
+
+    /// The signature does not match the stored public key
+    Signature,
+
+    /// The signature counter went backwards, so the authenticator may have been cloned
+    Counter,
+}
+
+impl fmt::Display for WebauthnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebauthnError::Malformed(e) => write!(f, "Malformed credential: {}", e),
+            WebauthnError::Challenge => write!(f, "Invalid challenge"),
+            WebauthnError::Origin => write!(f, "Invalid origin"),
+            WebauthnError::RelyingParty => write!(f, "Invalid relying party"),
+            WebauthnError::UserPresence => write!(f, "User presence is required"),
+            WebauthnError::UnsupportedAlgorithm(alg) => write!(f, "Unsupported algorithm {}", alg),
+            WebauthnError::Signature => write!(f, "Invalid signature"),
+            WebauthnError::Counter => write!(f, "Signature counter did not increase"),
+        }
+    }
+}
+
+impl From<cbor::CborError> for WebauthnError {
+    fn from(e: cbor::CborError) -> Self {
+        WebauthnError::Malformed(e.to_string())
+    }
+}
+
+/// The website passkeys are registered for
+#[derive(Clone, Debug)]
+pub struct RelyingParty {
+    /// The domain passkeys are scoped to, without scheme or port
+    pub id: String,
+
+    /// The name shown by the authenticator
+    pub name: String,
+
+    /// The origin the browser reports for the frontend
+    pub origin: String,
+}
+
+impl RelyingParty {
+    /// Load the relying party from the environment.
+    ///
+    /// Reads `WEBAUTHN_RP_ID` (defaults to `DOMAIN` without its port), `WEBAUTHN_ORIGIN`
+    /// (defaults to `https://<DOMAIN>`) and `APP_NAME`.
+    pub fn from_env() -> Self {
+        let domain = env::var("DOMAIN").unwrap();
+        RelyingParty {
+            id: env::var("WEBAUTHN_RP_ID").unwrap_or_else(|_| domain.split(':').next().unwrap_or_default().to_string()),
+            name: env::var("APP_NAME").unwrap(),
+            origin: env::var("WEBAUTHN_ORIGIN").unwrap_or_else(|_| format!("https://{}", domain)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RelyingPartyEntity {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEntity {
+    /// The user handle, base64url encoded. Returned by discoverable credentials on login.
+    pub id: String,
+    pub name: String,
+    pub display_name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CredentialParameters {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub alg: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+    pub resident_key: String,
+    pub require_resident_key: bool,
+    pub user_verification: String,
+}
+
+/// The `publicKey` options of `navigator.credentials.create()`, with binary fields base64url encoded
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreationOptions {
+    pub challenge: String,
+    pub rp: RelyingPartyEntity,
+    pub user: UserEntity,
+    pub pub_key_cred_params: Vec<CredentialParameters>,
+    pub timeout: usize,
+    pub attestation: String,
+    pub exclude_credentials: Vec<CredentialDescriptor>,
+    pub authenticator_selection: AuthenticatorSelection,
+}
+
+/// The `publicKey` options of `navigator.credentials.get()`, with binary fields base64url encoded
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    pub timeout: usize,
+    pub user_verification: String,
+    pub allow_credentials: Vec<CredentialDescriptor>,
+}
+
+/// The client data the browser signs along with the authenticator data
+#[derive(Debug, Deserialize)]
+pub struct ClientData {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub challenge: String,
+    pub origin: String,
+}
+
+impl ClientData {
+    fn check(&self, rp: &RelyingParty, kind: &str, challenge: &str) -> Result<(), WebauthnError> {
+        if self.kind != kind || self.challenge != challenge {
+            return Err(WebauthnError::Challenge);
+        }
+        if self.origin != rp.origin {
+            return Err(WebauthnError::Origin);
+        }
+        Ok(())
+    }
+}
+
+/// A credential that passed the registration ceremony
+#[derive(Debug)]
+pub struct NewCredential {
+    /// The credential id, base64url encoded
+    pub id: String,
+
+    /// The COSE-encoded public key, base64url encoded
+    pub public_key: String,
+
+    pub algorithm: i64,
+    pub sign_count: u32,
+}
+
+/// The result of a successful login ceremony
+#[derive(Debug)]
+pub struct VerifiedAssertion {
+    pub sign_count: u32,
+
+    /// Whether the authenticator verified the user with a PIN or biometrics
+    pub user_verified: bool,
+}
+
+/// Generate a random 256 bit challenge, base64url encoded
+pub fn generate_challenge() -> String {
+    URL_SAFE_NO_PAD.encode(rand::thread_rng().gen::<[u8; 32]>())
+}
+
+/// Decode a base64url value sent by the browser, with or without padding
+pub fn decode_base64url(value: &str) -> Result<Vec<u8>, WebauthnError> {
+    URL_SAFE_NO_PAD.decode(value.trim_end_matches('=')).map_err(|e| WebauthnError::Malformed(e.to_string()))
+}
+
+/// Build the options to register a new passkey for a user.
+/// The user's existing passkeys are excluded so an authenticator is not registered twice.
+pub fn creation_options(rp: &RelyingParty, user_id: &str, user: &User, challenge: &str) -> CreationOptions {
+    CreationOptions {
+        challenge: challenge.to_owned(),
+        rp: RelyingPartyEntity {
+            id: rp.id.to_owned(),
+            name: rp.name.to_owned(),
+        },
+        user: UserEntity {
+            id: URL_SAFE_NO_PAD.encode(user_id.as_bytes()),
+            name: user.email.to_owned(),
+            display_name: user.name.to_owned(),
+        },
+        pub_key_cred_params: [ES256, RS256]
+            .iter()
+            .map(|alg| CredentialParameters {
+                kind: "public-key".to_string(),
+                alg: *alg,
+            })
+            .collect(),
+        timeout: CHALLENGE_TTL_SECONDS * 1000,
+        attestation: "none".to_string(),
+        exclude_credentials: user
+            .passkeys
+            .iter()
+            .map(|p| CredentialDescriptor {
+                kind: "public-key".to_string(),
+                id: p.id.to_owned(),
+            })
+            .collect(),
+        authenticator_selection: AuthenticatorSelection {
+            resident_key: "required".to_string(),
+            require_resident_key: true,
+            user_verification: "preferred".to_string(),
+        },
+    }
+}
+
+/// Build the options to log in with a passkey.
+/// No credentials are listed, so the authenticator offers every passkey it holds for the site.
+pub fn request_options(rp: &RelyingParty, challenge: &str) -> RequestOptions {
+    RequestOptions {
+        challenge: challenge.to_owned(),
+        rp_id: rp.id.to_owned(),
+        timeout: CHALLENGE_TTL_SECONDS * 1000,
+        user_verification: "preferred".to_string(),
+        allow_credentials: vec![],
+    }
+}
+
+/// Parse the client data JSON, to find the challenge it answers
+pub fn parse_client_data(client_data_json: &[u8]) -> Result<ClientData, WebauthnError> {
+    serde_json::from_slice(client_data_json).map_err(|e| WebauthnError::Malformed(e.to_string()))
+}
+
+/// Verify the response to a registration challenge and extract the new credential.
+/// Attestation statements are not verified, since the options ask for no attestation.
+pub fn verify_registration(
+    rp: &RelyingParty,
+    challenge: &str,
+    client_data_json: &[u8],
+    attestation_object: &[u8],
+) -> Result<NewCredential, WebauthnError> {
+    parse_client_data(client_data_json)?.check(rp, "webauthn.create", challenge)?;
+
+    let (attestation, _) = cbor::decode(attestation_object)?;
+    let auth_data = attestation
+        .get_text("authData")
+        .and_then(Value::as_bytes)
+        .ok_or_else(|| WebauthnError::Malformed("Missing authenticator data".to_string()))?;
+    let auth_data = AuthenticatorData::parse(auth_data)?;
+    auth_data.check(rp)?;
+
+    let (id, public_key) = auth_data.credential.ok_or_else(|| WebauthnError::Malformed("Missing attested credential data".to_string()))?;
+    let algorithm = PublicKey::from_cose(&public_key)?.algorithm();
+
+    Ok(NewCredential {
+        id: URL_SAFE_NO_PAD.encode(id),
+        public_key: URL_SAFE_NO_PAD.encode(public_key),
+        algorithm,
+        sign_count: auth_data.sign_count,
+    })
+}
+
+/// Verify the response to a login challenge against a stored passkey
+pub fn verify_assertion(
+    rp: &RelyingParty,
+    challenge: &str,
+    passkey: &Passkey,
+    client_data_json: &[u8],
+    authenticator_data: &[u8],
+    signature: &[u8],
+) -> Result<VerifiedAssertion, WebauthnError> {
+    parse_client_data(client_data_json)?.check(rp, "webauthn.get", challenge)?;
+
+    let auth_data = AuthenticatorData::parse(authenticator_data)?;
+    auth_data.check(rp)?;
+
+    let mut signed = authenticator_data.to_vec();
+    signed.extend_from_slice(&Sha256::digest(client_data_json));
+    PublicKey::from_cose(&decode_base64url(&passkey.public_key)?)?.verify(&signed, signature)?;
+
+    // Authenticators without a counter always report 0
+    if (auth_data.sign_count != 0 || passkey.sign_count != 0) && auth_data.sign_count <= passkey.sign_count {
+        return Err(WebauthnError::Counter);
+    }
+
+    Ok(VerifiedAssertion {
+        sign_count: auth_data.sign_count,
+        user_verified: auth_data.flags & FLAG_USER_VERIFIED != 0,
+    })
+}
+
+/// The authenticator data of a registration or login response
+struct AuthenticatorData {
+    rp_id_hash: Vec<u8>,
+    flags: u8,
+    sign_count: u32,
+
+    /// The id and COSE public key of a new credential
+    credential: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl AuthenticatorData {
+    /// Parse the binary layout: rpIdHash (32) | flags (1) | signCount (4) | [aaguid (16) | idLength (2) | id | COSE key]
+    fn parse(data: &[u8]) -> Result<Self, WebauthnError> {
+        let malformed = || WebauthnError::Malformed("Authenticator data is too short".to_string());
+        if data.len() < 37 {
+            return Err(malformed());
+        }
+        let flags = data[32];
+        let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+        let mut credential = None;
+        if flags & FLAG_ATTESTED_CREDENTIAL_DATA != 0 {
+            let rest = data.get(55..).ok_or_else(malformed)?;
+            let id_length = u16::from_be_bytes([data[53], data[54]]) as usize;
+            let id = rest.get(..id_length).ok_or_else(malformed)?;
+            let (_, key_length) = cbor::decode(&rest[id_length..])?;
+            credential = Some((id.to_vec(), rest[id_length..id_length + key_length].to_vec()));
+        }
+
+        Ok(AuthenticatorData {
+            rp_id_hash: data[..32].to_vec(),
+            flags,
+            sign_count,
+            credential,
+        })
+    }
+
+    fn check(&self, rp: &RelyingParty) -> Result<(), WebauthnError> {
+        if self.rp_id_hash != Sha256::digest(rp.id.as_bytes()).as_slice() {
+            return Err(WebauthnError::RelyingParty);
+        }
+        if self.flags & FLAG_USER_PRESENT == 0 {
+            return Err(WebauthnError::UserPresence);
+        }
+        Ok(())
+    }
+}
+
+/// A credential public key in one of the supported algorithms
+enum PublicKey {
+    /// An uncompressed P-256 point
+    Es256(Vec<u8>),
+
+    /// An RSA modulus and exponent
+    Rs256 { n: Vec<u8>, e: Vec<u8> },
+}
+
+impl PublicKey {
+    /// Read a COSE_Key (RFC 9052). Labels: 1 = kty, 3 = alg, -1 = crv or n, -2 = x or e, -3 = y.
+    fn from_cose(bytes: &[u8]) -> Result<Self, WebauthnError> {
+        let (key, _) = cbor::decode(bytes)?;
+        let malformed = || WebauthnError::Malformed("Invalid public key".to_string());
+        let int = |label| key.get(label).and_then(Value::as_integer);
+        let field = |label| key.get(label).and_then(Value::as_bytes).ok_or_else(malformed);
+
+        match int(3).ok_or_else(malformed)? as i64 {
+            ES256 => {
+                if int(1) != Some(2) || int(-1) != Some(1) {
+                    return Err(malformed());
+                }
+                let (x, y) = (field(-2)?, field(-3)?);
+                if x.len() != 32 || y.len() != 32 {
+                    return Err(malformed());
+                }
+                Ok(PublicKey::Es256([&[0x04][..], x, y].concat()))
+            }
+            RS256 => {
+                if int(1) != Some(3) {
+                    return Err(malformed());
+                }
+                Ok(PublicKey::Rs256 {
+                    n: field(-1)?.to_vec(),
+                    e: field(-2)?.to_vec(),
+                })
+            }
+            alg => Err(WebauthnError::UnsupportedAlgorithm(alg)),
+        }
+    }
+
+    fn algorithm(&self) -> i64 {
+        match self {
+            PublicKey::Es256(_) => ES256,
+            PublicKey::Rs256 { .. } => RS256,
+        }
+    }
+
+    fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), WebauthnError> {
+        let result = match self {
+            PublicKey::Es256(point) => UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_ASN1, point).verify(message, signature),
+            PublicKey::Rs256 { n, e } => RsaPublicKeyComponents { n, e }.verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, signature),
+        };
+        result.map_err(|_| WebauthnError::Signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+
+    fn rp() -> RelyingParty {
+        RelyingParty {
+            id: "scrippt.tech".to_string(),
+            name: "Scrippt".to_string(),
+            origin: "https://scrippt.tech".to_string(),
+        }
+    }
+
+    fn cose_key(key_pair: &EcdsaKeyPair) -> Vec<u8> {
+        let point = key_pair.public_key().as_ref();
+        cbor::encode(&Value::Map(vec![
+            (Value::Integer(1), Value::Integer(2)),
+            (Value::Integer(3), Value::Integer(ES256 as i128)),
+            (Value::Integer(-1), Value::Integer(1)),
+            (Value::Integer(-2), Value::Bytes(point[1..33].to_vec())),
+            (Value::Integer(-3), Value::Bytes(point[33..].to_vec())),
+        ]))
+    }
+
+    fn auth_data(rp_id: &str, flags: u8, sign_count: u32, credential: Option<(&[u8], &[u8])>) -> Vec<u8> {
+        let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&sign_count.to_be_bytes());
+        if let Some((id, key)) = credential {
+            data.extend_from_slice(&[0; 16]);
+            data.extend_from_slice(&(id.len() as u16).to_be_bytes());
+            data.extend_from_slice(id);
+            data.extend_from_slice(key);
+        }
+        data
+    }
+
+    fn client_data(kind: &str, challenge: &str, origin: &str) -> Vec<u8> {
+        serde_json::json!({ "type": kind, "challenge": challenge, "origin": origin }).to_string().into_bytes()
+    }
+
+    #[test]
+    fn test_registration_and_assertion() {
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref()).unwrap();
+        let key = cose_key(&key_pair);
+
+        // registration
+        let challenge = generate_challenge();
+        let attestation = cbor::encode(&Value::Map(vec![
+            (Value::Text("fmt".to_string()), Value::Text("none".to_string())),
+            (Value::Text("attStmt".to_string()), Value::Map(vec![])),
+            (
+                Value::Text("authData".to_string()),
+                Value::Bytes(auth_data("scrippt.tech", 0x45, 0, Some((b"credential", &key)))),
+            ),
+        ]));
+        let credential = verify_registration(
+            &rp(),
+            &challenge,
+            &client_data("webauthn.create", &challenge, "https://scrippt.tech"),
+            &attestation,
+        )
+        .unwrap();
+        assert_eq!(credential.id, URL_SAFE_NO_PAD.encode(b"credential"));
+        assert_eq!(credential.algorithm, ES256);
+
+        // wrong origin
+        let phished = client_data("webauthn.create", &challenge, "https://scrippt.evil");
+        assert!(matches!(
+            verify_registration(&rp(), &challenge, &phished, &attestation),
+            Err(WebauthnError::Origin)
+        ));
+
+        // assertion
+        let passkey = Passkey {
+            id: credential.id,
+            name: "laptop".to_string(),
+            public_key: credential.public_key,
+            algorithm: credential.algorithm,
+            sign_count: 0,
+            date_created: 0,
+            date_last_used: None,
+        };
+        let challenge = generate_challenge();
+        let client_data_json = client_data("webauthn.get", &challenge, "https://scrippt.tech");
+        let data = auth_data("scrippt.tech", 0x05, 1, None);
+        let signed = [data.as_slice(), &Sha256::digest(&client_data_json)].concat();
+        let signature = key_pair.sign(&rng, &signed).unwrap();
+
+        let verified = verify_assertion(&rp(), &challenge, &passkey, &client_data_json, &data, signature.as_ref()).unwrap();
+        assert_eq!(verified.sign_count, 1);
+        assert!(verified.user_verified);
+
+        // a replayed counter is rejected
+        let passkey = Passkey { sign_count: 1, ..passkey };
+        assert!(matches!(
+            verify_assertion(&rp(), &challenge, &passkey, &client_data_json, &data, signature.as_ref()),
+            Err(WebauthnError::Counter)
+        ));
+
+        // a signature over other data is rejected
+        let other = auth_data("scrippt.tech", 0x05, 2, None);
+        assert!(matches!(
+            verify_assertion(&rp(), &challenge, &passkey, &client_data_json, &other, signature.as_ref()),
+            Err(WebauthnError::Signature)
+        ));
+    }
+}
@@ -0,0 +1,123 @@
+use rand::{distributions::Alphanumeric, Rng};
+use serde_json::json;
+
+use crate::billing::sign;
+
+/// A stand-in for the payment provider, which emits signed fixture events in its format.
+/// Used to exercise the webhook without calling the provider.
+pub struct FakeProvider {
+    secret: String,
+}
+
+/// A signed event, as delivered to the webhook
+pub struct SignedEvent {
+    /// The id of the event
+    pub id: String,
+
+    /// The raw body of the request
+    pub payload: String,
+
+    /// The value of the signature header
+    pub signature: String,
+}
+
+impl FakeProvider {
+    pub fn new(secret: &str) -> Self {
+        FakeProvider { secret: secret.to_string() }
+    }
+
+    /// A checkout completed by the user with id `user_id`, which starts subscription `subscription_id` for customer `customer_id`
+    pub fn checkout_completed(&self, user_id: &str, customer_id: &str, subscription_id: &str) -> SignedEvent {
+        self.event("checkout.session.completed", checkout(user_id, customer_id, subscription_id, "paid"))
+    }
+
+    /// A checkout completed with a payment that is still pending, such as a bank debit
+    pub fn checkout_completed_unpaid(&self, user_id: &str, customer_id: &str, subscription_id: &str) -> SignedEvent {
+        self.event("checkout.session.completed", checkout(user_id, customer_id, subscription_id, "unpaid"))
+    }
+
+    /// The pending payment of a checkout went through
+    pub fn checkout_async_payment_succeeded(&self, user_id: &str, customer_id: &str, subscription_id: &str) -> SignedEvent {
+        self.event(
+            "checkout.session.async_payment_succeeded",
+            checkout(user_id, customer_id, subscription_id, "paid"),
+        )
+    }
+
+    /// A change of the status of a subscription (e.g. `active`, `past_due`, `unpaid`)
+    pub fn subscription_updated(&self, customer_id: &str, subscription_id: &str, status: &str) -> SignedEvent {
+        self.event("customer.subscription.updated", subscription(customer_id, subscription_id, status))
+    }
+
+    /// A subscription that was canceled
+    pub fn subscription_deleted(&self, customer_id: &str, subscription_id: &str) -> SignedEvent {
+        self.event("customer.subscription.deleted", subscription(customer_id, subscription_id, "canceled"))
+    }
+
+    /// A failed payment of an invoice of a subscription
+    pub fn invoice_payment_failed(&self, customer_id: &str, subscription_id: &str) -> SignedEvent {
+        self.event(
+            "invoice.payment_failed",
+            json!({
+                "id": new_id("in"),
+                "object": "invoice",
+                "customer": customer_id,
+                "subscription": subscription_id,
+                "status": "open",
+                "attempt_count": 1
+            }),
+        )
+    }
+
+    /// Wrap an object in an event and sign it with the current time
+    pub fn event(&self, type_: &str, object: serde_json::Value) -> SignedEvent {
+        let id = new_id("evt");
+        let now = chrono::Utc::now().timestamp();
+        let payload = json!({
+            "id": id,
+            "object": "event",
+            "type": type_,
+            "created": now,
+            "livemode": false,
+            "data": { "object": object }
+        })
+        .to_string();
+        let signature = self.sign(&payload, now);
+        SignedEvent { id, payload, signature }
+    }
+
+    /// Get the signature header of a payload signed at `timestamp`
+    pub fn sign(&self, payload: &str, timestamp: i64) -> String {
+        format!("t={},v1={}", timestamp, sign(&self.secret, timestamp, payload.as_bytes()))
+    }
+}
+
+fn checkout(user_id: &str, customer_id: &str, subscription_id: &str, payment_status: &str) -> serde_json::Value {
+    json!({
+        "id": new_id("cs"),
+        "object": "checkout.session",
+        "client_reference_id": user_id,
+        "customer": customer_id,
+        "subscription": subscription_id,
+        "mode": "subscription",
+        "payment_status": payment_status,
+        "status": "complete"
+    })
+}
+
+fn subscription(customer_id: &str, subscription_id: &str, status: &str) -> serde_json::Value {
+    let now = chrono::Utc::now().timestamp();
+    json!({
+        "id": subscription_id,
+        "object": "subscription",
+        "customer": customer_id,
+        "status": status,
+        "current_period_start": now,
+        "current_period_end": now + 30 * 24 * 60 * 60
+    })
+}
+
+fn new_id(prefix: &str) -> String {
+    let suffix: String = rand::thread_rng().sample_iter(&Alphanumeric).take(24).map(char::from).collect();
+    format!("{}_{}", prefix, suffix)
+}
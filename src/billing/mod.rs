@@ -0,0 +1,246 @@
+pub mod fake;
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fmt;
+
+/// Name of the header carrying the signature of a webhook event
+pub const SIGNATURE_HEADER: &str = "Stripe-Signature";
+
+/// Number of seconds a signed event is accepted for, to limit replays
+pub const SIGNATURE_TOLERANCE_SECONDS: i64 = 5 * 60;
+
+/// A webhook event from the payment provider, in Stripe's event format.
+///
+/// ```
+/// {
+///     "id": "evt_...",
+///     "type": "customer.subscription.updated",
+///     "created": i64,
+///     "data": { "object": { ... } }
+/// }
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    /// The unique identifier of the event. Deliveries of the same event share it.
+    pub id: String,
+
+    /// The type of event (e.g. `checkout.session.completed`).
+    #[serde(rename = "type")]
+    pub type_: String,
+
+    /// The timestamp indicating when the event was created.
+    pub created: i64,
+
+    /// The object the event is about.
+    pub data: EventData,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EventData {
+    /// The object the event is about, whose shape depends on the type of event.
+    pub object: serde_json::Value,
+}
+
+/// A completed checkout, which starts a subscription
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CheckoutSession {
+    pub id: String,
+
+    /// The id of the user who checked out, set when the checkout is started.
+    pub client_reference_id: Option<String>,
+
+    /// The id of the customer at the payment provider.
+    pub customer: Option<String>,
+
+    /// The id of the subscription created by the checkout.
+    pub subscription: Option<String>,
+
+    /// The status of the payment (`paid`, `unpaid` or `no_payment_required`). Payments by bank debit
+    /// are `unpaid` when the checkout completes, and followed by an `async_payment_succeeded` or
+    /// `async_payment_failed` event. This field is optional.
+    pub payment_status: Option<String>,
+}
+
+impl CheckoutSession {
+    /// Check if the checkout was paid for, or needs no payment (e.g. a trial)
+    pub fn is_paid(&self) -> bool {
+        matches!(self.payment_status.as_deref(), Some("paid" | "no_payment_required"))
+    }
+}
+
+/// A subscription at the payment provider
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Subscription {
+    pub id: String,
+
+    /// The id of the customer at the payment provider.
+    pub customer: String,
+
+    /// The status of the subscription (e.g. `active`, `past_due`, `canceled`).
+    pub status: String,
+
+    /// The timestamp at which the current billing period ends. This field is optional.
+    pub current_period_end: Option<i64>,
+}
+
+/// An invoice of a subscription
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Invoice {
+    pub id: String,
+
+    /// The id of the customer at the payment provider.
+    pub customer: String,
+
+    /// The id of the subscription billed. This field is optional.
+    pub subscription: Option<String>,
+}
+
+/// The events that change the plan of a user
+#[derive(Debug)]
+pub enum BillingEvent {
+    CheckoutCompleted(CheckoutSession),
+    SubscriptionUpdated(Subscription),
+    SubscriptionDeleted(Subscription),
+    InvoicePaymentFailed(Invoice),
+}
+
+impl WebhookEvent {
+    /// Read the object of the event. Returns `Ok(None)` for types of events that are not handled.
+    pub fn parse(&self) -> Result<Option<BillingEvent>, serde_json::Error> {
+        let object = self.data.object.clone();
+        Ok(Some(match self.type_.as_str() {
+            "checkout.session.completed" | "checkout.session.async_payment_succeeded" | "checkout.session.async_payment_failed" => {
+                BillingEvent::CheckoutCompleted(serde_json::from_value(object)?)
+            }
+            "customer.subscription.updated" => BillingEvent::SubscriptionUpdated(serde_json::from_value(object)?),
+            "customer.subscription.deleted" => BillingEvent::SubscriptionDeleted(serde_json::from_value(object)?),
+            "invoice.payment_failed" => BillingEvent::InvoicePaymentFailed(serde_json::from_value(object)?),
+            _ => return Ok(None),
+        }))
+    }
+}
+
+/// Check if a subscription status gives access to the paid plan
+pub fn is_paying(status: &str) -> bool {
+    matches!(status, "active" | "trialing")
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SignatureError {
+    /// The signature header is missing a timestamp or signature
+    Malformed,
+
+    /// The event was signed too long ago
+    Expired,
+
+    /// No signature matches the payload
+    Mismatch,
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::Malformed => write!(f, "Malformed signature header"),
+            SignatureError::Expired => write!(f, "Signature timestamp is outside the tolerance"),
+            SignatureError::Mismatch => write!(f, "No signature matches the payload"),
+        }
+    }
+}
+
+/// Sign a payload the way the payment provider does: an HMAC-SHA256 of `<timestamp>.<payload>`.
+/// Returns the hex-encoded signature.
+pub fn sign(secret: &str, timestamp: i64, payload: &[u8]) -> String {
+    hex::encode(signed_payload(secret, timestamp, payload).finalize().into_bytes())
+}
+
+/// Verify the signature header of a webhook event (`t=<timestamp>,v1=<signature>[,v1=<signature>...]`).
+/// Several `v1` signatures are sent while the secret is being rolled; any of them can match.
+pub fn verify_signature(secret: &str, header: &str, payload: &[u8], now: i64) -> Result<(), SignatureError> {
+    let mut timestamp = None;
+    let mut signatures = vec![];
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => signatures.push(value),
+            _ => (),
+        }
+    }
+    let timestamp = timestamp.ok_or(SignatureError::Malformed)?;
+    if signatures.is_empty() {
+        return Err(SignatureError::Malformed);
+    }
+    if (now - timestamp).abs() > SIGNATURE_TOLERANCE_SECONDS {
+        return Err(SignatureError::Expired);
+    }
+
+    for signature in signatures {
+        let signature = match hex::decode(signature) {
+            Ok(signature) => signature,
+            Err(_) => continue,
+        };
+        // Constant-time comparison
+        if signed_payload(secret, timestamp, payload).verify_slice(&signature).is_ok() {
+            return Ok(());
+        }
+    }
+    Err(SignatureError::Mismatch)
+}
+
+fn signed_payload(secret: &str, timestamp: i64, payload: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(payload);
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_signature() {
+        let payload = br#"{"id":"evt_1"}"#;
+        let now = 1_700_000_000;
+        let header = format!("t={},v1={}", now, sign("whsec_test", now, payload));
+        assert_eq!(verify_signature("whsec_test", &header, payload, now + 10), Ok(()));
+
+        // rolled secrets send one signature per secret
+        let rolled = format!("t={},v1={},v1={}", now, sign("whsec_old", now, payload), sign("whsec_test", now, payload));
+        assert_eq!(verify_signature("whsec_test", &rolled, payload, now), Ok(()));
+
+        assert_eq!(verify_signature("whsec_other", &header, payload, now), Err(SignatureError::Mismatch));
+        assert_eq!(
+            verify_signature("whsec_test", &header, br#"{"id":"evt_2"}"#, now),
+            Err(SignatureError::Mismatch)
+        );
+        assert_eq!(verify_signature("whsec_test", &header, payload, now + 600), Err(SignatureError::Expired));
+        assert_eq!(verify_signature("whsec_test", "v1=abc", payload, now), Err(SignatureError::Malformed));
+    }
+
+    #[test]
+    fn test_parse_event() {
+        let event: WebhookEvent = serde_json::from_value(serde_json::json!({
+            "id": "evt_1",
+            "type": "customer.subscription.deleted",
+            "created": 1_700_000_000,
+            "data": { "object": { "id": "sub_1", "customer": "cus_1", "status": "canceled" } }
+        }))
+        .unwrap();
+        match event.parse().unwrap() {
+            Some(BillingEvent::SubscriptionDeleted(subscription)) => assert_eq!(subscription.customer, "cus_1"),
+            other => panic!("Unexpected event {:?}", other),
+        }
+
+        let event: WebhookEvent = serde_json::from_value(serde_json::json!({
+            "id": "evt_2",
+            "type": "customer.created",
+            "created": 1_700_000_000,
+            "data": { "object": {} }
+        }))
+        .unwrap();
+        assert!(event.parse().unwrap().is_none());
+    }
+}
@@ -1,21 +1,38 @@
 use actix_web::{
-    delete, get, patch, post,
-    web::{Data, Json, Query},
-    HttpResponse,
+    delete, get,
+    http::header,
+    patch, post,
+    web::{Data, Json, Path, Query},
+    HttpRequest, HttpResponse,
 };
+use rand::{distributions::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
 use std::env;
 
-use crate::auth::user_auth::AuthorizationService;
+use crate::auth::user_auth::{AuthorizationService, SessionAuthorizationService};
 use crate::handlers::types::{
-    AccountPatch, AuthResponse, Credentials, ErrorResponse, ExternalAccountQuery, VerificationCodeQuery, VerificationQuery,
+    AccountPatch, AccountRestore, Credentials, EmailChangeConfirmation, ErrorResponse, ExternalAccountQuery, ForgotPasswordQuery, MagicLinkLogin,
+    MagicLinkQuery, PasswordRejected, PasswordReset, RefreshRequest, VerificationCodeQuery, VerificationQuery,
 };
 use crate::utils;
 use crate::{
-    auth::jwt::{decode_google_token_id, encode_jwt, GoogleAuthClaims},
+    auth::client_ip::client_ip,
+    auth::jwks::JwksCache,
+    auth::lockout,
+    auth::oidc::OidcProviders,
+    auth::sessions::ClientInfo,
+    auth::tokens::{self, TokenError},
     repository::redis::RedisRepository,
 };
-use crate::{models::profile::Profile, models::user::User, repository::database::DatabaseRepository};
+use crate::{
+    models::audit::AuditEntry, models::profile::Profile, models::user::api_token::Scope, models::user::identity::LinkedIdentity,
+    models::user::plan::Plan, models::user::preferences::Preferences, models::user::User, repository::database::DatabaseRepository,
+};
 
+use super::api_token_handlers::require_scope;
+use super::export_handlers::{export_key, export_status_key};
+use super::invite_handlers::{redeem_invite, release_invite};
+use super::two_factor_handlers;
 use super::types::MessageResponse;
 
 /// API route to get a user's account by id. Returns a user's account information.
@@ -31,6 +48,9 @@ use super::types::MessageResponse;
 /// ```
 #[get("")]
 pub async fn get_account_by_id(db: Data<DatabaseRepository>, auth: AuthorizationService) -> HttpResponse {
+    if let Err(res) = require_scope(&auth, Scope::ProfileRead) {
+        return res;
+    }
     let id = auth.id;
     if id.is_empty() {
         return HttpResponse::BadRequest().json(ErrorResponse::new("Error getting account".to_string(), "Id is empty".to_string()));
@@ -46,6 +66,9 @@ pub async fn get_account_by_id(db: Data<DatabaseRepository>, auth: Authorization
 }
 
 /// API route to update a user's account.
+/// Changing the email only sends a confirmation code to the new address and returns
+/// `202 Accepted`. The email is changed once the code is confirmed with `/email/confirm`.
+///
 /// ### Request body:
 /// ```
 /// {
@@ -63,8 +86,13 @@ pub async fn get_account_by_id(db: Data<DatabaseRepository>, auth: Authorization
 /// }
 /// ```
 #[patch("")]
-pub async fn update_account(db: Data<DatabaseRepository>, mut req: Json<AccountPatch>, auth: AuthorizationService) -> HttpResponse {
-    let id = auth.id;
+pub async fn update_account(
+    db: Data<DatabaseRepository>,
+    redis: Data<RedisRepository>,
+    mut req: Json<AccountPatch>,
+    auth: SessionAuthorizationService,
+) -> HttpResponse {
+    let id = auth.0.id;
     if id.is_empty() {
         return HttpResponse::BadRequest().json(ErrorResponse::new("Error updating account".to_string(), "Id is empty".to_string()));
     }
@@ -73,7 +101,18 @@ pub async fn update_account(db: Data<DatabaseRepository>, mut req: Json<AccountP
         return HttpResponse::BadRequest().json(ErrorResponse::new("Error updating account".to_string(), "Invalid path".to_string()));
     }
 
+    if req.path == "email" {
+        return request_email_change(&db, &redis, &id, &req.value).await;
+    }
+
     if req.path == "password" {
+        let account = match db.get_account(&id).await {
+            Ok(account) => account,
+            Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error updating account".to_string(), e.to_string())),
+        };
+        if let Err(res) = check_password_policy(&req.value, &account.email, &account.name, "Invalid password") {
+            return res;
+        }
         req.value = utils::validation::generate_hash(&req.value);
     }
 
@@ -84,6 +123,13 @@ pub async fn update_account(db: Data<DatabaseRepository>, mut req: Json<AccountP
 
     let update_result = db.update_account(&id, to_update).await;
 
+    // Changing the password logs the user out everywhere
+    if req.path == "password" && update_result.is_ok() {
+        if let Err(e) = tokens::revoke_all_tokens(&redis, &id).await {
+            log::error!("Error revoking tokens after password change: {}", e);
+        }
+    }
+
     let res = db.get_account(&id).await.unwrap();
 
     match update_result {
@@ -98,37 +144,261 @@ pub async fn update_account(db: Data<DatabaseRepository>, mut req: Json<AccountP
     }
 }
 
+/// API route to confirm an email change with the code sent to the new address.
+/// The code can only be used once. On success, the old address is notified of the change.
+///
+/// ### Request body:
+/// ```
+/// {
+///    "code": String
+/// }
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// Account
+/// ```
+#[post("/email/confirm")]
+pub async fn confirm_email_change(
+    db: Data<DatabaseRepository>,
+    redis: Data<RedisRepository>,
+    req: Json<EmailChangeConfirmation>,
+    http_req: HttpRequest,
+    auth: SessionAuthorizationService,
+) -> HttpResponse {
+    let id = auth.0.id;
+    let key = email_change_key(&id);
+
+    let value = match redis.get(&key).await {
+        Ok(value) => value,
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error changing email".to_string(), e.to_string())),
+    };
+    let parts: Vec<&str> = value.splitn(3, ':').collect();
+    if parts.len() != 3 {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Invalid code".to_string(),
+            "No email change has been requested or the code has expired. Please request a new code.".to_string(),
+        ));
+    }
+    let (stored_code, status, new_email) = (parts[0], parts[1], parts[2]);
+
+    let ip = client_ip(&http_req);
+    let ip = ip.as_deref();
+    match lockout::check_lockout(&redis, &lockout::VERIFY_EMAIL, new_email, ip).await {
+        Ok(Some(seconds)) => return too_many_attempts(seconds),
+        Ok(None) => (),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error changing email".to_string(), e.to_string())),
+    }
+
+    if status == "used" {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Invalid code".to_string(),
+            "Code has already been used. Please request a new code.".to_string(),
+        ));
+    }
+    if stored_code != req.code {
+        return match lockout::record_failure(&redis, &lockout::VERIFY_EMAIL, new_email, ip).await {
+            Ok(Some(seconds)) => too_many_attempts(seconds),
+            Ok(None) => HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid code".to_string(), "Unauthorized".to_string())),
+            Err(e) => {
+                log::error!("Error recording failed email change: {}", e);
+                HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid code".to_string(), "Unauthorized".to_string()))
+            }
+        };
+    }
+
+    // The address may have been taken since the code was sent
+    if let Err(res) = check_email_available(&db, new_email).await {
+        return res;
+    }
+
+    let user = match db.get_user(&id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().json(ErrorResponse::new("Error changing email".to_string(), "Account not found".to_string())),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting account".to_string(), e.to_string())),
+    };
+
+    // Mark the code as used before changing the email so it cannot be replayed
+    match redis.getset(&key, &format!("{}:{}:{}", stored_code, "used", new_email)).await {
+        Ok(Some(previous)) if previous == value => (),
+        Ok(_) => {
+            return HttpResponse::BadRequest().json(ErrorResponse::new(
+                "Invalid code".to_string(),
+                "Code has already been used. Please request a new code.".to_string(),
+            ));
+        }
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error changing email".to_string(), e.to_string())),
+    }
+    let _ = redis.expire(&key, EMAIL_CHANGE_TTL_MINUTES * 60).await;
+    if let Err(e) = lockout::clear_failures(&redis, &lockout::VERIFY_EMAIL, new_email).await {
+        log::error!("Error clearing failed email changes: {}", e);
+    }
+
+    let update = AccountPatch {
+        path: "email".to_string(),
+        value: new_email.to_string(),
+    };
+    if let Err(e) = db.update_account(&id, update).await {
+        return HttpResponse::InternalServerError().json(ErrorResponse::new("Error changing email".to_string(), e.to_string()));
+    }
+
+    notify_email_changed(&user, new_email).await;
+
+    match db.get_account(&id).await {
+        Ok(account) => HttpResponse::Ok().json(account),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting account".to_string(), e.to_string())),
+    }
+}
+
+/// Number of days an account pending deletion can be restored before it is purged
+pub const DELETION_GRACE_DAYS: i64 = 30;
+
+/// API route to delete the account.
+///
+/// The account is only marked as pending deletion: every session is logged out, logging in is
+/// blocked and the user is emailed a link to restore the account. The account and all of its data
+/// are purged by a background task once the grace period is over.
+///
+/// The restore token is stored in the following key-value format:
+/// ```
+/// restore:<sha256(token)> -> user_id
+/// ```
 #[delete("")]
-pub async fn delete_account(db: Data<DatabaseRepository>, auth: AuthorizationService) -> HttpResponse {
-    let id = auth.id;
+pub async fn delete_account(db: Data<DatabaseRepository>, redis: Data<RedisRepository>, auth: SessionAuthorizationService) -> HttpResponse {
+    let id = auth.0.id;
     if id.is_empty() {
         return HttpResponse::BadRequest().json(ErrorResponse::new("Error deleting account".to_string(), "Id is empty".to_string()));
     }
 
-    let update_result = db.delete_account(&id).await;
+    let user = match db.get_user(&id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().json(ErrorResponse::new("Error deleting account".to_string(), "Account not found".to_string())),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error deleting account".to_string(), e.to_string())),
+    };
 
-    match update_result {
-        Ok(acc) => {
-            if acc.deleted_count == 1 {
-                HttpResponse::NoContent().finish()
-            } else {
-                HttpResponse::NotFound().json(ErrorResponse::new("Error deleting account".to_string(), "Account not found".to_string()))
+    if let Err(e) = db.set_account_deleted(&id, true).await {
+        return HttpResponse::InternalServerError().json(ErrorResponse::new("Error deleting account".to_string(), e.to_string()));
+    }
+    if let Err(e) = tokens::revoke_all_tokens(&redis, &id).await {
+        log::error!("Error revoking tokens after account deletion: {}", e);
+    }
+
+    let token: String = rand::thread_rng().sample_iter(&Alphanumeric).take(48).map(char::from).collect();
+    if let Err(e) = redis.set_ex(&restore_key(&token), &id, DELETION_GRACE_DAYS as usize * 24 * 60 * 60).await {
+        log::error!("Error storing restore token of account {}: {}", id, e);
+    }
+    notify_account_deleted(&user, &token).await;
+
+    HttpResponse::NoContent().finish()
+}
+
+/// API route to restore an account pending deletion with the token of the link from the deletion email.
+/// The user can log in again afterwards.
+///
+/// ### Request body:
+/// ```
+/// {
+///    "token": String
+/// }
+/// ```
+#[post("/restore")]
+pub async fn restore_account(db: Data<DatabaseRepository>, redis: Data<RedisRepository>, req: Json<AccountRestore>) -> HttpResponse {
+    let key = restore_key(&req.token);
+    let id = match redis.get(&key).await {
+        Ok(id) if !id.is_empty() => id,
+        Ok(_) => {
+            return HttpResponse::Unauthorized().json(ErrorResponse::new(
+                "Invalid link".to_string(),
+                "Restore link is invalid or has expired.".to_string(),
+            ))
+        }
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error restoring account".to_string(), e.to_string())),
+    };
+
+    match db.get_user(&id).await {
+        Ok(Some(user)) if user.date_deleted.is_some() => (),
+        Ok(_) => {
+            let _ = redis.del(&key).await;
+            return HttpResponse::NotFound().json(ErrorResponse::new(
+                "Error restoring account".to_string(),
+                "Account not found or not pending deletion".to_string(),
+            ));
+        }
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error restoring account".to_string(), e.to_string())),
+    }
+
+    if let Err(e) = db.set_account_deleted(&id, false).await {
+        return HttpResponse::InternalServerError().json(ErrorResponse::new("Error restoring account".to_string(), e.to_string()));
+    }
+    let _ = redis.del(&key).await;
+
+    HttpResponse::Ok().json(MessageResponse::new("Account restored. You can log in again.".to_string()))
+}
+
+/// Delete for good the accounts whose deletion grace period is over, along with their audit entries,
+/// token records, quota and export keys, invites and waitlist entry. Accounts that fail to be purged
+/// are logged and retried on the next run. Returns the number of purged and failed accounts.
+pub async fn purge_deleted_accounts(db: &DatabaseRepository, redis: &RedisRepository) -> Result<(usize, usize), String> {
+    let cutoff = chrono::Utc::now().timestamp() - DELETION_GRACE_DAYS * 24 * 60 * 60;
+    let users = db.get_users_deleted_before(cutoff).await.map_err(|e| e.to_string())?;
+
+    let (mut purged, mut failed) = (0, 0);
+    for user in &users {
+        let id = user.id.unwrap().to_hex();
+        match purge_account(db, redis, &id, user).await {
+            Ok(()) => {
+                log::info!("Purged account {}", id);
+                purged += 1;
+            }
+            Err(e) => {
+                log::error!("Error purging account {}: {}", id, e);
+                failed += 1;
             }
         }
-        Err(e) => HttpResponse::InternalServerError().json(e.to_string()),
     }
+    Ok((purged, failed))
+}
+
+/// Delete everything stored about a user, then the account itself
+async fn purge_account(db: &DatabaseRepository, redis: &RedisRepository, id: &str, user: &User) -> Result<(), String> {
+    db.delete_audit_entries(id).await.map_err(|e| e.to_string())?;
+    tokens::purge_tokens(redis, id).await.map_err(|e| e.to_string())?;
+    redis.del_matching(&format!("quota:{}:*", id)).await.map_err(|e| e.to_string())?;
+    redis.del(&export_key(id)).await.map_err(|e| e.to_string())?;
+    redis.del(&export_status_key(id)).await.map_err(|e| e.to_string())?;
+    db.delete_invites(id).await.map_err(|e| e.to_string())?;
+    db.remove_from_waitlist(&user.email).await.map_err(|e| e.to_string())?;
+
+    // There is no client for the payment provider, so subscriptions still running are
+    // flagged in the audit log for staff to cancel
+    if let Some(billing) = user.billing.as_ref().filter(|billing| billing.status != "canceled") {
+        let details = format!(
+            "customer {}, subscription {}",
+            billing.customer_id,
+            billing.subscription_id.as_deref().unwrap_or("none")
+        );
+        log::warn!("Purged account {} has a {} subscription: {}", id, billing.status, details);
+        let entry = AuditEntry::new("system", "billing.cancel_required", Some(id), Some(details));
+        db.add_audit_entry(entry).await.map_err(|e| e.to_string())?;
+    }
+
+    db.delete_account(id).await.map_err(|e| e.to_string())?;
+    Ok(())
 }
 
 // Authentication Handlers
 
-/// API route to create a user with an empty profile and no documents
+/// API route to create a user with an empty profile and no documents.
+/// An invite code is required when signups are invite-only, and is redeemed if given otherwise.
 ///
 /// ### Request body:
 /// ```
 /// {
 ///    "name": String,
 ///    "email": String,
-///    "password": String
+///    "password": String,
+///    "invite_code": String (optional)
 /// }
 /// ```
 ///
@@ -137,11 +407,12 @@ pub async fn delete_account(db: Data<DatabaseRepository>, auth: AuthorizationSer
 /// 201 Created
 /// {
 ///     "id": String,
-///     "token": String
+///     "token": String,
+///     "refresh_token": String
 /// }
 /// ```
 #[post("/create")]
-pub async fn create_account(db: Data<DatabaseRepository>, redis: Data<RedisRepository>, acc: Json<User>) -> HttpResponse {
+pub async fn create_account(db: Data<DatabaseRepository>, redis: Data<RedisRepository>, acc: Json<User>, req: HttpRequest) -> HttpResponse {
     let user = db.get_account_by_email(&acc.email).await;
     match user {
         Ok(user) => {
@@ -185,15 +456,21 @@ pub async fn create_account(db: Data<DatabaseRepository>, redis: Data<RedisRepos
             ));
         }
     };
-    match utils::validation::validate_signup(&acc.email, &password) {
+    match utils::validation::validate_email(&acc.email) {
         Ok(_) => (),
         Err(e) => {
             log::debug!("Invalid signup: {}", e);
             return HttpResponse::BadRequest().json(ErrorResponse::new("Invalid signup".to_string(), e.to_string()));
         }
     };
+    if let Err(res) = check_password_policy(&password, &acc.email, &acc.name, "Invalid signup") {
+        return res;
+    }
+    let invite_code = match redeem_invite(&db, acc.invite_code.as_deref()).await {
+        Ok(invite_code) => invite_code,
+        Err(res) => return res,
+    };
 
-    let secret = env::var("JWT_SECRET").unwrap();
     let hash_password = utils::validation::generate_hash(&password);
 
     let empty_profile = Profile {
@@ -207,26 +484,37 @@ pub async fn create_account(db: Data<DatabaseRepository>, redis: Data<RedisRepos
         id: None,
         name: acc.name.to_owned(),
         email: acc.email.to_owned(),
+        identities: vec![],
         external_id: None,
         external_provider: None,
         password: Some(hash_password),
         profile: Some(empty_profile),
         documents: Some(vec![]),
+        preferences: Preferences::default(),
+        two_factor: None,
+        roles: vec![],
+        plan: Plan::default(),
+        billing: None,
+        date_disabled: None,
+        date_deleted: None,
+        api_tokens: vec![],
+        passkeys: vec![],
+        invite_code: invite_code.clone(),
         date_created: Some(chrono::Utc::now().timestamp()),
         date_updated: Some(chrono::Utc::now().timestamp()),
     };
 
-    let result = db.create_account(data).await;
-
-    let id = result.as_ref().unwrap().inserted_id.as_object_id().unwrap().to_hex();
-    let domain = env::var("DOMAIN").unwrap();
-    let app_name = env::var("APP_NAME").unwrap();
-    let token = encode_jwt(app_name, id.to_owned(), domain, &secret);
-    if token.is_err() {
-        return HttpResponse::InternalServerError().json(ErrorResponse::new("Error creating account".to_string(), token.unwrap_err().to_string()));
-    }
-
-    let response = AuthResponse { id, token: token.unwrap() };
+    let id = match db.create_account(data).await {
+        Ok(result) => result.inserted_id.as_object_id().unwrap().to_hex(),
+        Err(e) => {
+            release_invite(&db, invite_code.as_deref()).await;
+            return HttpResponse::InternalServerError().json(ErrorResponse::new("Error creating account".to_string(), e.to_string()));
+        }
+    };
+    let response = match tokens::issue_tokens(&redis, &id, &[], &ClientInfo::from_request(&req)).await {
+        Ok(response) => response,
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error creating account".to_string(), e.to_string())),
+    };
 
     // Delete the verification code from the redis cache
     let res = redis.del(&acc.email).await;
@@ -236,10 +524,7 @@ pub async fn create_account(db: Data<DatabaseRepository>, redis: Data<RedisRepos
 
     // Return early if we are in test environment
     if std::env::var("ENV").unwrap() == "test" || std::env::var("ENV").unwrap() == "development" {
-        return match result {
-            Ok(_result) => HttpResponse::Created().json(response),
-            Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error creating account".to_string(), e.to_string())),
-        };
+        return HttpResponse::Created().json(response);
     }
 
     match utils::sendgrid::send_account_created(acc.email.as_str(), acc.name.as_str()).await {
@@ -247,17 +532,58 @@ pub async fn create_account(db: Data<DatabaseRepository>, redis: Data<RedisRepos
         Err(e) => log::error!("Error sending email: {:?}", e),
     }
 
-    match result {
-        Ok(_result) => HttpResponse::Created().json(response),
-        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error creating account".to_string(), e.to_string())),
-    }
+    HttpResponse::Created().json(response)
 }
 
+/// API route to log in with an email and password
+///
+/// ### Request body:
+/// ```
+/// {
+///    "email": String,
+///    "password": String
+/// }
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// {
+///     "id": String,
+///     "token": String,
+///     "refresh_token": String
+/// }
+/// ```
+///
+/// ### Response body (if the account has 2FA enabled):
+/// ```
+/// 202 Accepted
+/// {
+///     "two_factor_required": true,
+///     "challenge_token": String
+/// }
+/// ```
+/// The challenge token has to be sent to `/auth/login/2fa` with a TOTP or recovery code.
+///
+/// Failed attempts are counted per email and per client IP. Once over the limit,
+/// the route returns `429 Too Many Requests` with a `Retry-After` header, and the
+/// lockout doubles with every further failure.
 #[post("/auth/login")]
-pub async fn login_account(db: Data<DatabaseRepository>, cred: Json<Credentials>) -> HttpResponse {
+pub async fn login_account(db: Data<DatabaseRepository>, redis: Data<RedisRepository>, cred: Json<Credentials>, req: HttpRequest) -> HttpResponse {
+    let ip = client_ip(&req);
+    let ip = ip.as_deref();
+    match lockout::check_lockout(&redis, &lockout::LOGIN, &cred.email, ip).await {
+        Ok(Some(seconds)) => return too_many_attempts(seconds),
+        Ok(None) => (),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging in".to_string(), e.to_string())),
+    }
+
     let account = match db.get_account_by_email(&cred.email).await {
         Ok(Some(user)) => user,
         Ok(None) => {
+            if let Err(e) = lockout::record_failure(&redis, &lockout::LOGIN, &cred.email, ip).await {
+                log::error!("Error recording failed login: {}", e);
+            }
             return HttpResponse::NotFound().json(ErrorResponse::new("Error logging in".to_string(), "Account not found".to_string()));
         }
         Err(e) => {
@@ -268,116 +594,328 @@ pub async fn login_account(db: Data<DatabaseRepository>, cred: Json<Credentials>
 
     // if password is None, then the account was created with an external provider
     // and we can't login with it
-    if account.password.is_none() {
-        return HttpResponse::Unauthorized().body("Invalid password");
+    let valid = match account.password.as_ref().map(|hash| utils::validation::verify_hash(&cred.password, hash)) {
+        Some(Ok(valid)) => valid,
+        Some(Err(e)) => {
+            log::error!("Malformed password hash for account {}: {}", account.email, e);
+            false
+        }
+        None => false,
+    };
+    if !valid {
+        return match lockout::record_failure(&redis, &lockout::LOGIN, &cred.email, ip).await {
+            Ok(Some(seconds)) => {
+                notify_account_locked(&account, seconds).await;
+                too_many_attempts(seconds)
+            }
+            Ok(None) => HttpResponse::Unauthorized().body("Invalid password"),
+            Err(e) => {
+                log::error!("Error recording failed login: {}", e);
+                HttpResponse::Unauthorized().body("Invalid password")
+            }
+        };
+    }
+    if let Err(e) = lockout::clear_failures(&redis, &lockout::LOGIN, &cred.email).await {
+        log::error!("Error clearing failed logins: {}", e);
     }
 
-    if !utils::validation::verify_hash(&cred.password, account.password.as_ref().unwrap()) {
-        return HttpResponse::Unauthorized().body("Invalid password");
+    if account.date_disabled.is_some() {
+        return account_disabled();
+    }
+    if account.date_deleted.is_some() {
+        return account_deleted();
     }
 
     let id = account.id.unwrap().to_hex();
-    let secret = env::var("JWT_SECRET").unwrap();
-    let domain = env::var("DOMAIN").unwrap();
-    let app_name = env::var("APP_NAME").unwrap();
-    let token = encode_jwt(app_name, id.to_owned(), domain, &secret);
-    if token.is_err() {
-        return HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging in".to_string(), token.unwrap_err().to_string()));
+
+    // Hashes created with outdated argon2 parameters are replaced now that the password is known
+    if utils::validation::needs_rehash(account.password.as_ref().unwrap()) {
+        let update = AccountPatch {
+            path: "password".to_string(),
+            value: utils::validation::generate_hash(&cred.password),
+        };
+        if let Err(e) = db.update_account(&id, update).await {
+            log::error!("Error rehashing password of account {}: {}", id, e);
+        }
+    }
+
+    // Accounts with 2FA get a challenge token that has to be exchanged with a valid code
+    if account.two_factor.as_ref().map(|t| t.enabled).unwrap_or(false) {
+        return match two_factor_handlers::create_login_challenge(&redis, &id).await {
+            Ok(challenge) => HttpResponse::Accepted().json(challenge),
+            Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging in".to_string(), e.to_string())),
+        };
+    }
+
+    match tokens::issue_tokens(&redis, &id, &account.roles, &ClientInfo::from_request(&req)).await {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging in".to_string(), e.to_string())),
+    }
+}
+
+/// API route to exchange a refresh token for a new access token.
+/// The refresh token is rotated: the one sent in the request can no longer be used.
+/// Reusing an already rotated refresh token revokes every token issued from the same login.
+///
+/// ### Request body:
+/// ```
+/// {
+///    "refresh_token": String
+/// }
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// {
+///     "id": String,
+///     "token": String,
+///     "refresh_token": String
+/// }
+/// ```
+#[post("/auth/refresh")]
+pub async fn refresh_token(
+    db: Data<DatabaseRepository>,
+    redis: Data<RedisRepository>,
+    req: Json<RefreshRequest>,
+    http_req: HttpRequest,
+) -> HttpResponse {
+    match tokens::rotate_refresh_token(&redis, &db, &req.refresh_token, &ClientInfo::from_request(&http_req)).await {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(e @ (TokenError::Invalid | TokenError::Reused)) => {
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Error refreshing token".to_string(), e.to_string()))
+        }
+        Err(e) => {
+            log::error!("Error refreshing token: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse::new("Error refreshing token".to_string(), e.to_string()))
+        }
+    }
+}
+
+/// API route to log out the current session.
+/// The access token used for the request is denylisted until it expires, and its session
+/// is revoked with every refresh token issued from the same login.
+/// If a refresh token is sent, its token family is revoked as well.
+///
+/// ### Request body (optional):
+/// ```
+/// {
+///    "refresh_token": String
+/// }
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// {
+///     "message": "Logged out"
+/// }
+/// ```
+#[post("/auth/logout")]
+pub async fn logout(redis: Data<RedisRepository>, req: Option<Json<RefreshRequest>>, auth: SessionAuthorizationService) -> HttpResponse {
+    if let Err(e) = tokens::revoke_access_token(&redis, &auth.0.jti, auth.0.exp).await {
+        log::error!("Error revoking access token: {}", e);
+        return HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging out".to_string(), e.to_string()));
     }
 
-    HttpResponse::Ok().json(AuthResponse { id, token: token.unwrap() })
+    if !auth.0.sid.is_empty() {
+        if let Err(e) = tokens::revoke_family(&redis, &auth.0.sid).await {
+            log::error!("Error revoking session: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging out".to_string(), e.to_string()));
+        }
+    }
+
+    if let Some(req) = req {
+        if let Err(e) = tokens::revoke_refresh_token(&redis, &req.refresh_token).await {
+            log::error!("Error revoking refresh token: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging out".to_string(), e.to_string()));
+        }
+    }
+
+    HttpResponse::Ok().json(MessageResponse::new("Logged out".to_string()))
+}
+
+/// API route to log out of every session.
+/// Every access token and refresh token issued to the user so far is revoked.
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// {
+///     "message": "Logged out of all sessions"
+/// }
+/// ```
+#[post("/auth/logout-all")]
+pub async fn logout_all(redis: Data<RedisRepository>, auth: SessionAuthorizationService) -> HttpResponse {
+    match tokens::revoke_all_tokens(&redis, &auth.0.id).await {
+        Ok(_) => HttpResponse::Ok().json(MessageResponse::new("Logged out of all sessions".to_string())),
+        Err(e) => {
+            log::error!("Error revoking tokens: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging out".to_string(), e.to_string()))
+        }
+    }
 }
 
-#[post("/auth/google")]
-pub async fn authenticate_external_account(db: Data<DatabaseRepository>, query: Query<ExternalAccountQuery>) -> HttpResponse {
-    let secret = env::var("JWT_SECRET").unwrap();
-    let domain = env::var("DOMAIN").unwrap();
-    let app_name = env::var("APP_NAME").unwrap();
+/// API route to sign in with an OpenID Connect provider (e.g. `google`).
+/// Verifies the provider's ID token, then logs in to the account the identity is linked to.
+/// If there is none, the identity is linked to the account with the same email, but only if
+/// the provider reports the email as verified. Otherwise a new account is created, which
+/// requires an invite code when signups are invite-only.
+///
+/// Must be registered after the other `/auth/*` routes, since `{provider}` matches any name.
+///
+/// ### Query parameters:
+/// ```
+/// token_id: String
+/// invite_code: String (optional)
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK (existing account) or 201 Created (new account)
+/// {
+///     "id": String,
+///     "token": String,
+///     "refresh_token": String
+/// }
+/// ```
+///
+/// ### Response body (if the existing account has 2FA enabled):
+/// ```
+/// 202 Accepted
+/// {
+///     "two_factor_required": true,
+///     "challenge_token": String
+/// }
+/// ```
+/// The provider does not count as a second factor. The challenge token has to be sent
+/// to `/auth/login/2fa` with a TOTP or recovery code.
+///
+/// Returns `409 Conflict` if an account exists with the same unverified email.
+#[post("/auth/{provider}")]
+pub async fn authenticate_external_account(
+    db: Data<DatabaseRepository>,
+    redis: Data<RedisRepository>,
+    providers: Data<OidcProviders>,
+    jwks: Data<JwksCache>,
+    provider: Path<String>,
+    query: Query<ExternalAccountQuery>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let client = ClientInfo::from_request(&req);
+    let provider = match providers.get(&provider) {
+        Some(provider) => provider,
+        None => {
+            return HttpResponse::NotFound().json(ErrorResponse::new(
+                "Error authenticating account".to_string(),
+                format!("Unknown provider {}", provider),
+            ))
+        }
+    };
 
     let token = query.token_id.to_owned();
-    let google_claims: GoogleAuthClaims = match decode_google_token_id(&token).await {
-        Ok(c) => c,
+    let identity = match provider.verify(&token, &jwks).await {
+        Ok(identity) => identity,
         Err(e) => {
-            log::error!("Failed to decode google token. Error: {}", e);
+            log::error!("Failed to verify {} token. Error: {}", provider.name, e);
             return HttpResponse::BadRequest().json(e.to_string());
         }
     };
-    let email = google_claims.email;
+    let email = identity.email.to_lowercase();
+    let linked = LinkedIdentity {
+        provider: identity.provider.to_owned(),
+        subject: identity.subject.to_owned(),
+        email: email.to_owned(),
+        date_linked: chrono::Utc::now().timestamp(),
+    };
 
-    // Check if the account already exists
-    match db.get_account_by_email(&email).await {
-        Ok(user) => {
-            match user {
-                Some(user) => {
-                    // Account exists, returning token
-                    if user.external_id.is_none() || user.external_provider.is_none() {
-                        let updates = vec![
-                            AccountPatch {
-                                path: "external_id".to_string(),
-                                value: google_claims.sub.to_string(),
-                            },
-                            AccountPatch {
-                                path: "external_provider".to_string(),
-                                value: "google".to_string(),
-                            },
-                        ];
-                        let update_result = db.update_account_many(&user.id.unwrap().to_hex(), updates).await;
-                        if update_result.is_err() {
-                            let error_msg = "An account already exists under that email.";
-                            log::error!("{}", error_msg);
-                            return HttpResponse::InternalServerError()
-                                .json(ErrorResponse::new("Error migrating account".to_string(), error_msg.to_string()));
-                        }
-                    }
+    // Log in to the account the identity is linked to
+    match db.get_user_by_identity(&identity.provider, &identity.subject).await {
+        Ok(Some(user)) => {
+            let id = user.id.unwrap().to_hex();
+            if !user.identities.iter().any(|i| i.matches(&identity.provider, &identity.subject)) {
+                if let Err(e) = db.migrate_legacy_identity(&id, linked).await {
+                    log::error!("Failed to migrate legacy identity of account {}: {}", id, e);
+                }
+            }
+            return external_login(&redis, user, &client).await;
+        }
+        Ok(None) => (),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting account".to_string(), e.to_string()));
+        }
+    }
 
-                    let id = user.id.unwrap().to_hex();
-                    let token = encode_jwt(app_name, id.to_owned(), domain, &secret);
-                    if token.is_err() {
-                        let error_msg = "Failed to encode JWT";
-                        log::error!("{}", error_msg);
-                        return HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging in".to_string(), error_msg.to_string()));
-                    }
+    // Check if an account already exists with the same email
+    match db.get_account_by_email(&email).await {
+        Ok(Some(user)) => {
+            // Only link automatically if the provider vouches for the email,
+            // otherwise anyone could take over an account by claiming its email
+            if !identity.email_verified {
+                return HttpResponse::Conflict().json(ErrorResponse::new(
+                    "Error authenticating account".to_string(),
+                    format!(
+                        "An account already exists under that email. Log in and link your {} account from your account settings.",
+                        identity.provider
+                    ),
+                ));
+            }
 
-                    HttpResponse::Ok().json(AuthResponse { id, token: token.unwrap() })
-                }
-                None => {
-                    // Account does not exist, creating new account
-                    let empty_profile = Profile {
-                        education: vec![],
-                        experience: vec![],
-                        skills: vec![],
-                        date_updated: Some(chrono::Utc::now().timestamp()),
-                    };
-
-                    let data = User {
-                        id: None,
-                        name: google_claims.name,
-                        email,
-                        external_id: Some(google_claims.sub),
-                        external_provider: Some("google".to_string()),
-                        password: None,
-                        profile: Some(empty_profile),
-                        documents: Some(vec![]),
-                        date_created: Some(chrono::Utc::now().timestamp()),
-                        date_updated: Some(chrono::Utc::now().timestamp()),
-                    };
-
-                    let result = db.create_account(data).await;
-
-                    let id = result.as_ref().unwrap().inserted_id.as_object_id().unwrap().to_hex();
-                    let token = encode_jwt(app_name, id.to_owned(), domain, &secret);
-                    if token.is_err() {
-                        return HttpResponse::InternalServerError()
-                            .json(ErrorResponse::new("Error creating account".to_string(), token.unwrap_err().to_string()));
-                    }
+            let id = user.id.unwrap().to_hex();
+            if let Err(e) = db.add_identity(&id, linked).await {
+                log::error!("Failed to link {} identity to account {}: {}", identity.provider, id, e);
+                return HttpResponse::InternalServerError().json(ErrorResponse::new("Error linking account".to_string(), e.to_string()));
+            }
+            external_login(&redis, user, &client).await
+        }
+        Ok(None) => {
+            // Account does not exist, creating new account
+            let invite_code = match redeem_invite(&db, query.invite_code.as_deref()).await {
+                Ok(invite_code) => invite_code,
+                Err(res) => return res,
+            };
+            let empty_profile = Profile {
+                education: vec![],
+                experience: vec![],
+                skills: vec![],
+                date_updated: Some(chrono::Utc::now().timestamp()),
+            };
 
-                    let response = AuthResponse { id, token: token.unwrap() };
+            let data = User {
+                id: None,
+                name: identity.name.unwrap_or_else(|| email.split('@').next().unwrap_or_default().to_string()),
+                email,
+                identities: vec![linked],
+                external_id: None,
+                external_provider: None,
+                password: None,
+                profile: Some(empty_profile),
+                documents: Some(vec![]),
+                preferences: Preferences::default(),
+                two_factor: None,
+                roles: vec![],
+                plan: Plan::default(),
+                billing: None,
+                date_disabled: None,
+                date_deleted: None,
+                api_tokens: vec![],
+                passkeys: vec![],
+                invite_code: invite_code.clone(),
+                date_created: Some(chrono::Utc::now().timestamp()),
+                date_updated: Some(chrono::Utc::now().timestamp()),
+            };
 
-                    match result {
-                        Ok(_result) => HttpResponse::Created().json(response),
-                        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error creating account".to_string(), e.to_string())),
-                    }
+            let id = match db.create_account(data).await {
+                Ok(result) => result.inserted_id.as_object_id().unwrap().to_hex(),
+                Err(e) => {
+                    release_invite(&db, invite_code.as_deref()).await;
+                    return HttpResponse::InternalServerError().json(ErrorResponse::new("Error creating account".to_string(), e.to_string()));
                 }
+            };
+            match tokens::issue_tokens(&redis, &id, &[], &client).await {
+                Ok(response) => HttpResponse::Created().json(response),
+                Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error creating account".to_string(), e.to_string())),
             }
         }
         Err(e) => {
@@ -387,6 +925,33 @@ pub async fn authenticate_external_account(db: Data<DatabaseRepository>, query:
     }
 }
 
+/// Issue tokens for an account logging in with an external identity,
+/// or a challenge token if the account has 2FA enabled
+async fn external_login(redis: &RedisRepository, user: User, client: &ClientInfo) -> HttpResponse {
+    if user.date_disabled.is_some() {
+        return account_disabled();
+    }
+    if user.date_deleted.is_some() {
+        return account_deleted();
+    }
+
+    let id = user.id.unwrap().to_hex();
+    if user.two_factor.as_ref().map(|t| t.enabled).unwrap_or(false) {
+        return match two_factor_handlers::create_login_challenge(redis, &id).await {
+            Ok(challenge) => HttpResponse::Accepted().json(challenge),
+            Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging in".to_string(), e.to_string())),
+        };
+    }
+
+    match tokens::issue_tokens(redis, &id, &user.roles, client).await {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(e) => {
+            log::error!("Failed to issue tokens: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging in".to_string(), e.to_string()))
+        }
+    }
+}
+
 /// Route to generate a verification code and send it to the user's email
 /// The route generates a 6 digit code, stores it in a Redis cache
 /// and sends it to the user's email
@@ -448,20 +1013,33 @@ pub async fn get_verification_code(db: Data<DatabaseRepository>, redis: Data<Red
 /// and returns a `204` no content response.
 /// If the code is invalid or has been used, it returns a `400`
 /// bad request response
+/// Too many wrong codes for an email or from a client IP return a `429`
+/// too many requests response with a `Retry-After` header
 #[post("/auth/verify-email")]
-pub async fn verify_email(redis: Data<RedisRepository>, query: Query<VerificationQuery>) -> HttpResponse {
+pub async fn verify_email(redis: Data<RedisRepository>, query: Query<VerificationQuery>, req: HttpRequest) -> HttpResponse {
     let email = query.email.to_owned();
     let code = query.code.to_owned();
 
+    let ip = client_ip(&req);
+    let ip = ip.as_deref();
+    match lockout::check_lockout(&redis, &lockout::VERIFY_EMAIL, &email, ip).await {
+        Ok(Some(seconds)) => return too_many_attempts(seconds),
+        Ok(None) => (),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error verifying email".to_string(), e.to_string())),
+    }
+
     match redis.get(&email).await {
         Ok(value) => {
             let parts: Vec<&str> = value.split(':').collect();
             let stored_code = parts[0];
-            let status = parts[1];
+            let status = parts.get(1).copied().unwrap_or_default();
 
             if stored_code == code && status == "pending" {
                 let new_value = format!("{}:{}", stored_code, "used");
                 redis.set(&email, &new_value).await.unwrap();
+                if let Err(e) = lockout::clear_failures(&redis, &lockout::VERIFY_EMAIL, &email).await {
+                    log::error!("Error clearing failed verifications: {}", e);
+                }
                 HttpResponse::Ok().json("Email verified")
             } else if status == "used" {
                 HttpResponse::BadRequest().json(ErrorResponse::new(
@@ -469,9 +1047,471 @@ pub async fn verify_email(redis: Data<RedisRepository>, query: Query<Verificatio
                     "Code has already been used. Please use a new code.".to_string(),
                 ))
             } else {
-                HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid code".to_string(), "Unauthorized".to_string()))
+                match lockout::record_failure(&redis, &lockout::VERIFY_EMAIL, &email, ip).await {
+                    Ok(Some(seconds)) => too_many_attempts(seconds),
+                    Ok(None) => HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid code".to_string(), "Unauthorized".to_string())),
+                    Err(e) => {
+                        log::error!("Error recording failed verification: {}", e);
+                        HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid code".to_string(), "Unauthorized".to_string()))
+                    }
+                }
             }
         }
         Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error verifying email".to_string(), e.to_string())),
     }
 }
+
+/// Number of minutes a password reset code is valid for
+const RESET_CODE_TTL_MINUTES: usize = 15;
+
+/// Route to request a password reset code
+/// The route generates a 6 digit code, stores it in a Redis cache
+/// and sends it to the user's email
+///
+/// The code is valid for 15 minutes and is stored in the following
+/// key-value format:
+/// ```
+/// reset:email -> code:status
+/// ```
+///
+/// The status is either `pending` or `used`. The response is the same whether or not
+/// an account exists for the email, so the route cannot be used to look up accounts.
+#[post("/auth/forgot-password")]
+pub async fn forgot_password(db: Data<DatabaseRepository>, redis: Data<RedisRepository>, query: Query<ForgotPasswordQuery>) -> HttpResponse {
+    let email = query.email.to_lowercase();
+    let response = MessageResponse::new("If an account exists for this email, a reset code has been sent".to_string());
+
+    let user = match db.get_account_by_email(&email).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::Ok().json(response),
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting account".to_string(), e.to_string()));
+        }
+    };
+
+    let code = utils::validation::generate_verification_code();
+    let value = format!("{}:{}", code, "pending");
+    let seconds = RESET_CODE_TTL_MINUTES * 60;
+    if let Err(e) = redis.set_ex(&reset_key(&email), &value, seconds).await {
+        return HttpResponse::InternalServerError().json(ErrorResponse::new("Error requesting password reset".to_string(), e.to_string()));
+    }
+
+    // Return early if in test environment
+    if env::var("ENV").unwrap() == "test" {
+        return HttpResponse::Ok().json(response);
+    }
+
+    match utils::sendgrid::send_password_reset(&email, &user.name, &code).await {
+        Ok(_) => HttpResponse::Ok().json(response),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error sending email".to_string(), e.to_string())),
+    }
+}
+
+/// Route to reset a user's password given a reset code
+/// The code can only be used once. On success, every existing session
+/// of the user is logged out.
+///
+/// Wrong codes are counted per email and per client IP. Once over the limit,
+/// the route returns `429 Too Many Requests` with a `Retry-After` header and
+/// the code is deleted, so a new one has to be requested.
+///
+/// ### Request body:
+/// ```
+/// {
+///    "email": String,
+///    "code": String,
+///    "password": String
+/// }
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// {
+///     "message": "Password has been reset"
+/// }
+/// ```
+#[post("/auth/reset-password")]
+pub async fn reset_password(
+    db: Data<DatabaseRepository>,
+    redis: Data<RedisRepository>,
+    req: Json<PasswordReset>,
+    http_req: HttpRequest,
+) -> HttpResponse {
+    let email = req.email.to_lowercase();
+    let key = reset_key(&email);
+
+    let ip = client_ip(&http_req);
+    let ip = ip.as_deref();
+    match lockout::check_lockout(&redis, &lockout::RESET_PASSWORD, &email, ip).await {
+        Ok(Some(seconds)) => return too_many_attempts(seconds),
+        Ok(None) => (),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error resetting password".to_string(), e.to_string())),
+    }
+
+    let value = match redis.get(&key).await {
+        Ok(value) => value,
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error resetting password".to_string(), e.to_string())),
+    };
+    let parts: Vec<&str> = value.split(':').collect();
+    if parts.len() != 2 || parts[0] != req.code {
+        return match lockout::record_failure(&redis, &lockout::RESET_PASSWORD, &email, ip).await {
+            Ok(Some(seconds)) => {
+                // The code is burned once the email is locked out, so a new one has to be requested
+                if let Err(e) = redis.del(&key).await {
+                    log::error!("Error deleting reset code: {}", e);
+                }
+                too_many_attempts(seconds)
+            }
+            Ok(None) => HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid code".to_string(), "Unauthorized".to_string())),
+            Err(e) => {
+                log::error!("Error recording failed password reset: {}", e);
+                HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid code".to_string(), "Unauthorized".to_string()))
+            }
+        };
+    }
+    if parts[1] == "used" {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Invalid code".to_string(),
+            "Code has already been used. Please request a new code.".to_string(),
+        ));
+    }
+
+    let user = match db.get_account_by_email(&email).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(ErrorResponse::new(
+                "Error resetting password".to_string(),
+                "Account not found".to_string(),
+            ));
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting account".to_string(), e.to_string()));
+        }
+    };
+
+    // Check the password before using up the code, so the user can retry with another password
+    if let Err(res) = check_password_policy(&req.password, &user.email, &user.name, "Invalid password") {
+        return res;
+    }
+
+    // Mark the code as used before changing the password so it cannot be replayed
+    match redis.getset(&key, &format!("{}:{}", parts[0], "used")).await {
+        Ok(Some(previous)) if previous == value => (),
+        Ok(_) => {
+            return HttpResponse::BadRequest().json(ErrorResponse::new(
+                "Invalid code".to_string(),
+                "Code has already been used. Please request a new code.".to_string(),
+            ));
+        }
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error resetting password".to_string(), e.to_string())),
+    }
+    let _ = redis.expire(&key, RESET_CODE_TTL_MINUTES * 60).await;
+
+    let id = user.id.unwrap().to_hex();
+    let update = AccountPatch {
+        path: "password".to_string(),
+        value: utils::validation::generate_hash(&req.password),
+    };
+    if let Err(e) = db.update_account(&id, update).await {
+        return HttpResponse::InternalServerError().json(ErrorResponse::new("Error resetting password".to_string(), e.to_string()));
+    }
+
+    if let Err(e) = tokens::revoke_all_tokens(&redis, &id).await {
+        log::error!("Error revoking tokens after password reset: {}", e);
+    }
+    if let Err(e) = lockout::clear_failures(&redis, &lockout::RESET_PASSWORD, &email).await {
+        log::error!("Error clearing failed password resets: {}", e);
+    }
+
+    HttpResponse::Ok().json(MessageResponse::new("Password has been reset".to_string()))
+}
+
+/// Check a new password against the password policy.
+/// The password cannot contain the user's email or name.
+fn check_password_policy(password: &str, email: &str, name: &str, message: &str) -> Result<(), HttpResponse> {
+    let check = utils::password_policy::POLICY.check(password, &[email, name]);
+    if check.is_ok() {
+        return Ok(());
+    }
+    log::debug!("Password rejected by policy: {:?}", check.violations);
+    Err(HttpResponse::BadRequest().json(PasswordRejected::new(message.to_string(), check)))
+}
+
+/// Number of minutes a magic link is valid for
+const MAGIC_LINK_TTL_MINUTES: usize = 15;
+
+/// Number of seconds before another magic link can be sent to the same email
+const MAGIC_LINK_COOLDOWN_SECONDS: usize = 60;
+
+/// Route to request a magic link to log in without a password
+/// The route generates a random single-use token, stores its hash in a Redis cache
+/// and emails a link containing the token to the user
+///
+/// The token is valid for 15 minutes and is stored in the following
+/// key-value format:
+/// ```
+/// magic_link:<sha256(token)> -> user_id:status
+/// ```
+///
+/// The status is either `pending` or `used`. The response is the same whether or not
+/// an account exists for the email, so the route cannot be used to look up accounts.
+/// Accounts created with an external provider, which have no password, can use it too.
+///
+/// Must be registered before `/auth/{provider}`, which also matches this path.
+#[post("/auth/magic-link")]
+pub async fn request_magic_link(db: Data<DatabaseRepository>, redis: Data<RedisRepository>, query: Query<MagicLinkQuery>) -> HttpResponse {
+    let email = query.email.to_lowercase();
+    let response = MessageResponse::new("If an account exists for this email, a sign-in link has been sent".to_string());
+
+    let user = match db.get_account_by_email(&email).await {
+        Ok(Some(user)) if user.date_disabled.is_none() && user.date_deleted.is_none() => user,
+        Ok(_) => return HttpResponse::Ok().json(response),
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting account".to_string(), e.to_string()));
+        }
+    };
+
+    // Only one link per email per cooldown, so the route cannot be used to flood an inbox
+    let cooldown_key = magic_link_cooldown_key(&email);
+    match redis.exists(&cooldown_key).await {
+        Ok(true) => return HttpResponse::Ok().json(response),
+        Ok(false) => (),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error requesting sign-in link".to_string(), e.to_string())),
+    }
+
+    let token: String = rand::thread_rng().sample_iter(&Alphanumeric).take(48).map(char::from).collect();
+    let value = format!("{}:{}", user.id.unwrap().to_hex(), "pending");
+    if let Err(e) = redis.set_ex(&magic_link_key(&token), &value, MAGIC_LINK_TTL_MINUTES * 60).await {
+        return HttpResponse::InternalServerError().json(ErrorResponse::new("Error requesting sign-in link".to_string(), e.to_string()));
+    }
+    let _ = redis.set_ex(&cooldown_key, "sent", MAGIC_LINK_COOLDOWN_SECONDS).await;
+
+    // Return early if in test environment
+    if env::var("ENV").unwrap() == "test" {
+        return HttpResponse::Ok().json(response);
+    }
+
+    let base_url = env::var("MAGIC_LINK_URL").unwrap_or_else(|_| format!("https://{}/auth/magic-link", env::var("DOMAIN").unwrap()));
+    let link = format!("{}?token={}", base_url, token);
+    match utils::sendgrid::send_magic_link(&email, &user.name, &link).await {
+        Ok(_) => HttpResponse::Ok().json(response),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error sending email".to_string(), e.to_string())),
+    }
+}
+
+/// Route to log in with the token of a magic link
+/// The token can only be used once. Accounts with 2FA still have to
+/// finish logging in with `/auth/login/2fa`.
+///
+/// ### Request body:
+/// ```
+/// {
+///    "token": String
+/// }
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// {
+///     "id": String,
+///     "token": String,
+///     "refresh_token": String
+/// }
+/// ```
+///
+/// ### Response body (if the account has 2FA enabled):
+/// ```
+/// 202 Accepted
+/// {
+///     "two_factor_required": true,
+///     "challenge_token": String
+/// }
+/// ```
+#[post("/auth/magic-link/consume")]
+pub async fn consume_magic_link(
+    db: Data<DatabaseRepository>,
+    redis: Data<RedisRepository>,
+    req: Json<MagicLinkLogin>,
+    http_req: HttpRequest,
+) -> HttpResponse {
+    let key = magic_link_key(&req.token);
+
+    // Mark the token as used atomically so it cannot be used twice
+    let previous = match redis.getset(&key, "used").await {
+        Ok(previous) => previous.unwrap_or_default(),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging in".to_string(), e.to_string())),
+    };
+    let id = match previous.split_once(':') {
+        Some((id, "pending")) => id.to_owned(),
+        _ => {
+            let _ = redis.del(&key).await;
+            return HttpResponse::Unauthorized().json(ErrorResponse::new(
+                "Invalid link".to_string(),
+                "Sign-in link is invalid, expired or has already been used. Please request a new one.".to_string(),
+            ));
+        }
+    };
+    let _ = redis.expire(&key, MAGIC_LINK_TTL_MINUTES * 60).await;
+
+    let user = match db.get_user(&id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().json(ErrorResponse::new("Error logging in".to_string(), "Account not found".to_string())),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting account".to_string(), e.to_string())),
+    };
+    if user.date_disabled.is_some() {
+        return account_disabled();
+    }
+    if user.date_deleted.is_some() {
+        return account_deleted();
+    }
+
+    if user.two_factor.as_ref().map(|t| t.enabled).unwrap_or(false) {
+        return match two_factor_handlers::create_login_challenge(&redis, &id).await {
+            Ok(challenge) => HttpResponse::Accepted().json(challenge),
+            Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging in".to_string(), e.to_string())),
+        };
+    }
+
+    match tokens::issue_tokens(&redis, &id, &user.roles, &ClientInfo::from_request(&http_req)).await {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging in".to_string(), e.to_string())),
+    }
+}
+
+/// Number of minutes an email change code is valid for
+const EMAIL_CHANGE_TTL_MINUTES: usize = 10;
+
+/// Send a code to the new address of an email change.
+/// The change is stored in the following key-value format until it is confirmed:
+/// ```
+/// email_change:user_id -> code:status:new_email
+/// ```
+///
+/// The status is either `pending` or `used`. A new request replaces the previous one.
+async fn request_email_change(db: &DatabaseRepository, redis: &RedisRepository, id: &str, email: &str) -> HttpResponse {
+    let email = email.trim().to_lowercase();
+    if let Err(e) = utils::validation::validate_email(&email) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new("Error updating account".to_string(), e));
+    }
+    if let Err(res) = check_email_available(db, &email).await {
+        return res;
+    }
+
+    let user = match db.get_user(id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().json(ErrorResponse::new("Error updating account".to_string(), "Account not found".to_string())),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting account".to_string(), e.to_string())),
+    };
+
+    let code = utils::validation::generate_verification_code();
+    let value = format!("{}:{}:{}", code, "pending", email);
+    if let Err(e) = redis.set_ex(&email_change_key(id), &value, EMAIL_CHANGE_TTL_MINUTES * 60).await {
+        return HttpResponse::InternalServerError().json(ErrorResponse::new("Error updating account".to_string(), e.to_string()));
+    }
+
+    let response = MessageResponse::new("A confirmation code has been sent to the new email".to_string());
+
+    // Return early if in test environment
+    if env::var("ENV").unwrap() == "test" {
+        return HttpResponse::Accepted().json(response);
+    }
+
+    match utils::sendgrid::send_email_verification(&email, &user.name, &code).await {
+        Ok(_) => HttpResponse::Accepted().json(response),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error sending email".to_string(), e.to_string())),
+    }
+}
+
+/// Check that no account uses an email yet
+async fn check_email_available(db: &DatabaseRepository, email: &str) -> Result<(), HttpResponse> {
+    match db.get_account_by_email(email).await {
+        Ok(None) => Ok(()),
+        Ok(Some(_)) => Err(HttpResponse::Conflict().json(ErrorResponse::new(
+            "Error updating account".to_string(),
+            "Email already exists".to_string(),
+        ))),
+        Err(e) => Err(HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting account".to_string(), e.to_string()))),
+    }
+}
+
+/// Email the owner of an account just deleted a link to restore it
+async fn notify_account_deleted(account: &User, token: &str) {
+    // Skip in test environment
+    if env::var("ENV").unwrap() == "test" {
+        return;
+    }
+
+    let base_url = env::var("RESTORE_ACCOUNT_URL").unwrap_or_else(|_| format!("https://{}/account/restore", env::var("DOMAIN").unwrap()));
+    let link = format!("{}?token={}", base_url, token);
+    if let Err(e) = utils::sendgrid::send_account_deleted(&account.email, &account.name, &link, DELETION_GRACE_DAYS).await {
+        log::error!("Error sending account deleted email: {}", e);
+    }
+}
+
+/// Let the old address of an account know its email was changed
+async fn notify_email_changed(account: &User, new_email: &str) {
+    // Skip in test environment
+    if env::var("ENV").unwrap() == "test" {
+        return;
+    }
+
+    if let Err(e) = utils::sendgrid::send_email_changed(&account.email, &account.name, new_email).await {
+        log::error!("Error sending email changed email: {}", e);
+    }
+}
+
+fn restore_key(token: &str) -> String {
+    format!("restore:{}", hex::encode(Sha256::digest(token.as_bytes())))
+}
+
+fn email_change_key(id: &str) -> String {
+    format!("email_change:{}", id)
+}
+
+fn reset_key(email: &str) -> String {
+    format!("reset:{}", email)
+}
+
+fn magic_link_key(token: &str) -> String {
+    format!("magic_link:{}", hex::encode(Sha256::digest(token.as_bytes())))
+}
+
+fn magic_link_cooldown_key(email: &str) -> String {
+    format!("magic_link_sent:{}", email)
+}
+
+pub(crate) fn account_disabled() -> HttpResponse {
+    HttpResponse::Forbidden().json(ErrorResponse::new(
+        "Error logging in".to_string(),
+        "Account is disabled. Please contact support.".to_string(),
+    ))
+}
+
+pub(crate) fn account_deleted() -> HttpResponse {
+    HttpResponse::Forbidden().json(ErrorResponse::new(
+        "Error logging in".to_string(),
+        "Account is scheduled for deletion. Use the link in the confirmation email to restore it.".to_string(),
+    ))
+}
+
+pub(crate) fn too_many_attempts(seconds: usize) -> HttpResponse {
+    HttpResponse::TooManyRequests().insert_header((header::RETRY_AFTER, seconds.to_string())).json(ErrorResponse::new(
+        "Too many attempts".to_string(),
+        format!("Too many failed attempts. Please try again in {} seconds.", seconds),
+    ))
+}
+
+/// Email the owner of an account that was just locked out of logging in
+async fn notify_account_locked(account: &User, seconds: usize) {
+    // Skip in test environment
+    if env::var("ENV").unwrap() == "test" {
+        return;
+    }
+
+    let minutes = (seconds + 59) / 60;
+    if let Err(e) = utils::sendgrid::send_account_locked(&account.email, &account.name, minutes).await {
+        log::error!("Error sending account locked email: {}", e);
+    }
+}
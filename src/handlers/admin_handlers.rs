@@ -0,0 +1,411 @@
+use actix_web::{
+    delete, get, post, put,
+    web::{Data, Json, Path, Query},
+    HttpResponse,
+};
+
+use crate::auth::user_auth::{AdminAuthorizationService, StaffAuthorizationService};
+use crate::auth::{lockout, tokens};
+use crate::handlers::invite_handlers::{new_invite, notify_invite};
+use crate::handlers::types::{
+    AuditLogQuery, ErrorResponse, InviteQuery, InviteRequest, MessageResponse, PlanChange, QuotaReset, UserList, UserSearchQuery, WaitlistQuery,
+};
+use crate::models::{
+    audit::AuditEntry,
+    invite::InviteKind,
+    user::{metadata::UserMetadata, User},
+};
+use crate::repository::{database::DatabaseRepository, redis::RedisRepository};
+use crate::utils;
+
+/// Default number of users per page
+const DEFAULT_PAGE_SIZE: i64 = 20;
+
+/// Maximum number of users or audit entries returned at once
+const MAX_PAGE_SIZE: i64 = 100;
+
+/// Maximum number of accounts that can be created with one invite
+const MAX_INVITE_USES: u32 = 10_000;
+
+/// Staff-only API route to list users, or search them by name or email.
+///
+/// ### Query parameters:
+/// ```
+/// query: String (optional)
+/// page: u64 (optional, default 0)
+/// limit: i64 (optional, default 20, max 100)
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// {
+///     "users": [UserMetadata],
+///     "page": u64,
+///     "limit": i64
+/// }
+/// ```
+#[get("/users")]
+pub async fn search_users(db: Data<DatabaseRepository>, query: Query<UserSearchQuery>, auth: StaffAuthorizationService) -> HttpResponse {
+    let search = query.query.as_deref().map(str::trim).filter(|q| !q.is_empty());
+    let page = query.page.unwrap_or(0);
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let details = format!("query={:?} page={} limit={}", search, page, limit);
+    if let Err(res) = audit(&db, AuditEntry::new(&auth.0.id, "users.search", None, Some(details))).await {
+        return res;
+    }
+
+    match db.search_users(search, page.saturating_mul(limit as u64), limit).await {
+        Ok(users) => HttpResponse::Ok().json(UserList {
+            users: users.into_iter().map(UserMetadata::from).collect(),
+            page,
+            limit,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error searching users".to_string(), e.to_string())),
+    }
+}
+
+/// Staff-only API route to view the metadata of an account.
+/// Never includes the password hash, 2FA secrets or the user's content.
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// UserMetadata
+/// ```
+#[get("/users/{id}")]
+pub async fn get_user_metadata(db: Data<DatabaseRepository>, id: Path<String>, auth: StaffAuthorizationService) -> HttpResponse {
+    if let Err(res) = audit(&db, AuditEntry::new(&auth.0.id, "users.view", Some(&id), None)).await {
+        return res;
+    }
+
+    match db.get_user(&id).await {
+        Ok(Some(user)) => HttpResponse::Ok().json(UserMetadata::from(user)),
+        Ok(None) => HttpResponse::NotFound().json(ErrorResponse::new("Error getting account".to_string(), "Account not found".to_string())),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting account".to_string(), e.to_string())),
+    }
+}
+
+/// Admin-only API route to disable an account.
+/// Disabled accounts cannot log in, and every token issued to them is revoked.
+#[post("/users/{id}/disable")]
+pub async fn disable_user(
+    db: Data<DatabaseRepository>,
+    redis: Data<RedisRepository>,
+    id: Path<String>,
+    auth: AdminAuthorizationService,
+) -> HttpResponse {
+    if id.as_str() == auth.0.id {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Error disabling account".to_string(),
+            "You cannot disable your own account".to_string(),
+        ));
+    }
+    if let Err(res) = get_user(&db, &id).await {
+        return res;
+    }
+    if let Err(res) = audit(&db, AuditEntry::new(&auth.0.id, "users.disable", Some(&id), None)).await {
+        return res;
+    }
+
+    if let Err(e) = db.set_account_disabled(&id, true).await {
+        return HttpResponse::InternalServerError().json(ErrorResponse::new("Error disabling account".to_string(), e.to_string()));
+    }
+    match tokens::revoke_all_tokens(&redis, &id).await {
+        Ok(_) => HttpResponse::Ok().json(MessageResponse::new("Account disabled".to_string())),
+        Err(e) => {
+            log::error!("Error revoking tokens of disabled account {}: {}", id, e);
+            HttpResponse::InternalServerError().json(ErrorResponse::new("Error disabling account".to_string(), e.to_string()))
+        }
+    }
+}
+
+/// Admin-only API route to re-enable a disabled account.
+#[post("/users/{id}/enable")]
+pub async fn enable_user(db: Data<DatabaseRepository>, id: Path<String>, auth: AdminAuthorizationService) -> HttpResponse {
+    if let Err(res) = get_user(&db, &id).await {
+        return res;
+    }
+    if let Err(res) = audit(&db, AuditEntry::new(&auth.0.id, "users.enable", Some(&id), None)).await {
+        return res;
+    }
+
+    match db.set_account_disabled(&id, false).await {
+        Ok(_) => HttpResponse::Ok().json(MessageResponse::new("Account enabled".to_string())),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error enabling account".to_string(), e.to_string())),
+    }
+}
+
+/// Admin-only API route to change the plan of an account.
+///
+/// ### Request body:
+/// ```
+/// {
+///    "plan": "free" | "pro"
+/// }
+/// ```
+#[put("/users/{id}/plan")]
+pub async fn set_user_plan(db: Data<DatabaseRepository>, id: Path<String>, data: Json<PlanChange>, auth: AdminAuthorizationService) -> HttpResponse {
+    if let Err(res) = get_user(&db, &id).await {
+        return res;
+    }
+    let details = format!("plan={}", data.plan.as_str());
+    if let Err(res) = audit(&db, AuditEntry::new(&auth.0.id, "users.set_plan", Some(&id), Some(details))).await {
+        return res;
+    }
+
+    match db.set_plan(&id, data.plan).await {
+        Ok(_) => HttpResponse::Ok().json(MessageResponse::new("Plan changed".to_string())),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error changing plan".to_string(), e.to_string())),
+    }
+}
+
+/// Admin-only API route to reset the usage quotas of an account.
+///
+/// Quota counters are stored in Redis under keys in the following format:
+/// ```
+/// quota:<user_id>:<quota>
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// {
+///     "reset": usize
+/// }
+/// ```
+#[delete("/users/{id}/quotas")]
+pub async fn reset_user_quotas(
+    db: Data<DatabaseRepository>,
+    redis: Data<RedisRepository>,
+    id: Path<String>,
+    auth: AdminAuthorizationService,
+) -> HttpResponse {
+    if let Err(res) = get_user(&db, &id).await {
+        return res;
+    }
+    if let Err(res) = audit(&db, AuditEntry::new(&auth.0.id, "users.reset_quotas", Some(&id), None)).await {
+        return res;
+    }
+
+    match redis.del_matching(&format!("quota:{}:*", id)).await {
+        Ok(reset) => HttpResponse::Ok().json(QuotaReset { reset }),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error resetting quotas".to_string(), e.to_string())),
+    }
+}
+
+/// Staff-only API route to view the brute-force protection state of an account.
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// [
+///     {
+///         "policy": "login" | "verify_email" | "reset_password" | "two_factor",
+///         "failed_attempts": i64,
+///         "locked_for": usize | null
+///     }
+/// ]
+/// ```
+#[get("/users/{id}/lockout")]
+pub async fn get_user_lockout(
+    db: Data<DatabaseRepository>,
+    redis: Data<RedisRepository>,
+    id: Path<String>,
+    auth: StaffAuthorizationService,
+) -> HttpResponse {
+    let user = match get_user(&db, &id).await {
+        Ok(user) => user,
+        Err(res) => return res,
+    };
+    if let Err(res) = audit(&db, AuditEntry::new(&auth.0.id, "users.view_lockout", Some(&id), None)).await {
+        return res;
+    }
+
+    match lockout::lockout_state(&redis, &user.email, &id).await {
+        Ok(states) => HttpResponse::Ok().json(states),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting lockout state".to_string(), e.to_string())),
+    }
+}
+
+/// Admin-only API route to clear the failed attempts and lockouts of an account.
+/// Lockouts of client IPs are left in place.
+#[delete("/users/{id}/lockout")]
+pub async fn clear_user_lockout(
+    db: Data<DatabaseRepository>,
+    redis: Data<RedisRepository>,
+    id: Path<String>,
+    auth: AdminAuthorizationService,
+) -> HttpResponse {
+    let user = match get_user(&db, &id).await {
+        Ok(user) => user,
+        Err(res) => return res,
+    };
+    if let Err(res) = audit(&db, AuditEntry::new(&auth.0.id, "users.clear_lockout", Some(&id), None)).await {
+        return res;
+    }
+
+    for policy in lockout::POLICIES.iter() {
+        if let Err(e) = lockout::clear_failures(&redis, policy, policy.subject_of(&user.email, &id)).await {
+            return HttpResponse::InternalServerError().json(ErrorResponse::new("Error clearing lockout".to_string(), e.to_string()));
+        }
+    }
+    HttpResponse::Ok().json(MessageResponse::new("Lockout cleared".to_string()))
+}
+
+/// Admin-only API route to read the audit log, newest first.
+///
+/// ### Query parameters:
+/// ```
+/// target_id: String (optional)
+/// limit: i64 (optional, default 20, max 100)
+/// ```
+#[get("/audit")]
+pub async fn get_audit_log(db: Data<DatabaseRepository>, query: Query<AuditLogQuery>, auth: AdminAuthorizationService) -> HttpResponse {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let target_id = query.target_id.as_deref();
+    if let Err(res) = audit(&db, AuditEntry::new(&auth.0.id, "audit.view", target_id, None)).await {
+        return res;
+    }
+
+    match db.get_audit_entries(target_id, limit).await {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting audit log".to_string(), e.to_string())),
+    }
+}
+
+/// Staff-only API route to list the waitlist, oldest first.
+///
+/// ### Query parameters:
+/// ```
+/// pending: bool (optional, default false)
+/// page: u64 (optional, default 0)
+/// limit: i64 (optional, default 20, max 100)
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// [WaitlistEntry]
+/// ```
+#[get("/waitlist")]
+pub async fn get_waitlist(db: Data<DatabaseRepository>, query: Query<WaitlistQuery>, auth: StaffAuthorizationService) -> HttpResponse {
+    let pending = query.pending.unwrap_or(false);
+    let page = query.page.unwrap_or(0);
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let details = format!("pending={} page={} limit={}", pending, page, limit);
+    if let Err(res) = audit(&db, AuditEntry::new(&auth.0.id, "waitlist.view", None, Some(details))).await {
+        return res;
+    }
+
+    match db.get_waitlist(pending, page.saturating_mul(limit as u64), limit).await {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting waitlist".to_string(), e.to_string())),
+    }
+}
+
+/// Admin-only API route to issue an invite. If an email is given, the invite is sent to it
+/// and the matching waitlist entry is marked as invited.
+///
+/// ### Request body:
+/// ```
+/// {
+///    "email": String (optional),
+///    "max_uses": u32 (optional, default 1),
+///    "expires_in_days": i64 (optional)
+/// }
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 201 Created
+/// Invite
+/// ```
+#[post("/invites")]
+pub async fn create_invite(db: Data<DatabaseRepository>, data: Json<InviteRequest>, auth: AdminAuthorizationService) -> HttpResponse {
+    let email = data.email.as_deref().map(|email| email.trim().to_lowercase());
+    if let Some(email) = &email {
+        if let Err(e) = utils::validation::validate_email(email) {
+            return HttpResponse::BadRequest().json(ErrorResponse::new("Error creating invite".to_string(), e));
+        }
+    }
+    let max_uses = data.max_uses.unwrap_or(1);
+    if !(1..=MAX_INVITE_USES).contains(&max_uses) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Error creating invite".to_string(),
+            format!("Max uses must be between 1 and {}", MAX_INVITE_USES),
+        ));
+    }
+    let date_expires = match data.expires_in_days {
+        Some(days) if days <= 0 => {
+            return HttpResponse::BadRequest().json(ErrorResponse::new(
+                "Error creating invite".to_string(),
+                "Invites must expire in at least one day".to_string(),
+            ))
+        }
+        Some(days) => Some(chrono::Utc::now().timestamp() + days.min(3650) * 24 * 60 * 60),
+        None => None,
+    };
+
+    let details = format!("email={:?} max_uses={} date_expires={:?}", email, max_uses, date_expires);
+    if let Err(res) = audit(&db, AuditEntry::new(&auth.0.id, "invites.create", None, Some(details))).await {
+        return res;
+    }
+
+    let invite = new_invite(InviteKind::Admin, &auth.0.id, email, max_uses, date_expires);
+    if let Err(e) = db.add_invite(invite.clone()).await {
+        return HttpResponse::InternalServerError().json(ErrorResponse::new("Error creating invite".to_string(), e.to_string()));
+    }
+    if let Some(email) = &invite.email {
+        if let Err(e) = db.set_waitlist_invited(email).await {
+            log::error!("Error marking {} as invited: {}", email, e);
+        }
+    }
+    notify_invite(&invite).await;
+    HttpResponse::Created().json(invite)
+}
+
+/// Staff-only API route to list the latest invites, newest first.
+///
+/// ### Query parameters:
+/// ```
+/// created_by: String (optional)
+/// limit: i64 (optional, default 20, max 100)
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// [Invite]
+/// ```
+#[get("/invites")]
+pub async fn list_invites(db: Data<DatabaseRepository>, query: Query<InviteQuery>, auth: StaffAuthorizationService) -> HttpResponse {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let created_by = query.created_by.as_deref();
+    if let Err(res) = audit(&db, AuditEntry::new(&auth.0.id, "invites.view", created_by, None)).await {
+        return res;
+    }
+
+    match db.get_invites(created_by, limit).await {
+        Ok(invites) => HttpResponse::Ok().json(invites),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting invites".to_string(), e.to_string())),
+    }
+}
+
+/// Record an action before it is taken, so no action goes unaudited
+async fn audit(db: &DatabaseRepository, entry: AuditEntry) -> Result<(), HttpResponse> {
+    db.add_audit_entry(entry)
+        .await
+        .map(|_| ())
+        .map_err(|e| HttpResponse::InternalServerError().json(ErrorResponse::new("Error recording audit entry".to_string(), e.to_string())))
+}
+
+async fn get_user(db: &DatabaseRepository, id: &str) -> Result<User, HttpResponse> {
+    match db.get_user(id).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(HttpResponse::NotFound().json(ErrorResponse::new("Error getting account".to_string(), "Account not found".to_string()))),
+        Err(e) => Err(HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting account".to_string(), e.to_string()))),
+    }
+}
@@ -0,0 +1,143 @@
+use actix_web::{
+    delete, get, post,
+    web::{Data, Json, Path},
+    HttpResponse,
+};
+
+use crate::auth::api_tokens;
+use crate::auth::user_auth::{AuthorizationService, SessionAuthorizationService};
+use crate::handlers::types::{ApiTokenInfo, ApiTokenRequest, CreatedApiToken, ErrorResponse, MessageResponse};
+use crate::models::user::api_token::Scope;
+use crate::repository::database::DatabaseRepository;
+
+/// Maximum number of active personal access tokens per account
+const MAX_API_TOKENS: usize = 20;
+
+/// Maximum length of the name of a personal access token
+const MAX_API_TOKEN_NAME_LENGTH: usize = 64;
+
+/// Maximum number of days a personal access token can be valid for
+const MAX_API_TOKEN_TTL_DAYS: i64 = 365;
+
+/// API route to create a personal access token for scripted API access.
+/// The token is only shown once. Only its hash is stored.
+///
+/// ### Request body:
+/// ```
+/// {
+///    "name": String,
+///    "scopes": ["profile:read" | "profile:write" | "generate" | "documents:write"],
+///    "expires_in_days": i64 (optional, max 365)
+/// }
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 201 Created
+/// {
+///     "token": String,
+///     "id": String,
+///     "name": String,
+///     "prefix": String,
+///     "scopes": [String],
+///     "date_created": i64,
+///     "date_expires": i64 | null,
+///     "date_last_used": null
+/// }
+/// ```
+#[post("/tokens")]
+pub async fn create_api_token(db: Data<DatabaseRepository>, req: Json<ApiTokenRequest>, auth: SessionAuthorizationService) -> HttpResponse {
+    let name = req.name.trim();
+    if name.is_empty() || name.len() > MAX_API_TOKEN_NAME_LENGTH {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Error creating API token".to_string(),
+            format!("Name must be between 1 and {} characters", MAX_API_TOKEN_NAME_LENGTH),
+        ));
+    }
+    if req.scopes.is_empty() {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Error creating API token".to_string(),
+            "At least one scope is required".to_string(),
+        ));
+    }
+    let date_expires = match req.expires_in_days {
+        Some(days) if !(1..=MAX_API_TOKEN_TTL_DAYS).contains(&days) => {
+            return HttpResponse::BadRequest().json(ErrorResponse::new(
+                "Error creating API token".to_string(),
+                format!("Expiry must be between 1 and {} days", MAX_API_TOKEN_TTL_DAYS),
+            ))
+        }
+        Some(days) => Some((chrono::Utc::now() + chrono::Duration::days(days)).timestamp()),
+        None => None,
+    };
+
+    let user = match db.get_user(&auth.0.id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().json(ErrorResponse::new("Error getting account".to_string(), "Account not found".to_string())),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting account".to_string(), e.to_string())),
+    };
+    let now = chrono::Utc::now().timestamp();
+    if user.api_tokens.iter().filter(|t| t.is_active(now)).count() >= MAX_API_TOKENS {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Error creating API token".to_string(),
+            format!("Max API tokens reached. Revoke one of your {} tokens to create a new one", MAX_API_TOKENS),
+        ));
+    }
+
+    let mut scopes: Vec<Scope> = vec![];
+    for scope in req.scopes.iter() {
+        if !scopes.contains(scope) {
+            scopes.push(*scope);
+        }
+    }
+    let (token, record) = api_tokens::new_api_token(name, scopes, date_expires);
+    match db.add_api_token(&auth.0.id, record.clone()).await {
+        Ok(_) => HttpResponse::Created().json(CreatedApiToken {
+            token,
+            info: ApiTokenInfo::from(record),
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error creating API token".to_string(), e.to_string())),
+    }
+}
+
+/// API route to list the active personal access tokens of the account.
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// [ApiTokenInfo]
+/// ```
+#[get("/tokens")]
+pub async fn list_api_tokens(db: Data<DatabaseRepository>, auth: SessionAuthorizationService) -> HttpResponse {
+    match db.get_user(&auth.0.id).await {
+        Ok(Some(user)) => {
+            let now = chrono::Utc::now().timestamp();
+            let tokens: Vec<ApiTokenInfo> = user.api_tokens.into_iter().filter(|t| t.is_active(now)).map(ApiTokenInfo::from).collect();
+            HttpResponse::Ok().json(tokens)
+        }
+        Ok(None) => HttpResponse::NotFound().json(ErrorResponse::new("Error getting account".to_string(), "Account not found".to_string())),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting account".to_string(), e.to_string())),
+    }
+}
+
+/// API route to revoke a personal access token. It is rejected from then on.
+#[delete("/tokens/{token_id}")]
+pub async fn revoke_api_token(db: Data<DatabaseRepository>, token_id: Path<String>, auth: SessionAuthorizationService) -> HttpResponse {
+    match db.revoke_api_token(&auth.0.id, &token_id).await {
+        Ok(_) => HttpResponse::Ok().json(MessageResponse::new("API token revoked".to_string())),
+        Err(e) => HttpResponse::NotFound().json(ErrorResponse::new("Error revoking API token".to_string(), e.to_string())),
+    }
+}
+
+/// Check that the token used to authenticate allows `scope`.
+/// JWTs allow every scope; personal access tokens only the scopes they were created with.
+pub fn require_scope(auth: &AuthorizationService, scope: Scope) -> Result<(), HttpResponse> {
+    if auth.has_scope(scope) {
+        Ok(())
+    } else {
+        Err(HttpResponse::Forbidden().json(ErrorResponse::new(
+            "Insufficient scope".to_string(),
+            format!("This token does not have the `{}` scope", scope.as_str()),
+        )))
+    }
+}
@@ -0,0 +1,219 @@
+use actix_web::{
+    post,
+    web::{Bytes, Data},
+    HttpRequest, HttpResponse,
+};
+use mongodb::bson::{extjson::de::Error, oid::ObjectId};
+use std::env;
+
+use crate::billing::{self, BillingEvent, WebhookEvent, SIGNATURE_HEADER};
+use crate::handlers::types::{ErrorResponse, MessageResponse};
+use crate::models::billing_event::BillingEventRecord;
+use crate::models::user::{billing::Billing, plan::Plan, User};
+use crate::repository::database::DatabaseRepository;
+
+/// API route for the webhook of the payment provider, which sends events in Stripe's format.
+/// Events are signed with the secret in `BILLING_WEBHOOK_SECRET` and update the plan of the user they are about:
+///
+/// - `checkout.session.completed`: links the customer to the account of the user in `client_reference_id`,
+///   and upgrades the user once the checkout is paid
+/// - `checkout.session.async_payment_succeeded` and `checkout.session.async_payment_failed`: same as above,
+///   once a pending payment of a checkout goes through or fails
+/// - `customer.subscription.updated`: upgrades or downgrades the user, depending on the status of the subscription
+/// - `customer.subscription.deleted`: downgrades the user
+/// - `invoice.payment_failed`: downgrades the user until the subscription is active again
+///
+/// Every event is processed once: deliveries of an event that was already processed are acknowledged without effect.
+/// Events older than the last update of the subscription of the user are ignored, since events are not delivered in order.
+///
+/// ### Request headers:
+/// ```
+/// Stripe-Signature: t=<timestamp>,v1=<signature>
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// {
+///     "message": "Event processed" | "Event already processed" | "Event ignored"
+/// }
+/// ```
+#[post("/webhook")]
+pub async fn billing_webhook(db: Data<DatabaseRepository>, req: HttpRequest, body: Bytes) -> HttpResponse {
+    let secret = match env::var("BILLING_WEBHOOK_SECRET") {
+        Ok(secret) => secret,
+        Err(_) => {
+            log::error!("BILLING_WEBHOOK_SECRET is not set");
+            return HttpResponse::InternalServerError().json(ErrorResponse::new(
+                "Error processing event".to_string(),
+                "Billing is not configured".to_string(),
+            ));
+        }
+    };
+    let signature = match req.headers().get(SIGNATURE_HEADER).and_then(|value| value.to_str().ok()) {
+        Some(signature) => signature,
+        None => {
+            return HttpResponse::BadRequest().json(ErrorResponse::new(
+                "Invalid signature".to_string(),
+                "Missing signature header".to_string(),
+            ))
+        }
+    };
+    if let Err(e) = billing::verify_signature(&secret, signature, &body, chrono::Utc::now().timestamp()) {
+        log::warn!("Rejected billing event: {}", e);
+        return HttpResponse::BadRequest().json(ErrorResponse::new("Invalid signature".to_string(), e.to_string()));
+    }
+
+    let event: WebhookEvent = match serde_json::from_slice(&body) {
+        Ok(event) => event,
+        Err(e) => return HttpResponse::BadRequest().json(ErrorResponse::new("Invalid event".to_string(), e.to_string())),
+    };
+    let billing_event = match event.parse() {
+        Ok(Some(billing_event)) => billing_event,
+        Ok(None) => return HttpResponse::Ok().json(MessageResponse::new("Event ignored".to_string())),
+        Err(e) => return HttpResponse::BadRequest().json(ErrorResponse::new("Invalid event".to_string(), e.to_string())),
+    };
+
+    let record = BillingEventRecord {
+        id: event.id.clone(),
+        event_type: event.type_.clone(),
+        customer_id: customer_id(&billing_event),
+        date_processed: chrono::Utc::now().timestamp(),
+    };
+    match db.add_billing_event(record).await {
+        Ok(true) => (),
+        Ok(false) => return HttpResponse::Ok().json(MessageResponse::new("Event already processed".to_string())),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error processing event".to_string(), e.to_string())),
+    }
+
+    if let Err(e) = apply_event(&db, event.created, billing_event).await {
+        log::error!("Error processing billing event {}: {}", event.id, e);
+        // Forget the event so the provider's retry processes it
+        if let Err(e) = db.delete_billing_event(&event.id).await {
+            log::error!("Error forgetting billing event {}: {}", event.id, e);
+        }
+        return HttpResponse::InternalServerError().json(ErrorResponse::new("Error processing event".to_string(), e.to_string()));
+    }
+    HttpResponse::Ok().json(MessageResponse::new("Event processed".to_string()))
+}
+
+/// Update the plan and subscription of the user an event is about.
+/// Events about unknown users or older than the last update of the subscription are skipped.
+async fn apply_event(db: &DatabaseRepository, created: i64, event: BillingEvent) -> Result<(), Error> {
+    match event {
+        BillingEvent::CheckoutCompleted(session) => {
+            let (user_id, customer_id) = match (session.client_reference_id, session.customer) {
+                (Some(user_id), Some(customer_id)) if ObjectId::parse_str(&user_id).is_ok() => (user_id, customer_id),
+                _ => {
+                    log::warn!("Skipping checkout {} without a user or customer", session.id);
+                    return Ok(());
+                }
+            };
+            let user = match db.get_user(&user_id).await? {
+                Some(user) => user,
+                None => {
+                    log::warn!("Skipping checkout {} of unknown user {}", session.id, user_id);
+                    return Ok(());
+                }
+            };
+            if is_stale(&user, created) {
+                return Ok(());
+            }
+            // Pending payments keep the current plan until they go through
+            let (plan, status) = if session.is_paid() {
+                (Plan::Pro, "active".to_string())
+            } else {
+                (user.plan, session.payment_status.unwrap_or_else(|| "unpaid".to_string()))
+            };
+            let billing = Billing {
+                customer_id,
+                subscription_id: session.subscription,
+                status,
+                date_current_period_end: None,
+                date_updated: created,
+            };
+            db.set_billing(&user_id, plan, &billing).await?;
+        }
+        BillingEvent::SubscriptionUpdated(subscription) | BillingEvent::SubscriptionDeleted(subscription) => {
+            let user = match user_of_subscription(db, &subscription.customer, Some(&subscription.id), created).await? {
+                Some(user) => user,
+                None => return Ok(()),
+            };
+            // Deleted subscriptions have the `canceled` status
+            let plan = if billing::is_paying(&subscription.status) {
+                Plan::Pro
+            } else {
+                Plan::Free
+            };
+            let billing = Billing {
+                customer_id: subscription.customer,
+                subscription_id: Some(subscription.id),
+                status: subscription.status,
+                date_current_period_end: subscription.current_period_end,
+                date_updated: created,
+            };
+            db.set_billing(&user.id.unwrap().to_hex(), plan, &billing).await?;
+        }
+        BillingEvent::InvoicePaymentFailed(invoice) => {
+            let user = match user_of_subscription(db, &invoice.customer, invoice.subscription.as_deref(), created).await? {
+                Some(user) => user,
+                None => return Ok(()),
+            };
+            let billing = Billing {
+                status: "past_due".to_string(),
+                date_updated: created,
+                ..user.billing.unwrap()
+            };
+            db.set_billing(&user.id.unwrap().to_hex(), Plan::Free, &billing).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Find the user a subscription event is about, if the event is about their current subscription and is not stale
+async fn user_of_subscription(
+    db: &DatabaseRepository,
+    customer_id: &str,
+    subscription_id: Option<&str>,
+    created: i64,
+) -> Result<Option<User>, Error> {
+    let user = match db.get_user_by_customer(customer_id).await? {
+        Some(user) => user,
+        None => {
+            log::warn!("Skipping billing event of unknown customer {}", customer_id);
+            return Ok(None);
+        }
+    };
+    let current = user.billing.as_ref().and_then(|billing| billing.subscription_id.as_deref());
+    if let (Some(current), Some(subscription_id)) = (current, subscription_id) {
+        if current != subscription_id {
+            log::info!(
+                "Skipping billing event of replaced subscription {} of customer {}",
+                subscription_id,
+                customer_id
+            );
+            return Ok(None);
+        }
+    }
+    if is_stale(&user, created) {
+        return Ok(None);
+    }
+    Ok(Some(user))
+}
+
+/// Check if the subscription of the user was updated by a later event
+fn is_stale(user: &User, created: i64) -> bool {
+    let stale = user.billing.as_ref().map(|billing| billing.date_updated > created).unwrap_or(false);
+    if stale {
+        log::info!("Skipping billing event created at {}, older than the last update", created);
+    }
+    stale
+}
+
+fn customer_id(event: &BillingEvent) -> Option<String> {
+    match event {
+        BillingEvent::CheckoutCompleted(session) => session.customer.clone(),
+        BillingEvent::SubscriptionUpdated(subscription) | BillingEvent::SubscriptionDeleted(subscription) => Some(subscription.customer.clone()),
+        BillingEvent::InvoicePaymentFailed(invoice) => Some(invoice.customer.clone()),
+    }
+}
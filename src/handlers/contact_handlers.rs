@@ -0,0 +1,139 @@
+use actix_web::{
+    http::header,
+    post,
+    web::{Data, Json},
+    HttpRequest, HttpResponse,
+};
+use std::env;
+use std::io::Write;
+
+use crate::auth::client_ip::client_ip;
+use crate::handlers::types::{ContactRequest, ErrorResponse, MessageResponse};
+use crate::repository::redis::RedisRepository;
+use crate::utils;
+
+/// Maximum length of the name of the sender, in characters
+const MAX_NAME_LENGTH: usize = 100;
+
+/// Maximum length of a message, in characters
+const MAX_MESSAGE_LENGTH: usize = 5000;
+
+/// Number of messages an IP can send per window
+const MAX_MESSAGES_PER_WINDOW: i64 = 5;
+
+/// Number of seconds of the rate limiting window
+const RATE_LIMIT_WINDOW_SECONDS: usize = 60 * 60;
+
+/// Directory messages are written to instead of being sent in test and development. Overridden by `CONTACT_OUTBOX_DIR`.
+const CONTACT_OUTBOX_DIR: &str = "outbox";
+
+/// API route for the contact form of the website. Messages are emailed to info@scrippt.tech.
+///
+/// Each client IP (see `client_ip`) can send a limited number of messages per hour, counted in the following key-value format:
+/// ```
+/// contact:<ip> -> number of messages
+/// ```
+///
+/// Requests with the honeypot field filled in get the same response, but nothing is sent.
+/// In test and development, messages are appended to `<CONTACT_OUTBOX_DIR>/contact.jsonl` instead of being sent.
+///
+/// ### Request body:
+/// ```
+/// {
+///    "name": String,
+///    "email": String,
+///    "message": String,
+///    "website": String (optional, honeypot, must be left empty)
+/// }
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// {
+///     "message": "Message sent"
+/// }
+/// ```
+#[post("/contact")]
+pub async fn contact(redis: Data<RedisRepository>, data: Json<ContactRequest>, req: HttpRequest) -> HttpResponse {
+    let ip = match client_ip(&req) {
+        Some(ip) => ip,
+        None => {
+            return HttpResponse::BadRequest().json(ErrorResponse::new(
+                "Error sending message".to_string(),
+                "Unknown client address".to_string(),
+            ))
+        }
+    };
+    let key = format!("contact:{}", ip);
+    let sent = match redis.incr(&key).await {
+        Ok(sent) => sent,
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error sending message".to_string(), e.to_string())),
+    };
+    if sent == 1 {
+        if let Err(e) = redis.expire(&key, RATE_LIMIT_WINDOW_SECONDS).await {
+            log::error!("Error setting contact rate limit expiry: {}", e);
+        }
+    }
+    if sent > MAX_MESSAGES_PER_WINDOW {
+        let seconds = redis.ttl(&key).await.ok().filter(|ttl| *ttl > 0).unwrap_or(RATE_LIMIT_WINDOW_SECONDS as i64);
+        return HttpResponse::TooManyRequests().insert_header((header::RETRY_AFTER, seconds.to_string())).json(ErrorResponse::new(
+            "Error sending message".to_string(),
+            format!("Too many messages. Please try again in {} seconds.", seconds),
+        ));
+    }
+
+    let data = data.into_inner();
+    if data.website.as_deref().map(|website| !website.trim().is_empty()).unwrap_or(false) {
+        log::info!("Dropping contact form message from {} with the honeypot filled in", ip);
+        return HttpResponse::Ok().json(MessageResponse::new("Message sent".to_string()));
+    }
+
+    let name = data.name.trim();
+    let email = data.email.trim();
+    let message = data.message.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Error sending message".to_string(),
+            format!("Name must be between 1 and {} characters", MAX_NAME_LENGTH),
+        ));
+    }
+    if let Err(e) = utils::validation::validate_email(email) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new("Error sending message".to_string(), e));
+    }
+    if message.is_empty() || message.chars().count() > MAX_MESSAGE_LENGTH {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Error sending message".to_string(),
+            format!("Message must be between 1 and {} characters", MAX_MESSAGE_LENGTH),
+        ));
+    }
+
+    let env = env::var("ENV").unwrap();
+    let result = if env == "test" || env == "development" {
+        write_to_outbox(name, email, message).map_err(|e| e.to_string())
+    } else {
+        utils::sendgrid::send_contact_email(name, email, message).await.map_err(|e| e.to_string())
+    };
+
+    match result {
+        Ok(_) => HttpResponse::Ok().json(MessageResponse::new("Message sent".to_string())),
+        Err(e) => {
+            log::error!("Error sending contact form message: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse::new("Error sending message".to_string(), e))
+        }
+    }
+}
+
+/// Append a message to the local outbox, one JSON object per line
+fn write_to_outbox(name: &str, email: &str, message: &str) -> std::io::Result<()> {
+    let dir = env::var("CONTACT_OUTBOX_DIR").unwrap_or_else(|_| CONTACT_OUTBOX_DIR.to_string());
+    std::fs::create_dir_all(&dir)?;
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(std::path::Path::new(&dir).join("contact.jsonl"))?;
+    let line = serde_json::json!({
+        "name": name,
+        "email": email,
+        "message": message,
+        "date_sent": chrono::Utc::now().timestamp(),
+    });
+    writeln!(file, "{}", line)
+}
@@ -8,8 +8,9 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     auth::user_auth::AuthorizationService,
-    handlers::types::ErrorResponse,
+    handlers::{api_token_handlers::require_scope, plan_handlers::check_limit, types::ErrorResponse},
     models::document::{Document, Rating},
+    models::user::{api_token::Scope, plan::Resource},
     repository::database::DatabaseRepository,
 };
 
@@ -22,11 +23,11 @@ pub struct DocumentRequest {
     pub rating: Rating,
 }
 
-// MAX_DOCUMENTS
-const MAX_DOCUMENTS: usize = 3;
-
 #[put("")]
 pub async fn create_update_document(db: Data<DatabaseRepository>, doc: Json<DocumentRequest>, auth: AuthorizationService) -> HttpResponse {
+    if let Err(res) = require_scope(&auth, Scope::DocumentsWrite) {
+        return res;
+    }
     let id = auth.id;
     log::debug!("CREATING DOCUMENT: ID: {:#?}", id);
     if id.is_empty() {
@@ -59,12 +60,10 @@ pub async fn create_update_document(db: Data<DatabaseRepository>, doc: Json<Docu
             date_updated: Some(chrono::Utc::now().timestamp()),
         };
 
-        // Check if user has reached max documents
+        // Check if user has reached the document limit of their plan
         let account = db.get_account(&id).await.unwrap();
-        if (account.documents.len()) >= MAX_DOCUMENTS {
-            return HttpResponse::BadRequest().body(
-                "Max documents reached. We are working on functionality to add more documents. In the meantime, please delete one of your documents to add a new one.",
-            );
+        if let Err(res) = check_limit(account.plan, Resource::Documents, None, account.documents.len() as u64) {
+            return res;
         }
 
         match db.add_document(&id, new_doc).await {
@@ -85,6 +84,9 @@ pub async fn create_update_document(db: Data<DatabaseRepository>, doc: Json<Docu
 
 #[delete("{field_id}")]
 pub async fn delete_document(db: Data<DatabaseRepository>, path: Path<String>, auth: AuthorizationService) -> HttpResponse {
+    if let Err(res) = require_scope(&auth, Scope::DocumentsWrite) {
+        return res;
+    }
     let id = auth.id;
     if id.is_empty() {
         return HttpResponse::BadRequest().body("Invalid id");
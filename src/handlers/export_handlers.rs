@@ -0,0 +1,399 @@
+use actix_web::{get, http::header, web::Data, HttpResponse};
+use std::env;
+use std::fmt::Write;
+
+use crate::auth::user_auth::SessionAuthorizationService;
+use crate::handlers::types::{AccountExport, ApiTokenInfo, ErrorResponse, ExportStatus, ExportedAccount, PasskeyInfo};
+use crate::models::document::Rating;
+use crate::models::profile::{date::YearMonth, experience::ExperienceType};
+use crate::models::user::{preferences::Preferences, User};
+use crate::repository::{database::DatabaseRepository, redis::RedisRepository};
+use crate::utils::{self, archive::TarBuilder};
+
+/// Exports whose JSON is larger than this are built in the background. Overridden by `EXPORT_SYNC_MAX_BYTES`.
+const EXPORT_SYNC_MAX_BYTES: usize = 1024 * 1024;
+
+/// Number of seconds a built export is kept for download
+const EXPORT_TTL_SECONDS: usize = 24 * 60 * 60;
+
+/// Number of seconds after which a pending export is given up, so a failed build can be retried
+const EXPORT_PENDING_TTL_SECONDS: usize = 10 * 60;
+
+/// Number of seconds clients are asked to wait before asking for a pending export again
+const EXPORT_RETRY_AFTER_SECONDS: usize = 10;
+
+/// API route to download a copy of everything stored about the account: the account with its plan and preferences, profile, documents,
+/// linked identities, passkeys and API tokens. Secrets and hashes are left out.
+///
+/// The archive is a tar file with `export.json` and a human-readable `export.md`.
+/// Large exports are built in the background. The route then returns 202 until the archive is ready,
+/// and the user gets an email once it can be downloaded. A ready archive can be downloaded once.
+///
+/// Background exports are stored in the following key-value format:
+/// ```
+/// export_status:<user_id> -> pending | ready
+/// export:<user_id> -> archive
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// Content-Type: application/x-tar
+/// ```
+///
+/// ### Response body (if the export is being built):
+/// ```
+/// 202 Accepted
+/// {
+///     "status": "pending",
+///     "retry_after": usize
+/// }
+/// ```
+#[get("/export")]
+pub async fn export_account(db: Data<DatabaseRepository>, redis: Data<RedisRepository>, auth: SessionAuthorizationService) -> HttpResponse {
+    let id = auth.0.id;
+
+    match redis.get(&export_status_key(&id)).await {
+        Ok(status) if status == "pending" => return export_pending(),
+        Ok(status) if status == "ready" => match redis.get_bytes(&export_key(&id)).await {
+            Ok(archive) if !archive.is_empty() => {
+                let _ = redis.del(&export_key(&id)).await;
+                let _ = redis.del(&export_status_key(&id)).await;
+                return archive_response(archive);
+            }
+            // The archive expired, so a new one is built
+            Ok(_) => (),
+            Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error exporting account".to_string(), e.to_string())),
+        },
+        Ok(_) => (),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error exporting account".to_string(), e.to_string())),
+    }
+
+    let user = match db.get_user(&id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().json(ErrorResponse::new("Error exporting account".to_string(), "Account not found".to_string())),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error exporting account".to_string(), e.to_string())),
+    };
+    let export = build_export(&id, user);
+    let json = serde_json::to_vec_pretty(&export).expect("Failed to serialize export");
+
+    if json.len() <= export_sync_max_bytes() {
+        return archive_response(build_archive(&export, &json));
+    }
+
+    if let Err(e) = redis.set_ex(&export_status_key(&id), "pending", EXPORT_PENDING_TTL_SECONDS).await {
+        return HttpResponse::InternalServerError().json(ErrorResponse::new("Error exporting account".to_string(), e.to_string()));
+    }
+    let redis = redis.clone();
+    actix_rt::spawn(async move {
+        let archive = build_archive(&export, &json);
+        let stored = async {
+            redis.set_ex_bytes(&export_key(&id), &archive, EXPORT_TTL_SECONDS).await?;
+            redis.set_ex(&export_status_key(&id), "ready", EXPORT_TTL_SECONDS).await
+        };
+        match stored.await {
+            Ok(_) => notify_export_ready(&export.account).await,
+            Err(e) => {
+                log::error!("Error storing export of account {}: {}", id, e);
+                let _ = redis.del(&export_status_key(&id)).await;
+            }
+        }
+    });
+
+    export_pending()
+}
+
+/// Collect everything stored about a user
+fn build_export(id: &str, user: User) -> AccountExport {
+    AccountExport {
+        date_exported: chrono::Utc::now().timestamp(),
+        account: ExportedAccount {
+            id: id.to_owned(),
+            name: user.name,
+            email: user.email,
+            roles: user.roles,
+            plan: user.plan,
+            preferences: user.preferences,
+            two_factor_enabled: user.two_factor.map(|t| t.enabled).unwrap_or(false),
+            date_created: user.date_created,
+            date_updated: user.date_updated,
+            date_disabled: user.date_disabled,
+        },
+        profile: user.profile,
+        documents: user.documents.unwrap_or_default(),
+        identities: user.identities,
+        passkeys: user.passkeys.into_iter().map(PasskeyInfo::from).collect(),
+        api_tokens: user.api_tokens.into_iter().map(ApiTokenInfo::from).collect(),
+    }
+}
+
+fn build_archive(export: &AccountExport, json: &[u8]) -> Vec<u8> {
+    let mut tar = TarBuilder::new();
+    tar.append("export.json", json, export.date_exported);
+    tar.append("export.md", render_markdown(export).as_bytes(), export.date_exported);
+    tar.finish()
+}
+
+/// Render an export as a Markdown document meant to be read by the user
+fn render_markdown(export: &AccountExport) -> String {
+    let mut md = String::new();
+    let account = &export.account;
+
+    let _ = writeln!(md, "# Scrippt data export\n");
+    let _ = writeln!(
+        md,
+        "Everything Scrippt stores about {}, exported on {}.\n",
+        account.email,
+        format_date(Some(export.date_exported))
+    );
+
+    let _ = writeln!(md, "## Account\n");
+    let _ = writeln!(md, "- **Name:** {}", account.name);
+    let _ = writeln!(md, "- **Email:** {}", account.email);
+    let _ = writeln!(md, "- **Plan:** {}", account.plan.as_str());
+    let _ = writeln!(md, "- **Created:** {}", format_date(account.date_created));
+    let _ = writeln!(md, "- **Last updated:** {}", format_date(account.date_updated));
+    let _ = writeln!(
+        md,
+        "- **Two-factor authentication:** {}",
+        if account.two_factor_enabled { "enabled" } else { "disabled" }
+    );
+    if account.date_disabled.is_some() {
+        let _ = writeln!(md, "- **Disabled:** {}", format_date(account.date_disabled));
+    }
+    let _ = writeln!(md);
+
+    let _ = writeln!(md, "## Preferences\n");
+    let preferences = &account.preferences;
+    if preferences == &Preferences::default() {
+        let _ = writeln!(md, "No preferences.");
+    }
+    if let Some(tone) = preferences.tone {
+        let _ = writeln!(md, "- **Tone:** {}", tone.as_str());
+    }
+    if let Some(length) = preferences.target_length {
+        let _ = writeln!(md, "- **Target length:** {} words", length);
+    }
+    if let Some(language) = &preferences.language {
+        let _ = writeln!(md, "- **Language:** {}", language);
+    }
+    if let Some(first_person) = preferences.first_person {
+        let _ = writeln!(md, "- **Person:** {}", if first_person { "first" } else { "third" });
+    }
+    if let Some(model) = &preferences.model {
+        let _ = writeln!(md, "- **Model:** {}", model);
+    }
+    let _ = writeln!(md);
+
+    let _ = writeln!(md, "## Profile\n");
+    match &export.profile {
+        Some(profile) => {
+            let _ = writeln!(md, "### Education\n");
+            for education in &profile.education {
+                let _ = writeln!(md, "#### {}, {}\n", education.degree, education.school);
+                if !education.field_of_study.is_empty() {
+                    let _ = writeln!(md, "*{}*\n", education.field_of_study);
+                }
+                write_period(&mut md, education.start_date, education.end_date, education.current, "Currently enrolled");
+                write_place(&mut md, education.location.as_deref(), education.url.as_deref());
+                if !education.description.is_empty() {
+                    let _ = writeln!(md, "{}\n", education.description);
+                }
+                write_highlights(&mut md, &education.highlights);
+            }
+
+            let _ = writeln!(md, "### Experience\n");
+            for experience in &profile.experience {
+                let kind = match experience.type_ {
+                    ExperienceType::Work => "work",
+                    ExperienceType::Volunteer => "volunteer",
+                    ExperienceType::Personal => "personal",
+                    ExperienceType::Other => "other",
+                };
+                let _ = writeln!(md, "#### {} at {} ({})\n", experience.name, experience.at, kind);
+                write_period(
+                    &mut md,
+                    experience.start_date,
+                    experience.end_date,
+                    experience.current,
+                    "Current position",
+                );
+                write_place(&mut md, experience.location.as_deref(), experience.url.as_deref());
+                if !experience.description.is_empty() {
+                    let _ = writeln!(md, "{}\n", experience.description);
+                }
+                write_highlights(&mut md, &experience.highlights);
+            }
+
+            let _ = writeln!(md, "### Skills\n");
+            for skill in &profile.skills {
+                let _ = writeln!(md, "- {}", skill.skill);
+            }
+            let _ = writeln!(md);
+        }
+        None => {
+            let _ = writeln!(md, "No profile.\n");
+        }
+    }
+
+    let _ = writeln!(md, "## Documents\n");
+    if export.documents.is_empty() {
+        let _ = writeln!(md, "No documents.\n");
+    }
+    for document in &export.documents {
+        let rating = match document.rating {
+            Rating::None => "not rated",
+            Rating::Good => "good",
+            Rating::Bad => "bad",
+        };
+        let _ = writeln!(md, "### {}\n", document.title);
+        let _ = writeln!(
+            md,
+            "- **Created:** {}\n- **Last updated:** {}\n- **Rating:** {}\n",
+            format_date(document.date_created),
+            format_date(document.date_updated),
+            rating
+        );
+        let _ = writeln!(md, "**Prompt:**\n");
+        for line in document.prompt.lines() {
+            let _ = writeln!(md, "> {}", line);
+        }
+        let _ = writeln!(md, "\n**Content:**\n\n{}\n", document.content);
+    }
+
+    let _ = writeln!(md, "## Linked accounts\n");
+    if export.identities.is_empty() {
+        let _ = writeln!(md, "No linked accounts.");
+    }
+    for identity in &export.identities {
+        let _ = writeln!(
+            md,
+            "- {} ({}), linked on {}",
+            identity.provider,
+            identity.email,
+            format_date(Some(identity.date_linked))
+        );
+    }
+    let _ = writeln!(md);
+
+    let _ = writeln!(md, "## Passkeys\n");
+    if export.passkeys.is_empty() {
+        let _ = writeln!(md, "No passkeys.");
+    }
+    for passkey in &export.passkeys {
+        let _ = writeln!(
+            md,
+            "- {}, registered on {}, last used {}",
+            passkey.name,
+            format_date(Some(passkey.date_created)),
+            format_date(passkey.date_last_used)
+        );
+    }
+    let _ = writeln!(md);
+
+    let _ = writeln!(md, "## API tokens\n");
+    if export.api_tokens.is_empty() {
+        let _ = writeln!(md, "No API tokens.");
+    }
+    for token in &export.api_tokens {
+        let _ = writeln!(
+            md,
+            "- {} ({}...), created on {}, last used {}",
+            token.name,
+            token.prefix,
+            format_date(Some(token.date_created)),
+            format_date(token.date_last_used)
+        );
+    }
+    let _ = writeln!(md);
+
+    let _ = writeln!(md, "## Resume uploads\n");
+    let _ = writeln!(
+        md,
+        "Uploaded resumes are only used to fill in your profile and are not stored, so none are included."
+    );
+
+    md
+}
+
+fn format_date(timestamp: Option<i64>) -> String {
+    timestamp
+        .and_then(|ts| chrono::NaiveDateTime::from_timestamp_opt(ts, 0))
+        .map(|date| date.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_else(|| "never".to_string())
+}
+
+/// Write when an education or experience entry started and ended (e.g. `2021-09 – 2022-05` or `2022-08 – present`)
+fn write_period(md: &mut String, start_date: Option<YearMonth>, end_date: Option<YearMonth>, current: bool, current_label: &str) {
+    let end = match end_date {
+        Some(end_date) => end_date.to_string(),
+        None if current => "present".to_string(),
+        None => String::new(),
+    };
+    let period = match start_date {
+        Some(start_date) if end.is_empty() => format!("Started {}", start_date),
+        Some(start_date) => format!("{} – {}", start_date, end),
+        None if end_date.is_some() => format!("Until {}", end),
+        None if current => current_label.to_string(),
+        None => return,
+    };
+    let _ = writeln!(md, "{}\n", period);
+}
+
+/// Write where an education or experience entry took place, with its link
+fn write_place(md: &mut String, location: Option<&str>, url: Option<&str>) {
+    let place: Vec<&str> = [location, url].into_iter().flatten().filter(|part| !part.is_empty()).collect();
+    if !place.is_empty() {
+        let _ = writeln!(md, "{}\n", place.join(" · "));
+    }
+}
+
+fn write_highlights(md: &mut String, highlights: &[String]) {
+    for highlight in highlights {
+        let _ = writeln!(md, "- {}", highlight);
+    }
+    if !highlights.is_empty() {
+        let _ = writeln!(md);
+    }
+}
+
+fn archive_response(archive: Vec<u8>) -> HttpResponse {
+    let filename = format!("scrippt-export-{}.tar", chrono::Utc::now().format("%Y-%m-%d"));
+    HttpResponse::Ok()
+        .content_type("application/x-tar")
+        .insert_header((header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)))
+        .body(archive)
+}
+
+fn export_pending() -> HttpResponse {
+    HttpResponse::Accepted()
+        .insert_header((header::RETRY_AFTER, EXPORT_RETRY_AFTER_SECONDS.to_string()))
+        .json(ExportStatus {
+            status: "pending".to_string(),
+            retry_after: EXPORT_RETRY_AFTER_SECONDS,
+        })
+}
+
+/// Email the user that their export can be downloaded
+async fn notify_export_ready(account: &ExportedAccount) {
+    // Skip in test environment
+    if env::var("ENV").unwrap() == "test" {
+        return;
+    }
+
+    if let Err(e) = utils::sendgrid::send_export_ready(&account.email, &account.name, EXPORT_TTL_SECONDS / 3600).await {
+        log::error!("Error sending export ready email: {}", e);
+    }
+}
+
+fn export_sync_max_bytes() -> usize {
+    env::var("EXPORT_SYNC_MAX_BYTES").ok().and_then(|value| value.parse().ok()).unwrap_or(EXPORT_SYNC_MAX_BYTES)
+}
+
+pub(crate) fn export_status_key(id: &str) -> String {
+    format!("export_status:{}", id)
+}
+
+pub(crate) fn export_key(id: &str) -> String {
+    format!("export:{}", id)
+}
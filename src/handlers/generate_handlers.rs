@@ -9,9 +9,17 @@ use orca::prompt::prompt::PromptEngine;
 use orca::prompts;
 
 use crate::auth::user_auth::AuthorizationService;
+use crate::handlers::api_token_handlers::require_scope;
+use crate::handlers::plan_handlers::{release_quota, use_quota};
 use crate::handlers::types::ErrorResponse;
 use crate::models::profile::{education::Education, experience::Experience, skills::Skills, Profile};
+use crate::models::user::{
+    api_token::Scope,
+    plan::{Plan, Resource},
+    preferences::Preferences,
+};
 use crate::prompts::RESPONSE;
+use crate::repository::{database::DatabaseRepository, redis::RedisRepository};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Highlights {
@@ -19,6 +27,10 @@ pub struct Highlights {
     pub profile: Profile,
     pub additional: String,
     pub job_url: String,
+
+    /// Preferences for this request only. Set fields take precedence over the saved preferences.
+    #[serde(default)]
+    pub preferences: Preferences,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -38,11 +50,38 @@ struct PromptData {
     education: Vec<Education>,
     skills: Vec<Skills>,
     additional: String,
+    instructions: String,
     prompt: String,
 }
 
+/// API route to generate a response to a prompt from the highlights of a profile.
+/// The saved preferences of the user are applied, overridden by those sent with the request.
+/// Counts against the daily generations of the user's plan, unless the generation fails.
 #[post("/response")]
-pub async fn generate_openai(client: Data<OpenAIClient>, data: Json<Highlights>, _auth: AuthorizationService) -> HttpResponse {
+pub async fn generate_openai(
+    client: Data<OpenAIClient>,
+    db: Data<DatabaseRepository>,
+    redis: Data<RedisRepository>,
+    data: Json<Highlights>,
+    auth: AuthorizationService,
+) -> HttpResponse {
+    if let Err(res) = require_scope(&auth, Scope::Generate) {
+        return res;
+    }
+    if let Err(e) = data.preferences.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse::new("Error generating response.".to_string(), e));
+    }
+    let (saved, plan) = match db.get_user(&auth.id).await {
+        Ok(Some(user)) => (user.preferences, user.plan),
+        Ok(None) => (Preferences::default(), Plan::default()),
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(ErrorResponse::new("Error generating response.".to_string(), e.to_string()));
+        }
+    };
+    if let Err(res) = use_quota(&redis, &auth.id, plan, Resource::Generations).await {
+        return res;
+    }
+    let preferences = saved.merge(data.preferences.clone());
     let prompt = *RESPONSE;
 
     let prompt_data = PromptData {
@@ -50,10 +89,19 @@ pub async fn generate_openai(client: Data<OpenAIClient>, data: Json<Highlights>,
         education: data.profile.education.to_owned(),
         skills: data.profile.skills.to_owned(),
         additional: data.additional.to_owned(),
+        instructions: preferences.instructions(),
         prompt: data.prompt.to_owned(),
     };
 
-    let mut chain = LLMChain::new(client.get_ref()).with_prompt(prompts!(("system", prompt)));
+    let with_model;
+    let client = match &preferences.model {
+        Some(model) => {
+            with_model = client.get_ref().clone().with_model(model);
+            &with_model
+        }
+        None => client.get_ref(),
+    };
+    let mut chain = LLMChain::new(client).with_prompt(prompts!(("system", prompt)));
     chain.load_context(&prompt_data);
     let response = chain.execute().await;
 
@@ -61,6 +109,7 @@ pub async fn generate_openai(client: Data<OpenAIClient>, data: Json<Highlights>,
         Ok(response) => HttpResponse::Ok().json(GenerateResponse::new(response.content())),
         Err(e) => {
             log::error!("Error: {:#?}", e);
+            release_quota(&redis, &auth.id, Resource::Generations).await;
             HttpResponse::BadRequest().json(ErrorResponse::new("".to_string(), "Error generating response.".to_string()))
         }
     }
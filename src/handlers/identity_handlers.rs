@@ -0,0 +1,139 @@
+use actix_web::{
+    delete, get, post,
+    web::{Data, Path, Query},
+    HttpResponse,
+};
+
+use crate::auth::{jwks::JwksCache, oidc::OidcProviders, user_auth::SessionAuthorizationService};
+use crate::handlers::types::{ErrorResponse, ExternalAccountQuery, MessageResponse};
+use crate::models::user::{identity::LinkedIdentity, User};
+use crate::repository::database::DatabaseRepository;
+
+/// API route to list the external identities linked to the account.
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// [
+///     {
+///         "provider": String,
+///         "subject": String,
+///         "email": String,
+///         "date_linked": i64
+///     }
+/// ]
+/// ```
+#[get("/identities")]
+pub async fn list_identities(db: Data<DatabaseRepository>, auth: SessionAuthorizationService) -> HttpResponse {
+    match get_user(&db, &auth.0.id).await {
+        Ok(user) => HttpResponse::Ok().json(user.identities),
+        Err(res) => res,
+    }
+}
+
+/// API route to link an external identity to the account, so the user can log in with it.
+/// The email of the identity does not need to match the email of the account.
+///
+/// ### Query parameters:
+/// ```
+/// token_id: String
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 201 Created
+/// LinkedIdentity
+/// ```
+#[post("/identities/{provider}")]
+pub async fn link_identity(
+    db: Data<DatabaseRepository>,
+    providers: Data<OidcProviders>,
+    jwks: Data<JwksCache>,
+    provider: Path<String>,
+    query: Query<ExternalAccountQuery>,
+    auth: SessionAuthorizationService,
+) -> HttpResponse {
+    let provider = match providers.get(&provider) {
+        Some(provider) => provider,
+        None => {
+            return HttpResponse::NotFound().json(ErrorResponse::new(
+                "Error linking account".to_string(),
+                format!("Unknown provider {}", provider),
+            ))
+        }
+    };
+
+    let identity = match provider.verify(&query.token_id, &jwks).await {
+        Ok(identity) => identity,
+        Err(e) => {
+            log::error!("Failed to verify {} token. Error: {}", provider.name, e);
+            return HttpResponse::BadRequest().json(ErrorResponse::new("Error linking account".to_string(), e.to_string()));
+        }
+    };
+
+    match db.get_user_by_identity(&identity.provider, &identity.subject).await {
+        Ok(Some(user)) => {
+            let error = if user.id.map(|id| id.to_hex()).as_deref() == Some(auth.0.id.as_str()) {
+                "This identity is already linked to your account"
+            } else {
+                "This identity is linked to another account"
+            };
+            return HttpResponse::Conflict().json(ErrorResponse::new("Error linking account".to_string(), error.to_string()));
+        }
+        Ok(None) => (),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error linking account".to_string(), e.to_string())),
+    }
+
+    let linked = LinkedIdentity {
+        provider: identity.provider,
+        subject: identity.subject,
+        email: identity.email.to_lowercase(),
+        date_linked: chrono::Utc::now().timestamp(),
+    };
+    match db.add_identity(&auth.0.id, linked.clone()).await {
+        Ok(_) => HttpResponse::Created().json(linked),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error linking account".to_string(), e.to_string())),
+    }
+}
+
+/// API route to unlink an external identity from the account.
+/// The last way to log in to the account cannot be unlinked.
+#[delete("/identities/{provider}/{subject}")]
+pub async fn unlink_identity(db: Data<DatabaseRepository>, path: Path<(String, String)>, auth: SessionAuthorizationService) -> HttpResponse {
+    let (provider, subject) = path.into_inner();
+    let user = match get_user(&db, &auth.0.id).await {
+        Ok(user) => user,
+        Err(res) => return res,
+    };
+
+    if !user.identities.iter().any(|i| i.matches(&provider, &subject)) {
+        return HttpResponse::NotFound().json(ErrorResponse::new(
+            "Error unlinking account".to_string(),
+            "Identity not found".to_string(),
+        ));
+    }
+    if login_methods(&user) <= 1 {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Error unlinking account".to_string(),
+            "This is the only way to log in to your account. Set a password or link another account first.".to_string(),
+        ));
+    }
+
+    match db.remove_identity(&auth.0.id, &provider, &subject).await {
+        Ok(_) => HttpResponse::Ok().json(MessageResponse::new("Identity unlinked".to_string())),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error unlinking account".to_string(), e.to_string())),
+    }
+}
+
+/// Number of ways the user can log in: a password, every linked identity and every passkey
+pub(crate) fn login_methods(user: &User) -> usize {
+    user.password.is_some() as usize + user.identities.len() + user.external_id.is_some() as usize + user.passkeys.len()
+}
+
+async fn get_user(db: &DatabaseRepository, id: &str) -> Result<User, HttpResponse> {
+    match db.get_user(id).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(HttpResponse::NotFound().json(ErrorResponse::new("Error getting account".to_string(), "Account not found".to_string()))),
+        Err(e) => Err(HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting account".to_string(), e.to_string()))),
+    }
+}
@@ -0,0 +1,213 @@
+use actix_web::{
+    get, post,
+    web::{Data, Json},
+    HttpResponse,
+};
+use rand::Rng;
+use std::env;
+
+use crate::auth::user_auth::SessionAuthorizationService;
+use crate::handlers::types::{ErrorResponse, MessageResponse, ReferralRequest, WaitlistSignup};
+use crate::models::invite::{Invite, InviteKind};
+use crate::models::waitlist::WaitlistEntry;
+use crate::repository::database::DatabaseRepository;
+use crate::utils;
+
+/// Characters invite codes are made of. Leaves out characters that are easy to mix up (0/O, 1/I).
+const INVITE_CODE_CHARSET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+/// Length of an invite code
+const INVITE_CODE_LENGTH: usize = 10;
+
+/// Number of accounts that can be created with a referral invite
+const REFERRAL_INVITE_USES: u32 = 3;
+
+/// Number of days a referral invite can be redeemed for
+const REFERRAL_INVITE_DAYS: i64 = 30;
+
+/// Maximum number of referral invites a user can create
+const MAX_REFERRAL_INVITES: usize = 5;
+
+/// Maximum length of the name of someone on the waitlist
+const MAX_WAITLIST_NAME_LENGTH: usize = 100;
+
+/// API route to join the waitlist. Joining again with the same email keeps the original place in line.
+///
+/// ### Request body:
+/// ```
+/// {
+///    "email": String,
+///    "name": String (optional)
+/// }
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// {
+///     "message": "Added to the waitlist"
+/// }
+/// ```
+#[post("/waitlist")]
+pub async fn join_waitlist(db: Data<DatabaseRepository>, data: Json<WaitlistSignup>) -> HttpResponse {
+    let email = data.email.trim().to_lowercase();
+    if let Err(e) = utils::validation::validate_email(&email) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new("Error joining the waitlist".to_string(), e));
+    }
+    let name = data.name.as_deref().map(str::trim).filter(|name| !name.is_empty());
+    if name.map(|name| name.chars().count() > MAX_WAITLIST_NAME_LENGTH).unwrap_or(false) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Error joining the waitlist".to_string(),
+            format!("Name must be at most {} characters", MAX_WAITLIST_NAME_LENGTH),
+        ));
+    }
+
+    let entry = WaitlistEntry {
+        id: None,
+        email,
+        name: name.map(str::to_owned),
+        date_created: chrono::Utc::now().timestamp(),
+        date_invited: None,
+    };
+    match db.add_to_waitlist(entry).await {
+        Ok(_) => HttpResponse::Ok().json(MessageResponse::new("Added to the waitlist".to_string())),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error joining the waitlist".to_string(), e.to_string())),
+    }
+}
+
+/// API route to create an invite to refer someone. Each invite can be redeemed
+/// a few times within 30 days, and each user can create a limited number of them.
+/// If an email is given, the invite is sent to it.
+///
+/// ### Request body:
+/// ```
+/// {
+///    "email": String (optional)
+/// }
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 201 Created
+/// Invite
+/// ```
+#[post("/invites")]
+pub async fn create_referral_invite(db: Data<DatabaseRepository>, data: Json<ReferralRequest>, auth: SessionAuthorizationService) -> HttpResponse {
+    let email = data.email.as_deref().map(|email| email.trim().to_lowercase());
+    if let Some(email) = &email {
+        if let Err(e) = utils::validation::validate_email(email) {
+            return HttpResponse::BadRequest().json(ErrorResponse::new("Error creating invite".to_string(), e));
+        }
+    }
+
+    match db.get_invites(Some(&auth.0.id), MAX_REFERRAL_INVITES as i64).await {
+        Ok(invites) if invites.len() >= MAX_REFERRAL_INVITES => {
+            return HttpResponse::Forbidden().json(ErrorResponse::new(
+                "Error creating invite".to_string(),
+                format!("You can create at most {} invites", MAX_REFERRAL_INVITES),
+            ))
+        }
+        Ok(_) => (),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error creating invite".to_string(), e.to_string())),
+    }
+
+    let date_expires = chrono::Utc::now().timestamp() + REFERRAL_INVITE_DAYS * 24 * 60 * 60;
+    let invite = new_invite(InviteKind::Referral, &auth.0.id, email, REFERRAL_INVITE_USES, Some(date_expires));
+    match db.add_invite(invite.clone()).await {
+        Ok(_) => {
+            notify_invite(&invite).await;
+            HttpResponse::Created().json(invite)
+        }
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error creating invite".to_string(), e.to_string())),
+    }
+}
+
+/// API route to list the referral invites of the user.
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// [Invite]
+/// ```
+#[get("/invites")]
+pub async fn list_referral_invites(db: Data<DatabaseRepository>, auth: SessionAuthorizationService) -> HttpResponse {
+    match db.get_invites(Some(&auth.0.id), MAX_REFERRAL_INVITES as i64).await {
+        Ok(invites) => HttpResponse::Ok().json(invites),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting invites".to_string(), e.to_string())),
+    }
+}
+
+/// Create an invite with a new random code
+pub(crate) fn new_invite(kind: InviteKind, created_by: &str, email: Option<String>, max_uses: u32, date_expires: Option<i64>) -> Invite {
+    let mut rng = rand::thread_rng();
+    let code: String = (0..INVITE_CODE_LENGTH).map(|_| INVITE_CODE_CHARSET[rng.gen_range(0..INVITE_CODE_CHARSET.len())] as char).collect();
+    Invite {
+        id: None,
+        code,
+        kind,
+        created_by: created_by.to_owned(),
+        email,
+        max_uses,
+        uses: 0,
+        date_created: chrono::Utc::now().timestamp(),
+        date_expires,
+    }
+}
+
+/// Check the invite code of a new account and use it up.
+/// Returns the redeemed code, or `None` if no code was given and signups are open.
+///
+/// Signups require an invite when `INVITE_ONLY` is `true`.
+pub(crate) async fn redeem_invite(db: &DatabaseRepository, code: Option<&str>) -> Result<Option<String>, HttpResponse> {
+    let code = match code.map(|code| code.trim().to_uppercase()).filter(|code| !code.is_empty()) {
+        Some(code) => code,
+        None if invite_only() => {
+            return Err(HttpResponse::Forbidden().json(ErrorResponse::new(
+                "Error creating account".to_string(),
+                "An invite code is required to sign up. Join the waitlist to get one.".to_string(),
+            )))
+        }
+        None => return Ok(None),
+    };
+
+    match db.redeem_invite(&code).await {
+        Ok(Some(_)) => Ok(Some(code)),
+        Ok(None) => Err(HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Error creating account".to_string(),
+            "Invite code is invalid, expired or used up".to_string(),
+        ))),
+        Err(e) => Err(HttpResponse::InternalServerError().json(ErrorResponse::new("Error creating account".to_string(), e.to_string()))),
+    }
+}
+
+/// Give back the use of an invite redeemed by [`redeem_invite`], when the account could not be created
+pub(crate) async fn release_invite(db: &DatabaseRepository, code: Option<&str>) {
+    if let Some(code) = code {
+        if let Err(e) = db.release_invite(code).await {
+            log::error!("Error releasing invite {}: {}", code, e);
+        }
+    }
+}
+
+/// Email an invite to the address it was created for, if any
+pub(crate) async fn notify_invite(invite: &Invite) {
+    let email = match &invite.email {
+        Some(email) => email,
+        None => return,
+    };
+
+    // Skip in test environment
+    if env::var("ENV").unwrap() == "test" {
+        return;
+    }
+
+    let base_url = env::var("SIGNUP_URL").unwrap_or_else(|_| format!("https://{}/signup", env::var("DOMAIN").unwrap()));
+    let link = format!("{}?invite={}", base_url, invite.code);
+    if let Err(e) = utils::sendgrid::send_invite(email, &invite.code, &link).await {
+        log::error!("Error sending invite email: {}", e);
+    }
+}
+
+fn invite_only() -> bool {
+    env::var("INVITE_ONLY").map(|value| value == "true" || value == "1").unwrap_or(false)
+}
@@ -1,5 +1,18 @@
 pub mod account_handlers;
+pub mod admin_handlers;
+pub mod api_token_handlers;
+pub mod billing_handlers;
+pub mod contact_handlers;
 pub mod document_handlers;
+pub mod export_handlers;
 pub mod generate_handlers;
+pub mod identity_handlers;
+pub mod invite_handlers;
+pub mod passkey_handlers;
+pub mod plan_handlers;
+pub mod preferences_handlers;
 pub mod profile_handlers;
+pub mod session_handlers;
+pub mod two_factor_handlers;
 pub mod types;
+pub mod well_known_handlers;
@@ -0,0 +1,400 @@
+use actix_web::{
+    delete, get, post,
+    web::{Data, Json, Path},
+    HttpRequest, HttpResponse,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use redis::RedisError;
+
+use crate::auth::{
+    sessions::ClientInfo,
+    tokens,
+    user_auth::SessionAuthorizationService,
+    webauthn::{self, RelyingParty},
+};
+use crate::handlers::types::{ErrorResponse, MessageResponse, PasskeyInfo, PasskeyLogin, PasskeyRegistration};
+use crate::models::user::{passkey::Passkey, User};
+use crate::repository::{database::DatabaseRepository, redis::RedisRepository};
+
+use super::account_handlers::{account_deleted, account_disabled};
+use super::identity_handlers::login_methods;
+use super::two_factor_handlers;
+
+/// Maximum number of passkeys per account
+const MAX_PASSKEYS: usize = 20;
+
+/// Maximum length of the name of a passkey
+const MAX_PASSKEY_NAME_LENGTH: usize = 64;
+
+/// API route to start registering a passkey for the account.
+/// The options are passed to `navigator.credentials.create()` after decoding the base64url fields.
+///
+/// The challenge is stored in the following key-value format:
+/// ```
+/// webauthn_registration:<user_id>:<challenge> -> pending
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// {
+///     "challenge": String,
+///     "rp": { "id": String, "name": String },
+///     "user": { "id": String, "name": String, "displayName": String },
+///     "pubKeyCredParams": [{ "type": "public-key", "alg": i64 }],
+///     "timeout": usize,
+///     "attestation": "none",
+///     "excludeCredentials": [{ "type": "public-key", "id": String }],
+///     "authenticatorSelection": { "residentKey": "required", "requireResidentKey": true, "userVerification": "preferred" }
+/// }
+/// ```
+#[post("/passkeys/register/options")]
+pub async fn passkey_registration_options(
+    db: Data<DatabaseRepository>,
+    redis: Data<RedisRepository>,
+    auth: SessionAuthorizationService,
+) -> HttpResponse {
+    let user = match get_user(&db, &auth.0.id).await {
+        Ok(user) => user,
+        Err(res) => return res,
+    };
+
+    let challenge = webauthn::generate_challenge();
+    if let Err(e) = redis.set_ex(&registration_key(&auth.0.id, &challenge), "pending", webauthn::CHALLENGE_TTL_SECONDS).await {
+        return HttpResponse::InternalServerError().json(ErrorResponse::new("Error registering passkey".to_string(), e.to_string()));
+    }
+
+    HttpResponse::Ok().json(webauthn::creation_options(&RelyingParty::from_env(), &auth.0.id, &user, &challenge))
+}
+
+/// API route to finish registering a passkey with the response of the authenticator.
+///
+/// ### Request body:
+/// ```
+/// {
+///    "name": String (optional),
+///    "credential": {
+///        "id": String,
+///        "response": {
+///            "clientDataJSON": String,
+///            "attestationObject": String
+///        }
+///    }
+/// }
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 201 Created
+/// {
+///     "id": String,
+///     "name": String,
+///     "date_created": i64,
+///     "date_last_used": null
+/// }
+/// ```
+#[post("/passkeys/register")]
+pub async fn register_passkey(
+    db: Data<DatabaseRepository>,
+    redis: Data<RedisRepository>,
+    req: Json<PasskeyRegistration>,
+    auth: SessionAuthorizationService,
+) -> HttpResponse {
+    let name = req.name.as_deref().map(str::trim).unwrap_or("Passkey");
+    if name.is_empty() || name.len() > MAX_PASSKEY_NAME_LENGTH {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Error registering passkey".to_string(),
+            format!("Name must be between 1 and {} characters", MAX_PASSKEY_NAME_LENGTH),
+        ));
+    }
+
+    let user = match get_user(&db, &auth.0.id).await {
+        Ok(user) => user,
+        Err(res) => return res,
+    };
+    if user.passkeys.len() >= MAX_PASSKEYS {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Error registering passkey".to_string(),
+            format!("You can register at most {} passkeys", MAX_PASSKEYS),
+        ));
+    }
+
+    let response = &req.credential.response;
+    let (client_data_json, attestation_object) = match (
+        webauthn::decode_base64url(&response.client_data_json),
+        webauthn::decode_base64url(&response.attestation_object),
+    ) {
+        (Ok(client_data_json), Ok(attestation_object)) => (client_data_json, attestation_object),
+        (Err(e), _) | (_, Err(e)) => {
+            return HttpResponse::BadRequest().json(ErrorResponse::new("Error registering passkey".to_string(), e.to_string()))
+        }
+    };
+    let challenge = match webauthn::parse_client_data(&client_data_json) {
+        Ok(client_data) => client_data.challenge,
+        Err(e) => return HttpResponse::BadRequest().json(ErrorResponse::new("Error registering passkey".to_string(), e.to_string())),
+    };
+    match take_challenge(&redis, &registration_key(&auth.0.id, &challenge)).await {
+        Ok(true) => (),
+        Ok(false) => return invalid_challenge("Error registering passkey"),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error registering passkey".to_string(), e.to_string())),
+    }
+
+    let credential = match webauthn::verify_registration(&RelyingParty::from_env(), &challenge, &client_data_json, &attestation_object) {
+        Ok(credential) => credential,
+        Err(e) => return HttpResponse::BadRequest().json(ErrorResponse::new("Error registering passkey".to_string(), e.to_string())),
+    };
+
+    match db.get_user_by_passkey(&credential.id).await {
+        Ok(None) => (),
+        Ok(Some(_)) => {
+            return HttpResponse::Conflict().json(ErrorResponse::new(
+                "Error registering passkey".to_string(),
+                "This passkey is already registered".to_string(),
+            ))
+        }
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error registering passkey".to_string(), e.to_string())),
+    }
+
+    let passkey = Passkey {
+        id: credential.id,
+        name: name.to_owned(),
+        public_key: credential.public_key,
+        algorithm: credential.algorithm,
+        sign_count: credential.sign_count,
+        date_created: chrono::Utc::now().timestamp(),
+        date_last_used: None,
+    };
+    match db.add_passkey(&auth.0.id, passkey.clone()).await {
+        Ok(_) => HttpResponse::Created().json(PasskeyInfo::from(passkey)),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error registering passkey".to_string(), e.to_string())),
+    }
+}
+
+/// API route to list the passkeys registered for the account.
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// [
+///     {
+///         "id": String,
+///         "name": String,
+///         "date_created": i64,
+///         "date_last_used": i64 | null
+///     }
+/// ]
+/// ```
+#[get("/passkeys")]
+pub async fn list_passkeys(db: Data<DatabaseRepository>, auth: SessionAuthorizationService) -> HttpResponse {
+    match get_user(&db, &auth.0.id).await {
+        Ok(user) => HttpResponse::Ok().json(user.passkeys.into_iter().map(PasskeyInfo::from).collect::<Vec<_>>()),
+        Err(res) => res,
+    }
+}
+
+/// API route to remove a passkey. The last way to log in to the account cannot be removed.
+#[delete("/passkeys/{credential_id}")]
+pub async fn delete_passkey(db: Data<DatabaseRepository>, credential_id: Path<String>, auth: SessionAuthorizationService) -> HttpResponse {
+    let user = match get_user(&db, &auth.0.id).await {
+        Ok(user) => user,
+        Err(res) => return res,
+    };
+
+    if !user.passkeys.iter().any(|p| p.id == *credential_id) {
+        return HttpResponse::NotFound().json(ErrorResponse::new("Error removing passkey".to_string(), "Passkey not found".to_string()));
+    }
+    if login_methods(&user) <= 1 {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Error removing passkey".to_string(),
+            "This is the only way to log in to your account. Set a password or link another account first.".to_string(),
+        ));
+    }
+
+    match db.remove_passkey(&auth.0.id, &credential_id).await {
+        Ok(_) => HttpResponse::Ok().json(MessageResponse::new("Passkey removed".to_string())),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error removing passkey".to_string(), e.to_string())),
+    }
+}
+
+/// API route to start logging in with a passkey.
+/// The options are passed to `navigator.credentials.get()` after decoding the base64url fields.
+///
+/// The challenge is stored in the following key-value format:
+/// ```
+/// webauthn_login:<challenge> -> pending
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// {
+///     "challenge": String,
+///     "rpId": String,
+///     "timeout": usize,
+///     "userVerification": "preferred",
+///     "allowCredentials": []
+/// }
+/// ```
+#[post("/auth/passkey/options")]
+pub async fn passkey_login_options(redis: Data<RedisRepository>) -> HttpResponse {
+    let challenge = webauthn::generate_challenge();
+    if let Err(e) = redis.set_ex(&login_key(&challenge), "pending", webauthn::CHALLENGE_TTL_SECONDS).await {
+        return HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging in".to_string(), e.to_string()));
+    }
+
+    HttpResponse::Ok().json(webauthn::request_options(&RelyingParty::from_env(), &challenge))
+}
+
+/// API route to log in with a passkey. Returns the same tokens as logging in with a password.
+/// Accounts with 2FA get a challenge token instead, unless the authenticator verified the user.
+///
+/// ### Request body:
+/// ```
+/// {
+///    "id": String,
+///    "response": {
+///        "clientDataJSON": String,
+///        "authenticatorData": String,
+///        "signature": String,
+///        "userHandle": String (optional)
+///    }
+/// }
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// {
+///     "id": String,
+///     "token": String,
+///     "refresh_token": String
+/// }
+/// ```
+///
+/// ### Response body (if 2FA is enabled):
+/// ```
+/// 202 Accepted
+/// {
+///     "two_factor_required": true,
+///     "challenge_token": String
+/// }
+/// ```
+#[post("/auth/passkey")]
+pub async fn login_passkey(
+    db: Data<DatabaseRepository>,
+    redis: Data<RedisRepository>,
+    req: Json<PasskeyLogin>,
+    http_req: HttpRequest,
+) -> HttpResponse {
+    let response = &req.response;
+    let decoded = (
+        webauthn::decode_base64url(&req.id),
+        webauthn::decode_base64url(&response.client_data_json),
+        webauthn::decode_base64url(&response.authenticator_data),
+        webauthn::decode_base64url(&response.signature),
+    );
+    let (credential_id, client_data_json, authenticator_data, signature) = match decoded {
+        (Ok(id), Ok(client_data_json), Ok(authenticator_data), Ok(signature)) => {
+            (URL_SAFE_NO_PAD.encode(id), client_data_json, authenticator_data, signature)
+        }
+        (Err(e), ..) | (_, Err(e), ..) | (_, _, Err(e), _) | (.., Err(e)) => {
+            return HttpResponse::BadRequest().json(ErrorResponse::new("Error logging in".to_string(), e.to_string()))
+        }
+    };
+    let challenge = match webauthn::parse_client_data(&client_data_json) {
+        Ok(client_data) => client_data.challenge,
+        Err(e) => return HttpResponse::BadRequest().json(ErrorResponse::new("Error logging in".to_string(), e.to_string())),
+    };
+    match take_challenge(&redis, &login_key(&challenge)).await {
+        Ok(true) => (),
+        Ok(false) => return invalid_challenge("Error logging in"),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging in".to_string(), e.to_string())),
+    }
+
+    let account = match db.get_user_by_passkey(&credential_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return invalid_passkey(),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging in".to_string(), e.to_string())),
+    };
+    let id = account.id.unwrap().to_hex();
+
+    // The user handle is the one given on registration, so it has to point at the same account
+    if let Some(user_handle) = &response.user_handle {
+        if webauthn::decode_base64url(user_handle).ok().as_deref() != Some(id.as_bytes()) {
+            return invalid_passkey();
+        }
+    }
+
+    let passkey = account.passkeys.iter().find(|p| p.id == credential_id).unwrap();
+    let verified = match webauthn::verify_assertion(
+        &RelyingParty::from_env(),
+        &challenge,
+        passkey,
+        &client_data_json,
+        &authenticator_data,
+        &signature,
+    ) {
+        Ok(verified) => verified,
+        Err(e) => {
+            log::error!("Failed to verify passkey {} of account {}: {}", credential_id, id, e);
+            return invalid_passkey();
+        }
+    };
+    if let Err(e) = db.touch_passkey(&credential_id, verified.sign_count).await {
+        log::error!("Error updating passkey {}: {}", credential_id, e);
+    }
+
+    if account.date_disabled.is_some() {
+        return account_disabled();
+    }
+    if account.date_deleted.is_some() {
+        return account_deleted();
+    }
+
+    // A passkey verified with a PIN or biometrics already counts as two factors
+    if account.two_factor.as_ref().map(|t| t.enabled).unwrap_or(false) && !verified.user_verified {
+        return match two_factor_handlers::create_login_challenge(&redis, &id).await {
+            Ok(challenge) => HttpResponse::Accepted().json(challenge),
+            Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging in".to_string(), e.to_string())),
+        };
+    }
+
+    match tokens::issue_tokens(&redis, &id, &account.roles, &ClientInfo::from_request(&http_req)).await {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging in".to_string(), e.to_string())),
+    }
+}
+
+/// Mark a challenge as used. Returns true only the first time a pending challenge is taken.
+async fn take_challenge(redis: &RedisRepository, key: &str) -> Result<bool, RedisError> {
+    let previous = redis.getset(key, "used").await?;
+    redis.del(key).await?;
+    Ok(previous.as_deref() == Some("pending"))
+}
+
+fn invalid_challenge(message: &str) -> HttpResponse {
+    HttpResponse::Unauthorized().json(ErrorResponse::new(
+        message.to_string(),
+        "Challenge is invalid, expired or has already been used. Please try again.".to_string(),
+    ))
+}
+
+fn invalid_passkey() -> HttpResponse {
+    HttpResponse::Unauthorized().json(ErrorResponse::new("Error logging in".to_string(), "Invalid passkey".to_string()))
+}
+
+fn registration_key(user_id: &str, challenge: &str) -> String {
+    format!("webauthn_registration:{}:{}", user_id, challenge)
+}
+
+fn login_key(challenge: &str) -> String {
+    format!("webauthn_login:{}", challenge)
+}
+
+async fn get_user(db: &DatabaseRepository, id: &str) -> Result<User, HttpResponse> {
+    match db.get_user(id).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(HttpResponse::NotFound().json(ErrorResponse::new("Error getting account".to_string(), "Account not found".to_string()))),
+        Err(e) => Err(HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting account".to_string(), e.to_string()))),
+    }
+}
@@ -0,0 +1,167 @@
+use actix_web::{get, web::Data, HttpResponse};
+use redis::RedisError;
+
+use crate::auth::user_auth::SessionAuthorizationService;
+use crate::handlers::types::{ErrorResponse, LimitExceeded, PlanInfo, ResourceUsage};
+use crate::models::user::plan::{Plan, Resource};
+use crate::repository::{database::DatabaseRepository, redis::RedisRepository};
+
+/// API route to get the plan of the user and the usage of every limited resource.
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// {
+///     "plan": "free" | "pro",
+///     "usage": [
+///         {
+///             "resource": "documents" | "profile_entries" | "generations" | "resume_parses",
+///             "section": "experience" | "education" | "skills" (profile entries only),
+///             "limit": u64,
+///             "used": u64,
+///             "resets_at": i64 | null
+///         }
+///     ]
+/// }
+/// ```
+#[get("/plan")]
+pub async fn get_plan(db: Data<DatabaseRepository>, redis: Data<RedisRepository>, auth: SessionAuthorizationService) -> HttpResponse {
+    let account = match db.get_account(&auth.0.id).await {
+        Ok(account) => account,
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting plan".to_string(), e.to_string())),
+    };
+    let limits = account.plan.limits();
+    let now = chrono::Utc::now().timestamp();
+
+    let mut usage = vec![ResourceUsage {
+        resource: Resource::Documents,
+        section: None,
+        limit: limits.documents,
+        used: account.documents.len() as u64,
+        resets_at: None,
+    }];
+    for (section, used) in [
+        ("experience", account.profile.experience.len()),
+        ("education", account.profile.education.len()),
+        ("skills", account.profile.skills.len()),
+    ] {
+        usage.push(ResourceUsage {
+            resource: Resource::ProfileEntries,
+            section: Some(section.to_string()),
+            limit: limits.profile_entries,
+            used: used as u64,
+            resets_at: None,
+        });
+    }
+    for resource in [Resource::Generations, Resource::ResumeParses] {
+        let used = match redis.get(&quota_key(&auth.0.id, resource)).await {
+            Ok(used) => used.parse::<u64>().unwrap_or(0),
+            Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting plan".to_string(), e.to_string())),
+        };
+        let limit = limits.get(resource);
+        usage.push(ResourceUsage {
+            resource,
+            section: None,
+            limit,
+            used: used.min(limit),
+            resets_at: resource.period_end(now),
+        });
+    }
+
+    HttpResponse::Ok().json(PlanInfo { plan: account.plan, usage })
+}
+
+/// Check that the plan allows one more of a resource the user currently has `used` of.
+/// Used for documents and profile entries, which are counted from what the account stores.
+pub(crate) fn check_limit(plan: Plan, resource: Resource, section: Option<&str>, used: u64) -> Result<(), HttpResponse> {
+    let limit = plan.limits().get(resource);
+    if used < limit {
+        return Ok(());
+    }
+    Err(limit_exceeded(
+        plan,
+        ResourceUsage {
+            resource,
+            section: section.map(str::to_owned),
+            limit,
+            used,
+            resets_at: None,
+        },
+    ))
+}
+
+/// Count one use of a resource limited per period (generations and resume parses), if the plan allows it.
+/// The use is counted up front so concurrent requests cannot exceed the limit, and must be given back
+/// with [`release_quota`] if the work it was counted for fails.
+///
+/// Usage is counted in Redis until the end of the period, in the following key-value format:
+/// ```
+/// quota:<user_id>:<resource> -> number of uses
+/// ```
+pub(crate) async fn use_quota(redis: &RedisRepository, user_id: &str, plan: Plan, resource: Resource) -> Result<(), HttpResponse> {
+    let now = chrono::Utc::now().timestamp();
+    let resets_at = resource.period_end(now);
+    let key = quota_key(user_id, resource);
+
+    let counted = async {
+        let used = redis.incr(&key).await?;
+        if let Some(resets_at) = resets_at {
+            redis.expire(&key, (resets_at - now).max(1) as usize).await?;
+        }
+        Ok::<i64, RedisError>(used)
+    };
+    let used = match counted.await {
+        Ok(used) => used.max(0) as u64,
+        Err(e) => return Err(HttpResponse::InternalServerError().json(ErrorResponse::new("Error checking usage".to_string(), e.to_string()))),
+    };
+
+    let limit = plan.limits().get(resource);
+    if used <= limit {
+        return Ok(());
+    }
+    release_quota(redis, user_id, resource).await;
+    Err(limit_exceeded(
+        plan,
+        ResourceUsage {
+            resource,
+            section: None,
+            limit,
+            used: limit,
+            resets_at,
+        },
+    ))
+}
+
+/// Give back a use counted by [`use_quota`], when the request it was counted for failed
+pub(crate) async fn release_quota(redis: &RedisRepository, user_id: &str, resource: Resource) {
+    let key = quota_key(user_id, resource);
+    // A key that expired in the meantime was reset with the period, and must not go below zero
+    let released = match redis.exists(&key).await {
+        Ok(true) => redis.decr(&key).await.map(|_| ()),
+        Ok(false) => Ok(()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = released {
+        log::error!("Error releasing {} quota of user {}: {}", resource.as_str(), user_id, e);
+    }
+}
+
+/// Respond with `402 Payment Required` and the usage of the resource, since upgrading lifts the limit
+fn limit_exceeded(plan: Plan, usage: ResourceUsage) -> HttpResponse {
+    let allowance = match usage.resource {
+        Resource::Documents => format!("{} documents", usage.limit),
+        Resource::ProfileEntries => format!("{} entries per profile section", usage.limit),
+        Resource::Generations => format!("{} generated responses per day", usage.limit),
+        Resource::ResumeParses => format!("{} resume uploads per month", usage.limit),
+    };
+    HttpResponse::PaymentRequired().json(LimitExceeded {
+        message: "Plan limit reached".to_string(),
+        error: format!("Your {} plan allows {}. Upgrade your plan to get more.", plan.as_str(), allowance),
+        plan,
+        usage,
+    })
+}
+
+fn quota_key(user_id: &str, resource: Resource) -> String {
+    format!("quota:{}:{}", user_id, resource.as_str())
+}
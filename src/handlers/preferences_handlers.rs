@@ -0,0 +1,67 @@
+use actix_web::{
+    get, put,
+    web::{Data, Json},
+    HttpResponse,
+};
+
+use crate::auth::user_auth::SessionAuthorizationService;
+use crate::handlers::types::ErrorResponse;
+use crate::models::user::preferences::Preferences;
+use crate::repository::database::DatabaseRepository;
+
+/// API route to get the settings applied to every generated response.
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// {
+///     "tone": "professional" | "formal" | "friendly" | "enthusiastic" | "concise" | null,
+///     "target_length": u32 | null,
+///     "language": String | null,
+///     "first_person": bool | null,
+///     "model": String | null
+/// }
+/// ```
+#[get("/preferences")]
+pub async fn get_preferences(db: Data<DatabaseRepository>, auth: SessionAuthorizationService) -> HttpResponse {
+    match db.get_user(&auth.0.id).await {
+        Ok(Some(user)) => HttpResponse::Ok().json(user.preferences),
+        Ok(None) => HttpResponse::NotFound().json(ErrorResponse::new(
+            "Error getting preferences".to_string(),
+            "Account not found".to_string(),
+        )),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting preferences".to_string(), e.to_string())),
+    }
+}
+
+/// API route to replace the settings applied to every generated response.
+/// Fields left out or set to null are cleared.
+///
+/// ### Request body:
+/// ```
+/// {
+///    "tone": "professional" | "formal" | "friendly" | "enthusiastic" | "concise" (optional),
+///    "target_length": u32 (optional, 50 to 1000 words),
+///    "language": String (optional),
+///    "first_person": bool (optional),
+///    "model": "gpt-3.5-turbo" | "gpt-4" (optional)
+/// }
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// Preferences
+/// ```
+#[put("/preferences")]
+pub async fn update_preferences(db: Data<DatabaseRepository>, req: Json<Preferences>, auth: SessionAuthorizationService) -> HttpResponse {
+    let preferences = req.into_inner();
+    if let Err(e) = preferences.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse::new("Error updating preferences".to_string(), e));
+    }
+
+    match db.update_preferences(&auth.0.id, &preferences).await {
+        Ok(_) => HttpResponse::Ok().json(preferences),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error updating preferences".to_string(), e.to_string())),
+    }
+}
@@ -1,7 +1,10 @@
+use crate::handlers::api_token_handlers::require_scope;
+use crate::handlers::plan_handlers::{check_limit, release_quota, use_quota};
 use crate::handlers::types::ErrorResponse;
 use crate::models::profile::ProfileValue;
+use crate::models::user::{api_token::Scope, plan::Resource};
 use crate::prompts::PARSER;
-use crate::repository::database::DatabaseRepository;
+use crate::repository::{database::DatabaseRepository, redis::RedisRepository};
 use crate::{auth::user_auth::AuthorizationService, models::profile::Profile};
 use actix_web::{
     patch, post,
@@ -25,9 +28,6 @@ pub struct ProfilePatch {
     pub value: ProfileValue,
 }
 
-// MAX_PROFILE_FIELD
-const MAX_PROFILE_FIELD: usize = 5;
-
 /// # Change a user profile
 /// Follows RFC 6902
 ///
@@ -40,6 +40,10 @@ const MAX_PROFILE_FIELD: usize = 5;
 ///    "value": <new value>
 /// }
 /// ```
+/// Added and updated education and experience entries are validated: `start_date` and `end_date`
+/// are `YYYY-MM` months, the end date cannot be before the start date, current entries have no
+/// end date, and `url` must be an http(s) link. Invalid entries get a `400 Bad Request`.
+///
 /// ## Response:  (if successful)
 /// ```
 /// 200 OK
@@ -54,6 +58,9 @@ const MAX_PROFILE_FIELD: usize = 5;
 /// ```
 #[patch("")]
 pub async fn change_profile(db: Data<DatabaseRepository>, profile: Json<Vec<ProfilePatch>>, auth: AuthorizationService) -> HttpResponse {
+    if let Err(res) = require_scope(&auth, Scope::ProfileWrite) {
+        return res;
+    }
     let id = auth.id;
     if id.is_empty() {
         log::debug!("Invalid id");
@@ -67,10 +74,15 @@ pub async fn change_profile(db: Data<DatabaseRepository>, profile: Json<Vec<Prof
         let date = chrono::Utc::now().timestamp();
         log::debug!("Target: {:#?}", target);
         log::debug!("Value: {:#?}", value);
+        if order.op != "remove" {
+            if let Err(e) = value.validate() {
+                return HttpResponse::BadRequest().json(ErrorResponse::new("Invalid profile field".to_string(), e));
+            }
+        }
         match order.op.as_str() {
             "add" => {
-                if maxed_profile_field(&db, &id, &target).await.is_err() {
-                    return HttpResponse::BadRequest().body(format!("Max fields for {} reached. Remove to add a new one", target));
+                if let Err(res) = check_profile_limit(&db, &id, &target).await {
+                    return res;
                 }
                 match db.add_profile_field(&id, target, value, date).await {
                     Ok(_result) => continue,
@@ -109,20 +121,78 @@ pub async fn change_profile(db: Data<DatabaseRepository>, profile: Json<Vec<Prof
     }
 }
 
+/// Replace the profile of a user with the one parsed from a PDF resume.
+/// Counts against the monthly resume parses of the user's plan, unless the resume cannot
+/// be parsed. Sections longer than the plan allows are cut to the limit.
 #[post("/resume")]
 pub async fn profile_from_resume(
     client: Data<OpenAIClient>,
     db: Data<DatabaseRepository>,
-    mut payload: Payload,
+    redis: Data<RedisRepository>,
+    payload: Payload,
     auth: AuthorizationService,
 ) -> HttpResponse {
+    if let Err(res) = require_scope(&auth, Scope::ProfileWrite) {
+        return res;
+    }
     let id = auth.id;
+    let plan = match db.get_account(&id).await {
+        Ok(account) => account.plan,
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("error getting account".to_string(), e.to_string())),
+    };
+    if let Err(res) = use_quota(&redis, &id, plan, Resource::ResumeParses).await {
+        return res;
+    }
+    let mut profile = match parse_resume(&client, payload).await {
+        Ok(profile) => profile,
+        Err(res) => {
+            release_quota(&redis, &id, Resource::ResumeParses).await;
+            return res;
+        }
+    };
+
+    let max_entries = plan.limits().profile_entries as usize;
+    profile.experience.truncate(max_entries);
+    profile.education.truncate(max_entries);
+    profile.skills.truncate(max_entries);
+    match db.update_profile(&id, profile).await {
+        Ok(_) => match db.get_account(&id).await {
+            Ok(user) => HttpResponse::Ok().json(user),
+            Err(e) => {
+                log::error!("Error: {:#?}", e);
+                HttpResponse::InternalServerError().json(ErrorResponse::new("error getting account".to_string(), e.to_string()))
+            }
+        },
+        Err(e) => {
+            log::error!("Error: {:#?}", e);
+            release_quota(&redis, &id, Resource::ResumeParses).await;
+            HttpResponse::InternalServerError().json(ErrorResponse::new(
+                "Error parsing resume. Please make sure your resume is formatted correctly and try again.".to_string(),
+                e.to_string(),
+            ))
+        }
+    }
+}
+
+/// Read a PDF resume from the request body and parse it into a profile with the LLM
+async fn parse_resume(client: &OpenAIClient, mut payload: Payload) -> Result<Profile, HttpResponse> {
     let mut bytes = BytesMut::new();
     while let Some(item) = payload.next().await {
-        bytes.extend_from_slice(&item.unwrap());
+        match item {
+            Ok(item) => bytes.extend_from_slice(&item),
+            Err(e) => return Err(HttpResponse::BadRequest().json(ErrorResponse::new("Error reading resume.".to_string(), e.to_string()))),
+        }
     }
 
-    let record = PDF::from_buffer(bytes.to_vec(), false).spin().unwrap();
+    let record = match PDF::from_buffer(bytes.to_vec(), false).spin() {
+        Ok(record) => record,
+        Err(_) => {
+            return Err(HttpResponse::BadRequest().json(ErrorResponse::new(
+                "Please make sure your resume is a valid PDF and try again.".to_string(),
+                "Error reading resume.".to_string(),
+            )))
+        }
+    };
     let prompt = *PARSER;
 
     #[derive(Serialize)]
@@ -132,44 +202,28 @@ pub async fn profile_from_resume(
     }
 
     // Use Orca LLM Orchestrator to parse resume
-    let mut chain = LLMChain::new(client.get_ref()).with_prompt(prompts!(("system", prompt)));
+    let mut chain = LLMChain::new(client).with_prompt(prompts!(("system", prompt)));
     chain.load_context(&Data {
         record: "resume".to_string(),
         format: FORMAT.to_string(),
     });
     chain.load_record("record_content", record);
-    let resume_text = chain.execute().await;
-
-    if resume_text.is_err() {
-        return HttpResponse::InternalServerError().json(ErrorResponse::new(
-            "Please make sure your resume is formatted correctly and try again.".to_string(),
-            "Error parsing resume.".to_string(),
-        ));
-    }
+    let resume_text = match chain.execute().await {
+        Ok(resume_text) => resume_text,
+        Err(_) => {
+            return Err(HttpResponse::InternalServerError().json(ErrorResponse::new(
+                "Please make sure your resume is formatted correctly and try again.".to_string(),
+                "Error parsing resume.".to_string(),
+            )))
+        }
+    };
 
-    let profile = Profile::from_json(&resume_text.unwrap().content());
-    if profile.is_err() {
-        return HttpResponse::InternalServerError().json(ErrorResponse::new(
+    Profile::from_json(&resume_text.content()).map_err(|_| {
+        HttpResponse::InternalServerError().json(ErrorResponse::new(
             "Error parsing resume.".to_string(),
             "Error reading LLM response into JSON format".to_string(),
-        ));
-    }
-    match db.update_profile(&id, profile.unwrap()).await {
-        Ok(_) => match db.get_account(&id).await {
-            Ok(user) => HttpResponse::Ok().json(user),
-            Err(e) => {
-                log::error!("Error: {:#?}", e);
-                HttpResponse::InternalServerError().json(ErrorResponse::new("error getting account".to_string(), e.to_string()))
-            }
-        },
-        Err(e) => {
-            log::error!("Error: {:#?}", e);
-            HttpResponse::InternalServerError().json(ErrorResponse::new(
-                "Error parsing resume. Please make sure your resume is formatted correctly and try again.".to_string(),
-                e.to_string(),
-            ))
-        }
-    }
+        ))
+    })
 }
 
 const FORMAT: &str = r#"
@@ -181,6 +235,11 @@ const FORMAT: &str = r#"
             \"field_of_study\": <string>, // field of study (e.g. Computer Science)
             \"current\": <bool>, // whether the candidate is currently enrolled
             \"description\": <string>, // description of the degree (e.g. GPA, honors)
+            \"start_date\": <string> | null, // month the degree started, as YYYY-MM (e.g. 2019-09)
+            \"end_date\": <string> | null, // month the degree ended, as YYYY-MM; null if current
+            \"location\": <string> | null, // city and state or country of the school (e.g. Berkeley, CA)
+            \"url\": <string> | null, // full http(s) link to the school or program, only if written in the resume
+            \"highlights\": [<string>], // achievements, one per bullet point (e.g. Dean's List)
         }
     ],
     \"experience\": [
@@ -189,7 +248,12 @@ const FORMAT: &str = r#"
             \"type\": 'work' | 'volunteer' | 'personal' | 'other', // type of experience
             \"at\": <string>, // name of the company (e.g. Google)
             \"current\": <bool>, // whether the candidate currently works here
-            \"description\": <string>, // description of the position (e.g. responsibilities)
+            \"description\": <string>, // short summary of the position, without dates or bullet points
+            \"start_date\": <string> | null, // month the position started, as YYYY-MM (e.g. 2022-05)
+            \"end_date\": <string> | null, // month the position ended, as YYYY-MM; null if current
+            \"location\": <string> | null, // city and state or country, or Remote (e.g. Seattle, WA)
+            \"url\": <string> | null, // full http(s) link to the company or project, only if written in the resume
+            \"highlights\": [<string>], // responsibilities and achievements, one per bullet point
         }
     ],
     \"skills\": [
@@ -199,27 +263,19 @@ const FORMAT: &str = r#"
     ],
 }"#;
 
-async fn maxed_profile_field(db: &DatabaseRepository, id: &str, field: &str) -> Result<bool, String> {
-    let profile = db.get_account(id).await.unwrap().profile;
-    match field {
-        "experience" => {
-            if profile.experience.len() >= MAX_PROFILE_FIELD {
-                return Err("Max experience fields reached".to_owned());
-            }
-        }
-        "education" => {
-            if profile.education.len() >= MAX_PROFILE_FIELD {
-                return Err("Max education fields reached".to_owned());
-            }
-        }
-        "skills" => {
-            if profile.skills.len() >= MAX_PROFILE_FIELD {
-                return Err("Max skills fields reached".to_owned());
-            }
-        }
+/// Check that the plan of the user allows one more entry in a profile section
+async fn check_profile_limit(db: &DatabaseRepository, id: &str, field: &str) -> Result<(), HttpResponse> {
+    let account = db.get_account(id).await.unwrap();
+    let used = match field {
+        "experience" => account.profile.experience.len(),
+        "education" => account.profile.education.len(),
+        "skills" => account.profile.skills.len(),
         _ => {
-            return Err("Invalid field".to_owned());
+            return Err(HttpResponse::BadRequest().json(ErrorResponse::new(
+                "Invalid target".to_string(),
+                format!("Unknown profile section {}", field),
+            )))
         }
-    }
-    Ok(true)
+    };
+    check_limit(account.plan, Resource::ProfileEntries, Some(field), used as u64)
 }
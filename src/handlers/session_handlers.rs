@@ -0,0 +1,57 @@
+use actix_web::{
+    delete, get,
+    web::{Data, Path},
+    HttpResponse,
+};
+
+use crate::auth::{sessions, tokens, user_auth::SessionAuthorizationService};
+use crate::handlers::types::{ErrorResponse, MessageResponse, SessionInfo};
+use crate::repository::redis::RedisRepository;
+
+/// API route to list the devices the user is logged in on, most recently seen first.
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// [
+///     {
+///         "id": String,
+///         "user_agent": String,
+///         "ip": String,
+///         "date_created": i64,
+///         "date_last_seen": i64,
+///         "current": bool
+///     }
+/// ]
+/// ```
+#[get("/sessions")]
+pub async fn list_sessions(redis: Data<RedisRepository>, auth: SessionAuthorizationService) -> HttpResponse {
+    match sessions::list_sessions(&redis, &auth.0.id).await {
+        Ok(sessions) => {
+            let sessions: Vec<SessionInfo> = sessions
+                .into_iter()
+                .map(|session| SessionInfo {
+                    current: session.id == auth.0.sid,
+                    session,
+                })
+                .collect();
+            HttpResponse::Ok().json(sessions)
+        }
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting sessions".to_string(), e.to_string())),
+    }
+}
+
+/// API route to log out a session. Its refresh token and access tokens are rejected from then on.
+#[delete("/sessions/{session_id}")]
+pub async fn revoke_session(redis: Data<RedisRepository>, session_id: Path<String>, auth: SessionAuthorizationService) -> HttpResponse {
+    match sessions::list_sessions(&redis, &auth.0.id).await {
+        Ok(sessions) if sessions.iter().any(|s| s.id == *session_id) => (),
+        Ok(_) => return HttpResponse::NotFound().json(ErrorResponse::new("Error revoking session".to_string(), "Session not found".to_string())),
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error revoking session".to_string(), e.to_string())),
+    }
+
+    match tokens::revoke_family(&redis, &session_id).await {
+        Ok(_) => HttpResponse::Ok().json(MessageResponse::new("Session revoked".to_string())),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error revoking session".to_string(), e.to_string())),
+    }
+}
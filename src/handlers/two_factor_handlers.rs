@@ -0,0 +1,337 @@
+use actix_web::{
+    delete,
+    http::StatusCode,
+    post,
+    web::{Data, Json},
+    HttpRequest, HttpResponse,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use redis::RedisError;
+use std::env;
+
+use crate::auth::{client_ip::client_ip, lockout, sessions::ClientInfo, tokens, totp, user_auth::SessionAuthorizationService};
+use crate::handlers::account_handlers;
+use crate::handlers::types::{ErrorResponse, MessageResponse, RecoveryCodes, TwoFactorChallenge, TwoFactorCode, TwoFactorEnrollment, TwoFactorLogin};
+use crate::models::user::{two_factor::TwoFactor, User};
+use crate::repository::{database::DatabaseRepository, redis::RedisRepository};
+use crate::utils;
+
+/// Number of seconds a login challenge token is valid for
+const CHALLENGE_TTL_SECONDS: usize = 5 * 60;
+
+/// Number of wrong codes accepted for a login challenge before it is revoked
+const MAX_CHALLENGE_ATTEMPTS: i64 = 5;
+
+/// API route to start enrolling in TOTP two-factor authentication.
+/// Generates a new secret that has to be confirmed with `/2fa/confirm` before 2FA is required at login.
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// {
+///     "secret": String,
+///     "otpauth_uri": String
+/// }
+/// ```
+#[post("/2fa/enroll")]
+pub async fn enroll_two_factor(db: Data<DatabaseRepository>, auth: SessionAuthorizationService) -> HttpResponse {
+    let user = match get_user(&db, &auth.0.id).await {
+        Ok(user) => user,
+        Err(res) => return res,
+    };
+    if user.two_factor.as_ref().map(|t| t.enabled).unwrap_or(false) {
+        return HttpResponse::Conflict().json(ErrorResponse::new(
+            "Error enrolling in 2FA".to_string(),
+            "Two-factor authentication is already enabled".to_string(),
+        ));
+    }
+
+    let secret = totp::generate_secret();
+    let two_factor = TwoFactor {
+        secret: secret.to_owned(),
+        ..Default::default()
+    };
+    if let Err(e) = db.update_two_factor(&auth.0.id, Some(two_factor)).await {
+        return HttpResponse::InternalServerError().json(ErrorResponse::new("Error enrolling in 2FA".to_string(), e.to_string()));
+    }
+
+    let issuer = env::var("APP_NAME").unwrap();
+    let otpauth_uri = totp::otpauth_uri(&secret, &user.email, &issuer);
+    HttpResponse::Ok().json(TwoFactorEnrollment { secret, otpauth_uri })
+}
+
+/// API route to confirm a pending TOTP enrollment with a code from the authenticator app.
+/// Enables 2FA and returns one-time recovery codes, which are only shown once.
+///
+/// ### Request body:
+/// ```
+/// {
+///    "code": String
+/// }
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// {
+///     "recovery_codes": [String]
+/// }
+/// ```
+#[post("/2fa/confirm")]
+pub async fn confirm_two_factor(db: Data<DatabaseRepository>, req: Json<TwoFactorCode>, auth: SessionAuthorizationService) -> HttpResponse {
+    let user = match get_user(&db, &auth.0.id).await {
+        Ok(user) => user,
+        Err(res) => return res,
+    };
+    let mut two_factor = match user.two_factor {
+        Some(two_factor) if !two_factor.enabled => two_factor,
+        Some(_) => {
+            return HttpResponse::Conflict().json(ErrorResponse::new(
+                "Error confirming 2FA".to_string(),
+                "Two-factor authentication is already enabled".to_string(),
+            ))
+        }
+        None => {
+            return HttpResponse::BadRequest().json(ErrorResponse::new(
+                "Error confirming 2FA".to_string(),
+                "Two-factor enrollment has not been started".to_string(),
+            ))
+        }
+    };
+
+    let step = match totp::verify_code(&two_factor.secret, &req.code, now()) {
+        Some(step) => step,
+        None => return HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid code".to_string(), "Unauthorized".to_string())),
+    };
+
+    let recovery_codes = totp::generate_recovery_codes();
+    two_factor.enabled = true;
+    two_factor.last_step = Some(step as i64);
+    two_factor.recovery_codes = recovery_codes.iter().map(|c| utils::validation::generate_hash(c)).collect();
+    two_factor.date_enabled = Some(chrono::Utc::now().timestamp());
+
+    match db.update_two_factor(&auth.0.id, Some(two_factor)).await {
+        Ok(_) => HttpResponse::Ok().json(RecoveryCodes { recovery_codes }),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error confirming 2FA".to_string(), e.to_string())),
+    }
+}
+
+/// API route to disable two-factor authentication.
+/// Requires a valid TOTP code or recovery code. Wrong codes count towards the
+/// two-factor lockout of the user, which returns 429 with a `Retry-After` header.
+///
+/// ### Request body:
+/// ```
+/// {
+///    "code": String
+/// }
+/// ```
+#[delete("/2fa")]
+pub async fn disable_two_factor(
+    db: Data<DatabaseRepository>,
+    redis: Data<RedisRepository>,
+    req: Json<TwoFactorCode>,
+    http_req: HttpRequest,
+    auth: SessionAuthorizationService,
+) -> HttpResponse {
+    let user = match get_user(&db, &auth.0.id).await {
+        Ok(user) => user,
+        Err(res) => return res,
+    };
+    let two_factor = match user.two_factor {
+        Some(two_factor) if two_factor.enabled => two_factor,
+        _ => {
+            return HttpResponse::BadRequest().json(ErrorResponse::new(
+                "Error disabling 2FA".to_string(),
+                "Two-factor authentication is not enabled".to_string(),
+            ))
+        }
+    };
+
+    let ip = client_ip(&http_req);
+    if let Err(res) = check_code(&db, &redis, &auth.0.id, ip.as_deref(), &two_factor, &req.code, "Error disabling 2FA").await {
+        return res;
+    }
+
+    match db.update_two_factor(&auth.0.id, None).await {
+        Ok(_) => HttpResponse::Ok().json(MessageResponse::new("Two-factor authentication disabled".to_string())),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error disabling 2FA".to_string(), e.to_string())),
+    }
+}
+
+/// API route to finish logging in to an account with 2FA.
+/// Exchanges the challenge token returned by `/auth/login` and a valid
+/// TOTP code or recovery code for an `AuthResponse`.
+///
+/// A challenge is revoked after too many wrong codes. Wrong codes also count towards
+/// the two-factor lockout of the user and the client IP, across challenges, which
+/// returns 429 with a `Retry-After` header.
+///
+/// ### Request body:
+/// ```
+/// {
+///    "challenge_token": String,
+///    "code": String
+/// }
+/// ```
+///
+/// ### Response body (if successful):
+/// ```
+/// 200 OK
+/// {
+///     "id": String,
+///     "token": String,
+///     "refresh_token": String
+/// }
+/// ```
+#[post("/auth/login/2fa")]
+pub async fn login_two_factor(
+    db: Data<DatabaseRepository>,
+    redis: Data<RedisRepository>,
+    req: Json<TwoFactorLogin>,
+    http_req: HttpRequest,
+) -> HttpResponse {
+    let key = challenge_key(&req.challenge_token);
+    let id = match redis.get(&key).await {
+        Ok(id) if !id.is_empty() => id,
+        Ok(_) => {
+            return HttpResponse::Unauthorized().json(ErrorResponse::new(
+                "Invalid challenge".to_string(),
+                "Challenge token is invalid or has expired. Please log in again.".to_string(),
+            ))
+        }
+        Err(e) => return HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging in".to_string(), e.to_string())),
+    };
+
+    let user = match get_user(&db, &id).await {
+        Ok(user) => user,
+        Err(res) => return res,
+    };
+    if user.date_disabled.is_some() {
+        return account_handlers::account_disabled();
+    }
+    if user.date_deleted.is_some() {
+        return account_handlers::account_deleted();
+    }
+    let roles = user.roles;
+    let two_factor = match user.two_factor {
+        Some(two_factor) if two_factor.enabled => two_factor,
+        _ => {
+            return HttpResponse::BadRequest().json(ErrorResponse::new(
+                "Error logging in".to_string(),
+                "Two-factor authentication is not enabled".to_string(),
+            ))
+        }
+    };
+
+    let ip = client_ip(&http_req);
+    if let Err(res) = check_code(&db, &redis, &id, ip.as_deref(), &two_factor, &req.code, "Error logging in").await {
+        if res.status() == StatusCode::UNAUTHORIZED {
+            let attempts_key = format!("{}:attempts", key);
+            let attempts = redis.incr(&attempts_key).await.unwrap_or(MAX_CHALLENGE_ATTEMPTS);
+            let _ = redis.expire(&attempts_key, CHALLENGE_TTL_SECONDS).await;
+            if attempts >= MAX_CHALLENGE_ATTEMPTS {
+                let _ = redis.del(&key).await;
+            }
+        }
+        return res;
+    }
+
+    // The challenge can only be used once
+    let _ = redis.del(&key).await;
+
+    match tokens::issue_tokens(&redis, &id, &roles, &ClientInfo::from_request(&http_req)).await {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::new("Error logging in".to_string(), e.to_string())),
+    }
+}
+
+/// Verify a second factor under the two-factor lockout of the user and the client IP.
+/// Returns 429 while locked out and 401 for a wrong code, which is counted as a failure.
+async fn check_code(
+    db: &DatabaseRepository,
+    redis: &RedisRepository,
+    id: &str,
+    ip: Option<&str>,
+    two_factor: &TwoFactor,
+    code: &str,
+    error: &str,
+) -> Result<(), HttpResponse> {
+    match lockout::check_lockout(redis, &lockout::TWO_FACTOR, id, ip).await {
+        Ok(Some(seconds)) => return Err(account_handlers::too_many_attempts(seconds)),
+        Ok(None) => (),
+        Err(e) => return Err(HttpResponse::InternalServerError().json(ErrorResponse::new(error.to_string(), e.to_string()))),
+    }
+
+    match verify_second_factor(db, id, two_factor, code).await {
+        Ok(true) => {
+            if let Err(e) = lockout::clear_failures(redis, &lockout::TWO_FACTOR, id).await {
+                log::error!("Error clearing two-factor failures of {}: {}", id, e);
+            }
+            Ok(())
+        }
+        Ok(false) => match lockout::record_failure(redis, &lockout::TWO_FACTOR, id, ip).await {
+            Ok(Some(seconds)) => Err(account_handlers::too_many_attempts(seconds)),
+            Ok(None) => Err(HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid code".to_string(), "Unauthorized".to_string()))),
+            Err(e) => Err(HttpResponse::InternalServerError().json(ErrorResponse::new(error.to_string(), e.to_string()))),
+        },
+        Err(e) => Err(HttpResponse::InternalServerError().json(ErrorResponse::new(error.to_string(), e))),
+    }
+}
+
+/// Create a short-lived login challenge for a user with 2FA enabled.
+///
+/// Challenges are stored in the following key-value format:
+/// ```
+/// 2fa_challenge:<token> -> user_id
+/// ```
+pub async fn create_login_challenge(redis: &RedisRepository, id: &str) -> Result<TwoFactorChallenge, RedisError> {
+    let challenge_token: String = rand::thread_rng().sample_iter(&Alphanumeric).take(48).map(char::from).collect();
+    redis.set_ex(&challenge_key(&challenge_token), id, CHALLENGE_TTL_SECONDS).await?;
+    Ok(TwoFactorChallenge {
+        two_factor_required: true,
+        challenge_token,
+    })
+}
+
+/// Verify a TOTP code or a recovery code.
+/// Accepted TOTP time steps and recovery codes are consumed with a conditional update,
+/// so they cannot be replayed, even by concurrent requests.
+async fn verify_second_factor(db: &DatabaseRepository, id: &str, two_factor: &TwoFactor, code: &str) -> Result<bool, String> {
+    let code = code.trim();
+
+    if code.contains('-') {
+        let used = two_factor.recovery_codes.iter().find(|hash| utils::validation::verify_hash(code, hash).unwrap_or(false));
+        return match used {
+            Some(hash) => {
+                let result = db.use_recovery_code(id, hash).await.map_err(|e| e.to_string())?;
+                Ok(result.modified_count == 1)
+            }
+            None => Ok(false),
+        };
+    }
+
+    match totp::verify_code(&two_factor.secret, code, now()) {
+        Some(step) => {
+            let result = db.use_totp_step(id, step as i64).await.map_err(|e| e.to_string())?;
+            Ok(result.modified_count == 1)
+        }
+        None => Ok(false),
+    }
+}
+
+async fn get_user(db: &DatabaseRepository, id: &str) -> Result<User, HttpResponse> {
+    match db.get_user(id).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(HttpResponse::NotFound().json(ErrorResponse::new("Error getting account".to_string(), "Account not found".to_string()))),
+        Err(e) => Err(HttpResponse::InternalServerError().json(ErrorResponse::new("Error getting account".to_string(), e.to_string()))),
+    }
+}
+
+fn challenge_key(token: &str) -> String {
+    format!("2fa_challenge:{}", token)
+}
+
+fn now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
@@ -1,3 +1,16 @@
+use crate::models::document::Document;
+use crate::models::profile::Profile;
+use crate::models::user::{
+    api_token::{ApiToken, Scope},
+    identity::LinkedIdentity,
+    metadata::UserMetadata,
+    passkey::Passkey,
+    plan::{Plan, Resource},
+    preferences::Preferences,
+    role::Role,
+    session::Session,
+};
+use crate::utils::password_policy::{PasswordCheck, Strength, Violation};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -15,6 +28,34 @@ impl ErrorResponse {
     }
 }
 
+/// The response to a password rejected by the password policy, listing every rule it failed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordRejected {
+    /// The error explanation.
+    pub message: String,
+
+    /// The error message.
+    pub error: String,
+
+    /// The estimated strength of the password.
+    pub strength: Strength,
+
+    /// The rules the password does not follow.
+    pub violations: Vec<Violation>,
+}
+
+impl PasswordRejected {
+    pub fn new(message: String, check: PasswordCheck) -> Self {
+        let error = check.violations.iter().map(|v| v.message.as_str()).collect::<Vec<_>>().join(". ");
+        Self {
+            message,
+            error,
+            strength: check.strength,
+            violations: check.violations,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MessageResponse {
     /// The message.
@@ -36,6 +77,9 @@ pub struct AuthResponse {
 
     /// The JWT token used for authentication.
     pub token: String,
+
+    /// The opaque token used to get a new JWT once it expires.
+    pub refresh_token: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -56,10 +100,19 @@ pub struct Credentials {
     pub password: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshRequest {
+    /// The refresh token returned by a previous authentication.
+    pub refresh_token: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExternalAccountQuery {
     /// The external token id of the user (e.g. Google OAuth).
     pub token_id: String,
+
+    /// The invite code to redeem if a new account is created. This field is optional.
+    pub invite_code: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -71,6 +124,12 @@ pub struct VerificationCodeQuery {
     pub email: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailChangeConfirmation {
+    /// The confirmation code sent to the new email.
+    pub code: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VerificationQuery {
     /// The email of the user to verify.
@@ -80,4 +139,468 @@ pub struct VerificationQuery {
     pub code: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForgotPasswordQuery {
+    /// The email of the account to reset the password for.
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordReset {
+    /// The email of the account to reset the password for.
+    pub email: String,
+
+    /// The reset code sent to the user.
+    pub code: String,
+
+    /// The new password of the user.
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MagicLinkQuery {
+    /// The email of the account to send a sign-in link to.
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MagicLinkLogin {
+    /// The token from the sign-in link.
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountRestore {
+    /// The token from the restore link of the deletion email.
+    pub token: String,
+}
+
 // end of account handler types
+
+// Start of two-factor handler types
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorEnrollment {
+    /// The base32 encoded TOTP secret, for manual entry in an authenticator app.
+    pub secret: String,
+
+    /// The `otpauth://` URI of the secret, usually rendered as a QR code.
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorCode {
+    /// A TOTP code from the authenticator app, or a recovery code.
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecoveryCodes {
+    /// One-time recovery codes. They are only shown once.
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorChallenge {
+    /// Always true. Lets clients tell a challenge apart from an `AuthResponse`.
+    pub two_factor_required: bool,
+
+    /// The short-lived token to exchange for an `AuthResponse` with a valid code.
+    pub challenge_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorLogin {
+    /// The challenge token returned by the login route.
+    pub challenge_token: String,
+
+    /// A TOTP code from the authenticator app, or a recovery code.
+    pub code: String,
+}
+
+// end of two-factor handler types
+
+// Start of admin handler types
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserSearchQuery {
+    /// Part of the name or email of the users to find. Lists every user if empty.
+    pub query: Option<String>,
+
+    /// The page of results, starting at 0.
+    pub page: Option<u64>,
+
+    /// The number of users per page.
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserList {
+    /// The users on the requested page.
+    pub users: Vec<UserMetadata>,
+
+    /// The page of results, starting at 0.
+    pub page: u64,
+
+    /// The number of users per page.
+    pub limit: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditLogQuery {
+    /// Only show entries about this user. This field is optional.
+    pub target_id: Option<String>,
+
+    /// The number of entries to return.
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlanChange {
+    /// The new plan of the account.
+    pub plan: Plan,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QuotaReset {
+    /// The number of quota counters that were reset.
+    pub reset: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WaitlistQuery {
+    /// Only list people who were not invited yet. Defaults to false.
+    pub pending: Option<bool>,
+
+    /// The page of results, starting at 0.
+    pub page: Option<u64>,
+
+    /// The number of entries per page.
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InviteQuery {
+    /// Only list invites issued by this user. This field is optional.
+    pub created_by: Option<String>,
+
+    /// The number of invites to return.
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InviteRequest {
+    /// The email to send the invite to. This field is optional.
+    pub email: Option<String>,
+
+    /// The number of accounts that can be created with the invite. Defaults to 1.
+    pub max_uses: Option<u32>,
+
+    /// The number of days the invite can be redeemed for. Never expires if not set.
+    pub expires_in_days: Option<i64>,
+}
+
+// end of admin handler types
+
+// Start of API token handler types
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiTokenRequest {
+    /// The name of the token, to help the user remember what it is used for.
+    pub name: String,
+
+    /// What the token is allowed to do.
+    pub scopes: Vec<Scope>,
+
+    /// The number of days after which the token expires. The token never expires if empty.
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiTokenInfo {
+    /// The unique identifier of the token.
+    pub id: String,
+
+    /// The name of the token.
+    pub name: String,
+
+    /// The first characters of the token.
+    pub prefix: String,
+
+    /// What the token is allowed to do.
+    pub scopes: Vec<Scope>,
+
+    /// The timestamp indicating when the token was created.
+    pub date_created: i64,
+
+    /// The timestamp after which the token is rejected. This field is optional.
+    pub date_expires: Option<i64>,
+
+    /// The timestamp indicating when the token was last used. This field is optional.
+    pub date_last_used: Option<i64>,
+}
+
+impl From<ApiToken> for ApiTokenInfo {
+    fn from(token: ApiToken) -> Self {
+        ApiTokenInfo {
+            id: token.id,
+            name: token.name,
+            prefix: token.prefix,
+            scopes: token.scopes,
+            date_created: token.date_created,
+            date_expires: token.date_expires,
+            date_last_used: token.date_last_used,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatedApiToken {
+    /// The token to send as a Bearer token. It is only shown once.
+    pub token: String,
+
+    /// The details of the new token.
+    #[serde(flatten)]
+    pub info: ApiTokenInfo,
+}
+
+// end of API token handler types
+
+// Start of passkey handler types
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasskeyRegistration {
+    /// The name to give the passkey. Defaults to "Passkey".
+    pub name: Option<String>,
+
+    /// The credential returned by `navigator.credentials.create()`, with binary fields base64url encoded.
+    pub credential: RegistrationCredential,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegistrationCredential {
+    /// The credential id, base64url encoded.
+    pub id: String,
+
+    pub response: AttestationResponse,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub attestation_object: String,
+}
+
+/// The credential returned by `navigator.credentials.get()`, with binary fields base64url encoded.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasskeyLogin {
+    /// The credential id, base64url encoded.
+    pub id: String,
+
+    pub response: AssertionResponse,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+
+    /// The user handle stored with a discoverable credential. This field is optional.
+    pub user_handle: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasskeyInfo {
+    /// The credential id, base64url encoded.
+    pub id: String,
+
+    /// The name of the passkey.
+    pub name: String,
+
+    /// The timestamp indicating when the passkey was registered.
+    pub date_created: i64,
+
+    /// The timestamp indicating when the passkey was last used to log in. This field is optional.
+    pub date_last_used: Option<i64>,
+}
+
+impl From<Passkey> for PasskeyInfo {
+    fn from(passkey: Passkey) -> Self {
+        PasskeyInfo {
+            id: passkey.id,
+            name: passkey.name,
+            date_created: passkey.date_created,
+            date_last_used: passkey.date_last_used,
+        }
+    }
+}
+
+// end of passkey handler types
+
+// Start of export handler types
+
+/// Everything stored about a user, as included in a data export. Secrets and hashes are left out.
+#[derive(Debug, Serialize)]
+pub struct AccountExport {
+    /// The timestamp indicating when the export was made.
+    pub date_exported: i64,
+
+    pub account: ExportedAccount,
+
+    pub profile: Option<Profile>,
+
+    pub documents: Vec<Document>,
+
+    pub identities: Vec<LinkedIdentity>,
+
+    pub passkeys: Vec<PasskeyInfo>,
+
+    pub api_tokens: Vec<ApiTokenInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportedAccount {
+    /// The unique identifier of the user.
+    pub id: String,
+
+    /// The name of the user.
+    pub name: String,
+
+    /// The email address of the user.
+    pub email: String,
+
+    /// The staff roles of the user.
+    pub roles: Vec<Role>,
+
+    /// The plan of the user.
+    pub plan: Plan,
+
+    /// The settings applied to every generated response.
+    pub preferences: Preferences,
+
+    /// Whether the user has enabled two-factor authentication.
+    pub two_factor_enabled: bool,
+
+    /// The timestamp indicating when the user was created. This field is optional.
+    pub date_created: Option<i64>,
+
+    /// The timestamp indicating when the user was last updated. This field is optional.
+    pub date_updated: Option<i64>,
+
+    /// The timestamp indicating when the account was disabled by an admin. This field is optional.
+    pub date_disabled: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportStatus {
+    /// Always "pending". Ready exports are returned as an archive instead.
+    pub status: String,
+
+    /// The number of seconds to wait before asking again.
+    pub retry_after: usize,
+}
+
+// end of export handler types
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionInfo {
+    /// The details of the session.
+    #[serde(flatten)]
+    pub session: Session,
+
+    /// Whether the request was made with this session.
+    pub current: bool,
+}
+
+// Start of contact handler types
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContactRequest {
+    /// The name of the sender.
+    pub name: String,
+
+    /// The email to reply to.
+    pub email: String,
+
+    /// The message.
+    pub message: String,
+
+    /// Honeypot field hidden from people by the contact form. Only bots fill it in.
+    #[serde(default)]
+    pub website: Option<String>,
+}
+
+// end of contact handler types
+
+// Start of invite handler types
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WaitlistSignup {
+    /// The email to send the invite to.
+    pub email: String,
+
+    /// The name of the person signing up. This field is optional.
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReferralRequest {
+    /// The email to send the invite to. This field is optional.
+    pub email: Option<String>,
+}
+
+// end of invite handler types
+
+// Start of plan handler types
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// The limited resource.
+    pub resource: Resource,
+
+    /// The profile section the usage is about, for profile entries. This field is optional.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub section: Option<String>,
+
+    /// The limit of the plan.
+    pub limit: u64,
+
+    /// The current usage.
+    pub used: u64,
+
+    /// The timestamp at which the usage is reset, for resources counted per period. This field is optional.
+    pub resets_at: Option<i64>,
+}
+
+/// The response to a request that would go over a limit of the user's plan.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LimitExceeded {
+    /// The error explanation.
+    pub message: String,
+
+    /// The error message.
+    pub error: String,
+
+    /// The plan of the user.
+    pub plan: Plan,
+
+    /// The usage of the resource that reached its limit.
+    #[serde(flatten)]
+    pub usage: ResourceUsage,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlanInfo {
+    /// The plan of the user.
+    pub plan: Plan,
+
+    /// The usage of every limited resource.
+    pub usage: Vec<ResourceUsage>,
+}
+
+// end of plan handler types
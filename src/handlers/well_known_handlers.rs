@@ -0,0 +1,25 @@
+use actix_web::{get, http::header, HttpResponse};
+
+use crate::auth::keys::KEYS;
+
+/// Number of seconds clients may cache the JWK set.
+/// Kept well under the key grace window so verifiers pick up rotated keys in time.
+const JWKS_MAX_AGE_SECONDS: u32 = 60 * 60;
+
+/// API route to get the public keys that verify the access tokens issued by this server.
+/// Includes retired keys that are still in their grace window.
+/// The set is empty when tokens are signed with a shared secret.
+///
+/// ### Response body:
+/// ```
+/// 200 OK
+/// {
+///     "keys": [Jwk]
+/// }
+/// ```
+#[get("/.well-known/jwks.json")]
+pub async fn jwks() -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, format!("public, max-age={}", JWKS_MAX_AGE_SECONDS)))
+        .json(KEYS.jwks())
+}
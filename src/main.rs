@@ -7,10 +7,20 @@ use actix_web::{
 use dotenv::dotenv;
 use env_logger::fmt::Color;
 use orca::llm::openai::OpenAIClient;
-use server::handlers::{account_handlers, document_handlers, generate_handlers, profile_handlers};
+use server::auth::{client_ip, jwks::JwksCache, keys, oidc::OidcProviders};
+use server::handlers::{
+    account_handlers, admin_handlers, api_token_handlers, billing_handlers, contact_handlers, document_handlers, export_handlers, generate_handlers,
+    identity_handlers, invite_handlers, passkey_handlers, plan_handlers, preferences_handlers, profile_handlers, session_handlers,
+    two_factor_handlers, well_known_handlers,
+};
 use server::repository::{database::DatabaseRepository, redis::RedisRepository};
+use server::utils::{password_policy, validation};
 use std::env;
 use std::io::Write;
+use std::time::Duration;
+
+/// Number of seconds between two purges of deleted accounts
+const PURGE_INTERVAL_SECONDS: u64 = 60 * 60;
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
@@ -19,9 +29,10 @@ async fn main() -> std::io::Result<()> {
     // Check for environment variables
     env::var("MONGO_URI").expect("MONGO_URI must be set");
     env::var("REDIS_URI").expect("REDIS_URI must be set");
-    env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+    if env::var("JWT_KEYS_PATH").is_err() {
+        env::var("JWT_SECRET").expect("JWT_SECRET or JWT_KEYS_PATH must be set");
+    }
     env::var("GOOGLE_CLIENT_ID").expect("GOOGLE_CLIENT_ID must be set");
-    env::var("GOOGLE_JWK_PATH").expect("GOOGLE_JWK_PATH must be set");
     env::var("SENDGRID_API_KEY").expect("SENDGRID_API_KEY must be set");
     env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY must be set");
     env::var("ENV").expect("ENV must be set");
@@ -52,6 +63,12 @@ async fn main() -> std::io::Result<()> {
         })
         .init();
 
+    // Load the JWT signing keys, argon2 parameters, password policy and trusted proxies now so a bad config fails at startup
+    lazy_static::initialize(&keys::KEYS);
+    lazy_static::initialize(&validation::HASH_PARAMS);
+    lazy_static::initialize(&password_policy::POLICY);
+    lazy_static::initialize(&client_ip::TRUSTED_PROXIES);
+
     log::info!("Starting server on port 8080...");
 
     // Database
@@ -62,6 +79,26 @@ async fn main() -> std::io::Result<()> {
     let redis = RedisRepository::new(&env::var("REDIS_URI").unwrap());
     let redis_data = web::Data::new(redis);
 
+    // OpenID Connect providers
+    let providers = OidcProviders::from_env().unwrap_or_else(|e| panic!("Failed to load OpenID Connect providers: {}", e));
+    let providers_data = web::Data::new(providers);
+    let jwks_data = web::Data::new(JwksCache::new());
+
+    // Purge accounts whose deletion grace period is over
+    let purge_db = db_data.clone();
+    let purge_redis = redis_data.clone();
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(Duration::from_secs(PURGE_INTERVAL_SECONDS));
+        loop {
+            interval.tick().await;
+            match account_handlers::purge_deleted_accounts(&purge_db, &purge_redis).await {
+                Ok((_, 0)) => (),
+                Ok((purged, failed)) => log::warn!("Purged {} deleted accounts, {} failed", purged, failed),
+                Err(e) => log::error!("Error purging deleted accounts: {}", e),
+            }
+        }
+    });
+
     // Orca LLMChain
     let client = OpenAIClient::new();
     let client_data = web::Data::new(client);
@@ -79,22 +116,77 @@ async fn main() -> std::io::Result<()> {
             .app_data(redis_data.clone())
             .app_data(db_data.clone())
             .app_data(client_data.clone())
+            .app_data(providers_data.clone())
+            .app_data(jwks_data.clone())
             .service(
                 web::scope("/account")
                     .service(account_handlers::get_account_by_id)
                     .service(account_handlers::create_account)
-                    .service(account_handlers::authenticate_external_account)
                     .service(account_handlers::update_account)
+                    .service(account_handlers::confirm_email_change)
                     .service(account_handlers::delete_account)
+                    .service(account_handlers::restore_account)
+                    .service(export_handlers::export_account)
                     .service(account_handlers::login_account)
+                    .service(account_handlers::refresh_token)
+                    .service(account_handlers::logout)
+                    .service(account_handlers::logout_all)
+                    .service(account_handlers::forgot_password)
+                    .service(account_handlers::reset_password)
+                    .service(account_handlers::request_magic_link)
+                    .service(account_handlers::consume_magic_link)
+                    .service(two_factor_handlers::enroll_two_factor)
+                    .service(two_factor_handlers::confirm_two_factor)
+                    .service(two_factor_handlers::disable_two_factor)
+                    .service(two_factor_handlers::login_two_factor)
+                    .service(api_token_handlers::create_api_token)
+                    .service(api_token_handlers::list_api_tokens)
+                    .service(api_token_handlers::revoke_api_token)
+                    .service(identity_handlers::list_identities)
+                    .service(identity_handlers::link_identity)
+                    .service(identity_handlers::unlink_identity)
+                    .service(session_handlers::list_sessions)
+                    .service(session_handlers::revoke_session)
+                    .service(preferences_handlers::get_preferences)
+                    .service(preferences_handlers::update_preferences)
+                    .service(plan_handlers::get_plan)
+                    .service(invite_handlers::create_referral_invite)
+                    .service(invite_handlers::list_referral_invites)
+                    .service(passkey_handlers::passkey_registration_options)
+                    .service(passkey_handlers::register_passkey)
+                    .service(passkey_handlers::list_passkeys)
+                    .service(passkey_handlers::delete_passkey)
+                    .service(passkey_handlers::passkey_login_options)
+                    .service(passkey_handlers::login_passkey)
                     .service(account_handlers::get_verification_code)
-                    .service(account_handlers::verify_email),
+                    .service(account_handlers::verify_email)
+                    // Matches any `/auth/{provider}`, so it goes after the other `/auth/*` routes
+                    .service(account_handlers::authenticate_external_account),
             )
+            .service(well_known_handlers::jwks)
+            .service(contact_handlers::contact)
+            .service(invite_handlers::join_waitlist)
             .route(
                 "/health",
                 web::get().to(|| async { chrono::Utc::now().format("OK - %Y-%m-%d %H:%M:%S").to_string() }),
             )
             .service(web::scope("/profile").service(profile_handlers::change_profile).service(profile_handlers::profile_from_resume))
+            .service(
+                web::scope("/admin")
+                    .service(admin_handlers::search_users)
+                    .service(admin_handlers::get_user_metadata)
+                    .service(admin_handlers::disable_user)
+                    .service(admin_handlers::enable_user)
+                    .service(admin_handlers::set_user_plan)
+                    .service(admin_handlers::reset_user_quotas)
+                    .service(admin_handlers::get_user_lockout)
+                    .service(admin_handlers::clear_user_lockout)
+                    .service(admin_handlers::get_audit_log)
+                    .service(admin_handlers::get_waitlist)
+                    .service(admin_handlers::create_invite)
+                    .service(admin_handlers::list_invites),
+            )
+            .service(web::scope("/billing").service(billing_handlers::billing_webhook))
             .service(web::scope("/generate").service(generate_handlers::generate_openai))
             .service(web::scope("/document").service(document_handlers::create_update_document).service(document_handlers::delete_document))
     })
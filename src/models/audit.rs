@@ -0,0 +1,38 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// A struct representing an action taken on a staff-only route.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// The unique identifier for the entry. Serialized as "_id" in JSON.
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// The id of the staff member or admin who took the action.
+    pub actor_id: String,
+
+    /// The action taken (e.g. `users.disable`).
+    pub action: String,
+
+    /// The id of the user the action was taken on. This field is optional.
+    pub target_id: Option<String>,
+
+    /// Additional details about the action, such as a search query. This field is optional.
+    pub details: Option<String>,
+
+    /// The timestamp indicating when the action was taken.
+    pub date_created: i64,
+}
+
+impl AuditEntry {
+    pub fn new(actor_id: &str, action: &str, target_id: Option<&str>, details: Option<String>) -> Self {
+        AuditEntry {
+            id: None,
+            actor_id: actor_id.to_string(),
+            action: action.to_string(),
+            target_id: target_id.map(str::to_string),
+            details,
+            date_created: chrono::Utc::now().timestamp(),
+        }
+    }
+}
@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+/// A struct representing a webhook event of the payment provider that was processed.
+/// Kept so deliveries of the same event are only processed once.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BillingEventRecord {
+    /// The id of the event at the payment provider. Serialized as "_id" in JSON.
+    #[serde(rename = "_id")]
+    pub id: String,
+
+    /// The type of event (e.g. `checkout.session.completed`).
+    pub event_type: String,
+
+    /// The id of the customer the event is about. This field is optional.
+    pub customer_id: Option<String>,
+
+    /// The timestamp indicating when the event was processed.
+    pub date_processed: i64,
+}
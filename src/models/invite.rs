@@ -0,0 +1,44 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// A struct representing an invite code that can be redeemed to create an account.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Invite {
+    /// The unique identifier for the invite. Serialized as "_id" in JSON.
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// The code to enter when signing up.
+    pub code: String,
+
+    /// Who issued the invite.
+    pub kind: InviteKind,
+
+    /// The id of the admin or user who issued the invite.
+    pub created_by: String,
+
+    /// The email the invite was sent to. This field is optional.
+    pub email: Option<String>,
+
+    /// The number of accounts that can be created with the invite.
+    pub max_uses: u32,
+
+    /// The number of accounts created with the invite so far.
+    pub uses: u32,
+
+    /// The timestamp indicating when the invite was created.
+    pub date_created: i64,
+
+    /// The timestamp after which the invite can no longer be redeemed. This field is optional.
+    pub date_expires: Option<i64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InviteKind {
+    /// Issued by an admin, e.g. to someone on the waitlist
+    Admin,
+
+    /// Issued by a user to refer someone
+    Referral,
+}
@@ -1,4 +1,8 @@
+pub mod audit;
+pub mod billing_event;
 pub mod document;
+pub mod invite;
 pub mod profile;
 pub mod traits;
 pub mod user;
+pub mod waitlist;
@@ -0,0 +1,107 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A date with month precision, serialized as `YYYY-MM` (e.g. `2022-05`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct YearMonth {
+    pub year: i32,
+
+    /// Month of the year, from 1 to 12
+    pub month: u32,
+}
+
+impl YearMonth {
+    pub fn new(year: i32, month: u32) -> Result<Self, String> {
+        if !(1000..=9999).contains(&year) {
+            return Err(format!("Invalid year {}", year));
+        }
+        if !(1..=12).contains(&month) {
+            return Err(format!("Invalid month {}", month));
+        }
+        Ok(YearMonth { year, month })
+    }
+}
+
+impl FromStr for YearMonth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid date {}, expected YYYY-MM", s);
+        let (year, month) = s.trim().split_once('-').ok_or_else(invalid)?;
+        if year.len() != 4 || month.len() != 2 {
+            return Err(invalid());
+        }
+        YearMonth::new(year.parse().map_err(|_| invalid())?, month.parse().map_err(|_| invalid())?)
+    }
+}
+
+impl TryFrom<String> for YearMonth {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<YearMonth> for String {
+    fn from(date: YearMonth) -> Self {
+        date.to_string()
+    }
+}
+
+impl fmt::Display for YearMonth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}", self.year, self.month)
+    }
+}
+
+/// Deserialize an optional date, reading empty strings as no date
+pub(crate) fn deserialize_optional<'de, D>(deserializer: D) -> Result<Option<YearMonth>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(value) if !value.trim().is_empty() => value.parse().map(Some).map_err(serde::de::Error::custom),
+        _ => Ok(None),
+    }
+}
+
+/// Check that a period ends after it starts, and has no end date if it is current
+pub fn validate_period(start_date: Option<YearMonth>, end_date: Option<YearMonth>, current: bool) -> Result<(), String> {
+    match (start_date, end_date) {
+        (_, Some(_)) if current => Err("Current entries cannot have an end date".to_string()),
+        (Some(start_date), Some(end_date)) if end_date < start_date => Err(format!("End date {} is before start date {}", end_date, start_date)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_year_month() {
+        let date: YearMonth = "2022-05".parse().unwrap();
+        assert_eq!(date, YearMonth { year: 2022, month: 5 });
+        assert_eq!(date.to_string(), "2022-05");
+        assert_eq!(serde_json::to_string(&date).unwrap(), "\"2022-05\"");
+        assert!("2022-13".parse::<YearMonth>().is_err());
+        assert!("2022-5".parse::<YearMonth>().is_err());
+        assert!("May 2022".parse::<YearMonth>().is_err());
+        assert!("2021-12".parse::<YearMonth>().unwrap() < date);
+    }
+
+    #[test]
+    fn test_validate_period() {
+        let start = Some(YearMonth::new(2021, 9).unwrap());
+        let end = Some(YearMonth::new(2022, 5).unwrap());
+        assert!(validate_period(start, end, false).is_ok());
+        assert!(validate_period(start, None, true).is_ok());
+        assert!(validate_period(start, start, false).is_ok());
+        assert!(validate_period(end, start, false).is_err());
+        assert!(validate_period(start, end, true).is_err());
+        assert!(validate_period(None, end, true).is_err());
+    }
+}
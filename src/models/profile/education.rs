@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
 
+use crate::models::profile::date::{deserialize_optional, validate_period, YearMonth};
+use crate::models::profile::validate_details;
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Education {
     /// Field ID of the education object
@@ -19,4 +22,32 @@ pub struct Education {
 
     /// Description of the education
     pub description: String,
+
+    /// Month the education started (`YYYY-MM`). This field is optional.
+    #[serde(default, deserialize_with = "deserialize_optional")]
+    pub start_date: Option<YearMonth>,
+
+    /// Month the education ended (`YYYY-MM`). Left empty while it is current. This field is optional.
+    #[serde(default, deserialize_with = "deserialize_optional")]
+    pub end_date: Option<YearMonth>,
+
+    /// Location of the education (e.g. Ann Arbor, MI or Remote). This field is optional.
+    #[serde(default)]
+    pub location: Option<String>,
+
+    /// Link to the school or program. This field is optional.
+    #[serde(default)]
+    pub url: Option<String>,
+
+    /// Key achievements of the education, one per bullet point
+    #[serde(default)]
+    pub highlights: Vec<String>,
+}
+
+impl Education {
+    /// Check that the dates form a valid period and that the other details are well formed
+    pub fn validate(&self) -> Result<(), String> {
+        validate_period(self.start_date, self.end_date, self.current)?;
+        validate_details(self.location.as_deref(), self.url.as_deref(), &self.highlights)
+    }
 }
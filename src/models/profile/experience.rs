@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
 
+use crate::models::profile::date::{deserialize_optional, validate_period, YearMonth};
+use crate::models::profile::validate_details;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Experience {
     /// Field ID of the experience object
@@ -20,6 +23,34 @@ pub struct Experience {
 
     /// Description of the experience
     pub description: String,
+
+    /// Month the experience started (`YYYY-MM`). This field is optional.
+    #[serde(default, deserialize_with = "deserialize_optional")]
+    pub start_date: Option<YearMonth>,
+
+    /// Month the experience ended (`YYYY-MM`). Left empty while it is current. This field is optional.
+    #[serde(default, deserialize_with = "deserialize_optional")]
+    pub end_date: Option<YearMonth>,
+
+    /// Location of the experience (e.g. Ann Arbor, MI or Remote). This field is optional.
+    #[serde(default)]
+    pub location: Option<String>,
+
+    /// Link to the company, project or organization. This field is optional.
+    #[serde(default)]
+    pub url: Option<String>,
+
+    /// Key achievements of the experience, one per bullet point
+    #[serde(default)]
+    pub highlights: Vec<String>,
+}
+
+impl Experience {
+    /// Check that the dates form a valid period and that the other details are well formed
+    pub fn validate(&self) -> Result<(), String> {
+        validate_period(self.start_date, self.end_date, self.current)?;
+        validate_details(self.location.as_deref(), self.url.as_deref(), &self.highlights)
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
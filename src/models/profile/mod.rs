@@ -1,13 +1,28 @@
+pub mod date;
 pub mod education;
 pub mod experience;
 pub mod skills;
 mod traits;
 
-use crate::models::profile::{education::Education, experience::Experience, skills::Skills};
+use crate::models::profile::{
+    date::{validate_period, YearMonth},
+    education::Education,
+    experience::Experience,
+    skills::Skills,
+};
 use crate::models::traits::UpdateFieldId;
 use bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 
+/// Maximum number of highlights of an education or experience entry
+const MAX_HIGHLIGHTS: usize = 20;
+
+/// Maximum length of a highlight, in characters
+const MAX_HIGHLIGHT_LENGTH: usize = 500;
+
+/// Maximum length of a location, in characters
+const MAX_LOCATION_LENGTH: usize = 100;
+
 /// Profile models
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct Profile {
@@ -44,16 +59,38 @@ pub enum ProfileValue {
     Skills(Skills),
 }
 
+impl ProfileValue {
+    /// Check an education or experience entry. Other values have nothing to check.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ProfileValue::Experience(experience) => experience.validate(),
+            ProfileValue::Education(education) => education.validate(),
+            ProfileValue::Skills(_) | ProfileValue::FieldId(_) => Ok(()),
+        }
+    }
+}
+
 impl Profile {
     /// Generate a profile from a JSON string where date updated is the current time and the field ID is for each object is a UUID
     pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
-        let mut profile: Profile = serde_json::from_str(json)?;
+        let mut value: serde_json::Value = serde_json::from_str(json)?;
+        clear_invalid_dates(&mut value);
+        let mut profile: Profile = serde_json::from_value(value)?;
 
+        // Keep entries whose dates or details were misread, without the misread parts
         profile.education.iter_mut().for_each(|education| {
             education.update_field_id(Some(ObjectId::new().to_hex()));
+            if validate_period(education.start_date, education.end_date, education.current).is_err() {
+                education.end_date = None;
+            }
+            discard_invalid_details(&mut education.location, &mut education.url, &mut education.highlights);
         });
         profile.experience.iter_mut().for_each(|experience| {
             experience.update_field_id(Some(ObjectId::new().to_hex()));
+            if validate_period(experience.start_date, experience.end_date, experience.current).is_err() {
+                experience.end_date = None;
+            }
+            discard_invalid_details(&mut experience.location, &mut experience.url, &mut experience.highlights);
         });
         profile.skills.iter_mut().for_each(|skills| {
             skills.update_field_id(Some(ObjectId::new().to_hex()));
@@ -63,6 +100,70 @@ impl Profile {
     }
 }
 
+/// Clear the dates of education and experience entries that are not `YYYY-MM` (e.g. `Present`, `May 2022` or `2022`),
+/// so that misread dates in a resume do not fail the whole profile. Profile changes are still parsed strictly.
+fn clear_invalid_dates(profile: &mut serde_json::Value) {
+    for section in ["education", "experience"] {
+        let entries = match profile.get_mut(section).and_then(|entries| entries.as_array_mut()) {
+            Some(entries) => entries,
+            None => continue,
+        };
+        for entry in entries.iter_mut().filter_map(|entry| entry.as_object_mut()) {
+            for field in ["start_date", "end_date"] {
+                let valid = match entry.get(field) {
+                    None | Some(serde_json::Value::Null) => true,
+                    Some(serde_json::Value::String(date)) => date.trim().is_empty() || date.parse::<YearMonth>().is_ok(),
+                    Some(_) => false,
+                };
+                if !valid {
+                    entry.insert(field.to_string(), serde_json::Value::Null);
+                }
+            }
+        }
+    }
+}
+
+/// Check the location, link and highlights of an education or experience entry
+pub(crate) fn validate_details(location: Option<&str>, url: Option<&str>, highlights: &[String]) -> Result<(), String> {
+    if location.map(|location| location.chars().count() > MAX_LOCATION_LENGTH).unwrap_or(false) {
+        return Err(format!("Location must be at most {} characters", MAX_LOCATION_LENGTH));
+    }
+    if let Some(url) = url {
+        if !is_http_url(url) {
+            return Err(format!("Invalid URL {}, expected an http(s) link", url));
+        }
+    }
+    if highlights.len() > MAX_HIGHLIGHTS {
+        return Err(format!("At most {} highlights are allowed", MAX_HIGHLIGHTS));
+    }
+    if highlights.iter().any(|highlight| !is_valid_highlight(highlight)) {
+        return Err(format!("Highlights must be between 1 and {} characters", MAX_HIGHLIGHT_LENGTH));
+    }
+    Ok(())
+}
+
+/// Drop the details of an education or experience entry that would not pass validation.
+/// Used on parsed resumes, where a bad detail should not cost the whole entry.
+fn discard_invalid_details(location: &mut Option<String>, url: &mut Option<String>, highlights: &mut Vec<String>) {
+    if location.as_ref().map(|location| location.chars().count() > MAX_LOCATION_LENGTH).unwrap_or(false) {
+        *location = None;
+    }
+    if url.as_deref().map(|url| !is_http_url(url)).unwrap_or(false) {
+        *url = None;
+    }
+    highlights.retain(|highlight| is_valid_highlight(highlight));
+    highlights.truncate(MAX_HIGHLIGHTS);
+}
+
+fn is_http_url(url: &str) -> bool {
+    let rest = url.strip_prefix("https://").or_else(|| url.strip_prefix("http://")).unwrap_or_default();
+    !rest.is_empty() && !rest.contains(char::is_whitespace)
+}
+
+fn is_valid_highlight(highlight: &str) -> bool {
+    !highlight.trim().is_empty() && highlight.chars().count() <= MAX_HIGHLIGHT_LENGTH
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -76,7 +177,12 @@ mod tests {
                 "degree": "Bachelor of Science in Computer Science Engineering",
                 "field_of_study": "",
                 "current": false,
-                "description": "GPA: 3.51 / 4.00"
+                "description": "GPA: 3.51 / 4.00",
+                "start_date": "2019-09",
+                "end_date": "2023-05",
+                "location": "Ann Arbor, MI",
+                "url": "https://umich.edu",
+                "highlights": []
               }
             ],
             "experience": [
@@ -85,13 +191,24 @@ mod tests {
                 "type": "work",
                 "at": "C+E Partner Seller Experience Team",
                 "current": false,
-                "description": "Software Engineer Intern, May 2022 - August 2022\n- Improved Partner Center Marketplace search functionality by fixing deprecated practices on the backend APIs built on ASP.NET.\n- Implemented fixes to the Partner Center Marketplace site content and aesthetics, creating a friendlier user experience for Microsoft Partners.\n- Improved the testing infrastructure by writing additional unit and integration tests that assess the functionality of the new features and fixes applied to the Partner Center’s APIs."
+                "description": "Software Engineer Intern",
+                "start_date": "2022-05",
+                "end_date": "2022-08",
+                "location": "Redmond, WA",
+                "url": null,
+                "highlights": [
+                  "Improved Partner Center Marketplace search functionality by fixing deprecated practices on the backend APIs built on ASP.NET.",
+                  "Implemented fixes to the Partner Center Marketplace site content and aesthetics, creating a friendlier user experience for Microsoft Partners.",
+                  "Improved the testing infrastructure by writing additional unit and integration tests that assess the functionality of the new features and fixes applied to the Partner Center’s APIs."
+                ]
               },
               {
                 "name": "University of Michigan",
                 "type": "work",
                 "at": "College of Engineering",
                 "current": true,
+                "start_date": "2022-08",
+                "end_date": "",
                 "description": "Instructional Aide for EECS 485 – Web Systems, August 2022 - Present\n- Hosted office hours and lab sessions to provide secondary instruction on web development and distributed computing topics, including server-side dynamic pages, client-side dynamic pages, and MapReduce.\n- Aided in the maintenance of course projects and in the writing of course exams."
               },
               {
@@ -127,5 +244,93 @@ mod tests {
         assert_eq!(profile.education.len(), 1);
         assert_eq!(profile.experience.len(), 4);
         assert_eq!(profile.skills.len(), 3);
+
+        let internship = &profile.experience[0];
+        assert_eq!(internship.start_date, Some(YearMonth::new(2022, 5).unwrap()));
+        assert_eq!(internship.end_date, Some(YearMonth::new(2022, 8).unwrap()));
+        assert_eq!(internship.highlights.len(), 3);
+        assert!(internship.validate().is_ok());
+        // entries without structured fields still deserialize
+        assert!(profile.experience[2].start_date.is_none());
+        assert!(profile.experience[2].highlights.is_empty());
+        assert_eq!(profile.experience[1].end_date, None);
+        assert_eq!(profile.education[0].url.as_deref(), Some("https://umich.edu"));
+    }
+
+    #[test]
+    fn test_profile_from_json_clears_invalid_dates() {
+        let json = r#"{
+            "education": [
+              {
+                "school": "University of Michigan",
+                "degree": "Bachelor of Science",
+                "field_of_study": "Computer Science",
+                "current": false,
+                "description": "",
+                "start_date": "2019",
+                "end_date": "May 2023"
+              }
+            ],
+            "experience": [
+              {
+                "name": "VOID Tech",
+                "type": "work",
+                "at": "Executive Board",
+                "current": true,
+                "description": "VP of Projects",
+                "start_date": "2022-01",
+                "end_date": "Present"
+              }
+            ],
+            "skills": []
+          }"#;
+
+        let profile = Profile::from_json(json).unwrap();
+
+        let experience = &profile.experience[0];
+        assert_eq!(experience.start_date, Some(YearMonth::new(2022, 1).unwrap()));
+        assert_eq!(experience.end_date, None);
+        assert!(experience.validate().is_ok());
+        assert_eq!(profile.education[0].start_date, None);
+        assert_eq!(profile.education[0].end_date, None);
+
+        // profile changes are still parsed strictly
+        let strict = serde_json::from_str::<Experience>(
+            r#"{"name": "VOID Tech", "type": "work", "at": "", "current": true, "description": "", "end_date": "Present"}"#,
+        );
+        assert!(strict.is_err());
+    }
+
+    #[test]
+    fn test_profile_from_json_discards_invalid_details() {
+        let json = r#"{
+            "education": [],
+            "experience": [
+              {
+                "name": "Software Developer",
+                "type": "work",
+                "at": "Center for Academic Innovation",
+                "current": true,
+                "description": "",
+                "start_date": "2021-09",
+                "end_date": "2022-05",
+                "url": "umich.edu",
+                "highlights": ["Built an internal web platform", " "]
+              }
+            ],
+            "skills": []
+          }"#;
+
+        let profile = Profile::from_json(json).unwrap();
+        let experience = &profile.experience[0];
+        assert_eq!(experience.start_date, Some(YearMonth::new(2021, 9).unwrap()));
+        assert_eq!(experience.end_date, None);
+        assert_eq!(experience.url, None);
+        assert_eq!(experience.highlights, vec!["Built an internal web platform".to_string()]);
+        assert!(experience.validate().is_ok());
+
+        let mut invalid = experience.clone();
+        invalid.end_date = Some(YearMonth::new(2022, 5).unwrap());
+        assert!(ProfileValue::Experience(invalid).validate().is_err());
     }
 }
@@ -61,6 +61,11 @@ impl Default for Experience {
             at: String::new(),
             current: false,
             description: String::new(),
+            start_date: None,
+            end_date: None,
+            location: None,
+            url: None,
+            highlights: vec![],
         }
     }
 }
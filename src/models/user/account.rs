@@ -1,5 +1,6 @@
 use crate::models::document::Document;
 use crate::models::profile::Profile;
+use crate::models::user::{plan::Plan, preferences::Preferences};
 use serde::{Deserialize, Serialize};
 
 /// A struct representing an account.
@@ -19,4 +20,13 @@ pub struct Account {
 
     /// A list of document information associated with the account.
     pub documents: Vec<Document>,
+
+    /// The settings applied to every generated response.
+    pub preferences: Preferences,
+
+    /// The plan of the account, which sets its limits.
+    pub plan: Plan,
+
+    /// Whether two-factor authentication is required to log in to the account.
+    pub two_factor_enabled: bool,
 }
@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+
+/// What a personal access token is allowed to do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    /// Read the user's profile.
+    #[serde(rename = "profile:read")]
+    ProfileRead,
+
+    /// Change the user's profile.
+    #[serde(rename = "profile:write")]
+    ProfileWrite,
+
+    /// Generate responses.
+    #[serde(rename = "generate")]
+    Generate,
+
+    /// Create, update and delete documents.
+    #[serde(rename = "documents:write")]
+    DocumentsWrite,
+}
+
+impl Scope {
+    /// The name of the scope, as used in requests
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::ProfileRead => "profile:read",
+            Scope::ProfileWrite => "profile:write",
+            Scope::Generate => "generate",
+            Scope::DocumentsWrite => "documents:write",
+        }
+    }
+}
+
+/// A struct representing a personal access token used for scripted API access.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ApiToken {
+    /// The unique identifier of the token.
+    pub id: String,
+
+    /// The name given to the token by the user.
+    pub name: String,
+
+    /// The SHA-256 hash of the token. The token itself is only shown once, when created.
+    pub token_hash: String,
+
+    /// The first characters of the token, to help users tell their tokens apart.
+    pub prefix: String,
+
+    /// What the token is allowed to do.
+    pub scopes: Vec<Scope>,
+
+    /// The timestamp indicating when the token was created.
+    pub date_created: i64,
+
+    /// The timestamp after which the token is rejected. This field is optional.
+    pub date_expires: Option<i64>,
+
+    /// The timestamp indicating when the token was last used. This field is optional.
+    pub date_last_used: Option<i64>,
+
+    /// The timestamp indicating when the token was revoked. This field is optional.
+    pub date_revoked: Option<i64>,
+}
+
+impl ApiToken {
+    /// Check if the token can still be used at the given time
+    pub fn is_active(&self, now: i64) -> bool {
+        self.date_revoked.is_none() && self.date_expires.map(|expires| now < expires).unwrap_or(true)
+    }
+}
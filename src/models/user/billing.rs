@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+/// The subscription of a user at the payment provider.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Billing {
+    /// The id of the customer at the payment provider.
+    pub customer_id: String,
+
+    /// The id of the subscription at the payment provider. This field is optional.
+    pub subscription_id: Option<String>,
+
+    /// The status of the subscription (e.g. `active`, `past_due`, `canceled`).
+    pub status: String,
+
+    /// The timestamp at which the current billing period ends. This field is optional.
+    pub date_current_period_end: Option<i64>,
+
+    /// The timestamp indicating when the subscription was last updated by the payment provider.
+    pub date_updated: i64,
+}
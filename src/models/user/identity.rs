@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/// A struct representing an external identity (e.g. a Google account) linked to a user.
+/// Users can log in with any of their linked identities.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LinkedIdentity {
+    /// The name of the OpenID Connect provider the identity comes from (e.g. `google`).
+    pub provider: String,
+
+    /// The provider's unique identifier for the user.
+    pub subject: String,
+
+    /// The email reported by the provider when the identity was linked.
+    pub email: String,
+
+    /// The timestamp indicating when the identity was linked.
+    pub date_linked: i64,
+}
+
+impl LinkedIdentity {
+    /// Check if this is the identity `subject` at `provider`
+    pub fn matches(&self, provider: &str, subject: &str) -> bool {
+        self.provider == provider && self.subject == subject
+    }
+}
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::user::{billing::Billing, plan::Plan, role::Role, User};
+
+/// A struct representing the account metadata shown to staff.
+/// Never includes the password hash, 2FA secrets or the user's content.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserMetadata {
+    /// The unique identifier for the account.
+    pub id: String,
+
+    /// The name associated with the account.
+    pub name: String,
+
+    /// The email address associated with the account.
+    pub email: String,
+
+    /// The roles of the account.
+    pub roles: Vec<Role>,
+
+    /// The plan of the account.
+    pub plan: Plan,
+
+    /// The subscription of the account at the payment provider. This field is optional.
+    pub billing: Option<Billing>,
+
+    /// Whether the account can log in with a password.
+    pub has_password: bool,
+
+    /// The providers of the external identities linked to the account.
+    pub identity_providers: Vec<String>,
+
+    /// Whether two-factor authentication is required to log in to the account.
+    pub two_factor_enabled: bool,
+
+    /// The number of documents of the account.
+    pub document_count: usize,
+
+    /// The code of the invite redeemed to create the account. This field is optional.
+    pub invite_code: Option<String>,
+
+    /// The timestamp indicating when the account was created. This field is optional.
+    pub date_created: Option<i64>,
+
+    /// The timestamp indicating when the account was last updated. This field is optional.
+    pub date_updated: Option<i64>,
+
+    /// The timestamp indicating when the account was disabled. This field is optional.
+    pub date_disabled: Option<i64>,
+
+    /// The timestamp indicating when the user asked for the account to be deleted. This field is optional.
+    pub date_deleted: Option<i64>,
+}
+
+impl From<User> for UserMetadata {
+    fn from(user: User) -> Self {
+        UserMetadata {
+            id: user.id.map(|id| id.to_hex()).unwrap_or_default(),
+            name: user.name,
+            email: user.email,
+            roles: user.roles,
+            plan: user.plan,
+            billing: user.billing,
+            has_password: user.password.is_some(),
+            identity_providers: user.identities.into_iter().map(|i| i.provider).chain(user.external_provider).collect(),
+            two_factor_enabled: user.two_factor.map(|t| t.enabled).unwrap_or(false),
+            document_count: user.documents.map(|d| d.len()).unwrap_or(0),
+            invite_code: user.invite_code,
+            date_created: user.date_created,
+            date_updated: user.date_updated,
+            date_disabled: user.date_disabled,
+            date_deleted: user.date_deleted,
+        }
+    }
+}
@@ -1,10 +1,24 @@
 pub mod account;
+pub mod api_token;
+pub mod billing;
+pub mod identity;
+pub mod metadata;
+pub mod passkey;
+pub mod plan;
+pub mod preferences;
+pub mod role;
+pub mod session;
+pub mod two_factor;
 
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 
 use crate::models::document::Document;
 use crate::models::profile::Profile;
+use crate::models::user::{
+    api_token::ApiToken, billing::Billing, identity::LinkedIdentity, passkey::Passkey, plan::Plan, preferences::Preferences, role::Role,
+    two_factor::TwoFactor,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 /// A struct representing a user.
@@ -22,10 +36,18 @@ pub struct User {
     /// The password of the user. This field is optional.
     pub password: Option<String>,
 
-    /// An external identifier associated with the user.
+    /// The external identities the user can log in with.
+    #[serde(default)]
+    pub identities: Vec<LinkedIdentity>,
+
+    /// The external identifier of accounts created before several identities could be linked.
+    /// Moved to `identities` the next time the user logs in with it. Never written.
+    #[serde(default, skip_serializing)]
     pub external_id: Option<String>,
 
-    /// The external provider associated with the user.
+    /// The external provider of accounts created before several identities could be linked.
+    /// Moved to `identities` the next time the user logs in with it. Never written.
+    #[serde(default, skip_serializing)]
     pub external_provider: Option<String>,
 
     /// The user's profile information. This field is optional.
@@ -34,6 +56,45 @@ pub struct User {
     /// A list of document information associated with the user. This field is optional.
     pub documents: Option<Vec<Document>>,
 
+    /// The settings applied to every generated response.
+    #[serde(default)]
+    pub preferences: Preferences,
+
+    /// The TOTP two-factor authentication settings of the user. This field is optional.
+    pub two_factor: Option<TwoFactor>,
+
+    /// The roles granting access to staff-only routes. Empty for regular users.
+    #[serde(default)]
+    pub roles: Vec<Role>,
+
+    /// The plan of the user, which sets the limits of the account.
+    #[serde(default)]
+    pub plan: Plan,
+
+    /// The subscription of the user at the payment provider. This field is optional.
+    #[serde(default)]
+    pub billing: Option<Billing>,
+
+    /// The timestamp indicating when the account was disabled by an admin. This field is optional.
+    /// Disabled accounts cannot log in.
+    pub date_disabled: Option<i64>,
+
+    /// The timestamp indicating when the user asked for the account to be deleted. This field is optional.
+    /// Accounts pending deletion cannot log in and are purged once the grace period is over.
+    pub date_deleted: Option<i64>,
+
+    /// The personal access tokens of the user, including revoked and expired ones.
+    #[serde(default)]
+    pub api_tokens: Vec<ApiToken>,
+
+    /// The WebAuthn credentials the user can log in with.
+    #[serde(default)]
+    pub passkeys: Vec<Passkey>,
+
+    /// The code of the invite redeemed to create the account. This field is optional.
+    #[serde(default)]
+    pub invite_code: Option<String>,
+
     /// The timestamp indicating when the user was created. This field is optional.
     pub date_created: Option<i64>,
 
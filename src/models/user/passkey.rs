@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+/// A struct representing a WebAuthn credential (passkey) the user can log in with.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Passkey {
+    /// The credential id chosen by the authenticator, base64url encoded.
+    pub id: String,
+
+    /// The name given to the passkey by the user.
+    pub name: String,
+
+    /// The COSE-encoded public key of the credential, base64url encoded.
+    pub public_key: String,
+
+    /// The COSE algorithm of the public key, e.g. -7 for ES256.
+    pub algorithm: i64,
+
+    /// The signature counter last reported by the authenticator. Always 0 for authenticators without one.
+    pub sign_count: u32,
+
+    /// The timestamp indicating when the passkey was registered.
+    pub date_created: i64,
+
+    /// The timestamp indicating when the passkey was last used to log in. This field is optional.
+    pub date_last_used: Option<i64>,
+}
@@ -0,0 +1,129 @@
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+/// The plan of a user, which sets the limits of the account.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Plan {
+    /// Plan of every new account
+    #[default]
+    Free,
+
+    /// Paid plan with higher limits
+    Pro,
+}
+
+/// The limits of a plan
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Limits {
+    /// Maximum number of saved documents
+    pub documents: u64,
+
+    /// Maximum number of entries in each section of the profile (experience, education and skills)
+    pub profile_entries: u64,
+
+    /// Maximum number of generated responses per day (UTC)
+    pub generations_per_day: u64,
+
+    /// Maximum number of resumes parsed into the profile per month (UTC)
+    pub resume_parses_per_month: u64,
+}
+
+/// A limited resource
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resource {
+    Documents,
+    ProfileEntries,
+    Generations,
+    ResumeParses,
+}
+
+impl Plan {
+    /// Get the limits of the plan
+    pub fn limits(&self) -> Limits {
+        match self {
+            Plan::Free => Limits {
+                documents: 3,
+                profile_entries: 5,
+                generations_per_day: 20,
+                resume_parses_per_month: 3,
+            },
+            Plan::Pro => Limits {
+                documents: 100,
+                profile_entries: 50,
+                generations_per_day: 200,
+                resume_parses_per_month: 30,
+            },
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Plan::Free => "free",
+            Plan::Pro => "pro",
+        }
+    }
+}
+
+impl Limits {
+    /// Get the limit of a resource
+    pub fn get(&self, resource: Resource) -> u64 {
+        match resource {
+            Resource::Documents => self.documents,
+            Resource::ProfileEntries => self.profile_entries,
+            Resource::Generations => self.generations_per_day,
+            Resource::ResumeParses => self.resume_parses_per_month,
+        }
+    }
+}
+
+impl Resource {
+    /// Get the timestamp at which the usage of a resource counted per period is reset,
+    /// or `None` for resources that are not counted per period.
+    pub fn period_end(&self, now: i64) -> Option<i64> {
+        let date = NaiveDateTime::from_timestamp_opt(now, 0)?.date();
+        let end = match self {
+            Resource::Generations => date.succ_opt()?,
+            Resource::ResumeParses => match date.month() {
+                12 => NaiveDate::from_ymd_opt(date.year() + 1, 1, 1)?,
+                month => NaiveDate::from_ymd_opt(date.year(), month + 1, 1)?,
+            },
+            Resource::Documents | Resource::ProfileEntries => return None,
+        };
+        Some(end.and_hms_opt(0, 0, 0)?.timestamp())
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Resource::Documents => "documents",
+            Resource::ProfileEntries => "profile_entries",
+            Resource::Generations => "generations",
+            Resource::ResumeParses => "resume_parses",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_period_end() {
+        // 2023-12-31 15:30:00 UTC
+        let now = 1_704_036_600;
+        // 2024-01-01 00:00:00 UTC
+        assert_eq!(Resource::Generations.period_end(now), Some(1_704_067_200));
+        assert_eq!(Resource::ResumeParses.period_end(now), Some(1_704_067_200));
+        // 2024-02-10 00:00:00 UTC resets parses on 2024-03-01 00:00:00 UTC
+        assert_eq!(Resource::ResumeParses.period_end(1_707_523_200), Some(1_709_251_200));
+        assert_eq!(Resource::Documents.period_end(now), None);
+    }
+
+    #[test]
+    fn test_default_plan() {
+        assert_eq!(Plan::default(), Plan::Free);
+        assert!(Plan::Pro.limits().documents > Plan::Free.limits().documents);
+        assert_eq!(Plan::Free.limits().get(Resource::ProfileEntries), 5);
+    }
+}
@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+
+/// Models that can be picked to generate responses
+pub const MODELS: [&str; 2] = ["gpt-3.5-turbo", "gpt-4"];
+
+/// Bounds of the target length of a generated response, in words
+pub const MIN_TARGET_LENGTH: u32 = 50;
+pub const MAX_TARGET_LENGTH: u32 = 1000;
+
+/// Maximum length of the name of a language
+const MAX_LANGUAGE_LENGTH: usize = 32;
+
+/// A struct representing the settings applied to every generated response.
+/// Unset fields leave the choice to the model.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Preferences {
+    /// The tone of the responses. This field is optional.
+    pub tone: Option<Tone>,
+
+    /// The target length of the responses, in words. This field is optional.
+    pub target_length: Option<u32>,
+
+    /// The language to answer in (e.g. Spanish). This field is optional.
+    pub language: Option<String>,
+
+    /// Whether to write in the first person. This field is optional.
+    pub first_person: Option<bool>,
+
+    /// The model used to generate responses. This field is optional.
+    pub model: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tone {
+    /// Tone is professional
+    Professional,
+
+    /// Tone is formal
+    Formal,
+
+    /// Tone is friendly
+    Friendly,
+
+    /// Tone is enthusiastic
+    Enthusiastic,
+
+    /// Tone is concise
+    Concise,
+}
+
+impl Tone {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Tone::Professional => "professional",
+            Tone::Formal => "formal",
+            Tone::Friendly => "friendly",
+            Tone::Enthusiastic => "enthusiastic",
+            Tone::Concise => "concise",
+        }
+    }
+}
+
+impl Preferences {
+    /// Check that every set field has a supported value
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(length) = self.target_length {
+            if !(MIN_TARGET_LENGTH..=MAX_TARGET_LENGTH).contains(&length) {
+                return Err(format!(
+                    "Target length must be between {} and {} words",
+                    MIN_TARGET_LENGTH, MAX_TARGET_LENGTH
+                ));
+            }
+        }
+        if let Some(language) = &self.language {
+            if language.trim().is_empty() || language.len() > MAX_LANGUAGE_LENGTH {
+                return Err(format!("Language must be between 1 and {} characters", MAX_LANGUAGE_LENGTH));
+            }
+        }
+        if let Some(model) = &self.model {
+            if !MODELS.contains(&model.as_str()) {
+                return Err(format!("Model must be one of {}", MODELS.join(", ")));
+            }
+        }
+        Ok(())
+    }
+
+    /// Combine with the preferences sent with a request, which take precedence field by field
+    pub fn merge(self, overrides: Preferences) -> Preferences {
+        Preferences {
+            tone: overrides.tone.or(self.tone),
+            target_length: overrides.target_length.or(self.target_length),
+            language: overrides.language.or(self.language),
+            first_person: overrides.first_person.or(self.first_person),
+            model: overrides.model.or(self.model),
+        }
+    }
+
+    /// Write the preferences as instructions for the model, one per line
+    pub fn instructions(&self) -> String {
+        let mut instructions = vec![];
+        if let Some(tone) = self.tone {
+            instructions.push(format!("- Use a {} tone.", tone.as_str()));
+        }
+        if let Some(length) = self.target_length {
+            instructions.push(format!("- Aim for about {} words.", length));
+        }
+        if let Some(language) = &self.language {
+            instructions.push(format!("- Answer in {}.", language.trim()));
+        }
+        match self.first_person {
+            Some(true) => instructions.push("- Write in the first person.".to_string()),
+            Some(false) => instructions.push("- Write in the third person.".to_string()),
+            None => (),
+        }
+        instructions.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_and_instructions() {
+        let saved = Preferences {
+            tone: Some(Tone::Friendly),
+            target_length: Some(200),
+            language: Some("Spanish".to_string()),
+            first_person: Some(true),
+            model: None,
+        };
+        let overrides = Preferences {
+            tone: Some(Tone::Formal),
+            model: Some("gpt-4".to_string()),
+            ..Default::default()
+        };
+        let merged = saved.merge(overrides);
+        assert_eq!(merged.tone, Some(Tone::Formal));
+        assert_eq!(merged.target_length, Some(200));
+        assert_eq!(merged.model.as_deref(), Some("gpt-4"));
+        assert_eq!(
+            merged.instructions(),
+            "- Use a formal tone.\n- Aim for about 200 words.\n- Answer in Spanish.\n- Write in the first person."
+        );
+        assert_eq!(Preferences::default().instructions(), "");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Preferences::default().validate().is_ok());
+        let too_long = Preferences {
+            target_length: Some(5000),
+            ..Default::default()
+        };
+        assert!(too_long.validate().is_err());
+        let unknown_model = Preferences {
+            model: Some("davinci".to_string()),
+            ..Default::default()
+        };
+        assert!(unknown_model.validate().is_err());
+    }
+}
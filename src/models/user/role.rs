@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+/// A role granting access to staff-only routes.
+/// Admins can do everything staff can do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Support staff. Can look up accounts.
+    Staff,
+
+    /// Administrators. Can also disable accounts and reset quotas.
+    Admin,
+}
+
+impl Role {
+    /// Check if a user with the given roles has the privileges of `self`
+    pub fn granted_by(&self, roles: &[Role]) -> bool {
+        match self {
+            Role::Staff => roles.iter().any(|r| matches!(r, Role::Staff | Role::Admin)),
+            Role::Admin => roles.contains(&Role::Admin),
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+/// A struct representing a device the user is logged in on.
+/// A session lasts as long as the refresh token family it was created with.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Session {
+    /// The unique identifier for the session, carried by its access tokens as `sid`.
+    pub id: String,
+
+    /// The user agent of the client that last used the session. This field is optional.
+    pub user_agent: Option<String>,
+
+    /// The IP address of the client that last used the session. This field is optional.
+    pub ip: Option<String>,
+
+    /// The timestamp indicating when the user logged in.
+    pub date_created: i64,
+
+    /// The timestamp indicating when the session was last refreshed.
+    pub date_last_seen: i64,
+}
@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+/// A struct representing the TOTP two-factor authentication settings of a user.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TwoFactor {
+    /// The base32 encoded TOTP secret shared with the authenticator app.
+    pub secret: String,
+
+    /// Whether 2FA has been confirmed with a valid code and is required at login.
+    pub enabled: bool,
+
+    /// Argon2 hashes of the unused one-time recovery codes.
+    pub recovery_codes: Vec<String>,
+
+    /// The last TOTP time step that was accepted. Used to reject replayed codes.
+    pub last_step: Option<i64>,
+
+    /// The timestamp indicating when 2FA was enabled. This field is optional.
+    pub date_enabled: Option<i64>,
+}
@@ -0,0 +1,22 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// A struct representing someone waiting for an invite.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WaitlistEntry {
+    /// The unique identifier for the entry. Serialized as "_id" in JSON.
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// The email to send the invite to.
+    pub email: String,
+
+    /// The name of the person waiting. This field is optional.
+    pub name: Option<String>,
+
+    /// The timestamp indicating when the person joined the waitlist.
+    pub date_created: i64,
+
+    /// The timestamp indicating when an invite was sent. This field is optional.
+    pub date_invited: Option<i64>,
+}
@@ -20,6 +20,8 @@ lazy_static! {
     {{skills}}
     This is additional information you may use to help answer the question:
     {{additional}}
+    Follow these instructions when writing your answer, if there are any:
+    {{instructions}}
     
     Here is the prompt you will need to answer:
     {{prompt}}"#;
@@ -1,9 +1,11 @@
 use bson::to_bson;
+use futures::TryStreamExt;
 use log;
 use mongodb::{
     bson::oid::ObjectId,
     bson::{doc, extjson::de::Error},
-    options::{ClientOptions, UpdateOptions},
+    error::{ErrorKind, WriteFailure},
+    options::{ClientOptions, FindOneAndUpdateOptions, FindOptions, ReturnDocument, UpdateOptions},
     results::{DeleteResult, InsertOneResult, UpdateResult},
     Client, Collection,
 };
@@ -14,13 +16,24 @@ use crate::{
     models::{document::Rating, profile::Profile},
 };
 
+use crate::models::audit::AuditEntry;
+use crate::models::billing_event::BillingEventRecord;
 use crate::models::document::Document;
+use crate::models::invite::Invite;
 use crate::models::profile::ProfileValue;
 use crate::models::traits::{GetFieldId, UpdateFieldId};
-use crate::models::user::{account::Account, User};
+use crate::models::user::{
+    account::Account, api_token::ApiToken, billing::Billing, identity::LinkedIdentity, passkey::Passkey, plan::Plan, preferences::Preferences,
+    two_factor::TwoFactor, User,
+};
+use crate::models::waitlist::WaitlistEntry;
 
 pub struct DatabaseRepository {
     pub user_collection: Collection<User>,
+    pub audit_collection: Collection<AuditEntry>,
+    pub invite_collection: Collection<Invite>,
+    pub waitlist_collection: Collection<WaitlistEntry>,
+    pub billing_event_collection: Collection<BillingEventRecord>,
 }
 
 impl DatabaseRepository {
@@ -35,7 +48,17 @@ impl DatabaseRepository {
                 log::info!("Connected to MongoDB");
                 let db = client.database("scrippt");
                 let user_collection: Collection<User> = db.collection("users");
-                DatabaseRepository { user_collection }
+                let audit_collection: Collection<AuditEntry> = db.collection("audit_log");
+                let invite_collection: Collection<Invite> = db.collection("invites");
+                let waitlist_collection: Collection<WaitlistEntry> = db.collection("waitlist");
+                let billing_event_collection: Collection<BillingEventRecord> = db.collection("billing_events");
+                DatabaseRepository {
+                    user_collection,
+                    audit_collection,
+                    invite_collection,
+                    waitlist_collection,
+                    billing_event_collection,
+                }
             }
             Err(_) => {
                 log::error!("Failed to connect to MongoDB");
@@ -57,6 +80,9 @@ impl DatabaseRepository {
                     email: account.email,
                     profile: account.profile.unwrap(),
                     documents: account.documents.unwrap(),
+                    preferences: account.preferences,
+                    plan: account.plan,
+                    two_factor_enabled: account.two_factor.map(|t| t.enabled).unwrap_or(false),
                 };
                 Ok(account)
             }
@@ -70,6 +96,22 @@ impl DatabaseRepository {
         }
     }
 
+    /// Get a user by id
+    pub async fn get_user(&self, id: &str) -> Result<Option<User>, Error> {
+        let obj_id = match ObjectId::parse_str(id) {
+            Ok(obj_id) => obj_id,
+            Err(_) => return Ok(None),
+        };
+        let filter = doc! {"_id": obj_id};
+        match self.user_collection.find_one(filter, None).await {
+            Ok(user) => Ok(user),
+            Err(e) => {
+                log::error!("Failed to get user {}", id);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
     /// Get a user account by email
     pub async fn get_account_by_email(&self, email: &str) -> Result<Option<User>, Error> {
         let filter = doc! {"email": email.to_lowercase()};
@@ -84,19 +126,191 @@ impl DatabaseRepository {
         }
     }
 
+    /// Get the user an external identity is linked to.
+    /// Also finds accounts that still store the identity in the legacy `external_id` and `external_provider` fields.
+    pub async fn get_user_by_identity(&self, provider: &str, subject: &str) -> Result<Option<User>, Error> {
+        let filter = doc! {
+            "$or": [
+                { "identities": { "$elemMatch": { "provider": provider, "subject": subject } } },
+                { "external_provider": provider, "external_id": subject },
+            ]
+        };
+        match self.user_collection.find_one(filter, None).await {
+            Ok(user) => Ok(user),
+            Err(e) => {
+                log::error!("Failed to get user by {} identity", provider);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Link an external identity to an account
+    pub async fn add_identity(&self, id: &str, identity: LinkedIdentity) -> Result<UpdateResult, Error> {
+        self.push_identity(id, identity, doc! {}).await
+    }
+
+    /// Move the identity stored in the legacy `external_id` and `external_provider` fields to `identities`
+    pub async fn migrate_legacy_identity(&self, id: &str, identity: LinkedIdentity) -> Result<UpdateResult, Error> {
+        self.push_identity(id, identity, doc! { "$unset": { "external_id": "", "external_provider": "" } }).await
+    }
+
+    async fn push_identity(&self, id: &str, identity: LinkedIdentity, mut update: bson::Document) -> Result<UpdateResult, Error> {
+        let obj_id = ObjectId::parse_str(id).expect("Failed to parse object id");
+        let filter = doc! {
+            "_id": obj_id,
+            "identities": {
+                "$not": { "$elemMatch": { "provider": &identity.provider, "subject": &identity.subject } }
+            },
+        };
+        update.insert("$push", doc! { "identities": to_bson(&identity).unwrap() });
+        let result = self.user_collection.update_one(filter, update, None).await;
+        match result {
+            Ok(result) => match result.modified_count {
+                1 => Ok(result),
+                _ => Err(Error::DeserializationError {
+                    message: "Identity is already linked".to_string(),
+                }),
+            },
+            Err(e) => {
+                log::error!("Failed to link identity to account {}", id);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Unlink an external identity from an account
+    pub async fn remove_identity(&self, id: &str, provider: &str, subject: &str) -> Result<UpdateResult, Error> {
+        let obj_id = ObjectId::parse_str(id).expect("Failed to parse object id");
+        let filter = doc! {"_id": obj_id};
+        let update = doc! {
+            "$pull": {
+                "identities": { "provider": provider, "subject": subject }
+            }
+        };
+        let result = self.user_collection.update_one(filter, update, None).await;
+        match result {
+            Ok(result) => match result.modified_count {
+                1 => Ok(result),
+                _ => Err(Error::DeserializationError {
+                    message: "Identity not found".to_string(),
+                }),
+            },
+            Err(e) => {
+                log::error!("Failed to unlink identity from account {}", id);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Replace the generation preferences of an account
+    pub async fn update_preferences(&self, id: &str, preferences: &Preferences) -> Result<UpdateResult, Error> {
+        let obj_id = ObjectId::parse_str(id).expect("Failed to parse object id");
+        let filter = doc! {"_id": obj_id};
+        let update = doc! {
+            "$set": {
+                "preferences": to_bson(preferences).unwrap(),
+                "date_updated": chrono::Utc::now().timestamp(),
+            }
+        };
+        let result = self.user_collection.update_one(filter, update, None).await;
+        match result {
+            Ok(result) => match result.matched_count {
+                1 => Ok(result),
+                _ => Err(Error::DeserializationError {
+                    message: "Account not found".to_string(),
+                }),
+            },
+            Err(e) => {
+                log::error!("Failed to update preferences for account {}", id);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Add a passkey to an account
+    pub async fn add_passkey(&self, id: &str, passkey: Passkey) -> Result<UpdateResult, Error> {
+        let obj_id = ObjectId::parse_str(id).expect("Failed to parse object id");
+        let filter = doc! {"_id": obj_id};
+        let update = doc! {
+            "$push": {
+                "passkeys": to_bson(&passkey).unwrap(),
+            }
+        };
+        let result = self.user_collection.update_one(filter, update, None).await;
+        match result {
+            Ok(result) => match result.modified_count {
+                1 => Ok(result),
+                _ => Err(Error::DeserializationError {
+                    message: "Failed to add passkey".to_string(),
+                }),
+            },
+            Err(e) => {
+                log::error!("Failed to add passkey for account {}", id);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Get the user owning the passkey with the given credential id
+    pub async fn get_user_by_passkey(&self, credential_id: &str) -> Result<Option<User>, Error> {
+        let filter = doc! {"passkeys.id": credential_id};
+        match self.user_collection.find_one(filter, None).await {
+            Ok(user) => Ok(user),
+            Err(e) => {
+                log::error!("Failed to get user by passkey");
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Record that a passkey was just used to log in, with the signature counter it reported
+    pub async fn touch_passkey(&self, credential_id: &str, sign_count: u32) -> Result<UpdateResult, Error> {
+        let filter = doc! {"passkeys.id": credential_id};
+        let update = doc! {
+            "$set": {
+                "passkeys.$.sign_count": sign_count as i64,
+                "passkeys.$.date_last_used": chrono::Utc::now().timestamp(),
+            }
+        };
+        match self.user_collection.update_one(filter, update, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                log::error!("Failed to update passkey last use");
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Remove a passkey from an account
+    pub async fn remove_passkey(&self, id: &str, credential_id: &str) -> Result<UpdateResult, Error> {
+        let obj_id = ObjectId::parse_str(id).expect("Failed to parse object id");
+        let filter = doc! {"_id": obj_id};
+        let update = doc! {
+            "$pull": {
+                "passkeys": { "id": credential_id }
+            }
+        };
+        let result = self.user_collection.update_one(filter, update, None).await;
+        match result {
+            Ok(result) => match result.modified_count {
+                1 => Ok(result),
+                _ => Err(Error::DeserializationError {
+                    message: "Passkey not found".to_string(),
+                }),
+            },
+            Err(e) => {
+                log::error!("Failed to remove passkey from account {}", id);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
     /// Create a new account
     pub async fn create_account(&self, user: User) -> Result<InsertOneResult, Error> {
         let new_doc = User {
             id: None,
-            name: user.name,
             email: user.email.to_lowercase(),
-            password: user.password,
-            external_id: user.external_id,
-            external_provider: user.external_provider,
-            profile: user.profile,
-            documents: user.documents,
-            date_created: user.date_created,
-            date_updated: user.date_updated,
+            ..user
         };
         let user = self.user_collection.insert_one(new_doc, None).await;
         match user {
@@ -158,6 +372,522 @@ impl DatabaseRepository {
         }
     }
 
+    /// Consume a TOTP time step of an account, unless the same or a later step was already accepted.
+    /// The check and the update are one operation, so concurrent requests cannot both use a step.
+    pub async fn use_totp_step(&self, id: &str, step: i64) -> Result<UpdateResult, Error> {
+        let obj_id = ObjectId::parse_str(id).expect("Failed to parse object id");
+        let filter = doc! {
+            "_id": obj_id,
+            "two_factor.enabled": true,
+            "$or": [{ "two_factor.last_step": null }, { "two_factor.last_step": { "$lt": step } }],
+        };
+        let update = doc! {"$set": {"two_factor.last_step": step}};
+        match self.user_collection.update_one(filter, update, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                log::error!("Failed to use TOTP step for account {}", id);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Consume a recovery code of an account by its hash, if it was not used already
+    pub async fn use_recovery_code(&self, id: &str, hash: &str) -> Result<UpdateResult, Error> {
+        let obj_id = ObjectId::parse_str(id).expect("Failed to parse object id");
+        let filter = doc! {"_id": obj_id, "two_factor.recovery_codes": hash};
+        let update = doc! {"$pull": {"two_factor.recovery_codes": hash}};
+        match self.user_collection.update_one(filter, update, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                log::error!("Failed to use recovery code for account {}", id);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Set or remove the two-factor authentication settings of an account
+    pub async fn update_two_factor(&self, id: &str, two_factor: Option<TwoFactor>) -> Result<UpdateResult, Error> {
+        let obj_id = ObjectId::parse_str(id).expect("Failed to parse object id");
+        let filter = doc! {"_id": obj_id};
+        let update = match two_factor {
+            Some(two_factor) => doc! {
+                "$set": {
+                    "two_factor": to_bson(&two_factor).unwrap(),
+                }
+            },
+            None => doc! {
+                "$unset": {
+                    "two_factor": "",
+                }
+            },
+        };
+        let result = self.user_collection.update_one(filter, update, None).await;
+        match result {
+            Ok(result) => match result.matched_count {
+                1 => Ok(result),
+                _ => Err(Error::DeserializationError {
+                    message: "Failed to update two-factor settings".to_string(),
+                }),
+            },
+            Err(e) => {
+                log::error!("Failed to update two-factor settings for account {}", id);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Search users by name or email, newest first.
+    /// Lists every user if `query` is None.
+    pub async fn search_users(&self, query: Option<&str>, skip: u64, limit: i64) -> Result<Vec<User>, Error> {
+        let filter = query.map(|query| {
+            let pattern = regex::escape(query);
+            doc! {
+                "$or": [
+                    { "email": { "$regex": &pattern, "$options": "i" } },
+                    { "name": { "$regex": &pattern, "$options": "i" } },
+                ]
+            }
+        });
+        let options = FindOptions::builder().sort(doc! {"date_created": -1}).skip(skip).limit(limit).build();
+        let result = match self.user_collection.find(filter, options).await {
+            Ok(cursor) => cursor.try_collect::<Vec<User>>().await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(users) => Ok(users),
+            Err(e) => {
+                log::error!("Failed to search users");
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Disable or re-enable an account
+    pub async fn set_account_disabled(&self, id: &str, disabled: bool) -> Result<UpdateResult, Error> {
+        let obj_id = ObjectId::parse_str(id).expect("Failed to parse object id");
+        let filter = doc! {"_id": obj_id};
+        let update = match disabled {
+            true => doc! {
+                "$set": {
+                    "date_disabled": chrono::Utc::now().timestamp(),
+                }
+            },
+            false => doc! {
+                "$unset": {
+                    "date_disabled": "",
+                }
+            },
+        };
+        let result = self.user_collection.update_one(filter, update, None).await;
+        match result {
+            Ok(result) => match result.matched_count {
+                1 => Ok(result),
+                _ => Err(Error::DeserializationError {
+                    message: "Account not found".to_string(),
+                }),
+            },
+            Err(e) => {
+                log::error!("Failed to update disabled status for account {}", id);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Change the plan of an account
+    pub async fn set_plan(&self, id: &str, plan: Plan) -> Result<UpdateResult, Error> {
+        let obj_id = ObjectId::parse_str(id).expect("Failed to parse object id");
+        let filter = doc! {"_id": obj_id};
+        let update = doc! {
+            "$set": {
+                "plan": to_bson(&plan).unwrap(),
+                "date_updated": chrono::Utc::now().timestamp(),
+            }
+        };
+        let result = self.user_collection.update_one(filter, update, None).await;
+        match result {
+            Ok(result) => match result.matched_count {
+                1 => Ok(result),
+                _ => Err(Error::DeserializationError {
+                    message: "Account not found".to_string(),
+                }),
+            },
+            Err(e) => {
+                log::error!("Failed to update plan for account {}", id);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Get a user account by the id of its customer at the payment provider
+    pub async fn get_user_by_customer(&self, customer_id: &str) -> Result<Option<User>, Error> {
+        let filter = doc! {"billing.customer_id": customer_id};
+        match self.user_collection.find_one(filter, None).await {
+            Ok(user) => Ok(user),
+            Err(e) => {
+                log::error!("Failed to get account of customer {}", customer_id);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Set the plan of an account along with the subscription it comes from
+    pub async fn set_billing(&self, id: &str, plan: Plan, billing: &Billing) -> Result<UpdateResult, Error> {
+        let obj_id = ObjectId::parse_str(id).expect("Failed to parse object id");
+        let filter = doc! {"_id": obj_id};
+        let update = doc! {
+            "$set": {
+                "plan": to_bson(&plan).unwrap(),
+                "billing": to_bson(billing).unwrap(),
+                "date_updated": chrono::Utc::now().timestamp(),
+            }
+        };
+        let result = self.user_collection.update_one(filter, update, None).await;
+        match result {
+            Ok(result) => match result.matched_count {
+                1 => Ok(result),
+                _ => Err(Error::DeserializationError {
+                    message: "Account not found".to_string(),
+                }),
+            },
+            Err(e) => {
+                log::error!("Failed to update billing for account {}", id);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Mark an account as pending deletion, or restore it
+    pub async fn set_account_deleted(&self, id: &str, deleted: bool) -> Result<UpdateResult, Error> {
+        let obj_id = ObjectId::parse_str(id).expect("Failed to parse object id");
+        let filter = doc! {"_id": obj_id};
+        let update = match deleted {
+            true => doc! {
+                "$set": {
+                    "date_deleted": chrono::Utc::now().timestamp(),
+                }
+            },
+            false => doc! {
+                "$unset": {
+                    "date_deleted": "",
+                }
+            },
+        };
+        let result = self.user_collection.update_one(filter, update, None).await;
+        match result {
+            Ok(result) => match result.matched_count {
+                1 => Ok(result),
+                _ => Err(Error::DeserializationError {
+                    message: "Account not found".to_string(),
+                }),
+            },
+            Err(e) => {
+                log::error!("Failed to update deleted status for account {}", id);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Get the accounts marked as pending deletion before the given timestamp
+    pub async fn get_users_deleted_before(&self, timestamp: i64) -> Result<Vec<User>, Error> {
+        let filter = doc! {"date_deleted": { "$lte": timestamp }};
+        let result = match self.user_collection.find(filter, None).await {
+            Ok(cursor) => cursor.try_collect::<Vec<User>>().await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(users) => Ok(users),
+            Err(e) => {
+                log::error!("Failed to get deleted users");
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Record an action taken on a staff-only route
+    pub async fn add_audit_entry(&self, entry: AuditEntry) -> Result<InsertOneResult, Error> {
+        let result = self.audit_collection.insert_one(entry, None).await;
+        match result {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                log::error!("Failed to add audit entry {}", e);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Get the latest audit entries, optionally only those about one user
+    pub async fn get_audit_entries(&self, target_id: Option<&str>, limit: i64) -> Result<Vec<AuditEntry>, Error> {
+        let filter = target_id.map(|target_id| doc! {"target_id": target_id});
+        let options = FindOptions::builder().sort(doc! {"date_created": -1}).limit(limit).build();
+        let result = match self.audit_collection.find(filter, options).await {
+            Ok(cursor) => cursor.try_collect::<Vec<AuditEntry>>().await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(entries) => Ok(entries),
+            Err(e) => {
+                log::error!("Failed to get audit entries");
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Delete the audit entries about one user
+    pub async fn delete_audit_entries(&self, target_id: &str) -> Result<DeleteResult, Error> {
+        let filter = doc! {"target_id": target_id};
+        match self.audit_collection.delete_many(filter, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                log::error!("Failed to delete audit entries of {}", target_id);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Record a webhook event of the payment provider before processing it.
+    /// Returns `false` if the event was already recorded, in which case it must not be processed again.
+    pub async fn add_billing_event(&self, record: BillingEventRecord) -> Result<bool, Error> {
+        match self.billing_event_collection.insert_one(record, None).await {
+            Ok(_) => Ok(true),
+            Err(e) => match *e.kind {
+                // Duplicate key: the event id is already recorded
+                ErrorKind::Write(WriteFailure::WriteError(ref write_error)) if write_error.code == 11000 => Ok(false),
+                _ => {
+                    log::error!("Failed to add billing event {}", e);
+                    Err(Error::DeserializationError { message: e.to_string() })
+                }
+            },
+        }
+    }
+
+    /// Forget a webhook event of the payment provider, so it is processed again when it is delivered again
+    pub async fn delete_billing_event(&self, id: &str) -> Result<DeleteResult, Error> {
+        match self.billing_event_collection.delete_one(doc! {"_id": id}, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                log::error!("Failed to delete billing event {}", id);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Store a new invite
+    pub async fn add_invite(&self, invite: Invite) -> Result<InsertOneResult, Error> {
+        match self.invite_collection.insert_one(invite, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                log::error!("Failed to add invite {}", e);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Get the latest invites, optionally only those issued by one user
+    pub async fn get_invites(&self, created_by: Option<&str>, limit: i64) -> Result<Vec<Invite>, Error> {
+        let filter = created_by.map(|created_by| doc! {"created_by": created_by});
+        let options = FindOptions::builder().sort(doc! {"date_created": -1}).limit(limit).build();
+        let result = match self.invite_collection.find(filter, options).await {
+            Ok(cursor) => cursor.try_collect::<Vec<Invite>>().await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(invites) => Ok(invites),
+            Err(e) => {
+                log::error!("Failed to get invites");
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Use up one use of an invite, if it exists, has uses left and has not expired.
+    /// Returns the redeemed invite, or `None` if it cannot be redeemed.
+    pub async fn redeem_invite(&self, code: &str) -> Result<Option<Invite>, Error> {
+        let now = chrono::Utc::now().timestamp();
+        let filter = doc! {
+            "code": code,
+            "$expr": { "$lt": ["$uses", "$max_uses"] },
+            "$or": [{ "date_expires": null }, { "date_expires": { "$gt": now } }],
+        };
+        let update = doc! {"$inc": {"uses": 1}};
+        let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+        match self.invite_collection.find_one_and_update(filter, update, options).await {
+            Ok(invite) => Ok(invite),
+            Err(e) => {
+                log::error!("Failed to redeem invite {}", code);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Delete the invites issued by one user
+    pub async fn delete_invites(&self, created_by: &str) -> Result<DeleteResult, Error> {
+        let filter = doc! {"created_by": created_by};
+        match self.invite_collection.delete_many(filter, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                log::error!("Failed to delete invites of {}", created_by);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Give back one use of an invite, when the account it was redeemed for could not be created
+    pub async fn release_invite(&self, code: &str) -> Result<UpdateResult, Error> {
+        let filter = doc! {"code": code, "uses": { "$gt": 0 }};
+        let update = doc! {"$inc": {"uses": -1}};
+        match self.invite_collection.update_one(filter, update, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                log::error!("Failed to release invite {}", code);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Add someone to the waitlist. Emails already on it are left as they are.
+    pub async fn add_to_waitlist(&self, entry: WaitlistEntry) -> Result<UpdateResult, Error> {
+        let filter = doc! {"email": entry.email.to_lowercase()};
+        let update = doc! {
+            "$setOnInsert": {
+                "name": entry.name,
+                "date_created": entry.date_created,
+                "date_invited": entry.date_invited,
+            }
+        };
+        let options = UpdateOptions::builder().upsert(true).build();
+        match self.waitlist_collection.update_one(filter, update, options).await {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                log::error!("Failed to add {} to the waitlist", entry.email);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Get the waitlist, oldest first, optionally only those who were not invited yet
+    pub async fn get_waitlist(&self, pending: bool, skip: u64, limit: i64) -> Result<Vec<WaitlistEntry>, Error> {
+        let filter = if pending { Some(doc! {"date_invited": null}) } else { None };
+        let options = FindOptions::builder().sort(doc! {"date_created": 1}).skip(skip).limit(limit).build();
+        let result = match self.waitlist_collection.find(filter, options).await {
+            Ok(cursor) => cursor.try_collect::<Vec<WaitlistEntry>>().await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(entries) => Ok(entries),
+            Err(e) => {
+                log::error!("Failed to get waitlist");
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Mark someone on the waitlist as invited
+    pub async fn set_waitlist_invited(&self, email: &str) -> Result<UpdateResult, Error> {
+        let filter = doc! {"email": email.to_lowercase()};
+        let update = doc! {"$set": {"date_invited": chrono::Utc::now().timestamp()}};
+        match self.waitlist_collection.update_one(filter, update, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                log::error!("Failed to mark {} as invited", email);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Remove someone from the waitlist
+    pub async fn remove_from_waitlist(&self, email: &str) -> Result<DeleteResult, Error> {
+        let filter = doc! {"email": email.to_lowercase()};
+        match self.waitlist_collection.delete_one(filter, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                log::error!("Failed to remove {} from the waitlist", email);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Add a personal access token to an account
+    pub async fn add_api_token(&self, id: &str, token: ApiToken) -> Result<UpdateResult, Error> {
+        let obj_id = ObjectId::parse_str(id).expect("Failed to parse object id");
+        let filter = doc! {"_id": obj_id};
+        let update = doc! {
+            "$push": {
+                "api_tokens": to_bson(&token).unwrap(),
+            }
+        };
+        let result = self.user_collection.update_one(filter, update, None).await;
+        match result {
+            Ok(result) => match result.modified_count {
+                1 => Ok(result),
+                _ => Err(Error::DeserializationError {
+                    message: "Failed to add API token".to_string(),
+                }),
+            },
+            Err(e) => {
+                log::error!("Failed to add API token for account {}", id);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Get the user owning the personal access token with the given hash
+    pub async fn get_user_by_api_token(&self, token_hash: &str) -> Result<Option<User>, Error> {
+        let filter = doc! {"api_tokens.token_hash": token_hash};
+        match self.user_collection.find_one(filter, None).await {
+            Ok(user) => Ok(user),
+            Err(e) => {
+                log::error!("Failed to get user by API token");
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Record that a personal access token was just used
+    pub async fn touch_api_token(&self, token_hash: &str) -> Result<UpdateResult, Error> {
+        let filter = doc! {"api_tokens.token_hash": token_hash};
+        let update = doc! {
+            "$set": {
+                "api_tokens.$.date_last_used": chrono::Utc::now().timestamp(),
+            }
+        };
+        match self.user_collection.update_one(filter, update, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                log::error!("Failed to update API token last use");
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
+    /// Revoke a personal access token of an account
+    pub async fn revoke_api_token(&self, id: &str, token_id: &str) -> Result<UpdateResult, Error> {
+        let obj_id = ObjectId::parse_str(id).expect("Failed to parse object id");
+        let filter = doc! {
+            "_id": obj_id,
+            "api_tokens": {
+                "$elemMatch": { "id": token_id, "date_revoked": null }
+            },
+        };
+        let update = doc! {
+            "$set": {
+                "api_tokens.$.date_revoked": chrono::Utc::now().timestamp(),
+            }
+        };
+        let result = self.user_collection.update_one(filter, update, None).await;
+        match result {
+            Ok(result) => match result.modified_count {
+                1 => Ok(result),
+                _ => Err(Error::DeserializationError {
+                    message: "API token not found".to_string(),
+                }),
+            },
+            Err(e) => {
+                log::error!("Failed to revoke API token for account {}", id);
+                Err(Error::DeserializationError { message: e.to_string() })
+            }
+        }
+    }
+
     /// Update profile embedded document
     pub async fn update_profile(&self, id: &str, mut profile: Profile) -> Result<UpdateResult, Error> {
         let obj_id = ObjectId::parse_str(id).expect("Failed to parse object id");
@@ -396,8 +1126,14 @@ impl DatabaseRepository {
         if std::env::var("ENV").unwrap() != "test" {
             panic!("Cannot drop database in non-test environment")
         }
-        let result = self.user_collection.drop(None).await;
-        match result {
+        let result = async {
+            self.user_collection.drop(None).await?;
+            self.audit_collection.drop(None).await?;
+            self.invite_collection.drop(None).await?;
+            self.waitlist_collection.drop(None).await?;
+            self.billing_event_collection.drop(None).await
+        };
+        match result.await {
             Ok(_) => Ok(()),
             Err(e) => {
                 log::error!("Failed to drop database");
@@ -45,4 +45,93 @@ impl RedisRepository {
         con.expire(key, seconds).await?;
         Ok(())
     }
+
+    /// Get the number of seconds until a key expires.
+    /// Negative if the key does not exist or does not expire.
+    pub async fn ttl(&self, key: &str) -> Result<i64, RedisError> {
+        let mut con = self.client.get_async_connection().await.unwrap();
+        let res: i64 = con.ttl(key).await?;
+        Ok(res)
+    }
+
+    /// Set a value in Redis that expires after a given number of seconds
+    pub async fn set_ex(&self, key: &str, value: &str, seconds: usize) -> Result<(), RedisError> {
+        let mut con = self.client.get_async_connection().await.unwrap();
+        con.set_ex::<_, _, ()>(key, value, seconds).await?;
+        Ok(())
+    }
+
+    /// Get a binary value from Redis. Empty if the key does not exist.
+    pub async fn get_bytes(&self, key: &str) -> Result<Vec<u8>, RedisError> {
+        let mut con = self.client.get_async_connection().await.unwrap();
+        let res: Option<Vec<u8>> = con.get(key).await?;
+        Ok(res.unwrap_or_default())
+    }
+
+    /// Set a binary value in Redis that expires after a given number of seconds
+    pub async fn set_ex_bytes(&self, key: &str, value: &[u8], seconds: usize) -> Result<(), RedisError> {
+        let mut con = self.client.get_async_connection().await.unwrap();
+        con.set_ex::<_, _, ()>(key, value, seconds).await?;
+        Ok(())
+    }
+
+    /// Atomically set a value in Redis and return the previous value
+    pub async fn getset(&self, key: &str, value: &str) -> Result<Option<String>, RedisError> {
+        let mut con = self.client.get_async_connection().await.unwrap();
+        let res: Option<String> = con.getset(key, value).await?;
+        Ok(res)
+    }
+
+    /// Increment a counter in Redis and return its new value
+    pub async fn incr(&self, key: &str) -> Result<i64, RedisError> {
+        let mut con = self.client.get_async_connection().await.unwrap();
+        let res: i64 = con.incr(key, 1).await?;
+        Ok(res)
+    }
+
+    /// Decrement a counter in Redis and return its new value
+    pub async fn decr(&self, key: &str) -> Result<i64, RedisError> {
+        let mut con = self.client.get_async_connection().await.unwrap();
+        let res: i64 = con.decr(key, 1).await?;
+        Ok(res)
+    }
+
+    /// Add a member to a set in Redis
+    pub async fn sadd(&self, key: &str, member: &str) -> Result<(), RedisError> {
+        let mut con = self.client.get_async_connection().await.unwrap();
+        con.sadd::<_, _, ()>(key, member).await?;
+        Ok(())
+    }
+
+    /// Remove a member from a set in Redis
+    pub async fn srem(&self, key: &str, member: &str) -> Result<(), RedisError> {
+        let mut con = self.client.get_async_connection().await.unwrap();
+        con.srem::<_, _, ()>(key, member).await?;
+        Ok(())
+    }
+
+    /// Get every member of a set in Redis. Empty if the set does not exist.
+    pub async fn smembers(&self, key: &str) -> Result<Vec<String>, RedisError> {
+        let mut con = self.client.get_async_connection().await.unwrap();
+        let res: Vec<String> = con.smembers(key).await?;
+        Ok(res)
+    }
+
+    /// Delete every key matching a glob-style pattern and return the number of deleted keys.
+    /// Uses SCAN, so it does not block Redis on large keyspaces.
+    pub async fn del_matching(&self, pattern: &str) -> Result<usize, RedisError> {
+        let mut con = self.client.get_async_connection().await.unwrap();
+        let mut keys: Vec<String> = vec![];
+        {
+            let mut iter = con.scan_match::<_, String>(pattern).await?;
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+        }
+        if keys.is_empty() {
+            return Ok(0);
+        }
+        con.del::<_, ()>(&keys).await?;
+        Ok(keys.len())
+    }
 }
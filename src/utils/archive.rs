@@ -0,0 +1,89 @@
+/// Size of a tar block. Headers and file contents are padded to it.
+const BLOCK_SIZE: usize = 512;
+
+/// Maximum length of a path in a tar header without the ustar prefix field
+const MAX_PATH_LENGTH: usize = 100;
+
+/// Builder for an uncompressed tar (ustar) archive of regular files, readable with `tar -xf`
+/// and the archive tools built into macOS and Windows.
+#[derive(Debug, Default)]
+pub struct TarBuilder {
+    data: Vec<u8>,
+}
+
+impl TarBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file to the archive. Paths are truncated to 100 bytes.
+    pub fn append(&mut self, path: &str, contents: &[u8], mtime: i64) {
+        let mut header = [0u8; BLOCK_SIZE];
+        let path = &path.as_bytes()[..path.len().min(MAX_PATH_LENGTH)];
+        header[..path.len()].copy_from_slice(path);
+        write_octal(&mut header[100..108], 0o644);
+        write_octal(&mut header[108..116], 0);
+        write_octal(&mut header[116..124], 0);
+        write_octal(&mut header[124..136], contents.len() as u64);
+        write_octal(&mut header[136..148], mtime.max(0) as u64);
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+
+        // The checksum is computed with its own field filled with spaces
+        header[148..156].copy_from_slice(b"        ");
+        let checksum: u64 = header.iter().map(|b| *b as u64).sum();
+        write_octal(&mut header[148..155], checksum);
+
+        self.data.extend_from_slice(&header);
+        self.data.extend_from_slice(contents);
+        self.pad();
+    }
+
+    /// Finish the archive with the two empty blocks that mark its end
+    pub fn finish(mut self) -> Vec<u8> {
+        self.data.extend_from_slice(&[0; 2 * BLOCK_SIZE]);
+        self.data
+    }
+
+    fn pad(&mut self) {
+        let remainder = self.data.len() % BLOCK_SIZE;
+        if remainder != 0 {
+            self.data.resize(self.data.len() + BLOCK_SIZE - remainder, 0);
+        }
+    }
+}
+
+/// Write a zero-padded octal number followed by a NUL into a header field
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    field[..field.len() - 1].copy_from_slice(&digits.as_bytes()[digits.len() - (field.len() - 1)..]);
+    field[field.len() - 1] = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tar_layout() {
+        let mut tar = TarBuilder::new();
+        tar.append("export.json", b"{}", 1_700_000_000);
+        tar.append("export.md", &[b'a'; 600], 1_700_000_000);
+        let data = tar.finish();
+
+        // header + 1 block, header + 2 blocks, 2 end blocks
+        assert_eq!(data.len(), 7 * BLOCK_SIZE);
+        assert_eq!(&data[..11], b"export.json");
+        assert_eq!(&data[124..136], b"00000000002\0");
+        assert_eq!(&data[257..263], b"ustar\0");
+        assert_eq!(&data[512..514], b"{}");
+        assert_eq!(&data[1024..1033], b"export.md");
+        assert_eq!(&data[1024 + 124..1024 + 136], b"00000001130\0");
+
+        let checksum: u64 = data[..BLOCK_SIZE].iter().enumerate().map(|(i, b)| if (148..156).contains(&i) { 32 } else { *b as u64 }).sum();
+        let stored = std::str::from_utf8(&data[148..154]).unwrap();
+        assert_eq!(u64::from_str_radix(stored, 8).unwrap(), checksum);
+        assert!(data[5 * BLOCK_SIZE..].iter().all(|b| *b == 0));
+    }
+}
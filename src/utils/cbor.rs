@@ -0,0 +1,264 @@
+use std::fmt;
+
+/// A decoded CBOR (RFC 8949) data item.
+/// Only covers what WebAuthn uses: integers, byte and text strings, arrays, maps and simple values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Integer(i128),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Bool(bool),
+    Null,
+}
+
+/// Errors that can happen while decoding CBOR
+#[derive(Debug, PartialEq, Eq)]
+pub enum CborError {
+    /// The input ended in the middle of a data item
+    UnexpectedEnd,
+
+    /// The input uses a CBOR feature this decoder does not support
+    Unsupported(String),
+
+    /// A text string is not valid UTF-8
+    InvalidText,
+}
+
+impl fmt::Display for CborError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CborError::UnexpectedEnd => write!(f, "Unexpected end of CBOR input"),
+            CborError::Unsupported(what) => write!(f, "Unsupported CBOR {}", what),
+            CborError::InvalidText => write!(f, "Invalid UTF-8 in CBOR text string"),
+        }
+    }
+}
+
+/// Nesting depth after which input is rejected, so crafted input cannot overflow the stack
+const MAX_DEPTH: usize = 16;
+
+impl Value {
+    /// Look up an integer key in a map
+    pub fn get(&self, key: i128) -> Option<&Value> {
+        self.entry(|k| *k == Value::Integer(key))
+    }
+
+    /// Look up a text key in a map
+    pub fn get_text(&self, key: &str) -> Option<&Value> {
+        self.entry(|k| matches!(k, Value::Text(text) if text == key))
+    }
+
+    pub fn as_integer(&self) -> Option<i128> {
+        match self {
+            Value::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn entry(&self, matches: impl Fn(&Value) -> bool) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries.iter().find(|(k, _)| matches(k)).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+/// Decode the first data item of `input`.
+/// Returns the item and the number of bytes it takes, since WebAuthn appends data after some items.
+pub fn decode(input: &[u8]) -> Result<(Value, usize), CborError> {
+    let mut decoder = Decoder { input, pos: 0 };
+    let value = decoder.value(0)?;
+    Ok((value, decoder.pos))
+}
+
+/// Encode a data item. Map entries are written in the order given.
+pub fn encode(value: &Value) -> Vec<u8> {
+    let mut out = vec![];
+    write(&mut out, value);
+    out
+}
+
+struct Decoder<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn value(&mut self, depth: usize) -> Result<Value, CborError> {
+        if depth > MAX_DEPTH {
+            return Err(CborError::Unsupported("nesting depth".to_string()));
+        }
+        let initial = self.take(1)?[0];
+        let major = initial >> 5;
+        let info = initial & 0x1f;
+
+        if major == 7 {
+            return match info {
+                20 => Ok(Value::Bool(false)),
+                21 => Ok(Value::Bool(true)),
+                22 => Ok(Value::Null),
+                _ => Err(CborError::Unsupported(format!("simple value {}", info))),
+            };
+        }
+
+        let argument = self.argument(info)?;
+        match major {
+            0 => Ok(Value::Integer(argument as i128)),
+            1 => Ok(Value::Integer(-1 - argument as i128)),
+            2 => Ok(Value::Bytes(self.take(self.length(argument)?)?.to_vec())),
+            3 => {
+                let bytes = self.take(self.length(argument)?)?;
+                String::from_utf8(bytes.to_vec()).map(Value::Text).map_err(|_| CborError::InvalidText)
+            }
+            4 => {
+                let mut items = vec![];
+                for _ in 0..self.length(argument)? {
+                    items.push(self.value(depth + 1)?);
+                }
+                Ok(Value::Array(items))
+            }
+            5 => {
+                let mut entries = vec![];
+                for _ in 0..self.length(argument)? {
+                    let key = self.value(depth + 1)?;
+                    let value = self.value(depth + 1)?;
+                    entries.push((key, value));
+                }
+                Ok(Value::Map(entries))
+            }
+            _ => Err(CborError::Unsupported("tag".to_string())),
+        }
+    }
+
+    fn argument(&mut self, info: u8) -> Result<u64, CborError> {
+        let size = match info {
+            0..=23 => return Ok(info as u64),
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            _ => return Err(CborError::Unsupported("indefinite length".to_string())),
+        };
+        Ok(self.take(size)?.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+    }
+
+    /// A length can never exceed the remaining input, which also bounds allocations
+    fn length(&self, argument: u64) -> Result<usize, CborError> {
+        if argument > (self.input.len() - self.pos) as u64 {
+            return Err(CborError::UnexpectedEnd);
+        }
+        Ok(argument as usize)
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], CborError> {
+        if self.input.len() - self.pos < n {
+            return Err(CborError::UnexpectedEnd);
+        }
+        let bytes = &self.input[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+}
+
+fn write(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Integer(value) if *value >= 0 => write_head(out, 0, *value as u64),
+        Value::Integer(value) => write_head(out, 1, (-1 - *value) as u64),
+        Value::Bytes(bytes) => {
+            write_head(out, 2, bytes.len() as u64);
+            out.extend_from_slice(bytes);
+        }
+        Value::Text(text) => {
+            write_head(out, 3, text.len() as u64);
+            out.extend_from_slice(text.as_bytes());
+        }
+        Value::Array(items) => {
+            write_head(out, 4, items.len() as u64);
+            items.iter().for_each(|item| write(out, item));
+        }
+        Value::Map(entries) => {
+            write_head(out, 5, entries.len() as u64);
+            for (key, value) in entries {
+                write(out, key);
+                write(out, value);
+            }
+        }
+        Value::Bool(false) => out.push(0xf4),
+        Value::Bool(true) => out.push(0xf5),
+        Value::Null => out.push(0xf6),
+    }
+}
+
+fn write_head(out: &mut Vec<u8>, major: u8, argument: u64) {
+    let major = major << 5;
+    match argument {
+        0..=23 => out.push(major | argument as u8),
+        24..=0xff => out.extend_from_slice(&[major | 24, argument as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend_from_slice(&(argument as u16).to_be_bytes());
+        }
+        0x10000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend_from_slice(&(argument as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend_from_slice(&argument.to_be_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let value = Value::Map(vec![
+            (Value::Integer(1), Value::Integer(2)),
+            (Value::Integer(-7), Value::Bytes(vec![0; 300])),
+            (Value::Text("fmt".to_string()), Value::Text("none".to_string())),
+            (Value::Text("list".to_string()), Value::Array(vec![Value::Bool(true), Value::Null])),
+        ]);
+        let bytes = encode(&value);
+        assert_eq!(decode(&bytes).unwrap(), (value.clone(), bytes.len()));
+        assert_eq!(value.get(-7).and_then(Value::as_bytes).map(<[u8]>::len), Some(300));
+        assert_eq!(value.get_text("fmt").and_then(Value::as_text), Some("none"));
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut bytes = encode(&Value::Integer(-257));
+        assert_eq!(bytes, vec![0x39, 0x01, 0x00]);
+        bytes.extend_from_slice(&[0xa0]);
+        assert_eq!(decode(&bytes).unwrap(), (Value::Integer(-257), 3));
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(decode(&[0x58, 0x05, 0x00]), Err(CborError::UnexpectedEnd));
+        assert_eq!(
+            decode(&[0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+            Err(CborError::UnexpectedEnd)
+        );
+        assert!(decode(&[0x5f]).is_err());
+        assert!(decode(&[0x81; 64]).is_err());
+    }
+}
@@ -1,2 +1,5 @@
+pub mod archive;
+pub mod cbor;
+pub mod password_policy;
 pub mod sendgrid;
 pub mod validation;
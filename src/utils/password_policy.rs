@@ -0,0 +1,327 @@
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, env};
+
+/// Common and breached passwords, one per line
+const COMMON_PASSWORDS_LIST: &str = include_str!("../../assets/common_passwords.txt");
+
+/// Shortest part of an email or name that passwords cannot contain.
+/// Shorter parts (e.g. initials) would reject too many good passwords.
+const MIN_PERSONAL_INFO_LENGTH: usize = 3;
+
+lazy_static! {
+    /// The password policy, loaded from the environment.
+    ///
+    /// Reads `PASSWORD_MIN_LENGTH`, `PASSWORD_MAX_LENGTH`, `PASSWORD_REQUIRE_LOWERCASE`,
+    /// `PASSWORD_REQUIRE_UPPERCASE`, `PASSWORD_REQUIRE_DIGIT`, `PASSWORD_REQUIRE_SYMBOL`
+    /// and `PASSWORD_MIN_STRENGTH`.
+    pub static ref POLICY: PasswordPolicy = PasswordPolicy::from_env().unwrap_or_else(|e| panic!("Failed to load password policy: {}", e));
+
+    static ref COMMON_PASSWORDS: HashSet<String> = COMMON_PASSWORDS_LIST
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect();
+}
+
+/// How hard a password is to guess
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strength {
+    VeryWeak,
+    Weak,
+    Fair,
+    Strong,
+    VeryStrong,
+}
+
+impl Strength {
+    fn from_env(value: &str) -> Option<Self> {
+        match value {
+            "very_weak" => Some(Strength::VeryWeak),
+            "weak" => Some(Strength::Weak),
+            "fair" => Some(Strength::Fair),
+            "strong" => Some(Strength::Strong),
+            "very_strong" => Some(Strength::VeryStrong),
+            _ => None,
+        }
+    }
+}
+
+/// A rule of the password policy
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    MinLength,
+    MaxLength,
+    Lowercase,
+    Uppercase,
+    Digit,
+    Symbol,
+    PersonalInfo,
+    Common,
+    Strength,
+}
+
+/// A rule a password does not follow
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Violation {
+    /// The rule that failed.
+    pub rule: Rule,
+
+    /// What the user has to change, in plain words.
+    pub message: String,
+}
+
+impl Violation {
+    fn new(rule: Rule, message: String) -> Self {
+        Violation { rule, message }
+    }
+}
+
+/// The result of checking a password against the policy
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordCheck {
+    /// The estimated strength of the password.
+    pub strength: Strength,
+
+    /// The rules the password does not follow. Empty if the password is accepted.
+    pub violations: Vec<Violation>,
+}
+
+impl PasswordCheck {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// The rules passwords have to follow
+#[derive(Clone, Debug)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    pub min_strength: Strength,
+}
+
+impl Default for PasswordPolicy {
+    /// Length and strength rules only, without character classes, as recommended by NIST SP 800-63B
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 8,
+            max_length: 128,
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            min_strength: Strength::Fair,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Load the policy from the environment. Unset variables keep their default.
+    pub fn from_env() -> Result<Self, String> {
+        let mut policy = PasswordPolicy::default();
+        if let Ok(value) = env::var("PASSWORD_MIN_LENGTH") {
+            policy.min_length = value.parse().map_err(|_| "PASSWORD_MIN_LENGTH must be a number".to_string())?;
+        }
+        if let Ok(value) = env::var("PASSWORD_MAX_LENGTH") {
+            policy.max_length = value.parse().map_err(|_| "PASSWORD_MAX_LENGTH must be a number".to_string())?;
+        }
+        if let Ok(value) = env::var("PASSWORD_MIN_STRENGTH") {
+            policy.min_strength = Strength::from_env(&value).ok_or_else(|| format!("Unknown PASSWORD_MIN_STRENGTH {}", value))?;
+        }
+        for (name, flag) in [
+            ("PASSWORD_REQUIRE_LOWERCASE", &mut policy.require_lowercase),
+            ("PASSWORD_REQUIRE_UPPERCASE", &mut policy.require_uppercase),
+            ("PASSWORD_REQUIRE_DIGIT", &mut policy.require_digit),
+            ("PASSWORD_REQUIRE_SYMBOL", &mut policy.require_symbol),
+        ] {
+            if let Ok(value) = env::var(name) {
+                *flag = value.parse().map_err(|_| format!("{} must be true or false", name))?;
+            }
+        }
+        if policy.min_length > policy.max_length {
+            return Err("PASSWORD_MIN_LENGTH cannot be greater than PASSWORD_MAX_LENGTH".to_string());
+        }
+        Ok(policy)
+    }
+
+    /// Check a password against every rule.
+    /// `personal_info` holds the user's email and name, which the password cannot contain.
+    ///
+    /// # Usage
+    /// ```rust
+    /// let check = POLICY.check("password", &["johndoe@email.com", "John Doe"]);
+    /// assert!(!check.is_ok());
+    /// ```
+    pub fn check(&self, password: &str, personal_info: &[&str]) -> PasswordCheck {
+        let mut violations = vec![];
+        let length = password.chars().count();
+
+        if length < self.min_length {
+            violations.push(Violation::new(
+                Rule::MinLength,
+                format!("Password must be at least {} characters", self.min_length),
+            ));
+        }
+        if length > self.max_length {
+            violations.push(Violation::new(
+                Rule::MaxLength,
+                format!("Password must be at most {} characters", self.max_length),
+            ));
+        }
+        if self.require_lowercase && !password.chars().any(char::is_lowercase) {
+            violations.push(Violation::new(Rule::Lowercase, "Password must contain a lowercase letter".to_string()));
+        }
+        if self.require_uppercase && !password.chars().any(char::is_uppercase) {
+            violations.push(Violation::new(Rule::Uppercase, "Password must contain an uppercase letter".to_string()));
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            violations.push(Violation::new(Rule::Digit, "Password must contain a digit".to_string()));
+        }
+        if self.require_symbol && password.chars().all(char::is_alphanumeric) {
+            violations.push(Violation::new(Rule::Symbol, "Password must contain a symbol".to_string()));
+        }
+        if contains_personal_info(password, personal_info) {
+            violations.push(Violation::new(
+                Rule::PersonalInfo,
+                "Password cannot contain your email or name".to_string(),
+            ));
+        }
+
+        let common = is_common(password);
+        if common {
+            violations.push(Violation::new(
+                Rule::Common,
+                "Password is too common. Please choose a less predictable password".to_string(),
+            ));
+        }
+
+        let strength = if common { Strength::VeryWeak } else { estimate_strength(password) };
+        if !common && strength < self.min_strength {
+            violations.push(Violation::new(
+                Rule::Strength,
+                "Password is too easy to guess. Try a longer password or a few unrelated words".to_string(),
+            ));
+        }
+
+        PasswordCheck { strength, violations }
+    }
+}
+
+/// Check if a password is on the list of common and breached passwords
+pub fn is_common(password: &str) -> bool {
+    COMMON_PASSWORDS.contains(&password.to_lowercase())
+}
+
+/// Estimate how hard a password is to guess from its length and the characters it uses.
+/// Repeated characters and runs like `abc` or `321` count for little.
+pub fn estimate_strength(password: &str) -> Strength {
+    let has = |f: fn(&char) -> bool| password.chars().any(|c| f(&c));
+    let mut pool = 0;
+    if has(|c| c.is_ascii_lowercase()) {
+        pool += 26;
+    }
+    if has(|c| c.is_ascii_uppercase()) {
+        pool += 26;
+    }
+    if has(|c| c.is_ascii_digit()) {
+        pool += 10;
+    }
+    if has(|c| c.is_ascii_punctuation() || *c == ' ') {
+        pool += 33;
+    }
+    if has(|c| !c.is_ascii()) {
+        pool += 100;
+    }
+    if pool == 0 {
+        return Strength::VeryWeak;
+    }
+
+    let mut effective_length = 0.0;
+    let mut previous: Option<char> = None;
+    for c in password.chars() {
+        let predictable = match previous {
+            Some(p) => (p as i64 - c as i64).abs() <= 1,
+            None => false,
+        };
+        effective_length += if predictable { 0.25 } else { 1.0 };
+        previous = Some(c);
+    }
+
+    let bits = effective_length * (pool as f64).log2();
+    match bits {
+        b if b < 28.0 => Strength::VeryWeak,
+        b if b < 36.0 => Strength::Weak,
+        b if b < 60.0 => Strength::Fair,
+        b if b < 128.0 => Strength::Strong,
+        _ => Strength::VeryStrong,
+    }
+}
+
+/// Check if a password contains the user's email, the part of it before the `@`, or a part of their name
+fn contains_personal_info(password: &str, personal_info: &[&str]) -> bool {
+    let password = password.to_lowercase();
+    personal_info
+        .iter()
+        .flat_map(|info| {
+            let info = info.to_lowercase();
+            let mut parts: Vec<String> = info.split(|c: char| c.is_whitespace() || c == '@').map(str::to_string).collect();
+            parts.push(info);
+            parts
+        })
+        .filter(|part| part.chars().count() >= MIN_PERSONAL_INFO_LENGTH)
+        .any(|part| password.contains(&part))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(check: &PasswordCheck) -> Vec<Rule> {
+        check.violations.iter().map(|v| v.rule).collect()
+    }
+
+    #[test]
+    fn test_default_policy() {
+        let policy = PasswordPolicy::default();
+        assert!(policy.check("correct-horse-battery", &["johndoe@email.com", "John Doe"]).is_ok());
+        assert_eq!(rules(&policy.check("short", &[])), vec![Rule::MinLength, Rule::Strength]);
+        assert_eq!(rules(&policy.check("Password1", &[])), vec![Rule::Common]);
+        assert_eq!(
+            rules(&policy.check("johndoe-rocks-42", &["johndoe@email.com", "John Doe"])),
+            vec![Rule::PersonalInfo]
+        );
+    }
+
+    #[test]
+    fn test_character_classes() {
+        let policy = PasswordPolicy {
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: true,
+            ..PasswordPolicy::default()
+        };
+        assert_eq!(
+            rules(&policy.check("correcthorsebattery", &[])),
+            vec![Rule::Uppercase, Rule::Digit, Rule::Symbol]
+        );
+        assert!(policy.check("Correct-horse-battery-9", &[]).is_ok());
+    }
+
+    #[test]
+    fn test_estimate_strength() {
+        assert_eq!(estimate_strength("aaaaaaaaaaaa"), Strength::VeryWeak);
+        assert_eq!(estimate_strength("abcdefghijkl"), Strength::VeryWeak);
+        assert_eq!(estimate_strength("x7Kq!2mZ"), Strength::Fair);
+        assert_eq!(estimate_strength("correct-horse-battery"), Strength::Strong);
+        assert!(estimate_strength("correct horse battery staple and a few more words") >= Strength::Strong);
+    }
+}
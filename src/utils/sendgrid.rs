@@ -74,3 +74,171 @@ pub async fn send_contact_email(name: &str, email: &str, message: &str) -> Resul
 
     Ok(())
 }
+
+/// Send a password reset code to the user.
+pub async fn send_password_reset(email: &str, name: &str, code: &str) -> Result<(), SendgridError> {
+    let api_key = std::env::var("SENDGRID_API_KEY").unwrap();
+    let client = Sender::new(api_key);
+
+    let personalization = Personalization::new(Email::new(email.to_string()));
+
+    let body = format!(
+        "Hi {},\n\nUse the following code to reset your Scrippt password: {}\n\nThe code expires in 15 minutes. If you did not request a password reset, you can ignore this email.",
+        name, code
+    );
+    let sender = Email::new("noreply@scrippt.tech".to_string()).set_name("Scrippt".to_string());
+    let message = Message::new(sender)
+        .set_subject("Scrippt: Reset your password")
+        .add_personalization(personalization)
+        .add_content(Content::new().set_content_type("text/plain").set_value(&body));
+
+    let resp = client.send(&message).await?;
+
+    log::debug!("[SENDGRID] Password reset response email: {:?}", resp);
+
+    Ok(())
+}
+
+/// Let the user know their account was temporarily locked after too many failed login attempts.
+pub async fn send_account_locked(email: &str, name: &str, minutes: usize) -> Result<(), SendgridError> {
+    let api_key = std::env::var("SENDGRID_API_KEY").unwrap();
+    let client = Sender::new(api_key);
+
+    let personalization = Personalization::new(Email::new(email.to_string()));
+
+    let body = format!(
+        "Hi {},\n\nWe noticed several failed attempts to log in to your Scrippt account, so logging in has been blocked for {} minutes.\n\nIf this was not you, we recommend resetting your password and enabling two-factor authentication.",
+        name, minutes
+    );
+    let sender = Email::new("noreply@scrippt.tech".to_string()).set_name("Scrippt".to_string());
+    let message = Message::new(sender)
+        .set_subject("Scrippt: Failed login attempts on your account")
+        .add_personalization(personalization)
+        .add_content(Content::new().set_content_type("text/plain").set_value(&body));
+
+    let resp = client.send(&message).await?;
+
+    log::debug!("[SENDGRID] Account locked response email: {:?}", resp);
+
+    Ok(())
+}
+
+/// Send a single-use sign-in link to the user.
+pub async fn send_magic_link(email: &str, name: &str, link: &str) -> Result<(), SendgridError> {
+    let api_key = std::env::var("SENDGRID_API_KEY").unwrap();
+    let client = Sender::new(api_key);
+
+    let personalization = Personalization::new(Email::new(email.to_string()));
+
+    let body = format!(
+        "Hi {},\n\nUse the following link to sign in to Scrippt: {}\n\nThe link can only be used once and expires in 15 minutes. If you did not request it, you can ignore this email.",
+        name, link
+    );
+    let sender = Email::new("noreply@scrippt.tech".to_string()).set_name("Scrippt".to_string());
+    let message = Message::new(sender)
+        .set_subject("Scrippt: Your sign-in link")
+        .add_personalization(personalization)
+        .add_content(Content::new().set_content_type("text/plain").set_value(&body));
+
+    let resp = client.send(&message).await?;
+
+    log::debug!("[SENDGRID] Magic link response email: {:?}", resp);
+
+    Ok(())
+}
+
+/// Let the user know the email of their account was changed. Sent to the old address.
+pub async fn send_email_changed(email: &str, name: &str, new_email: &str) -> Result<(), SendgridError> {
+    let api_key = std::env::var("SENDGRID_API_KEY").unwrap();
+    let client = Sender::new(api_key);
+
+    let personalization = Personalization::new(Email::new(email.to_string()));
+
+    let body = format!(
+        "Hi {},\n\nThe email of your Scrippt account was changed to {}. You will no longer receive emails about your account at this address.\n\nIf you did not make this change, please contact support right away.",
+        name, new_email
+    );
+    let sender = Email::new("noreply@scrippt.tech".to_string()).set_name("Scrippt".to_string());
+    let message = Message::new(sender)
+        .set_subject("Scrippt: Your email was changed")
+        .add_personalization(personalization)
+        .add_content(Content::new().set_content_type("text/plain").set_value(&body));
+
+    let resp = client.send(&message).await?;
+
+    log::debug!("[SENDGRID] Email changed response email: {:?}", resp);
+
+    Ok(())
+}
+
+/// Let the user know the export of their data they asked for can be downloaded.
+pub async fn send_export_ready(email: &str, name: &str, hours: usize) -> Result<(), SendgridError> {
+    let api_key = std::env::var("SENDGRID_API_KEY").unwrap();
+    let client = Sender::new(api_key);
+
+    let personalization = Personalization::new(Email::new(email.to_string()));
+
+    let body = format!(
+        "Hi {},\n\nThe copy of your Scrippt data you asked for is ready. Download it from your account settings within {} hour(s), after which it is deleted.\n\nIf you did not ask for it, please change your password and contact support.",
+        name, hours
+    );
+    let sender = Email::new("noreply@scrippt.tech".to_string()).set_name("Scrippt".to_string());
+    let message = Message::new(sender)
+        .set_subject("Scrippt: Your data export is ready")
+        .add_personalization(personalization)
+        .add_content(Content::new().set_content_type("text/plain").set_value(&body));
+
+    let resp = client.send(&message).await?;
+
+    log::debug!("[SENDGRID] Export ready response email: {:?}", resp);
+
+    Ok(())
+}
+
+/// Confirm to the user that their account is scheduled for deletion, with a link to restore it.
+pub async fn send_account_deleted(email: &str, name: &str, link: &str, days: i64) -> Result<(), SendgridError> {
+    let api_key = std::env::var("SENDGRID_API_KEY").unwrap();
+    let client = Sender::new(api_key);
+
+    let personalization = Personalization::new(Email::new(email.to_string()));
+
+    let body = format!(
+        "Hi {},\n\nYour Scrippt account is scheduled for deletion. It will be deleted for good along with all of your data in {} days.\n\nChanged your mind? Restore your account with the following link: {}\n\nIf you did not delete your account, please restore it and change your password right away.",
+        name, days, link
+    );
+    let sender = Email::new("noreply@scrippt.tech".to_string()).set_name("Scrippt".to_string());
+    let message = Message::new(sender)
+        .set_subject("Scrippt: Your account is scheduled for deletion")
+        .add_personalization(personalization)
+        .add_content(Content::new().set_content_type("text/plain").set_value(&body));
+
+    let resp = client.send(&message).await?;
+
+    log::debug!("[SENDGRID] Account deleted response email: {:?}", resp);
+
+    Ok(())
+}
+
+/// Send an invite code to someone so they can create an account.
+pub async fn send_invite(email: &str, code: &str, link: &str) -> Result<(), SendgridError> {
+    let api_key = std::env::var("SENDGRID_API_KEY").unwrap();
+    let client = Sender::new(api_key);
+
+    let personalization = Personalization::new(Email::new(email.to_string()));
+
+    let body = format!(
+        "Hi,\n\nYou are invited to join Scrippt! Create your account with the following link: {}\n\nOr enter the invite code {} when signing up.",
+        link, code
+    );
+    let sender = Email::new("noreply@scrippt.tech".to_string()).set_name("Scrippt".to_string());
+    let message = Message::new(sender)
+        .set_subject("Scrippt: You are invited")
+        .add_personalization(personalization)
+        .add_content(Content::new().set_content_type("text/plain").set_value(&body));
+
+    let resp = client.send(&message).await?;
+
+    log::debug!("[SENDGRID] Invite response email: {:?}", resp);
+
+    Ok(())
+}
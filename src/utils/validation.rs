@@ -1,35 +1,133 @@
-use argon2;
+use argon2::{self, ThreadMode, Variant, Version};
 use email_address_parser::EmailAddress;
+use lazy_static::lazy_static;
 use rand::Rng;
+use std::env;
 
 // Password hashing
 
+lazy_static! {
+    /// The argon2 parameters new password hashes are created with, loaded from the environment.
+    ///
+    /// Reads `ARGON2_VARIANT`, `ARGON2_MEMORY_KIB`, `ARGON2_ITERATIONS` and `ARGON2_PARALLELISM`.
+    pub static ref HASH_PARAMS: HashParams = HashParams::from_env().unwrap_or_else(|e| panic!("Failed to load argon2 parameters: {}", e));
+}
+
+/// The argon2 parameters of a password hash
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HashParams {
+    pub variant: Variant,
+    pub mem_cost: u32,
+    pub time_cost: u32,
+    pub lanes: u32,
+}
+
+impl Default for HashParams {
+    /// The minimum argon2id parameters recommended by OWASP
+    fn default() -> Self {
+        HashParams {
+            variant: Variant::Argon2id,
+            mem_cost: 19 * 1024,
+            time_cost: 2,
+            lanes: 1,
+        }
+    }
+}
+
+impl HashParams {
+    /// Load the parameters from the environment. Unset variables keep their default.
+    pub fn from_env() -> Result<Self, String> {
+        let mut params = HashParams::default();
+        if let Ok(variant) = env::var("ARGON2_VARIANT") {
+            params.variant = Variant::from_str(&variant).map_err(|_| format!("Unknown ARGON2_VARIANT {}", variant))?;
+        }
+        if let Ok(mem_cost) = env::var("ARGON2_MEMORY_KIB") {
+            params.mem_cost = mem_cost.parse().map_err(|_| "ARGON2_MEMORY_KIB must be a number".to_string())?;
+        }
+        if let Ok(time_cost) = env::var("ARGON2_ITERATIONS") {
+            params.time_cost = time_cost.parse().map_err(|_| "ARGON2_ITERATIONS must be a number".to_string())?;
+        }
+        if let Ok(lanes) = env::var("ARGON2_PARALLELISM") {
+            params.lanes = lanes.parse().map_err(|_| "ARGON2_PARALLELISM must be a number".to_string())?;
+        }
+        Ok(params)
+    }
+
+    /// Read the parameters of an encoded hash (`$argon2id$v=19$m=19456,t=2,p=1$<salt>$<hash>`)
+    pub fn from_hash(hash: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = hash.split('$').collect();
+        if parts.len() != 6 || !parts[0].is_empty() {
+            return Err("Malformed password hash".to_string());
+        }
+        let variant = Variant::from_str(parts[1]).map_err(|_| "Unknown password hash variant".to_string())?;
+
+        let mut params = HashParams {
+            variant,
+            mem_cost: 0,
+            time_cost: 0,
+            lanes: 0,
+        };
+        for param in parts[3].split(',') {
+            let (name, value) = param.split_once('=').ok_or("Malformed password hash parameters")?;
+            let value = value.parse::<u32>().map_err(|_| "Malformed password hash parameters".to_string())?;
+            match name {
+                "m" => params.mem_cost = value,
+                "t" => params.time_cost = value,
+                "p" => params.lanes = value,
+                _ => return Err("Malformed password hash parameters".to_string()),
+            }
+        }
+        Ok(params)
+    }
+
+    fn config(&self) -> argon2::Config<'static> {
+        argon2::Config {
+            variant: self.variant,
+            version: Version::Version13,
+            mem_cost: self.mem_cost,
+            time_cost: self.time_cost,
+            lanes: self.lanes,
+            thread_mode: ThreadMode::Sequential,
+            secret: &[],
+            ad: &[],
+            hash_length: 32,
+        }
+    }
+}
+
 /// Generate a hash from a password
 pub fn generate_hash(password: &str) -> String {
+    generate_hash_with(password, &HASH_PARAMS)
+}
+
+/// Generate a hash from a password with the given parameters
+pub fn generate_hash_with(password: &str, params: &HashParams) -> String {
     let salt = rand::thread_rng().gen::<[u8; 32]>();
-    let config = argon2::Config::default();
-    let hash = argon2::hash_encoded(password.as_bytes(), &salt, &config).unwrap();
-    hash
+    argon2::hash_encoded(password.as_bytes(), &salt, &params.config()).unwrap()
+}
+
+/// Verify a password against a hash.
+/// Returns an error if the stored hash is malformed.
+pub fn verify_hash(password: &str, hash: &str) -> Result<bool, String> {
+    argon2::verify_encoded(hash, password.as_bytes()).map_err(|e| e.to_string())
 }
 
-/// Verify a password against a hash
-pub fn verify_hash(password: &str, hash: &str) -> bool {
-    argon2::verify_encoded(hash, password.as_bytes()).unwrap()
+/// Check if a hash was created with other parameters than the current ones,
+/// and should be replaced the next time the password is known
+pub fn needs_rehash(hash: &str) -> bool {
+    match HashParams::from_hash(hash) {
+        Ok(params) => params != *HASH_PARAMS,
+        Err(_) => true,
+    }
 }
 
 // Information validation
 
-/// Validate a user's email and password
-pub fn validate_signup(email: &str, password: &str) -> Result<(), String> {
+/// Validate a user's email. Passwords are validated by the password policy.
+pub fn validate_email(email: &str) -> Result<(), String> {
     if email.is_empty() {
         return Err("Email cannot be empty".to_string());
     }
-    if password.is_empty() {
-        return Err("Password cannot be empty".to_string());
-    }
-    if password.len() < 8 {
-        return Err("Password must be at least 8 characters".to_string());
-    }
     if !EmailAddress::is_valid(email, None) {
         return Err("Invalid email".to_string());
     }
@@ -51,3 +149,39 @@ pub fn get_expiration_time(minutes: usize) -> usize {
     let expiration_time = now + chrono::Duration::minutes(minutes as i64);
     expiration_time.timestamp() as usize
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_params_roundtrip() {
+        let params = HashParams {
+            variant: Variant::Argon2i,
+            mem_cost: 4096,
+            time_cost: 3,
+            lanes: 1,
+        };
+        let hash = generate_hash_with("password", &params);
+        assert_eq!(HashParams::from_hash(&hash).unwrap(), params);
+        assert!(verify_hash("password", &hash).unwrap());
+        assert!(!verify_hash("badpassword", &hash).unwrap());
+    }
+
+    #[test]
+    fn test_needs_rehash() {
+        let outdated = HashParams {
+            time_cost: HASH_PARAMS.time_cost + 1,
+            ..*HASH_PARAMS
+        };
+        assert!(needs_rehash(&generate_hash_with("password", &outdated)));
+        assert!(!needs_rehash(&generate_hash("password")));
+    }
+
+    #[test]
+    fn test_malformed_hash() {
+        assert!(verify_hash("password", "not a hash").is_err());
+        assert!(HashParams::from_hash("$argon2id$v=19$m=abc$salt$hash").is_err());
+        assert!(needs_rehash("not a hash"));
+    }
+}
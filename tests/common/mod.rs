@@ -0,0 +1,87 @@
+#![allow(dead_code)]
+
+use actix_http::body::MessageBody;
+use actix_service::ServiceFactory;
+use actix_web::{
+    dev::{ServiceRequest, ServiceResponse},
+    error::Error,
+    middleware, test, web, App,
+};
+use server::repository::{database::DatabaseRepository, redis::RedisRepository};
+use std::sync::Once;
+
+static INIT: Once = Once::new();
+
+pub const PASSWORD: &str = "correct-horse-battery";
+
+/// Build an app with an empty database and the given routes
+pub async fn get_app(
+    routes: fn(&mut web::ServiceConfig),
+) -> App<impl ServiceFactory<ServiceRequest, Response = ServiceResponse<impl MessageBody>, Config = (), InitError = (), Error = Error>> {
+    // set up the logger to debug
+    INIT.call_once(env_logger::init);
+    let db = DatabaseRepository::new("mongodb://localhost:27017").await;
+    let redis = RedisRepository::new("redis://localhost:6379");
+    let _ = db.drop_database().await;
+    let _ = redis.del_matching("attempts:*").await;
+    let _ = redis.del_matching("lockout:*").await;
+    let _ = redis.del_matching("quota:*").await;
+    let _ = redis.del_matching("export*").await;
+    App::new()
+        .wrap(middleware::NormalizePath::trim())
+        .wrap(middleware::Logger::default())
+        .app_data(web::Data::new(db))
+        .app_data(web::Data::new(redis))
+        .configure(routes)
+}
+
+/// Sign up with a verified email and return the response
+pub async fn signup<S, B>(app: &S, name: &str, email: &str, invite_code: Option<&str>) -> ServiceResponse<B>
+where
+    S: actix_service::Service<actix_http::Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let redis = RedisRepository::new("redis://localhost:6379");
+    redis.set(email, "123456:used").await.unwrap();
+    let req = test::TestRequest::post()
+        .uri("/account/create/")
+        .set_json(serde_json::json!({
+            "name": name,
+            "email": email,
+            "password": PASSWORD,
+            "invite_code": invite_code
+        }))
+        .to_request();
+    test::call_service(app, req).await
+}
+
+/// Create an account and return its id and access token
+pub async fn create_user<S, B>(app: &S, name: &str, email: &str) -> (String, String)
+where
+    S: actix_service::Service<actix_http::Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let resp = signup(app, name, email, None).await;
+    assert_eq!(resp.status(), 201);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    (json["id"].as_str().unwrap().to_string(), json["token"].as_str().unwrap().to_string())
+}
+
+/// Log in and return the access token
+pub async fn login<S, B>(app: &S, email: &str) -> String
+where
+    S: actix_service::Service<actix_http::Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let req = test::TestRequest::post()
+        .uri("/account/auth/login")
+        .set_json(serde_json::json!({
+            "email": email,
+            "password": PASSWORD
+        }))
+        .to_request();
+    let resp = test::call_service(app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    json["token"].as_str().unwrap().to_string()
+}
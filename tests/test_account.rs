@@ -10,17 +10,36 @@ use actix_web::{
     test,
     web,
     App,
+    HttpResponse,
+    HttpServer,
 };
 use server::handlers::account_handlers::*;
+use server::handlers::identity_handlers::*;
+use server::handlers::two_factor_handlers::*;
+use server::models::invite::{Invite, InviteKind};
+use server::models::user::{billing::Billing, plan::Plan, two_factor::TwoFactor};
+use server::models::waitlist::WaitlistEntry;
 use server::repository::{database::DatabaseRepository, redis::RedisRepository};
 // use std::env;
+use jsonwebtoken::{jwk::JwkSet, Algorithm, EncodingKey, Header};
 use more_asserts::*;
 use serial_test::serial;
-use server::auth::jwt::{decode_google_token_id, decode_jwt};
+use server::auth::jwks::JwksCache;
+use server::auth::jwt::decode_jwt;
+use server::auth::keys::SigningKey;
+use server::auth::lockout;
+use server::auth::oidc::{OidcProvider, OidcProviders};
+use server::auth::totp;
+use server::utils::validation::{self, HashParams};
+use sha2::{Digest, Sha256};
 use std::sync::Once;
 
 static INIT: Once = Once::new();
 
+const MOCK_ISSUER: &str = "https://issuer.example.com";
+const MOCK_CLIENT_ID: &str = "scrippt-test";
+const MOCK_KEY: &[u8] = include_bytes!("fixtures/keys/rsa-test.pem");
+
 async fn get_app(
 ) -> App<impl ServiceFactory<ServiceRequest, Response = ServiceResponse<impl MessageBody>, Config = (), InitError = (), Error = Error>> {
     // set up the logger to debug
@@ -28,22 +47,96 @@ async fn get_app(
     let db = DatabaseRepository::new("mongodb://localhost:27017").await;
     let redis = RedisRepository::new("redis://localhost:6379");
     let _ = db.drop_database().await;
+    let _ = redis.del_matching("attempts:*").await;
+    let _ = redis.del_matching("lockout:*").await;
+    let _ = redis.del_matching("magic_link_sent:*").await;
+    let mut providers = OidcProviders::default();
+    providers.add(OidcProvider {
+        name: "mock".to_string(),
+        issuers: vec![MOCK_ISSUER.to_string()],
+        jwks_url: format!("{}/jwks", start_mock_issuer()),
+        audiences: vec![MOCK_CLIENT_ID.to_string()],
+        algorithms: vec![Algorithm::RS256],
+        claims: Default::default(),
+    });
     App::new()
         .wrap(middleware::NormalizePath::trim())
         .wrap(middleware::Logger::default())
         .app_data(web::Data::new(db))
         .app_data(web::Data::new(redis))
+        .app_data(web::Data::new(providers))
+        .app_data(web::Data::new(JwksCache::new()))
         .service(
             web::scope("/account")
                 .service(create_account)
-                .service(authenticate_external_account)
                 .service(get_account_by_id)
                 .service(update_account)
+                .service(confirm_email_change)
                 .service(delete_account)
+                .service(restore_account)
                 .service(login_account)
+                .service(refresh_token)
+                .service(logout)
+                .service(logout_all)
+                .service(forgot_password)
+                .service(reset_password)
+                .service(enroll_two_factor)
+                .service(confirm_two_factor)
+                .service(disable_two_factor)
+                .service(login_two_factor)
                 .service(get_verification_code)
-                .service(verify_email),
+                .service(verify_email)
+                .service(request_magic_link)
+                .service(consume_magic_link)
+                .service(list_identities)
+                .service(link_identity)
+                .service(unlink_identity)
+                .service(authenticate_external_account),
+        )
+}
+
+/// Start a local OpenID Connect issuer serving the JWK set of the test key.
+/// Returns the base URL of the issuer.
+fn start_mock_issuer() -> String {
+    let jwk = SigningKey::from_pem("mock".to_string(), Algorithm::RS256, MOCK_KEY, None).unwrap().jwk.unwrap();
+    let jwks = JwkSet { keys: vec![jwk] };
+    let server = HttpServer::new(move || {
+        let jwks = jwks.clone();
+        App::new().route(
+            "/jwks",
+            web::get().to(move || {
+                let jwks = jwks.clone();
+                async move { HttpResponse::Ok().insert_header((header::CACHE_CONTROL, "public, max-age=3600")).json(jwks) }
+            }),
         )
+    })
+    .workers(1)
+    .bind("127.0.0.1:0")
+    .unwrap();
+    let address = server.addrs()[0];
+    actix_rt::spawn(server.run());
+    format!("http://{}", address)
+}
+
+/// Sign an ID token with the mock issuer's key
+fn mock_id_token(claims: serde_json::Value) -> String {
+    let mut header = Header::new(Algorithm::RS256);
+    header.kid = Some("mock".to_string());
+    jsonwebtoken::encode(&header, &claims, &EncodingKey::from_rsa_pem(MOCK_KEY).unwrap()).unwrap()
+}
+
+fn mock_claims(sub: &str, email: &str, name: &str) -> serde_json::Value {
+    let now = chrono::Utc::now().timestamp();
+    serde_json::json!({
+        "iss": MOCK_ISSUER,
+        "aud": MOCK_CLIENT_ID,
+        "sub": sub,
+        "email": email,
+        "email_verified": true,
+        "name": name,
+        "iat": now,
+        "exp": now + 300,
+    })
 }
 
 async fn create_some_account(name: &str, email: &str) -> actix_http::Request {
@@ -54,7 +147,7 @@ async fn create_some_account(name: &str, email: &str) -> actix_http::Request {
         .set_json(serde_json::json!({
             "name": name,
             "email": email,
-            "password": "password"
+            "password": "correct-horse-battery"
         }))
         .to_request()
 }
@@ -80,7 +173,7 @@ async fn test_create_account() {
     let token = json["token"].as_str().unwrap();
 
     // assert that the token is a valid jwt
-    let jwt = decode_jwt(token.to_string(), "secret").unwrap();
+    let jwt = decode_jwt(token.to_string()).unwrap();
     assert_eq!(jwt.iss, std::env::var("APP_NAME").unwrap());
     assert_eq!(jwt.sub, id);
     assert_eq!(jwt.aud, std::env::var("DOMAIN").unwrap());
@@ -90,16 +183,47 @@ async fn test_create_account() {
     assert_eq!(jwt.jti.len(), 36);
 }
 
+/// This test creates accounts with passwords the password policy rejects,
+/// and asserts that every failed rule is returned
+#[actix_rt::test]
+#[serial]
+async fn test_create_account_weak_password() {
+    let app = test::init_service(get_app().await).await;
+    let redis = RedisRepository::new("redis://localhost:6379");
+    redis.set("johndoe@email.com", "123456:used").await.unwrap();
+
+    for (password, rules) in [
+        ("short", vec!["min_length", "strength"]),
+        ("Password1", vec!["common"]),
+        ("johndoe-rocks-42", vec!["personal_info"]),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/account/create/")
+            .set_json(serde_json::json!({
+                "name": "John Doe",
+                "email": "johndoe@email.com",
+                "password": password
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+        let json: serde_json::Value = test::read_body_json(resp).await;
+        let violations: Vec<&str> = json["violations"].as_array().unwrap().iter().map(|v| v["rule"].as_str().unwrap()).collect();
+        assert_eq!(violations, rules);
+        assert!(json["strength"].is_string());
+    }
+}
+
+/// This test signs in with an ID token from a mock OpenID Connect issuer,
+/// which creates an account, and then signs in again to the same account.
 #[actix_rt::test]
 #[serial]
-#[ignore = "This test requires a valid google token id"]
 async fn test_external_account() {
     let app = get_app().await;
     let app = test::init_service(app).await;
-    std::env::set_var("GOOGLE_JWK_PATH", ".jwk");
 
-    let google_token_id = "<token_goes_here>";
-    let req = test::TestRequest::post().uri(format!("/account/auth/google?token_id={}", google_token_id).as_str()).to_request();
+    let id_token = mock_id_token(mock_claims("mock-user-1", "JaneDoe@email.com", "Jane Doe"));
+    let req = test::TestRequest::post().uri(format!("/account/auth/mock?token_id={}", id_token).as_str()).to_request();
     let resp = test::call_service(&app, req).await;
 
     assert_eq!(resp.status(), 201);
@@ -110,8 +234,6 @@ async fn test_external_account() {
     let id = json["id"].as_str().unwrap();
     let token = json["token"].as_str().unwrap();
 
-    let google_jwt_claims = decode_google_token_id(google_token_id).await.expect("failed to decode google token id");
-
     // get account and compare
     let req = test::TestRequest::get()
         .uri("/account/")
@@ -124,23 +246,25 @@ async fn test_external_account() {
     let body = test::read_body(resp).await;
     let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
 
-    log::debug!("{:?}", json["id"].as_str());
-
     assert_eq!(json["id"].as_str().unwrap(), id);
-    assert_eq!(json["email"].as_str().unwrap(), google_jwt_claims.email);
-    assert_eq!(json["name"].as_str().unwrap(), google_jwt_claims.name);
+    assert_eq!(json["email"].as_str().unwrap(), "janedoe@email.com");
+    assert_eq!(json["name"].as_str().unwrap(), "Jane Doe");
 
     // test login
-    let req = test::TestRequest::post().uri(format!("/account/auth/google?token_id={}", google_token_id).as_str()).to_request();
+    let req = test::TestRequest::post().uri(format!("/account/auth/mock?token_id={}", id_token).as_str()).to_request();
     let resp = test::call_service(&app, req).await;
 
     assert_eq!(resp.status(), 200);
 
+    let body = test::read_body(resp).await;
+    let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    assert_eq!(json["id"].as_str().unwrap(), id);
+
     // try to login with empty password
     let req = test::TestRequest::post()
         .uri("/account/auth/login")
         .set_json(serde_json::json!({
-            "email": google_jwt_claims.email,
+            "email": "janedoe@email.com",
             "password": "".to_string(),
         }))
         .to_request();
@@ -152,14 +276,153 @@ async fn test_external_account() {
     let req = test::TestRequest::post()
         .uri("/account/auth/login")
         .set_json(serde_json::json!({
-            "email": google_jwt_claims.email,
+            "email": "janedoe@email.com",
         }))
         .to_request();
     let resp = test::call_service(&app, req).await;
 
     assert_eq!(resp.status(), 400);
+}
 
-    // assert that the token is a valid jwt
+/// This test asserts that ID tokens are rejected when the provider is unknown,
+/// or when the token was issued for another audience or by another issuer
+#[actix_rt::test]
+#[serial]
+async fn test_external_account_invalid_token() {
+    let app = get_app().await;
+    let app = test::init_service(app).await;
+
+    let id_token = mock_id_token(mock_claims("mock-user-2", "janedoe@email.com", "Jane Doe"));
+    let req = test::TestRequest::post().uri(format!("/account/auth/unknown?token_id={}", id_token).as_str()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
+    let mut claims = mock_claims("mock-user-2", "janedoe@email.com", "Jane Doe");
+    claims["aud"] = serde_json::json!("another-client");
+    let req = test::TestRequest::post().uri(format!("/account/auth/mock?token_id={}", mock_id_token(claims)).as_str()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    let mut claims = mock_claims("mock-user-2", "janedoe@email.com", "Jane Doe");
+    claims["iss"] = serde_json::json!("https://another-issuer.example.com");
+    let req = test::TestRequest::post().uri(format!("/account/auth/mock?token_id={}", mock_id_token(claims)).as_str()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+}
+
+/// This test links external identities to a password account
+///
+/// It verifies that an identity is only linked automatically when the provider
+/// reports the email as verified, that identities can be linked and unlinked
+/// explicitly, and that the last way to log in cannot be unlinked
+#[actix_rt::test]
+#[serial]
+async fn test_external_account_linking() {
+    let app = get_app().await;
+    let app = test::init_service(app).await;
+    let req = create_some_account("John Doe", "johndoe@email.com").await;
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let id = json["id"].as_str().unwrap().to_string();
+    let token = json["token"].as_str().unwrap().to_string();
+
+    // an unverified email is not linked automatically
+    let mut claims = mock_claims("mock-user-3", "johndoe@email.com", "John Doe");
+    claims["email_verified"] = serde_json::json!(false);
+    let req = test::TestRequest::post().uri(format!("/account/auth/mock?token_id={}", mock_id_token(claims)).as_str()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+
+    // a verified email is
+    let id_token = mock_id_token(mock_claims("mock-user-3", "johndoe@email.com", "John Doe"));
+    let req = test::TestRequest::post().uri(format!("/account/auth/mock?token_id={}", id_token).as_str()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["id"].as_str().unwrap(), id);
+
+    // link another identity with a different email
+    let id_token = mock_id_token(mock_claims("mock-user-4", "john@work.com", "John Doe"));
+    let req = test::TestRequest::post()
+        .uri(format!("/account/identities/mock?token_id={}", id_token).as_str())
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let req = test::TestRequest::post()
+        .uri(format!("/account/identities/mock?token_id={}", id_token).as_str())
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+
+    let req = test::TestRequest::get()
+        .uri("/account/identities")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json.as_array().unwrap().len(), 2);
+
+    // unlink it
+    let req = test::TestRequest::delete()
+        .uri("/account/identities/mock/mock-user-4")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    // an account without a password cannot unlink its only identity
+    let id_token = mock_id_token(mock_claims("mock-user-5", "janedoe@email.com", "Jane Doe"));
+    let req = test::TestRequest::post().uri(format!("/account/auth/mock?token_id={}", id_token).as_str()).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let token = json["token"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::delete()
+        .uri("/account/identities/mock/mock-user-5")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+}
+
+/// This test signs in with an external identity to an account with 2FA enabled,
+/// and asserts that the second factor is still required
+#[actix_rt::test]
+#[serial]
+async fn test_external_account_two_factor() {
+    let app = get_app().await;
+    let app = test::init_service(app).await;
+    let req = create_some_account("John Doe", "johndoe@email.com").await;
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let id = json["id"].as_str().unwrap().to_string();
+
+    let db = DatabaseRepository::new("mongodb://localhost:27017").await;
+    let two_factor = TwoFactor {
+        secret: totp::generate_secret(),
+        enabled: true,
+        ..Default::default()
+    };
+    db.update_two_factor(&id, Some(two_factor)).await.unwrap();
+
+    // when the identity is linked, and when logging in with it afterwards
+    let id_token = mock_id_token(mock_claims("mock-user-6", "johndoe@email.com", "John Doe"));
+    for _ in 0..2 {
+        let req = test::TestRequest::post().uri(format!("/account/auth/mock?token_id={}", id_token).as_str()).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 202);
+        let json: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(json["two_factor_required"], true);
+        assert!(json["token"].is_null());
+        assert!(json["challenge_token"].is_string());
+    }
 }
 
 /// This test creates an account, then tries to create another account with the same email
@@ -227,7 +490,7 @@ async fn test_create_account_bad_request() {
         .set_json(serde_json::json!({
             "name": "John Doe",
             "email": "bad-email",
-            "password": "password"
+            "password": "correct-horse-battery"
         }))
         .to_request();
 
@@ -252,7 +515,7 @@ async fn test_create_account_bad_request() {
         .uri("/account/create/")
         .set_json(serde_json::json!({
             "email": "jane@email.com",
-            "password": "password"
+            "password": "correct-horse-battery"
         }))
         .to_request();
 
@@ -366,6 +629,7 @@ async fn test_update_account() {
     let body = test::read_body(resp).await;
     let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
     let token = json["token"].as_str().unwrap();
+    let id = json["id"].as_str().unwrap();
 
     // Update the name
     let req = test::TestRequest::patch()
@@ -385,7 +649,7 @@ async fn test_update_account() {
     assert_eq!(json["name"], "Jane Doe");
     assert_eq!(json["email"], "johndoe@email.com");
 
-    // Update the email
+    // Request an email change, which sends a code to the new email
     let req = test::TestRequest::patch()
         .uri("/account/")
         .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
@@ -395,6 +659,20 @@ async fn test_update_account() {
         }))
         .to_request();
 
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 202);
+
+    // The email is only changed once the code is confirmed
+    let redis = RedisRepository::new("redis://localhost:6379");
+    let value = redis.get(&format!("email_change:{}", id)).await.unwrap();
+    let code = value.split(':').next().unwrap().to_string();
+
+    let req = test::TestRequest::post()
+        .uri("/account/email/confirm")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(serde_json::json!({ "code": code }))
+        .to_request();
+
     let resp = test::call_service(&server, req).await;
     assert_eq!(resp.status(), 200);
 
@@ -426,7 +704,7 @@ async fn test_update_account() {
         .uri("/account/auth/login/")
         .set_json(serde_json::json!({
             "email": "janedoe@email.com",
-            "password": "password"
+            "password": "correct-horse-battery"
         }))
         .to_request();
     let resp = test::call_service(&server, req).await;
@@ -445,6 +723,67 @@ async fn test_update_account() {
     assert_eq!(resp.status(), 200);
 }
 
+/// This test asserts that an email change cannot take another account's email,
+/// and that the confirmation code cannot be guessed or used twice
+#[actix_rt::test]
+#[serial]
+async fn test_email_change_rejected() {
+    let server = test::init_service(get_app().await).await;
+    let req = create_some_account("Jane Doe", "janedoe@email.com").await;
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 201);
+    let req = create_some_account("John Doe", "johndoe@email.com").await;
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 201);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let token = json["token"].as_str().unwrap().to_string();
+    let id = json["id"].as_str().unwrap().to_string();
+
+    for (email, status) in [("JaneDoe@email.com", 409), ("not-an-email", 400)] {
+        let req = test::TestRequest::patch()
+            .uri("/account/")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .set_json(serde_json::json!({ "path": "email", "value": email }))
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert_eq!(resp.status(), status);
+    }
+
+    let req = test::TestRequest::patch()
+        .uri("/account/")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(serde_json::json!({ "path": "email", "value": "john@email.com" }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 202);
+
+    let redis = RedisRepository::new("redis://localhost:6379");
+    let value = redis.get(&format!("email_change:{}", id)).await.unwrap();
+    let code = value.split(':').next().unwrap().to_string();
+    let wrong_code = if code == "000000" { "111111" } else { "000000" };
+
+    for (code, status) in [(wrong_code, 401), (code.as_str(), 200), (code.as_str(), 400)] {
+        let req = test::TestRequest::post()
+            .uri("/account/email/confirm")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .set_json(serde_json::json!({ "code": code }))
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert_eq!(resp.status(), status);
+    }
+
+    // The old email no longer logs in
+    let req = test::TestRequest::post()
+        .uri("/account/auth/login/")
+        .set_json(serde_json::json!({
+            "email": "johndoe@email.com",
+            "password": "correct-horse-battery"
+        }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 404);
+}
+
 /// This test creates an account, then tries to delete the account
 ///
 /// It verifies that the response is 204 No Content
@@ -461,6 +800,8 @@ async fn test_delete_account() {
     let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
     let token = json["token"].as_str().unwrap();
 
+    let id = json["id"].as_str().unwrap();
+
     let req = test::TestRequest::delete()
         .uri("/account/")
         .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
@@ -468,6 +809,121 @@ async fn test_delete_account() {
     let resp = test::call_service(&server, req).await;
 
     assert_eq!(resp.status(), 204);
+
+    // The account is pending deletion, so its tokens are revoked and it cannot log in
+    let req = test::TestRequest::get()
+        .uri("/account/")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 401);
+
+    let login = || {
+        test::TestRequest::post()
+            .uri("/account/auth/login/")
+            .set_json(serde_json::json!({
+                "email": "johndoe@email.com",
+                "password": "correct-horse-battery"
+            }))
+            .to_request()
+    };
+    let resp = test::call_service(&server, login()).await;
+    assert_eq!(resp.status(), 403);
+
+    // The emailed token is never stored, so store a known one the same way
+    let redis = RedisRepository::new("redis://localhost:6379");
+    let restore_token = "restore-test-token";
+    let key = format!("restore:{}", hex::encode(Sha256::digest(restore_token.as_bytes())));
+    redis.set_ex(&key, id, 60).await.unwrap();
+
+    let req = test::TestRequest::post().uri("/account/restore").set_json(serde_json::json!({ "token": "wrong-token" })).to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 401);
+
+    let req = test::TestRequest::post().uri("/account/restore").set_json(serde_json::json!({ "token": restore_token })).to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let resp = test::call_service(&server, login()).await;
+    assert_eq!(resp.status(), 200);
+}
+
+/// This test deletes an account, then purges it once the grace period is over
+#[actix_rt::test]
+#[serial]
+async fn test_purge_deleted_account() {
+    let app = get_app().await;
+    let server = test::init_service(app).await;
+    let req = create_some_account("John Doe", "johndoe@email.com").await;
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 201);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let id = json["id"].as_str().unwrap();
+    let token = json["token"].as_str().unwrap();
+
+    let req = test::TestRequest::delete()
+        .uri("/account/")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 204);
+
+    let db = DatabaseRepository::new("mongodb://localhost:27017").await;
+    let redis = RedisRepository::new("redis://localhost:6379");
+
+    // Still within the grace period
+    assert_eq!(purge_deleted_accounts(&db, &redis).await.unwrap(), (0, 0));
+    assert!(db.get_user(id).await.unwrap().is_some());
+
+    // Data stored about the user outside of the account
+    redis.set(&format!("quota:{}:generate", id), "3").await.unwrap();
+    redis.set(&format!("export:{}", id), "archive").await.unwrap();
+    redis.set(&format!("export_status:{}", id), "ready").await.unwrap();
+    let invite = Invite {
+        id: None,
+        code: "ABCDEFGH".to_string(),
+        kind: InviteKind::Referral,
+        created_by: id.to_string(),
+        email: None,
+        max_uses: 1,
+        uses: 0,
+        date_created: chrono::Utc::now().timestamp(),
+        date_expires: None,
+    };
+    db.add_invite(invite).await.unwrap();
+    let entry = WaitlistEntry {
+        id: None,
+        email: "johndoe@email.com".to_string(),
+        name: None,
+        date_created: chrono::Utc::now().timestamp(),
+        date_invited: None,
+    };
+    db.add_to_waitlist(entry).await.unwrap();
+    let billing = Billing {
+        customer_id: "cus_1".to_string(),
+        subscription_id: Some("sub_1".to_string()),
+        status: "active".to_string(),
+        date_current_period_end: None,
+        date_updated: chrono::Utc::now().timestamp(),
+    };
+    db.set_billing(id, Plan::Pro, &billing).await.unwrap();
+
+    let deleted_at = chrono::Utc::now().timestamp() - (DELETION_GRACE_DAYS + 1) * 24 * 60 * 60;
+    let filter = bson::doc! {"_id": bson::oid::ObjectId::parse_str(id).unwrap()};
+    db.user_collection.update_one(filter, bson::doc! {"$set": {"date_deleted": deleted_at}}, None).await.unwrap();
+
+    assert_eq!(purge_deleted_accounts(&db, &redis).await.unwrap(), (1, 0));
+    assert!(db.get_user(id).await.unwrap().is_none());
+    assert!(!redis.exists(&format!("quota:{}:generate", id)).await.unwrap());
+    assert!(!redis.exists(&format!("export:{}", id)).await.unwrap());
+    assert!(!redis.exists(&format!("export_status:{}", id)).await.unwrap());
+    assert!(db.get_invites(Some(id), 10).await.unwrap().is_empty());
+    assert!(db.get_waitlist(false, 0, 10).await.unwrap().is_empty());
+
+    // The running subscription is flagged for cancellation
+    let entries = db.get_audit_entries(Some(id), 10).await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].action, "billing.cancel_required");
 }
 
 /// This test creates an account, then tries to login with the account
@@ -488,7 +944,7 @@ async fn test_account_login() {
         .uri("/account/auth/login/")
         .set_json(serde_json::json!({
             "email": "johndoe@email.com",
-            "password": "password"
+            "password": "correct-horse-battery"
         }))
         .to_request();
     let resp = test::call_service(&server, req).await;
@@ -498,7 +954,7 @@ async fn test_account_login() {
     let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
     let id = json["id"].as_str().unwrap();
     let token = json["token"].as_str().unwrap();
-    let jwt = decode_jwt(token.to_string(), &std::env::var("JWT_SECRET").expect("JWT secret must be set")).unwrap();
+    let jwt = decode_jwt(token.to_string()).unwrap();
     assert_eq!(jwt.iss, std::env::var("APP_NAME").unwrap());
     assert_eq!(jwt.sub, id);
     assert_eq!(jwt.aud, std::env::var("DOMAIN").unwrap());
@@ -535,97 +991,301 @@ async fn test_account_login_invalid_credentials() {
         .uri("/account/auth/login/")
         .set_json(serde_json::json!({
             "email": "wrong@email.com",
-            "password": "password"
+            "password": "correct-horse-battery"
         }))
         .to_request();
     let resp = test::call_service(&server, req).await;
     assert_eq!(resp.status(), 404);
 }
 
-/// This tests the verification code endpoint
-/// It verifies that the code was added to the redis cache
-#[actix_rt::test]
-#[serial]
-async fn test_verification_code() {
-    let app = get_app().await;
-    let server = test::init_service(app).await;
-    let req = test::TestRequest::post().uri("/account/auth/verification-code?name=test&email=some@email.com").to_request();
-    let res = test::call_service(&server, req).await;
-    assert_eq!(res.status(), 200);
-
-    // Verify that the redis cache contains the email as a key
-    let redis = RedisRepository::new("redis://localhost:6379");
-    let value = redis.get("some@email.com").await.unwrap();
-    let val = value.split(':').collect::<Vec<&str>>();
-    let exists = redis.exists("some@email.com").await.unwrap();
-    assert_eq!(val[0].len(), 6);
-    assert_eq!(val[1], "pending");
-    assert!(exists);
-}
-
-/// Create account integration test
-/// Sends a verification code to the email address
-/// Creates an account with the verification code
+/// This test creates an account, then fails to log in until the email is locked out
+///
+/// It verifies that the lockout returns 429 Too Many Requests with a Retry-After header,
+/// that even the correct password is rejected while locked out,
+/// and that clearing the lockout lets the user log in again
 #[actix_rt::test]
 #[serial]
-async fn test_create_account_verified() {
-    let email = "johndoe@gmail.com";
-    let name = "John";
+async fn test_account_login_lockout() {
     let app = get_app().await;
     let server = test::init_service(app).await;
-    let req = test::TestRequest::post().uri(&format!("/account/auth/verification-code?name={}&email={}", name, email)).to_request();
-    let res = test::call_service(&server, req).await;
-    assert_eq!(res.status(), 200);
-
-    // Verify that the redis cache contains the email as a key
-    let redis = RedisRepository::new("redis://localhost:6379");
-    let value = redis.get(email).await.unwrap();
-    let val = value.split(':').collect::<Vec<&str>>();
-    let exists = redis.exists(email).await.unwrap();
-    assert_eq!(val[0].len(), 6);
-    assert_eq!(val[1], "pending");
-    assert!(exists);
+    let req = create_some_account("John Doe", "johndoe@email.com").await;
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 201);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let id = json["id"].as_str().unwrap().to_string();
+
+    for _ in 0..lockout::LOGIN.email_attempts {
+        let req = test::TestRequest::post()
+            .uri("/account/auth/login/")
+            .set_json(serde_json::json!({
+                "email": "johndoe@email.com",
+                "password": "badpassword"
+            }))
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert_eq!(resp.status(), 401);
+    }
 
-    // Try to create an account, but fail because the email is not verified
     let req = test::TestRequest::post()
-        .uri("/account/create/")
+        .uri("/account/auth/login/")
         .set_json(serde_json::json!({
-            "name": name,
-            "email": email,
-            "password": "password",
+            "email": "johndoe@email.com",
+            "password": "badpassword"
         }))
         .to_request();
-    let res = test::call_service(&server, req).await;
-    assert_eq!(res.status(), 400);
-    // Get the response body and log the message
-    let body = test::read_body(res).await;
-    log::debug!("body: {}", std::str::from_utf8(&body).unwrap());
-
-    // submit code for verification
-    let req = test::TestRequest::post().uri(&format!("/account/auth/verify-email?email={}&code={}", email, val[0])).to_request();
-    let res = test::call_service(&server, req).await;
-    assert_eq!(res.status(), 200);
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 429);
+    let retry_after = resp.headers().get(header::RETRY_AFTER).unwrap().to_str().unwrap().parse::<usize>().unwrap();
+    assert_gt!(retry_after, 0);
 
-    // Create account
+    // the correct password is rejected while locked out
     let req = test::TestRequest::post()
-        .uri("/account/create/")
+        .uri("/account/auth/login/")
         .set_json(serde_json::json!({
-            "name": name,
-            "email": email,
-            "password": "password",
+            "email": "johndoe@email.com",
+            "password": "correct-horse-battery"
         }))
         .to_request();
-    let res = test::call_service(&server, req).await;
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 429);
 
-    assert_eq!(res.status(), 201);
+    let redis = RedisRepository::new("redis://localhost:6379");
+    let states = lockout::lockout_state(&redis, "johndoe@email.com", &id).await.unwrap();
+    let login = states.iter().find(|s| s.policy == "login").unwrap();
+    assert_eq!(login.failed_attempts, lockout::LOGIN.email_attempts + 1);
+    assert!(login.locked_for.is_some());
 
-    // Verify that the redis cache no longer contains the email as a key
-    let exists = redis.exists(email).await.unwrap();
-    assert!(!exists);
+    lockout::clear_failures(&redis, &lockout::LOGIN, "johndoe@email.com").await.unwrap();
+    let req = test::TestRequest::post()
+        .uri("/account/auth/login/")
+        .set_json(serde_json::json!({
+            "email": "johndoe@email.com",
+            "password": "correct-horse-battery"
+        }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
 }
 
-/// This test sends a verification code to an email address
-/// Then tries to verify the email address with an invalid code
+/// This test fails to log in to many emails from one client, with a different `X-Forwarded-For` header every time
+///
+/// It verifies that the header is ignored for connections that do not come from a trusted proxy,
+/// so the client IP is still locked out
+#[actix_rt::test]
+#[serial]
+async fn test_account_login_ip_lockout_ignores_forwarded_for() {
+    let app = get_app().await;
+    let server = test::init_service(app).await;
+    let peer: std::net::SocketAddr = "203.0.113.7:4000".parse().unwrap();
+
+    // one more failure than allowed locks out the IP, while no single email is over its limit
+    for i in 0..=lockout::LOGIN.ip_attempts {
+        let req = test::TestRequest::post()
+            .uri("/account/auth/login/")
+            .peer_addr(peer)
+            .insert_header((header::X_FORWARDED_FOR, format!("198.51.100.{}", i)))
+            .set_json(serde_json::json!({
+                "email": format!("user{}@email.com", i % 5),
+                "password": "badpassword"
+            }))
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert_eq!(resp.status(), 404);
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/account/auth/login/")
+        .peer_addr(peer)
+        .insert_header((header::X_FORWARDED_FOR, "198.51.100.200"))
+        .set_json(serde_json::json!({
+            "email": "someone-else@email.com",
+            "password": "badpassword"
+        }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 429);
+}
+
+/// This test stores a password hash created with outdated argon2 parameters, then logs in
+///
+/// It verifies that the login succeeds and that the hash is replaced with one using the current parameters
+#[actix_rt::test]
+#[serial]
+async fn test_account_login_rehash() {
+    let app = get_app().await;
+    let server = test::init_service(app).await;
+    let req = create_some_account("John Doe", "johndoe@email.com").await;
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let outdated = HashParams {
+        variant: argon2::Variant::Argon2i,
+        mem_cost: 4096,
+        time_cost: 3,
+        lanes: 1,
+    };
+    let db = DatabaseRepository::new("mongodb://localhost:27017").await;
+    db.user_collection
+        .update_one(
+            mongodb::bson::doc! {"email": "johndoe@email.com"},
+            mongodb::bson::doc! {"$set": {"password": validation::generate_hash_with("correct-horse-battery", &outdated)}},
+            None,
+        )
+        .await
+        .unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/account/auth/login/")
+        .set_json(serde_json::json!({
+            "email": "johndoe@email.com",
+            "password": "correct-horse-battery"
+        }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let user = db.get_account_by_email("johndoe@email.com").await.unwrap().unwrap();
+    let hash = user.password.unwrap();
+    assert_eq!(HashParams::from_hash(&hash).unwrap(), *validation::HASH_PARAMS);
+    assert!(validation::verify_hash("correct-horse-battery", &hash).unwrap());
+}
+
+/// This test requests a magic link, then logs in with it
+///
+/// It verifies that the request does not reveal whether an account exists,
+/// that the link logs in to the account, and that it can only be used once
+#[actix_rt::test]
+#[serial]
+async fn test_magic_link_login() {
+    let app = get_app().await;
+    let server = test::init_service(app).await;
+    let req = create_some_account("John Doe", "johndoe@email.com").await;
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 201);
+    let body = test::read_body(resp).await;
+    let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    let id = json["id"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::post().uri("/account/auth/magic-link?email=johndoe@email.com").to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+    let known: serde_json::Value = test::read_body_json(resp).await;
+
+    let req = test::TestRequest::post().uri("/account/auth/magic-link?email=nobody@email.com").to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+    let unknown: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(known, unknown);
+
+    // The emailed token is never stored, so store a known one the same way
+    let redis = RedisRepository::new("redis://localhost:6379");
+    let token = "magic-link-test-token";
+    let key = format!("magic_link:{}", hex::encode(Sha256::digest(token.as_bytes())));
+    redis.set_ex(&key, &format!("{}:pending", id), 60).await.unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/account/auth/magic-link/consume")
+        .set_json(serde_json::json!({ "token": token }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["id"].as_str().unwrap(), id);
+    assert!(json["refresh_token"].is_string());
+
+    // The link can only be used once
+    let req = test::TestRequest::post()
+        .uri("/account/auth/magic-link/consume")
+        .set_json(serde_json::json!({ "token": token }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 401);
+}
+
+/// This tests the verification code endpoint
+/// It verifies that the code was added to the redis cache
+#[actix_rt::test]
+#[serial]
+async fn test_verification_code() {
+    let app = get_app().await;
+    let server = test::init_service(app).await;
+    let req = test::TestRequest::post().uri("/account/auth/verification-code?name=test&email=some@email.com").to_request();
+    let res = test::call_service(&server, req).await;
+    assert_eq!(res.status(), 200);
+
+    // Verify that the redis cache contains the email as a key
+    let redis = RedisRepository::new("redis://localhost:6379");
+    let value = redis.get("some@email.com").await.unwrap();
+    let val = value.split(':').collect::<Vec<&str>>();
+    let exists = redis.exists("some@email.com").await.unwrap();
+    assert_eq!(val[0].len(), 6);
+    assert_eq!(val[1], "pending");
+    assert!(exists);
+}
+
+/// Create account integration test
+/// Sends a verification code to the email address
+/// Creates an account with the verification code
+#[actix_rt::test]
+#[serial]
+async fn test_create_account_verified() {
+    let email = "johndoe@gmail.com";
+    let name = "John";
+    let app = get_app().await;
+    let server = test::init_service(app).await;
+    let req = test::TestRequest::post().uri(&format!("/account/auth/verification-code?name={}&email={}", name, email)).to_request();
+    let res = test::call_service(&server, req).await;
+    assert_eq!(res.status(), 200);
+
+    // Verify that the redis cache contains the email as a key
+    let redis = RedisRepository::new("redis://localhost:6379");
+    let value = redis.get(email).await.unwrap();
+    let val = value.split(':').collect::<Vec<&str>>();
+    let exists = redis.exists(email).await.unwrap();
+    assert_eq!(val[0].len(), 6);
+    assert_eq!(val[1], "pending");
+    assert!(exists);
+
+    // Try to create an account, but fail because the email is not verified
+    let req = test::TestRequest::post()
+        .uri("/account/create/")
+        .set_json(serde_json::json!({
+            "name": name,
+            "email": email,
+            "password": "correct-horse-battery",
+        }))
+        .to_request();
+    let res = test::call_service(&server, req).await;
+    assert_eq!(res.status(), 400);
+    // Get the response body and log the message
+    let body = test::read_body(res).await;
+    log::debug!("body: {}", std::str::from_utf8(&body).unwrap());
+
+    // submit code for verification
+    let req = test::TestRequest::post().uri(&format!("/account/auth/verify-email?email={}&code={}", email, val[0])).to_request();
+    let res = test::call_service(&server, req).await;
+    assert_eq!(res.status(), 200);
+
+    // Create account
+    let req = test::TestRequest::post()
+        .uri("/account/create/")
+        .set_json(serde_json::json!({
+            "name": name,
+            "email": email,
+            "password": "correct-horse-battery",
+        }))
+        .to_request();
+    let res = test::call_service(&server, req).await;
+
+    assert_eq!(res.status(), 201);
+
+    // Verify that the redis cache no longer contains the email as a key
+    let exists = redis.exists(email).await.unwrap();
+    assert!(!exists);
+}
+
+/// This test sends a verification code to an email address
+/// Then tries to verify the email address with an invalid code
 #[actix_rt::test]
 #[serial]
 async fn test_invalid_verification() {
@@ -640,7 +1300,7 @@ async fn test_invalid_verification() {
         .set_json(serde_json::json!({
             "name": name,
             "email": email,
-            "password": "password",
+            "password": "correct-horse-battery",
         }))
         .to_request();
     let res = test::call_service(&server, req).await;
@@ -676,3 +1336,527 @@ async fn test_invalid_verification() {
     let body = test::read_body(res).await;
     log::debug!("Invalid verification code body: {}", std::str::from_utf8(&body).unwrap());
 }
+
+/// This test creates an account, then rotates its refresh token
+///
+/// It verifies that the refresh returns a new token pair,
+/// that the old refresh token cannot be reused,
+/// and that reusing it revokes the newly issued refresh token as well
+#[actix_rt::test]
+#[serial]
+async fn test_refresh_token_rotation() {
+    let app = get_app().await;
+    let server = test::init_service(app).await;
+    let req = create_some_account("John Doe", "johndoe@email.com").await;
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let body = test::read_body(resp).await;
+    let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    let id = json["id"].as_str().unwrap().to_string();
+    let first_refresh = json["refresh_token"].as_str().unwrap().to_string();
+
+    // Rotate the refresh token
+    let req = test::TestRequest::post()
+        .uri("/account/auth/refresh")
+        .set_json(serde_json::json!({ "refresh_token": first_refresh }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let body = test::read_body(resp).await;
+    let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    let second_refresh = json["refresh_token"].as_str().unwrap().to_string();
+    let token = json["token"].as_str().unwrap();
+    assert_ne!(first_refresh, second_refresh);
+    let jwt = decode_jwt(token.to_string()).unwrap();
+    assert_eq!(jwt.sub, id);
+
+    // Reuse the first refresh token
+    let req = test::TestRequest::post()
+        .uri("/account/auth/refresh")
+        .set_json(serde_json::json!({ "refresh_token": first_refresh }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 401);
+
+    // The whole family is revoked, so the second refresh token is rejected too
+    let req = test::TestRequest::post()
+        .uri("/account/auth/refresh")
+        .set_json(serde_json::json!({ "refresh_token": second_refresh }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 401);
+
+    // Unknown refresh token
+    let req = test::TestRequest::post()
+        .uri("/account/auth/refresh")
+        .set_json(serde_json::json!({ "refresh_token": "not-a-token" }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 401);
+}
+
+/// This test creates an account, then logs out of the current session
+///
+/// It verifies that the access token and the refresh token are rejected after logging out
+#[actix_rt::test]
+#[serial]
+async fn test_logout() {
+    let app = get_app().await;
+    let server = test::init_service(app).await;
+    let req = create_some_account("John Doe", "johndoe@email.com").await;
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let body = test::read_body(resp).await;
+    let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    let token = json["token"].as_str().unwrap();
+    let refresh = json["refresh_token"].as_str().unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/account/auth/logout")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(serde_json::json!({ "refresh_token": refresh }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let req = test::TestRequest::get()
+        .uri("/account/")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 401);
+
+    let req = test::TestRequest::post()
+        .uri("/account/auth/refresh")
+        .set_json(serde_json::json!({ "refresh_token": refresh }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 401);
+}
+
+/// This test creates an account, logs in a second time, then logs out everywhere
+///
+/// It verifies that the tokens of both sessions are rejected
+#[actix_rt::test]
+#[serial]
+async fn test_logout_all() {
+    let app = get_app().await;
+    let server = test::init_service(app).await;
+    let req = create_some_account("John Doe", "johndoe@email.com").await;
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let body = test::read_body(resp).await;
+    let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    let first_token = json["token"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::post()
+        .uri("/account/auth/login/")
+        .set_json(serde_json::json!({
+            "email": "johndoe@email.com",
+            "password": "correct-horse-battery"
+        }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let body = test::read_body(resp).await;
+    let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    let second_token = json["token"].as_str().unwrap().to_string();
+    let second_refresh = json["refresh_token"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::post()
+        .uri("/account/auth/logout-all")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", first_token)))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+
+    for token in [first_token, second_token] {
+        let req = test::TestRequest::get()
+            .uri("/account/")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert_eq!(resp.status(), 401);
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/account/auth/refresh")
+        .set_json(serde_json::json!({ "refresh_token": second_refresh }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 401);
+}
+
+/// This test logs out everywhere and logs in again right away
+///
+/// It verifies that tokens issued in the same second as the revocation are accepted
+#[actix_rt::test]
+#[serial]
+async fn test_login_after_logout_all() {
+    let app = get_app().await;
+    let server = test::init_service(app).await;
+    let req = create_some_account("John Doe", "johndoe@email.com").await;
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let body = test::read_body(resp).await;
+    let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    let old_token = json["token"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::post()
+        .uri("/account/auth/logout-all")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", old_token)))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let req = test::TestRequest::post()
+        .uri("/account/auth/login/")
+        .set_json(serde_json::json!({
+            "email": "johndoe@email.com",
+            "password": "correct-horse-battery"
+        }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let body = test::read_body(resp).await;
+    let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    let token = json["token"].as_str().unwrap().to_string();
+    let refresh_token = json["refresh_token"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::get()
+        .uri("/account/")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let req = test::TestRequest::post()
+        .uri("/account/auth/refresh")
+        .set_json(serde_json::json!({ "refresh_token": refresh_token }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+
+    // the token from before the revocation is still rejected
+    let req = test::TestRequest::get()
+        .uri("/account/")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", old_token)))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 401);
+}
+
+/// This test creates an account, then resets its password with an emailed code
+///
+/// It verifies that the old session is logged out, that the new password works
+/// and that the reset code cannot be used twice
+#[actix_rt::test]
+#[serial]
+async fn test_reset_password() {
+    let app = get_app().await;
+    let server = test::init_service(app).await;
+    let req = create_some_account("John Doe", "johndoe@email.com").await;
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let body = test::read_body(resp).await;
+    let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    let token = json["token"].as_str().unwrap().to_string();
+
+    // Unknown emails get the same response
+    let req = test::TestRequest::post().uri("/account/auth/forgot-password?email=nobody@email.com").to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let req = test::TestRequest::post().uri("/account/auth/forgot-password?email=johndoe@email.com").to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let redis = RedisRepository::new("redis://localhost:6379");
+    let value = redis.get("reset:johndoe@email.com").await.unwrap();
+    let val = value.split(':').collect::<Vec<&str>>();
+    assert_eq!(val[0].len(), 6);
+    assert_eq!(val[1], "pending");
+
+    // Wrong code
+    let req = test::TestRequest::post()
+        .uri("/account/auth/reset-password")
+        .set_json(serde_json::json!({
+            "email": "johndoe@email.com",
+            "code": "000000",
+            "password": "new-password"
+        }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 401);
+
+    let reset = serde_json::json!({
+        "email": "johndoe@email.com",
+        "code": val[0],
+        "password": "new-password"
+    });
+    let req = test::TestRequest::post().uri("/account/auth/reset-password").set_json(&reset).to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+
+    // The code is single use
+    let req = test::TestRequest::post().uri("/account/auth/reset-password").set_json(&reset).to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 400);
+
+    // Existing sessions are logged out
+    let req = test::TestRequest::get()
+        .uri("/account/")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 401);
+
+    let req = test::TestRequest::post()
+        .uri("/account/auth/login/")
+        .set_json(serde_json::json!({
+            "email": "johndoe@email.com",
+            "password": "new-password"
+        }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+}
+
+/// This test guesses the password reset code of an account until the email is locked out
+///
+/// It verifies that the lockout returns 429 Too Many Requests and that the code is deleted,
+/// so even the right code no longer works
+#[actix_rt::test]
+#[serial]
+async fn test_reset_password_lockout() {
+    let app = get_app().await;
+    let server = test::init_service(app).await;
+    let req = create_some_account("John Doe", "johndoe@email.com").await;
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let req = test::TestRequest::post().uri("/account/auth/forgot-password?email=johndoe@email.com").to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+    let redis = RedisRepository::new("redis://localhost:6379");
+    let value = redis.get("reset:johndoe@email.com").await.unwrap();
+    let code = value.split(':').next().unwrap().to_string();
+    let wrong_code = if code == "000000" { "111111" } else { "000000" };
+
+    let reset = |code: &str| {
+        test::TestRequest::post()
+            .uri("/account/auth/reset-password")
+            .set_json(serde_json::json!({
+                "email": "johndoe@email.com",
+                "code": code,
+                "password": "new-password-after-reset"
+            }))
+            .to_request()
+    };
+
+    for _ in 0..lockout::RESET_PASSWORD.email_attempts {
+        let resp = test::call_service(&server, reset(wrong_code)).await;
+        assert_eq!(resp.status(), 401);
+    }
+    let resp = test::call_service(&server, reset(wrong_code)).await;
+    assert_eq!(resp.status(), 429);
+    assert!(resp.headers().get(header::RETRY_AFTER).is_some());
+
+    // the code was deleted, so it does not work even once the lockout is over
+    assert!(!redis.exists("reset:johndoe@email.com").await.unwrap());
+    let resp = test::call_service(&server, reset(&code)).await;
+    assert_eq!(resp.status(), 429);
+    lockout::clear_failures(&redis, &lockout::RESET_PASSWORD, "johndoe@email.com").await.unwrap();
+    let resp = test::call_service(&server, reset(&code)).await;
+    assert_eq!(resp.status(), 401);
+
+    let req = test::TestRequest::post()
+        .uri("/account/auth/login/")
+        .set_json(serde_json::json!({
+            "email": "johndoe@email.com",
+            "password": "correct-horse-battery"
+        }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+}
+
+/// This test enrolls an account in TOTP two-factor authentication, then logs in with it
+///
+/// It verifies that login returns a challenge instead of tokens, that wrong codes are rejected,
+/// and that a recovery code completes the login only once
+#[actix_rt::test]
+#[serial]
+async fn test_two_factor_login() {
+    let app = get_app().await;
+    let server = test::init_service(app).await;
+    let req = create_some_account("John Doe", "johndoe@email.com").await;
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let body = test::read_body(resp).await;
+    let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    let token = json["token"].as_str().unwrap().to_string();
+
+    // Enroll
+    let req = test::TestRequest::post()
+        .uri("/account/2fa/enroll")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let body = test::read_body(resp).await;
+    let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    let secret = json["secret"].as_str().unwrap().to_string();
+    assert!(json["otpauth_uri"].as_str().unwrap().starts_with("otpauth://totp/"));
+
+    // Confirm with a valid code
+    let code = totp::generate_code(&secret, chrono::Utc::now().timestamp() as u64).unwrap();
+    let req = test::TestRequest::post()
+        .uri("/account/2fa/confirm")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(serde_json::json!({ "code": code }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let body = test::read_body(resp).await;
+    let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    let recovery_codes = json["recovery_codes"].as_array().unwrap();
+    assert_eq!(recovery_codes.len(), totp::RECOVERY_CODE_COUNT);
+    let recovery_code = recovery_codes[0].as_str().unwrap().to_string();
+
+    // Login now returns a challenge
+    let login = serde_json::json!({
+        "email": "johndoe@email.com",
+        "password": "correct-horse-battery"
+    });
+    let req = test::TestRequest::post().uri("/account/auth/login/").set_json(&login).to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 202);
+
+    let body = test::read_body(resp).await;
+    let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    assert_eq!(json["two_factor_required"], true);
+    assert!(json["token"].is_null());
+    let challenge = json["challenge_token"].as_str().unwrap().to_string();
+
+    // Wrong code
+    let req = test::TestRequest::post()
+        .uri("/account/auth/login/2fa")
+        .set_json(serde_json::json!({ "challenge_token": challenge, "code": "000000" }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 401);
+
+    // Recovery code
+    let req = test::TestRequest::post()
+        .uri("/account/auth/login/2fa")
+        .set_json(serde_json::json!({ "challenge_token": challenge, "code": recovery_code }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let body = test::read_body(resp).await;
+    let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    assert!(json["token"].is_string());
+
+    // The challenge and the recovery code are single use
+    let req = test::TestRequest::post().uri("/account/auth/login/").set_json(&login).to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 202);
+    let body = test::read_body(resp).await;
+    let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    let new_challenge = json["challenge_token"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::post()
+        .uri("/account/auth/login/2fa")
+        .set_json(serde_json::json!({ "challenge_token": challenge, "code": recovery_code }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 401);
+
+    let req = test::TestRequest::post()
+        .uri("/account/auth/login/2fa")
+        .set_json(serde_json::json!({ "challenge_token": new_challenge, "code": recovery_code }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 401);
+}
+
+/// This test guesses TOTP codes over many login challenges until the user is locked out
+///
+/// It verifies that each new challenge does not reset the count of wrong codes, and that
+/// the lockout also applies to disabling 2FA
+#[actix_rt::test]
+#[serial]
+async fn test_two_factor_lockout() {
+    let app = get_app().await;
+    let server = test::init_service(app).await;
+    let req = create_some_account("John Doe", "johndoe@email.com").await;
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 201);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let token = json["token"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::post()
+        .uri("/account/2fa/enroll")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let secret = json["secret"].as_str().unwrap().to_string();
+
+    let code = totp::generate_code(&secret, chrono::Utc::now().timestamp() as u64).unwrap();
+    let req = test::TestRequest::post()
+        .uri("/account/2fa/confirm")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(serde_json::json!({ "code": code }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let login = serde_json::json!({
+        "email": "johndoe@email.com",
+        "password": "correct-horse-battery"
+    });
+    let mut status = 0;
+    for _ in 0..=lockout::TWO_FACTOR.email_attempts {
+        // a new challenge for every guess
+        let req = test::TestRequest::post().uri("/account/auth/login/").set_json(&login).to_request();
+        let resp = test::call_service(&server, req).await;
+        assert_eq!(resp.status(), 202);
+        let json: serde_json::Value = test::read_body_json(resp).await;
+        let challenge = json["challenge_token"].as_str().unwrap().to_string();
+
+        let req = test::TestRequest::post()
+            .uri("/account/auth/login/2fa")
+            .set_json(serde_json::json!({ "challenge_token": challenge, "code": "000000" }))
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        status = resp.status().as_u16();
+        if status == 429 {
+            assert!(resp.headers().get(header::RETRY_AFTER).is_some());
+        }
+    }
+    assert_eq!(status, 429);
+
+    // disabling 2FA from a session is locked out as well
+    let req = test::TestRequest::delete()
+        .uri("/account/2fa")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(serde_json::json!({ "code": "000000" }))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), 429);
+}
@@ -0,0 +1,163 @@
+#![cfg(test)]
+
+mod common;
+
+use actix_http::header;
+use actix_web::{test, web};
+use common::{create_user, get_app, login, PASSWORD};
+use mongodb::bson::{doc, oid::ObjectId};
+use serial_test::serial;
+use server::handlers::account_handlers::*;
+use server::handlers::admin_handlers::*;
+use server::repository::{database::DatabaseRepository, redis::RedisRepository};
+
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/account").service(create_account).service(get_account_by_id).service(login_account).service(refresh_token))
+        .service(
+            web::scope("/admin")
+                .service(search_users)
+                .service(get_user_metadata)
+                .service(disable_user)
+                .service(enable_user)
+                .service(reset_user_quotas)
+                .service(get_audit_log),
+        );
+}
+
+async fn grant_role(id: &str, role: &str) {
+    let db = DatabaseRepository::new("mongodb://localhost:27017").await;
+    db.user_collection
+        .update_one(doc! {"_id": ObjectId::parse_str(id).unwrap()}, doc! {"$set": {"roles": [role]}}, None)
+        .await
+        .unwrap();
+}
+
+/// This test asserts that staff routes require a staff role,
+/// and that admin routes require the admin role
+#[actix_rt::test]
+#[serial]
+async fn test_admin_requires_role() {
+    let app = test::init_service(get_app(routes).await).await;
+    let user_id = create_user(&app, "John Doe", "johndoe@email.com").await.0;
+    let staff_id = create_user(&app, "Jane Doe", "janedoe@email.com").await.0;
+    grant_role(&staff_id, "staff").await;
+
+    let user_token = login(&app, "johndoe@email.com").await;
+    let staff_token = login(&app, "janedoe@email.com").await;
+
+    // regular users cannot use staff routes
+    let req = test::TestRequest::get()
+        .uri("/admin/users")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", user_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 403);
+
+    // staff can look up users
+    let req = test::TestRequest::get()
+        .uri("/admin/users?query=john")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", staff_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let users = json["users"].as_array().unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0]["id"].as_str().unwrap(), user_id);
+
+    // staff cannot disable accounts
+    let req = test::TestRequest::post()
+        .uri(format!("/admin/users/{}/disable", user_id).as_str())
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", staff_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 403);
+}
+
+/// This test views an account's metadata, disables and re-enables the account,
+/// resets its quotas, and asserts that every action was audited
+#[actix_rt::test]
+#[serial]
+async fn test_admin_manage_account() {
+    let app = test::init_service(get_app(routes).await).await;
+    let user_id = create_user(&app, "John Doe", "johndoe@email.com").await.0;
+    let admin_id = create_user(&app, "Jane Doe", "janedoe@email.com").await.0;
+    grant_role(&admin_id, "admin").await;
+
+    let user_token = login(&app, "johndoe@email.com").await;
+    let admin_token = login(&app, "janedoe@email.com").await;
+
+    // view metadata without secrets
+    let req = test::TestRequest::get()
+        .uri(format!("/admin/users/{}", user_id).as_str())
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", admin_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["email"].as_str().unwrap(), "johndoe@email.com");
+    assert!(json["has_password"].as_bool().unwrap());
+    assert!(json.get("password").is_none());
+
+    // disable the account
+    let req = test::TestRequest::post()
+        .uri(format!("/admin/users/{}/disable", user_id).as_str())
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", admin_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    // existing tokens are revoked and logging in is blocked
+    let req = test::TestRequest::get()
+        .uri("/account/")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", user_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+
+    let req = test::TestRequest::post()
+        .uri("/account/auth/login")
+        .set_json(serde_json::json!({
+            "email": "johndoe@email.com",
+            "password": PASSWORD
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 403);
+
+    // re-enable the account
+    let req = test::TestRequest::post()
+        .uri(format!("/admin/users/{}/enable", user_id).as_str())
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", admin_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    login(&app, "johndoe@email.com").await;
+
+    // reset quotas
+    let redis = RedisRepository::new("redis://localhost:6379");
+    redis.set(&format!("quota:{}:generations", user_id), "10").await.unwrap();
+    let req = test::TestRequest::delete()
+        .uri(format!("/admin/users/{}/quotas", user_id).as_str())
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", admin_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["reset"].as_u64().unwrap(), 1);
+    assert!(!redis.exists(&format!("quota:{}:generations", user_id)).await.unwrap());
+
+    // every action was audited
+    let req = test::TestRequest::get()
+        .uri(format!("/admin/audit?target_id={}", user_id).as_str())
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", admin_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let actions: Vec<&str> = json.as_array().unwrap().iter().map(|e| e["action"].as_str().unwrap()).collect();
+    for action in ["users.view", "users.disable", "users.enable", "users.reset_quotas"] {
+        assert!(actions.contains(&action), "{} was not audited", action);
+    }
+    assert!(json.as_array().unwrap().iter().all(|e| e["actor_id"].as_str().unwrap() == admin_id));
+}
@@ -0,0 +1,132 @@
+#![cfg(test)]
+
+mod common;
+
+use actix_http::header;
+use actix_web::{test, web};
+use common::{create_user, get_app, login};
+use serial_test::serial;
+use server::handlers::account_handlers::*;
+use server::handlers::api_token_handlers::*;
+use server::handlers::document_handlers::*;
+
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/account")
+            .service(create_account)
+            .service(get_account_by_id)
+            .service(login_account)
+            .service(create_api_token)
+            .service(list_api_tokens)
+            .service(revoke_api_token),
+    )
+    .service(web::scope("/document").service(delete_document));
+}
+
+/// This test creates a personal access token, uses it within and outside of its scopes,
+/// and asserts that it is rejected once revoked
+#[actix_rt::test]
+#[serial]
+async fn test_api_token_lifecycle() {
+    let app = test::init_service(get_app(routes).await).await;
+    create_user(&app, "John Doe", "johndoe@email.com").await;
+    let jwt = login(&app, "johndoe@email.com").await;
+
+    // create a token
+    let req = test::TestRequest::post()
+        .uri("/account/tokens")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", jwt)))
+        .set_json(serde_json::json!({
+            "name": "bulk generation",
+            "scopes": ["profile:read"],
+            "expires_in_days": 30
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let token = json["token"].as_str().unwrap().to_string();
+    let token_id = json["id"].as_str().unwrap().to_string();
+    assert!(token.starts_with(json["prefix"].as_str().unwrap()));
+    assert!(json.get("token_hash").is_none());
+
+    // the token is listed without its secret
+    let req = test::TestRequest::get()
+        .uri("/account/tokens")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", jwt)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let tokens = json.as_array().unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(tokens[0]["name"].as_str().unwrap(), "bulk generation");
+    assert!(tokens[0].get("token").is_none());
+
+    // the token can be used within its scopes
+    let req = test::TestRequest::get()
+        .uri("/account/")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    // but not outside of them
+    let req = test::TestRequest::delete()
+        .uri("/document/some-document")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 403);
+
+    // and never to manage the account
+    let req = test::TestRequest::get()
+        .uri("/account/tokens")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 403);
+
+    // revoke the token
+    let req = test::TestRequest::delete()
+        .uri(format!("/account/tokens/{}", token_id).as_str())
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", jwt)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let req = test::TestRequest::get()
+        .uri("/account/")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+}
+
+/// This test asserts that invalid token requests are rejected
+#[actix_rt::test]
+#[serial]
+async fn test_api_token_invalid_request() {
+    let app = test::init_service(get_app(routes).await).await;
+    create_user(&app, "John Doe", "johndoe@email.com").await;
+    let jwt = login(&app, "johndoe@email.com").await;
+
+    for body in [
+        serde_json::json!({ "name": "", "scopes": ["generate"] }),
+        serde_json::json!({ "name": "no scopes", "scopes": [] }),
+        serde_json::json!({ "name": "too long", "scopes": ["generate"], "expires_in_days": 1000 }),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/account/tokens")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", jwt)))
+            .set_json(body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+    }
+
+    // unknown tokens are rejected
+    let req = test::TestRequest::get().uri("/account/").insert_header((header::AUTHORIZATION, "Bearer scr_unknown")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+}
@@ -0,0 +1,191 @@
+#![cfg(test)]
+
+mod common;
+
+use actix_http::{body::MessageBody, header};
+use actix_web::{dev::ServiceResponse, error::Error, test, web};
+use common::{create_user, get_app};
+use serial_test::serial;
+use server::billing::fake::{FakeProvider, SignedEvent};
+use server::handlers::account_handlers::*;
+use server::handlers::billing_handlers::*;
+use server::handlers::plan_handlers::*;
+use server::repository::database::DatabaseRepository;
+
+const SECRET: &str = "whsec_test";
+
+fn routes(cfg: &mut web::ServiceConfig) {
+    // the webhook reads its secret on every request
+    std::env::set_var("BILLING_WEBHOOK_SECRET", SECRET);
+    cfg.service(web::scope("/account").service(create_account).service(get_plan))
+        .service(web::scope("/billing").service(billing_webhook));
+}
+
+/// Deliver an event to the webhook and return the status and message of the response
+async fn deliver<S, B>(app: &S, event: &SignedEvent) -> (u16, String)
+where
+    S: actix_service::Service<actix_http::Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let req = test::TestRequest::post()
+        .uri("/billing/webhook")
+        .insert_header((header::CONTENT_TYPE, "application/json"))
+        .insert_header(("Stripe-Signature", event.signature.as_str()))
+        .set_payload(event.payload.clone())
+        .to_request();
+    let resp = test::call_service(app, req).await;
+    let status = resp.status().as_u16();
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    (status, json["message"].as_str().unwrap_or_default().to_string())
+}
+
+/// Get the plan of the user
+async fn plan<S, B>(app: &S, token: &str) -> String
+where
+    S: actix_service::Service<actix_http::Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let req = test::TestRequest::get()
+        .uri("/account/plan")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    json["plan"].as_str().unwrap().to_string()
+}
+
+/// This test rejects events that are not signed with the webhook secret
+#[actix_rt::test]
+#[serial]
+async fn test_webhook_signature() {
+    let app = test::init_service(get_app(routes).await).await;
+    let (user_id, token) = create_user(&app, "John Doe", "johndoe@email.com").await;
+
+    // signed with another secret
+    let forged = FakeProvider::new("whsec_other").checkout_completed(&user_id, "cus_1", "sub_1");
+    let (status, _) = deliver(&app, &forged).await;
+    assert_eq!(status, 400);
+
+    // tampered with after signing
+    let provider = FakeProvider::new(SECRET);
+    let mut tampered = provider.checkout_completed(&user_id, "cus_1", "sub_1");
+    tampered.payload = tampered.payload.replace("cus_1", "cus_2");
+    let (status, _) = deliver(&app, &tampered).await;
+    assert_eq!(status, 400);
+
+    // signed too long ago
+    let mut expired = provider.checkout_completed(&user_id, "cus_1", "sub_1");
+    expired.signature = provider.sign(&expired.payload, chrono::Utc::now().timestamp() - 600);
+    let (status, _) = deliver(&app, &expired).await;
+    assert_eq!(status, 400);
+
+    let req = test::TestRequest::post()
+        .uri("/billing/webhook")
+        .set_payload(provider.checkout_completed(&user_id, "cus_1", "sub_1").payload)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    assert_eq!(plan(&app, &token).await, "free");
+
+    // events that do not change plans are acknowledged
+    let other = provider.event("customer.created", serde_json::json!({ "id": "cus_1" }));
+    let (status, message) = deliver(&app, &other).await;
+    assert_eq!(status, 200);
+    assert_eq!(message, "Event ignored");
+}
+
+/// This test upgrades a user on checkout and follows the subscription until it is canceled
+#[actix_rt::test]
+#[serial]
+async fn test_subscription_lifecycle() {
+    let app = test::init_service(get_app(routes).await).await;
+    let (user_id, token) = create_user(&app, "John Doe", "johndoe@email.com").await;
+    let provider = FakeProvider::new(SECRET);
+
+    let checkout = provider.checkout_completed(&user_id, "cus_1", "sub_1");
+    let (status, message) = deliver(&app, &checkout).await;
+    assert_eq!(status, 200);
+    assert_eq!(message, "Event processed");
+    assert_eq!(plan(&app, &token).await, "pro");
+
+    let db = DatabaseRepository::new("mongodb://localhost:27017").await;
+    let billing = db.get_user(&user_id).await.unwrap().unwrap().billing.unwrap();
+    assert_eq!(billing.customer_id, "cus_1");
+    assert_eq!(billing.subscription_id.as_deref(), Some("sub_1"));
+
+    let (status, _) = deliver(&app, &provider.invoice_payment_failed("cus_1", "sub_1")).await;
+    assert_eq!(status, 200);
+    assert_eq!(plan(&app, &token).await, "free");
+    let billing = db.get_user(&user_id).await.unwrap().unwrap().billing.unwrap();
+    assert_eq!(billing.status, "past_due");
+
+    let (status, _) = deliver(&app, &provider.subscription_updated("cus_1", "sub_1", "active")).await;
+    assert_eq!(status, 200);
+    assert_eq!(plan(&app, &token).await, "pro");
+    let billing = db.get_user(&user_id).await.unwrap().unwrap().billing.unwrap();
+    assert!(billing.date_current_period_end.is_some());
+
+    // events about another subscription of the customer do not change the plan
+    let (status, _) = deliver(&app, &provider.subscription_deleted("cus_1", "sub_0")).await;
+    assert_eq!(status, 200);
+    assert_eq!(plan(&app, &token).await, "pro");
+
+    let (status, _) = deliver(&app, &provider.subscription_deleted("cus_1", "sub_1")).await;
+    assert_eq!(status, 200);
+    assert_eq!(plan(&app, &token).await, "free");
+    let billing = db.get_user(&user_id).await.unwrap().unwrap().billing.unwrap();
+    assert_eq!(billing.status, "canceled");
+}
+
+/// This test completes a checkout whose payment is pending, and only upgrades the user once it is paid
+#[actix_rt::test]
+#[serial]
+async fn test_unpaid_checkout() {
+    let app = test::init_service(get_app(routes).await).await;
+    let (user_id, token) = create_user(&app, "John Doe", "johndoe@email.com").await;
+    let provider = FakeProvider::new(SECRET);
+
+    let checkout = provider.checkout_completed_unpaid(&user_id, "cus_1", "sub_1");
+    let (status, message) = deliver(&app, &checkout).await;
+    assert_eq!(status, 200);
+    assert_eq!(message, "Event processed");
+    assert_eq!(plan(&app, &token).await, "free");
+
+    let db = DatabaseRepository::new("mongodb://localhost:27017").await;
+    let billing = db.get_user(&user_id).await.unwrap().unwrap().billing.unwrap();
+    assert_eq!(billing.customer_id, "cus_1");
+    assert_eq!(billing.status, "unpaid");
+
+    let paid = provider.checkout_async_payment_succeeded(&user_id, "cus_1", "sub_1");
+    let (status, _) = deliver(&app, &paid).await;
+    assert_eq!(status, 200);
+    assert_eq!(plan(&app, &token).await, "pro");
+    let billing = db.get_user(&user_id).await.unwrap().unwrap().billing.unwrap();
+    assert_eq!(billing.status, "active");
+}
+
+/// This test delivers the same event twice and only processes it once
+#[actix_rt::test]
+#[serial]
+async fn test_webhook_idempotency() {
+    let app = test::init_service(get_app(routes).await).await;
+    let (user_id, token) = create_user(&app, "John Doe", "johndoe@email.com").await;
+    let provider = FakeProvider::new(SECRET);
+
+    let checkout = provider.checkout_completed(&user_id, "cus_1", "sub_1");
+    let (status, message) = deliver(&app, &checkout).await;
+    assert_eq!(status, 200);
+    assert_eq!(message, "Event processed");
+
+    let (status, _) = deliver(&app, &provider.subscription_deleted("cus_1", "sub_1")).await;
+    assert_eq!(status, 200);
+    assert_eq!(plan(&app, &token).await, "free");
+
+    // a retry of the checkout does not upgrade the user again
+    let (status, message) = deliver(&app, &checkout).await;
+    assert_eq!(status, 200);
+    assert_eq!(message, "Event already processed");
+    assert_eq!(plan(&app, &token).await, "free");
+}
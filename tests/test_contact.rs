@@ -0,0 +1,129 @@
+#![cfg(test)]
+
+use actix_http::{body::MessageBody, header};
+use actix_service::ServiceFactory;
+use actix_web::{
+    dev::{ServiceRequest, ServiceResponse},
+    error::Error,
+    middleware, test, web, App,
+};
+use serial_test::serial;
+use server::handlers::contact_handlers::*;
+use server::repository::redis::RedisRepository;
+use std::sync::Once;
+
+static INIT: Once = Once::new();
+
+const OUTBOX_DIR: &str = "target/test-outbox";
+
+/// Address the test messages are sent from
+const PEER: &str = "203.0.113.7:4000";
+
+async fn get_app(
+) -> App<impl ServiceFactory<ServiceRequest, Response = ServiceResponse<impl MessageBody>, Config = (), InitError = (), Error = Error>> {
+    // set up the logger to debug
+    INIT.call_once(env_logger::init);
+    std::env::set_var("CONTACT_OUTBOX_DIR", OUTBOX_DIR);
+    let _ = std::fs::remove_dir_all(OUTBOX_DIR);
+    let redis = RedisRepository::new("redis://localhost:6379");
+    let _ = redis.del_matching("contact:*").await;
+    App::new()
+        .wrap(middleware::NormalizePath::trim())
+        .wrap(middleware::Logger::default())
+        .app_data(web::Data::new(redis))
+        .service(contact)
+}
+
+fn outbox() -> Vec<serde_json::Value> {
+    std::fs::read_to_string(format!("{}/contact.jsonl", OUTBOX_DIR))
+        .unwrap_or_default()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+/// This test sends a message to the outbox and checks the validation
+#[actix_rt::test]
+#[serial]
+async fn test_contact() {
+    let app = test::init_service(get_app().await).await;
+
+    let req = test::TestRequest::post()
+        .uri("/contact")
+        .peer_addr(PEER.parse().unwrap())
+        .set_json(serde_json::json!({
+            "name": "John Doe",
+            "email": "johndoe@email.com",
+            "message": "Hello there!"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["message"].as_str().unwrap(), "Message sent");
+    let messages = outbox();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["email"].as_str().unwrap(), "johndoe@email.com");
+    assert_eq!(messages[0]["message"].as_str().unwrap(), "Hello there!");
+
+    // the honeypot is silently dropped
+    let req = test::TestRequest::post()
+        .uri("/contact")
+        .peer_addr(PEER.parse().unwrap())
+        .set_json(serde_json::json!({
+            "name": "Bot",
+            "email": "bot@email.com",
+            "message": "Buy now",
+            "website": "https://spam.example"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(outbox().len(), 1);
+
+    // invalid messages are rejected
+    for body in [
+        serde_json::json!({ "name": "", "email": "johndoe@email.com", "message": "Hi" }),
+        serde_json::json!({ "name": "John Doe", "email": "not-an-email", "message": "Hi" }),
+        serde_json::json!({ "name": "John Doe", "email": "johndoe@email.com", "message": "a".repeat(5001) }),
+    ] {
+        let req = test::TestRequest::post().uri("/contact").peer_addr(PEER.parse().unwrap()).set_json(body).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+    }
+    assert_eq!(outbox().len(), 1);
+}
+
+/// This test sends messages from one IP until it is rate limited, whatever forwarded address it claims
+#[actix_rt::test]
+#[serial]
+async fn test_contact_rate_limit() {
+    let app = test::init_service(get_app().await).await;
+
+    let send = |ip: &str, forwarded_for: &str| {
+        test::TestRequest::post()
+            .uri("/contact")
+            .peer_addr(format!("{}:4000", ip).parse().unwrap())
+            .insert_header((header::X_FORWARDED_FOR, forwarded_for.to_string()))
+            .set_json(serde_json::json!({
+                "name": "John Doe",
+                "email": "johndoe@email.com",
+                "message": "Hello there!"
+            }))
+            .to_request()
+    };
+
+    for i in 0..5 {
+        let resp = test::call_service(&app, send("10.0.0.1", &format!("198.51.100.{}", i))).await;
+        assert_eq!(resp.status(), 200);
+    }
+    // a forged X-Forwarded-For header does not get around the limit
+    let resp = test::call_service(&app, send("10.0.0.1", "198.51.100.99")).await;
+    assert_eq!(resp.status(), 429);
+    assert!(resp.headers().get(header::RETRY_AFTER).is_some());
+
+    // other IPs are not limited
+    let resp = test::call_service(&app, send("10.0.0.2", "198.51.100.1")).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(outbox().len(), 6);
+}
@@ -0,0 +1,125 @@
+#![cfg(test)]
+
+mod common;
+
+use actix_http::{body::MessageBody, header};
+use actix_web::{dev::ServiceResponse, error::Error, test, web};
+use common::{create_user, get_app};
+use serial_test::serial;
+use server::handlers::account_handlers::*;
+use server::handlers::export_handlers::*;
+use server::models::user::preferences::Preferences;
+use server::repository::database::DatabaseRepository;
+use std::time::Duration;
+
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/account").service(create_account).service(export_account));
+}
+
+/// Create an account with preferences and return its access token
+async fn create<S, B>(app: &S) -> String
+where
+    S: actix_service::Service<actix_http::Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let (id, token) = create_user(app, "John Doe", "johndoe@email.com").await;
+    let db = DatabaseRepository::new("mongodb://localhost:27017").await;
+    let preferences = Preferences {
+        language: Some("Spanish".to_string()),
+        ..Preferences::default()
+    };
+    db.update_preferences(&id, &preferences).await.unwrap();
+    token
+}
+
+/// Read the files of a tar archive
+fn untar(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut files = vec![];
+    let mut pos = 0;
+    while pos + 512 <= data.len() && data[pos] != 0 {
+        let header = &data[pos..pos + 512];
+        let name = String::from_utf8(header[..100].iter().take_while(|b| **b != 0).cloned().collect()).unwrap();
+        let size = usize::from_str_radix(std::str::from_utf8(&header[124..135]).unwrap(), 8).unwrap();
+        files.push((name, data[pos + 512..pos + 512 + size].to_vec()));
+        pos += 512 + (size + 511) / 512 * 512;
+    }
+    files
+}
+
+fn assert_export(archive: &[u8]) {
+    let files = untar(archive);
+    let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, vec!["export.json", "export.md"]);
+
+    let json: serde_json::Value = serde_json::from_slice(&files[0].1).unwrap();
+    assert_eq!(json["account"]["email"].as_str().unwrap(), "johndoe@email.com");
+    assert_eq!(json["account"]["name"].as_str().unwrap(), "John Doe");
+    assert!(json["documents"].as_array().unwrap().is_empty());
+    assert!(json["account"].get("password").is_none());
+    assert_eq!(json["account"]["plan"].as_str().unwrap(), "free");
+    assert_eq!(json["account"]["preferences"]["language"].as_str().unwrap(), "Spanish");
+
+    let markdown = String::from_utf8(files[1].1.clone()).unwrap();
+    assert!(markdown.starts_with("# Scrippt data export"));
+    assert!(markdown.contains("johndoe@email.com"));
+    assert!(markdown.contains("- **Plan:** free"));
+    assert!(markdown.contains("- **Language:** Spanish"));
+}
+
+/// This test downloads the export of a small account right away
+#[actix_rt::test]
+#[serial]
+async fn test_export_account() {
+    let app = test::init_service(get_app(routes).await).await;
+    let token = create(&app).await;
+
+    let req = test::TestRequest::get()
+        .uri("/account/export")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "application/x-tar");
+    assert!(resp.headers().get(header::CONTENT_DISPOSITION).unwrap().to_str().unwrap().starts_with("attachment"));
+    let body = test::read_body(resp).await;
+    assert_export(&body);
+
+    // exports need a logged in user
+    let req = test::TestRequest::get().uri("/account/export").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+}
+
+/// This test builds the export in the background and downloads it once it is ready
+#[actix_rt::test]
+#[serial]
+async fn test_export_account_in_background() {
+    std::env::set_var("EXPORT_SYNC_MAX_BYTES", "0");
+    let app = test::init_service(get_app(routes).await).await;
+    let token = create(&app).await;
+
+    let req = test::TestRequest::get()
+        .uri("/account/export")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 202);
+    assert!(resp.headers().get(header::RETRY_AFTER).is_some());
+
+    let mut archive = None;
+    for _ in 0..50 {
+        let req = test::TestRequest::get()
+            .uri("/account/export")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        if resp.status() == 200 {
+            archive = Some(test::read_body(resp).await);
+            break;
+        }
+        assert_eq!(resp.status(), 202);
+        actix_rt::time::sleep(Duration::from_millis(100)).await;
+    }
+    std::env::remove_var("EXPORT_SYNC_MAX_BYTES");
+    assert_export(&archive.expect("export was not built in time"));
+}
@@ -0,0 +1,229 @@
+#![cfg(test)]
+
+mod common;
+
+use actix_http::{body::MessageBody, header};
+use actix_web::{dev::ServiceResponse, error::Error, test, web};
+use common::{create_user, get_app, login, signup};
+use mongodb::bson::{doc, oid::ObjectId};
+use serial_test::serial;
+use server::handlers::account_handlers::*;
+use server::handlers::admin_handlers::*;
+use server::handlers::invite_handlers::*;
+use server::repository::database::DatabaseRepository;
+
+fn routes(cfg: &mut web::ServiceConfig) {
+    std::env::remove_var("INVITE_ONLY");
+    cfg.service(join_waitlist)
+        .service(
+            web::scope("/account")
+                .service(create_account)
+                .service(login_account)
+                .service(create_referral_invite)
+                .service(list_referral_invites),
+        )
+        .service(web::scope("/admin").service(get_user_metadata).service(get_waitlist).service(create_invite).service(list_invites));
+}
+
+/// Create an admin account and return its access token
+async fn create_admin<S, B>(app: &S) -> String
+where
+    S: actix_service::Service<actix_http::Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let (id, _) = create_user(app, "John Doe", "admin@email.com").await;
+
+    let db = DatabaseRepository::new("mongodb://localhost:27017").await;
+    db.user_collection
+        .update_one(doc! {"_id": ObjectId::parse_str(&id).unwrap()}, doc! {"$set": {"roles": ["admin"]}}, None)
+        .await
+        .unwrap();
+
+    login(app, "admin@email.com").await
+}
+
+/// This test joins the waitlist and invites the person from it
+#[actix_rt::test]
+#[serial]
+async fn test_waitlist() {
+    let app = test::init_service(get_app(routes).await).await;
+    let admin_token = create_admin(&app).await;
+
+    // joining twice keeps a single entry
+    for _ in 0..2 {
+        let req = test::TestRequest::post()
+            .uri("/waitlist")
+            .set_json(serde_json::json!({ "email": "JaneDoe@email.com", "name": "Jane Doe" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+    }
+    let req = test::TestRequest::post().uri("/waitlist").set_json(serde_json::json!({ "email": "not-an-email" })).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    let req = test::TestRequest::get()
+        .uri("/admin/waitlist?pending=true")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", admin_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json.as_array().unwrap().len(), 1);
+    assert_eq!(json[0]["email"].as_str().unwrap(), "janedoe@email.com");
+
+    let req = test::TestRequest::post()
+        .uri("/admin/invites")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", admin_token)))
+        .set_json(serde_json::json!({ "email": "janedoe@email.com", "expires_in_days": 7 }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["kind"].as_str().unwrap(), "admin");
+    assert_eq!(json["max_uses"].as_u64().unwrap(), 1);
+    assert!(json["date_expires"].as_i64().is_some());
+
+    // invited people are no longer pending
+    let req = test::TestRequest::get()
+        .uri("/admin/waitlist?pending=true")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", admin_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert!(json.as_array().unwrap().is_empty());
+}
+
+/// This test requires an invite to sign up and redeems it
+#[actix_rt::test]
+#[serial]
+async fn test_invite_only_signup() {
+    let app = test::init_service(get_app(routes).await).await;
+    let admin_token = create_admin(&app).await;
+    std::env::set_var("INVITE_ONLY", "true");
+
+    let req = test::TestRequest::post()
+        .uri("/admin/invites")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", admin_token)))
+        .set_json(serde_json::json!({ "max_uses": 1 }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let code = json["code"].as_str().unwrap().to_string();
+
+    let resp = signup(&app, "John Doe", "johndoe@email.com", None).await;
+    assert_eq!(resp.status(), 403);
+    let resp = signup(&app, "John Doe", "johndoe@email.com", Some("NOTACODE")).await;
+    assert_eq!(resp.status(), 400);
+
+    // codes are case-insensitive
+    let resp = signup(&app, "John Doe", "johndoe@email.com", Some(&code.to_lowercase())).await;
+    assert_eq!(resp.status(), 201);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let id = json["id"].as_str().unwrap().to_string();
+
+    // the invite is used up
+    let resp = signup(&app, "John Doe", "janedoe@email.com", Some(&code)).await;
+    assert_eq!(resp.status(), 400);
+    std::env::remove_var("INVITE_ONLY");
+
+    // the redeemed invite is recorded on the account
+    let req = test::TestRequest::get()
+        .uri(&format!("/admin/users/{}", id))
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", admin_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["invite_code"].as_str().unwrap(), code);
+
+    let req = test::TestRequest::get()
+        .uri("/admin/invites")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", admin_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json[0]["uses"].as_u64().unwrap(), 1);
+}
+
+/// This test gives back the use of an invite, as done when the account it was redeemed for cannot be created
+#[actix_rt::test]
+#[serial]
+async fn test_release_invite() {
+    let app = test::init_service(get_app(routes).await).await;
+    let admin_token = create_admin(&app).await;
+
+    let req = test::TestRequest::post()
+        .uri("/admin/invites")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", admin_token)))
+        .set_json(serde_json::json!({ "max_uses": 1 }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let code = json["code"].as_str().unwrap().to_string();
+
+    let resp = signup(&app, "John Doe", "johndoe@email.com", Some(&code)).await;
+    assert_eq!(resp.status(), 201);
+
+    let db = DatabaseRepository::new("mongodb://localhost:27017").await;
+    assert_eq!(db.release_invite(&code).await.unwrap().modified_count, 1);
+
+    // the released use can be redeemed again
+    let resp = signup(&app, "John Doe", "janedoe@email.com", Some(&code)).await;
+    assert_eq!(resp.status(), 201);
+
+    // uses never go below zero
+    assert_eq!(db.release_invite(&code).await.unwrap().modified_count, 1);
+    assert_eq!(db.release_invite(&code).await.unwrap().modified_count, 0);
+}
+
+/// This test creates referral invites up to the limit and signs up with one
+#[actix_rt::test]
+#[serial]
+async fn test_referral_invites() {
+    let app = test::init_service(get_app(routes).await).await;
+    let resp = signup(&app, "John Doe", "johndoe@email.com", None).await;
+    assert_eq!(resp.status(), 201);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let token = json["token"].as_str().unwrap().to_string();
+
+    let mut codes = vec![];
+    for _ in 0..5 {
+        let req = test::TestRequest::post()
+            .uri("/account/invites")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .set_json(serde_json::json!({}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 201);
+        let json: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(json["kind"].as_str().unwrap(), "referral");
+        codes.push(json["code"].as_str().unwrap().to_string());
+    }
+    let req = test::TestRequest::post()
+        .uri("/account/invites")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(serde_json::json!({}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 403);
+
+    let req = test::TestRequest::get()
+        .uri("/account/invites")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json.as_array().unwrap().len(), 5);
+
+    let resp = signup(&app, "John Doe", "janedoe@email.com", Some(&codes[0])).await;
+    assert_eq!(resp.status(), 201);
+
+    // referral invites cannot be created without logging in
+    let req = test::TestRequest::post().uri("/account/invites").set_json(serde_json::json!({})).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+}
@@ -0,0 +1,123 @@
+#![cfg(test)]
+
+use actix_http::header;
+use actix_web::{web, App, HttpResponse, HttpServer};
+use jsonwebtoken::{jwk::JwkSet, Algorithm};
+use server::auth::jwks::JwksCache;
+use server::auth::keys::SigningKey;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// What the mock JWKS endpoint serves
+struct MockJwks {
+    keys: JwkSet,
+    max_age: u64,
+    fail: bool,
+    requests: usize,
+}
+
+fn jwk(kid: &str) -> jsonwebtoken::jwk::Jwk {
+    let (algorithm, pem) = match kid {
+        "ed" => (Algorithm::EdDSA, &include_bytes!("fixtures/keys/ed25519-test.pem")[..]),
+        _ => (Algorithm::RS256, &include_bytes!("fixtures/keys/rsa-test.pem")[..]),
+    };
+    SigningKey::from_pem(kid.to_string(), algorithm, pem, None).unwrap().jwk.unwrap()
+}
+
+/// Start a local JWKS endpoint. Returns its URL and the state it serves.
+fn start_mock_jwks(kids: &[&str], max_age: u64) -> (String, Arc<Mutex<MockJwks>>) {
+    let state = Arc::new(Mutex::new(MockJwks {
+        keys: JwkSet {
+            keys: kids.iter().map(|kid| jwk(kid)).collect(),
+        },
+        max_age,
+        fail: false,
+        requests: 0,
+    }));
+
+    let served = state.clone();
+    let server = HttpServer::new(move || {
+        let served = served.clone();
+        App::new().route(
+            "/jwks",
+            web::get().to(move || {
+                let served = served.clone();
+                async move {
+                    let mut state = served.lock().unwrap();
+                    state.requests += 1;
+                    if state.fail {
+                        return HttpResponse::InternalServerError().finish();
+                    }
+                    HttpResponse::Ok()
+                        .insert_header((header::CACHE_CONTROL, format!("public, max-age={}", state.max_age)))
+                        .json(&state.keys)
+                }
+            }),
+        )
+    })
+    .workers(1)
+    .bind("127.0.0.1:0")
+    .unwrap();
+    let url = format!("http://{}/jwks", server.addrs()[0]);
+    actix_rt::spawn(server.run());
+    (url, state)
+}
+
+/// Key sets are only fetched once while they are fresh
+#[actix_rt::test]
+async fn test_jwks_cached_for_max_age() {
+    let (url, state) = start_mock_jwks(&["rsa"], 3600);
+    let cache = JwksCache::new();
+
+    assert!(cache.find(&url, "rsa").await.unwrap().is_some());
+    assert!(cache.find(&url, "rsa").await.unwrap().is_some());
+    assert_eq!(cache.get(&url).await.unwrap().keys.len(), 1);
+    assert_eq!(state.lock().unwrap().requests, 1);
+}
+
+/// An unknown kid triggers a refetch, at most once per cooldown
+#[actix_rt::test]
+async fn test_jwks_refetch_on_unknown_kid() {
+    let (url, state) = start_mock_jwks(&["rsa"], 3600);
+    let cache = JwksCache::with_refetch_cooldown(Duration::from_secs(0));
+    assert!(cache.find(&url, "rsa").await.unwrap().is_some());
+
+    // The provider rotates its keys
+    state.lock().unwrap().keys = JwkSet {
+        keys: vec![jwk("rsa"), jwk("ed")],
+    };
+    assert!(cache.find(&url, "ed").await.unwrap().is_some());
+    assert_eq!(state.lock().unwrap().requests, 2);
+
+    // Made-up key ids do not refetch during the cooldown
+    let cache = JwksCache::with_refetch_cooldown(Duration::from_secs(3600));
+    assert!(cache.find(&url, "made-up").await.unwrap().is_none());
+    assert!(cache.find(&url, "made-up").await.unwrap().is_none());
+    assert_eq!(state.lock().unwrap().requests, 3);
+}
+
+/// Expired key sets are refreshed in the background, and the last good
+/// key set keeps being served when the refresh fails
+#[actix_rt::test]
+async fn test_jwks_background_refresh() {
+    let (url, state) = start_mock_jwks(&["rsa"], 0);
+    let cache = JwksCache::new();
+    assert!(cache.find(&url, "rsa").await.unwrap().is_some());
+
+    // The provider goes down: the stale key set is served while refreshing
+    state.lock().unwrap().fail = true;
+    assert!(cache.find(&url, "rsa").await.unwrap().is_some());
+    actix_rt::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(state.lock().unwrap().requests, 2);
+    assert!(cache.find(&url, "rsa").await.unwrap().is_some());
+
+    // A key set that was never fetched cannot be served
+    let (url, state) = start_mock_jwks(&["rsa"], 0);
+    state.lock().unwrap().fail = true;
+    assert!(cache.get(&url).await.is_err());
+
+    // Once the provider is back, the next refresh picks up its keys
+    state.lock().unwrap().fail = false;
+    state.lock().unwrap().keys = JwkSet { keys: vec![jwk("ed")] };
+    assert!(cache.refresh(&url).await.unwrap().find("ed").is_some());
+}
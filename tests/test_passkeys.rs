@@ -0,0 +1,234 @@
+#![cfg(test)]
+
+use actix_http::{body::MessageBody, header};
+use actix_service::ServiceFactory;
+use actix_web::{
+    dev::{ServiceRequest, ServiceResponse},
+    error::Error,
+    middleware, test, web, App,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+use serial_test::serial;
+use server::auth::webauthn::RelyingParty;
+use server::handlers::account_handlers::*;
+use server::handlers::passkey_handlers::*;
+use server::repository::{database::DatabaseRepository, redis::RedisRepository};
+use server::utils::cbor::{self, Value};
+use sha2::{Digest, Sha256};
+use std::sync::Once;
+
+static INIT: Once = Once::new();
+
+async fn get_app(
+) -> App<impl ServiceFactory<ServiceRequest, Response = ServiceResponse<impl MessageBody>, Config = (), InitError = (), Error = Error>> {
+    // set up the logger to debug
+    INIT.call_once(env_logger::init);
+    let db = DatabaseRepository::new("mongodb://localhost:27017").await;
+    let redis = RedisRepository::new("redis://localhost:6379");
+    let _ = db.drop_database().await;
+    let _ = redis.del_matching("attempts:*").await;
+    let _ = redis.del_matching("lockout:*").await;
+    App::new()
+        .wrap(middleware::NormalizePath::trim())
+        .wrap(middleware::Logger::default())
+        .app_data(web::Data::new(db))
+        .app_data(web::Data::new(redis))
+        .service(
+            web::scope("/account")
+                .service(create_account)
+                .service(get_account_by_id)
+                .service(passkey_registration_options)
+                .service(register_passkey)
+                .service(list_passkeys)
+                .service(delete_passkey)
+                .service(passkey_login_options)
+                .service(login_passkey),
+        )
+}
+
+/// A software authenticator holding a single ES256 passkey
+struct Authenticator {
+    key_pair: EcdsaKeyPair,
+    credential_id: Vec<u8>,
+    sign_count: u32,
+}
+
+impl Authenticator {
+    fn new() -> Self {
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &SystemRandom::new()).unwrap();
+        Authenticator {
+            key_pair: EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref()).unwrap(),
+            credential_id: b"software-authenticator".to_vec(),
+            sign_count: 0,
+        }
+    }
+
+    fn id(&self) -> String {
+        URL_SAFE_NO_PAD.encode(&self.credential_id)
+    }
+
+    /// Answer the options of `navigator.credentials.create()`
+    fn create(&self, options: &serde_json::Value) -> serde_json::Value {
+        let rp = RelyingParty::from_env();
+        let point = self.key_pair.public_key().as_ref();
+        let cose_key = cbor::encode(&Value::Map(vec![
+            (Value::Integer(1), Value::Integer(2)),
+            (Value::Integer(3), Value::Integer(-7)),
+            (Value::Integer(-1), Value::Integer(1)),
+            (Value::Integer(-2), Value::Bytes(point[1..33].to_vec())),
+            (Value::Integer(-3), Value::Bytes(point[33..].to_vec())),
+        ]));
+
+        let mut auth_data = self.auth_data(options["rp"]["id"].as_str().unwrap(), 0x45);
+        auth_data.extend_from_slice(&[0; 16]);
+        auth_data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+        auth_data.extend_from_slice(&self.credential_id);
+        auth_data.extend_from_slice(&cose_key);
+
+        let attestation_object = cbor::encode(&Value::Map(vec![
+            (Value::Text("fmt".to_string()), Value::Text("none".to_string())),
+            (Value::Text("attStmt".to_string()), Value::Map(vec![])),
+            (Value::Text("authData".to_string()), Value::Bytes(auth_data)),
+        ]));
+        serde_json::json!({
+            "id": self.id(),
+            "response": {
+                "clientDataJSON": client_data("webauthn.create", options["challenge"].as_str().unwrap(), &rp.origin),
+                "attestationObject": URL_SAFE_NO_PAD.encode(attestation_object)
+            }
+        })
+    }
+
+    /// Answer the options of `navigator.credentials.get()`
+    fn get(&mut self, options: &serde_json::Value, user_handle: &str) -> serde_json::Value {
+        let rp = RelyingParty::from_env();
+        self.sign_count += 1;
+        let auth_data = self.auth_data(options["rpId"].as_str().unwrap(), 0x05);
+        let client_data_json = client_data("webauthn.get", options["challenge"].as_str().unwrap(), &rp.origin);
+        let signed = [auth_data.as_slice(), &Sha256::digest(URL_SAFE_NO_PAD.decode(&client_data_json).unwrap())].concat();
+        let signature = self.key_pair.sign(&SystemRandom::new(), &signed).unwrap();
+        serde_json::json!({
+            "id": self.id(),
+            "response": {
+                "clientDataJSON": client_data_json,
+                "authenticatorData": URL_SAFE_NO_PAD.encode(auth_data),
+                "signature": URL_SAFE_NO_PAD.encode(signature.as_ref()),
+                "userHandle": user_handle
+            }
+        })
+    }
+
+    fn auth_data(&self, rp_id: &str, flags: u8) -> Vec<u8> {
+        let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&self.sign_count.to_be_bytes());
+        data
+    }
+}
+
+fn client_data(kind: &str, challenge: &str, origin: &str) -> String {
+    let json = serde_json::json!({ "type": kind, "challenge": challenge, "origin": origin });
+    URL_SAFE_NO_PAD.encode(json.to_string())
+}
+
+/// This test registers a passkey with a software authenticator, logs in with it
+/// and asserts that a login challenge cannot be replayed
+#[actix_rt::test]
+#[serial]
+async fn test_passkey_registration_and_login() {
+    let app = test::init_service(get_app().await).await;
+    let redis = RedisRepository::new("redis://localhost:6379");
+    redis.set("johndoe@email.com", "123456:used").await.unwrap();
+    let req = test::TestRequest::post()
+        .uri("/account/create/")
+        .set_json(serde_json::json!({
+            "name": "John Doe",
+            "email": "johndoe@email.com",
+            "password": "correct-horse-battery"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let id = json["id"].as_str().unwrap().to_string();
+    let token = json["token"].as_str().unwrap().to_string();
+
+    // register a passkey
+    let mut authenticator = Authenticator::new();
+    let req = test::TestRequest::post()
+        .uri("/account/passkeys/register/options")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let options: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(options["user"]["id"].as_str().unwrap(), URL_SAFE_NO_PAD.encode(&id));
+
+    let req = test::TestRequest::post()
+        .uri("/account/passkeys/register")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(serde_json::json!({ "name": "laptop", "credential": authenticator.create(&options) }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+
+    // the registration challenge cannot be used twice
+    let req = test::TestRequest::post()
+        .uri("/account/passkeys/register")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(serde_json::json!({ "name": "laptop", "credential": authenticator.create(&options) }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+
+    let req = test::TestRequest::get()
+        .uri("/account/passkeys")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let passkeys = json.as_array().unwrap();
+    assert_eq!(passkeys.len(), 1);
+    assert_eq!(passkeys[0]["id"].as_str().unwrap(), authenticator.id());
+    assert_eq!(passkeys[0]["name"].as_str().unwrap(), "laptop");
+    assert!(passkeys[0].get("public_key").is_none());
+
+    // log in with the passkey
+    let req = test::TestRequest::post().uri("/account/auth/passkey/options").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let options: serde_json::Value = test::read_body_json(resp).await;
+
+    let assertion = authenticator.get(&options, &URL_SAFE_NO_PAD.encode(&id));
+    let req = test::TestRequest::post().uri("/account/auth/passkey").set_json(&assertion).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["id"].as_str().unwrap(), id);
+    assert!(json["token"].as_str().is_some());
+    assert!(json["refresh_token"].as_str().is_some());
+
+    let req = test::TestRequest::get()
+        .uri("/account/")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", json["token"].as_str().unwrap())))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    // the same assertion cannot be replayed
+    let req = test::TestRequest::post().uri("/account/auth/passkey").set_json(&assertion).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+
+    // a login challenge cannot be answered for another account
+    let req = test::TestRequest::post().uri("/account/auth/passkey/options").to_request();
+    let resp = test::call_service(&app, req).await;
+    let options: serde_json::Value = test::read_body_json(resp).await;
+    let assertion = authenticator.get(&options, &URL_SAFE_NO_PAD.encode("000000000000000000000000"));
+    let req = test::TestRequest::post().uri("/account/auth/passkey").set_json(&assertion).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+}
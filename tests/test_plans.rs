@@ -0,0 +1,132 @@
+#![cfg(test)]
+
+mod common;
+
+use actix_http::header;
+use actix_web::{test, web};
+use common::{create_user, get_app, login};
+use mongodb::bson::{doc, oid::ObjectId};
+use serial_test::serial;
+use server::handlers::account_handlers::*;
+use server::handlers::admin_handlers::*;
+use server::handlers::document_handlers::*;
+use server::handlers::plan_handlers::*;
+use server::handlers::profile_handlers::*;
+use server::repository::database::DatabaseRepository;
+
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/account").service(create_account).service(login_account).service(get_plan))
+        .service(web::scope("/profile").service(change_profile))
+        .service(web::scope("/document").service(create_update_document))
+        .service(web::scope("/admin").service(set_user_plan));
+}
+
+fn add_skill(token: &str, skill: &str) -> actix_http::Request {
+    test::TestRequest::patch()
+        .uri("/profile/")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(serde_json::json!([{
+            "op": "add",
+            "target": "skills",
+            "value": { "type": "skills", "value": { "skill": skill } }
+        }]))
+        .to_request()
+}
+
+fn add_document(token: &str, title: &str) -> actix_http::Request {
+    test::TestRequest::put()
+        .uri("/document")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(serde_json::json!({
+            "title": title,
+            "prompt": "Why do you want to work here?",
+            "content": "Because I like it.",
+            "rating": "none"
+        }))
+        .to_request()
+}
+
+/// This test fills the profile and documents of a free account up to the limits of its plan
+#[actix_rt::test]
+#[serial]
+async fn test_free_plan_limits() {
+    let app = test::init_service(get_app(routes).await).await;
+    let (_, token) = create_user(&app, "John Doe", "johndoe@email.com").await;
+
+    for i in 0..5 {
+        let resp = test::call_service(&app, add_skill(&token, &format!("Skill {}", i))).await;
+        assert_eq!(resp.status(), 200);
+    }
+    let resp = test::call_service(&app, add_skill(&token, "One too many")).await;
+    assert_eq!(resp.status(), 402);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["plan"].as_str().unwrap(), "free");
+    assert_eq!(json["resource"].as_str().unwrap(), "profile_entries");
+    assert_eq!(json["section"].as_str().unwrap(), "skills");
+    assert_eq!(json["limit"].as_u64().unwrap(), 5);
+    assert_eq!(json["used"].as_u64().unwrap(), 5);
+
+    for i in 0..3 {
+        let resp = test::call_service(&app, add_document(&token, &format!("Document {}", i))).await;
+        assert_eq!(resp.status(), 200);
+    }
+    let resp = test::call_service(&app, add_document(&token, "One too many")).await;
+    assert_eq!(resp.status(), 402);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["resource"].as_str().unwrap(), "documents");
+    assert_eq!(json["used"].as_u64().unwrap(), 3);
+
+    let req = test::TestRequest::get()
+        .uri("/account/plan")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["plan"].as_str().unwrap(), "free");
+    let usage = json["usage"].as_array().unwrap();
+    let documents = usage.iter().find(|u| u["resource"] == "documents").unwrap();
+    assert_eq!(documents["used"].as_u64().unwrap(), 3);
+    let generations = usage.iter().find(|u| u["resource"] == "generations").unwrap();
+    assert_eq!(generations["used"].as_u64().unwrap(), 0);
+    assert!(generations["resets_at"].as_i64().is_some());
+}
+
+/// This test upgrades an account and goes over the limits of the free plan
+#[actix_rt::test]
+#[serial]
+async fn test_upgraded_plan_limits() {
+    let app = test::init_service(get_app(routes).await).await;
+    create_user(&app, "John Doe", "admin@email.com").await;
+    let (user_id, token) = create_user(&app, "John Doe", "johndoe@email.com").await;
+
+    let db = DatabaseRepository::new("mongodb://localhost:27017").await;
+    let admin = db.get_account_by_email("admin@email.com").await.unwrap().unwrap();
+    db.user_collection.update_one(doc! {"_id": admin.id.unwrap()}, doc! {"$set": {"roles": ["admin"]}}, None).await.unwrap();
+    let admin_token = login(&app, "admin@email.com").await;
+
+    // regular users cannot change plans
+    let req = test::TestRequest::put()
+        .uri(&format!("/admin/users/{}/plan", user_id))
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(serde_json::json!({ "plan": "pro" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 403);
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/admin/users/{}/plan", user_id))
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", admin_token)))
+        .set_json(serde_json::json!({ "plan": "pro" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    for i in 0..6 {
+        let resp = test::call_service(&app, add_skill(&token, &format!("Skill {}", i))).await;
+        assert_eq!(resp.status(), 200);
+    }
+
+    let user = db.user_collection.find_one(doc! {"_id": ObjectId::parse_str(&user_id).unwrap()}, None).await.unwrap().unwrap();
+    assert_eq!(user.profile.unwrap().skills.len(), 6);
+}
@@ -0,0 +1,76 @@
+#![cfg(test)]
+
+mod common;
+
+use actix_http::header;
+use actix_web::{test, web};
+use common::{create_user, get_app};
+use serial_test::serial;
+use server::handlers::account_handlers::*;
+use server::handlers::preferences_handlers::*;
+
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/account").service(create_account).service(get_preferences).service(update_preferences));
+}
+
+/// This test saves preferences and reads them back
+#[actix_rt::test]
+#[serial]
+async fn test_preferences() {
+    let app = test::init_service(get_app(routes).await).await;
+    let (_, token) = create_user(&app, "John Doe", "johndoe@email.com").await;
+
+    // new accounts have no preferences
+    let req = test::TestRequest::get()
+        .uri("/account/preferences")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert!(json["tone"].is_null());
+    assert!(json["model"].is_null());
+
+    let req = test::TestRequest::put()
+        .uri("/account/preferences")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(serde_json::json!({
+            "tone": "friendly",
+            "target_length": 250,
+            "language": "Spanish",
+            "first_person": true,
+            "model": "gpt-4"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let req = test::TestRequest::get()
+        .uri("/account/preferences")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(json["tone"].as_str().unwrap(), "friendly");
+    assert_eq!(json["target_length"].as_u64().unwrap(), 250);
+    assert_eq!(json["language"].as_str().unwrap(), "Spanish");
+    assert!(json["first_person"].as_bool().unwrap());
+    assert_eq!(json["model"].as_str().unwrap(), "gpt-4");
+
+    // unsupported values are rejected
+    for body in [serde_json::json!({ "model": "davinci" }), serde_json::json!({ "target_length": 10 })] {
+        let req = test::TestRequest::put()
+            .uri("/account/preferences")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .set_json(body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+    }
+
+    // preferences need a logged in user
+    let req = test::TestRequest::get().uri("/account/preferences").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+}
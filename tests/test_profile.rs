@@ -8,6 +8,7 @@ use actix_web::{
     middleware, test, web, App,
 };
 use assert_json_diff::assert_json_include;
+use serial_test::serial;
 use server::handlers::account_handlers::create_account;
 use server::handlers::profile_handlers::change_profile;
 use server::repository::{database::DatabaseRepository, redis::RedisRepository};
@@ -39,12 +40,13 @@ async fn create_some_account(name: &str, email: &str) -> actix_http::Request {
         .set_json(serde_json::json!({
             "name": name,
             "email": email,
-            "password": "password"
+            "password": "correct-horse-battery"
         }))
         .to_request()
 }
 
 #[actix_rt::test]
+#[serial]
 async fn test_profile() {
     let app = get_app().await;
     let app = test::init_service(app).await;
@@ -155,3 +157,89 @@ async fn test_profile() {
     assert_eq!(removed_experience.len(), 0);
     assert_json_include!(actual: added_education, expected: education);
 }
+
+/// This test adds entries with structured dates, location and links, and rejects invalid ones
+#[actix_rt::test]
+#[serial]
+async fn test_profile_structured_fields() {
+    let app = get_app().await;
+    let app = test::init_service(app).await;
+    let req = create_some_account("Jane Doe", "janedoe@gmail.com").await;
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let body = test::read_body(resp).await;
+    let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    let token = json["token"].as_str().unwrap().to_string();
+
+    let add = |target: &str, value: serde_json::Value| {
+        test::TestRequest::patch()
+            .uri("/profile/")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .set_json(serde_json::json!([{
+                "op": "add",
+                "target": target,
+                "value": { "type": target, "value": value }
+            }]))
+            .to_request()
+    };
+
+    let experience = serde_json::json!({
+        "name": "Software Engineer Intern",
+        "type": "work",
+        "at": "Microsoft",
+        "current": false,
+        "description": "Partner Center Marketplace",
+        "start_date": "2022-05",
+        "end_date": "2022-08",
+        "location": "Redmond, WA",
+        "url": "https://partner.microsoft.com",
+        "highlights": ["Improved marketplace search", "Wrote integration tests"]
+    });
+    let resp = test::call_service(&app, add("experience", experience.clone())).await;
+    assert_eq!(resp.status(), 200);
+    let body = test::read_body(resp).await;
+    let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    let added_experience = json["profile"]["experience"][0].as_object().unwrap();
+    assert_json_include!(actual: added_experience, expected: experience);
+
+    let education = serde_json::json!({
+        "school": "University of Michigan",
+        "degree": "Bachelor of Science",
+        "field_of_study": "Computer Science",
+        "current": true,
+        "description": "",
+        "start_date": "2019-09",
+        "location": "Ann Arbor, MI",
+        "highlights": ["Dean's List"]
+    });
+    let resp = test::call_service(&app, add("education", education.clone())).await;
+    assert_eq!(resp.status(), 200);
+    let body = test::read_body(resp).await;
+    let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    let added_education = json["profile"]["education"][0].as_object().unwrap();
+    assert_json_include!(actual: added_education, expected: education);
+    assert!(added_education["end_date"].is_null());
+
+    // current entries cannot have an end date
+    let mut invalid = education.clone();
+    invalid["end_date"] = serde_json::json!("2023-05");
+    let resp = test::call_service(&app, add("education", invalid)).await;
+    assert_eq!(resp.status(), 400);
+
+    // entries cannot end before they start
+    let mut invalid = experience.clone();
+    invalid["end_date"] = serde_json::json!("2021-08");
+    let resp = test::call_service(&app, add("experience", invalid)).await;
+    assert_eq!(resp.status(), 400);
+
+    // dates are months
+    let mut invalid = experience.clone();
+    invalid["start_date"] = serde_json::json!("May 2022");
+    let resp = test::call_service(&app, add("experience", invalid)).await;
+    assert_eq!(resp.status(), 400);
+
+    let mut invalid = experience.clone();
+    invalid["url"] = serde_json::json!("partner.microsoft.com");
+    let resp = test::call_service(&app, add("experience", invalid)).await;
+    assert_eq!(resp.status(), 400);
+}
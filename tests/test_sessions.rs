@@ -0,0 +1,112 @@
+#![cfg(test)]
+
+mod common;
+
+use actix_http::{body::MessageBody, header};
+use actix_web::{dev::ServiceResponse, error::Error, test, web};
+use common::{create_user, get_app, PASSWORD};
+use serial_test::serial;
+use server::handlers::account_handlers::*;
+use server::handlers::session_handlers::*;
+
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/account")
+            .service(create_account)
+            .service(get_account_by_id)
+            .service(login_account)
+            .service(refresh_token)
+            .service(list_sessions)
+            .service(revoke_session),
+    );
+}
+
+/// Log in from a device and return the access token and refresh token
+async fn login<S, B>(app: &S, user_agent: &str) -> (String, String)
+where
+    S: actix_service::Service<actix_http::Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let req = test::TestRequest::post()
+        .uri("/account/auth/login")
+        .insert_header((header::USER_AGENT, user_agent))
+        .set_json(serde_json::json!({
+            "email": "johndoe@email.com",
+            "password": PASSWORD
+        }))
+        .to_request();
+    let resp = test::call_service(app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    (
+        json["token"].as_str().unwrap().to_string(),
+        json["refresh_token"].as_str().unwrap().to_string(),
+    )
+}
+
+/// This test logs in from two devices, lists the sessions from the first one,
+/// then revokes the second one and asserts that its tokens are rejected
+#[actix_rt::test]
+#[serial]
+async fn test_session_management() {
+    let app = test::init_service(get_app(routes).await).await;
+    create_user(&app, "John Doe", "johndoe@email.com").await;
+
+    let (laptop_token, _) = login(&app, "laptop").await;
+    let (phone_token, phone_refresh) = login(&app, "phone").await;
+
+    // every login is listed, and the current one is marked
+    let req = test::TestRequest::get()
+        .uri("/account/sessions")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", laptop_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let sessions = json.as_array().unwrap();
+    assert_eq!(sessions.len(), 3);
+    let current: Vec<&serde_json::Value> = sessions.iter().filter(|s| s["current"].as_bool().unwrap()).collect();
+    assert_eq!(current.len(), 1);
+    assert_eq!(current[0]["user_agent"].as_str().unwrap(), "laptop");
+    let phone = sessions.iter().find(|s| s["user_agent"].as_str() == Some("phone")).unwrap();
+    let phone_id = phone["id"].as_str().unwrap().to_string();
+
+    // unknown sessions cannot be revoked
+    let req = test::TestRequest::delete()
+        .uri("/account/sessions/unknown")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", laptop_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
+    // revoke the phone session
+    let req = test::TestRequest::delete()
+        .uri(format!("/account/sessions/{}", phone_id).as_str())
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", laptop_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    // its access token and refresh token are rejected
+    let req = test::TestRequest::get()
+        .uri("/account/")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", phone_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+
+    let req = test::TestRequest::post()
+        .uri("/account/auth/refresh")
+        .set_json(serde_json::json!({ "refresh_token": phone_refresh }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+
+    // the laptop session is not affected
+    let req = test::TestRequest::get()
+        .uri("/account/")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", laptop_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+}